@@ -1,4 +1,5 @@
 use crate::error::ServiceResult;
+use crate::git::ClonedRepo;
 use crate::service::ServiceClient;
 use std::path::PathBuf;
 use std::time::{Duration, SystemTime};
@@ -39,12 +40,36 @@ impl Client {
         uri: U,
         parent_dir: Option<P>,
         directory: Option<D>,
-    ) -> impl futures::Future<Output = Result<ServiceResult<()>, RpcError>> + '_ {
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+    ) -> impl futures::Future<Output = Result<ServiceResult<ClonedRepo>, RpcError>> + '_ {
         self.inner.clone_in(
             Self::make_context(),
             uri.into(),
             parent_dir.map(Into::into),
             directory.map(Into::into),
+            shallow_since,
+            depth,
+        )
+    }
+
+    pub fn clone_branch_in<U: Into<String>, P: Into<PathBuf>, D: Into<String>, B: Into<String>>(
+        &self,
+        uri: U,
+        parent_dir: Option<P>,
+        directory: Option<D>,
+        branch: B,
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+    ) -> impl futures::Future<Output = Result<ServiceResult<ClonedRepo>, RpcError>> + '_ {
+        self.inner.clone_branch_in(
+            Self::make_context(),
+            uri.into(),
+            parent_dir.map(Into::into),
+            directory.map(Into::into),
+            branch.into(),
+            shallow_since,
+            depth,
         )
     }
 
@@ -0,0 +1,104 @@
+use crate::trace::{async_git_command, trace_exit, trace_start, traced_output_async};
+use crate::{ServiceError, ServiceResult};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+
+/// Set to 1 to forward git's output as it clones, rather than only reporting it on failure
+pub const VERBOSE_ENV_VAR: &str = "YB_POOL_VERBOSE";
+
+fn is_verbose() -> bool {
+    std::env::var(VERBOSE_ENV_VAR).as_deref() == Ok("1")
+}
+
+/// Run a 'git clone' `command`. If it fails, git's stderr is included in the returned
+/// `ServiceError::CloneFailed`. In verbose mode (see `VERBOSE_ENV_VAR`) stderr is also forwarded
+/// line by line as git writes it.
+pub(crate) async fn run_clone(command: &mut Command) -> ServiceResult<()> {
+    let verbose = is_verbose();
+    trace_start(command.as_std());
+    let mut child = command
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let mut lines = BufReader::new(child.stderr.take().unwrap()).lines();
+    let mut stderr = String::new();
+    while let Some(line) = lines.next_line().await? {
+        if verbose {
+            eprintln!("{line}");
+        }
+        stderr.push_str(&line);
+        stderr.push('\n');
+    }
+
+    let status = child.wait().await;
+    trace_exit(command.as_std(), status.as_ref().copied());
+    let status = status?;
+    if !status.success() {
+        return Err(ServiceError::CloneFailed(format!(
+            "{status}: {}",
+            stderr.trim()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Whether `refspec` looks like a (possibly abbreviated) commit SHA, which 'git clone --branch'
+/// doesn't accept
+pub(crate) fn is_commit_sha(refspec: &str) -> bool {
+    (7..=40).contains(&refspec.len()) && refspec.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Where a clone ended up, and what it checked out
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClonedRepo {
+    /// Absolute path of the new clone
+    pub path: PathBuf,
+    /// SHA of HEAD in the new clone, unless the cloned repo was empty
+    pub head: Option<String>,
+}
+
+/// The directory 'git clone' picks for `uri` when not given one, e.g. 'foo' for
+/// 'https://example.com/foo.git'
+fn humanish_name(uri: &str) -> &str {
+    let uri = uri.trim_end_matches('/');
+    let uri = uri.strip_suffix("/.git").unwrap_or(uri);
+    let name = uri.rsplit(['/', ':']).next().unwrap_or(uri);
+    name.strip_suffix(".git").unwrap_or(name)
+}
+
+/// Find the clone of `uri` that 'git clone' just made in `directory` (or the directory it picked)
+/// relative to `cwd` (or the current directory), and the commit it checked out
+pub(crate) async fn resolve_clone(
+    cwd: Option<&Path>,
+    uri: &str,
+    directory: Option<&str>,
+) -> ServiceResult<ClonedRepo> {
+    let parent_dir = match cwd {
+        Some(cwd) => cwd.to_path_buf(),
+        None => std::env::current_dir()?,
+    };
+    let path =
+        tokio::fs::canonicalize(parent_dir.join(directory.unwrap_or_else(|| humanish_name(uri))))
+            .await?;
+
+    let output = traced_output_async(
+        async_git_command()
+            .arg("rev-parse")
+            .arg("--verify")
+            .arg("--quiet")
+            .arg("HEAD")
+            .current_dir(&path),
+    )
+    .await?;
+    let head = output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string());
+
+    Ok(ClonedRepo { path, head })
+}
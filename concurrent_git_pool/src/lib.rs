@@ -1,12 +1,15 @@
 pub mod client;
 mod error;
+mod git;
 pub mod pool;
 pub mod pool_helper;
 pub mod server;
 pub mod service;
+pub mod trace;
 
 pub use client::Client;
 pub use error::{ServiceError, ServiceResult};
+pub use git::{ClonedRepo, VERBOSE_ENV_VAR};
 
 pub use pool_helper::PoolHelper;
 
@@ -1,4 +1,6 @@
-use crate::error::{ServiceError, ServiceResult};
+use crate::error::ServiceResult;
+use crate::git::{is_commit_sha, resolve_clone, run_clone, ClonedRepo};
+use crate::trace::async_git_command;
 use futures::future::Shared;
 use futures::prelude::*;
 use sha2::{Digest, Sha256};
@@ -8,7 +10,6 @@ use std::future::Future;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use tempfile::TempDir;
-use tokio::process::Command;
 use tokio::sync::Mutex;
 
 #[derive(Debug)]
@@ -30,21 +31,92 @@ impl Pool {
         cwd: Option<C>,
         remote: R,
         directory: Option<D>,
-    ) -> ServiceResult<()>
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+    ) -> ServiceResult<ClonedRepo>
     where
         C: AsRef<Path>,
         R: AsRef<str>,
         D: AsRef<str>,
     {
+        self.clone_from_cache(
+            cwd.as_ref().map(AsRef::as_ref),
+            remote.as_ref(),
+            directory.as_ref().map(AsRef::as_ref),
+            shallow_since,
+            depth,
+            None,
+        )
+        .await
+    }
+
+    /// Like `clone_in`, but only clone `branch` ('git clone --branch <branch> --single-branch'),
+    /// which is much cheaper for repos with many branches. Falls back to a full clone if `branch`
+    /// looks like a commit SHA or the single-branch clone fails.
+    pub async fn clone_branch_in<C, R, D, B>(
+        &self,
+        cwd: Option<C>,
+        remote: R,
+        directory: Option<D>,
+        branch: B,
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+    ) -> ServiceResult<ClonedRepo>
+    where
+        C: AsRef<Path>,
+        R: AsRef<str>,
+        D: AsRef<str>,
+        B: AsRef<str>,
+    {
+        let cwd = cwd.as_ref().map(AsRef::as_ref);
         let remote = remote.as_ref();
-        let path = self.lookup_or_clone(remote).await.unwrap();
+        let directory = directory.as_ref().map(AsRef::as_ref);
+        let branch = branch.as_ref();
+
+        if !is_commit_sha(branch) {
+            match self
+                .clone_from_cache(
+                    cwd,
+                    remote,
+                    directory,
+                    shallow_since.clone(),
+                    depth,
+                    Some(branch),
+                )
+                .await
+            {
+                Ok(cloned) => return Ok(cloned),
+                Err(e) => tracing::debug!(
+                    "single-branch clone of {}@{} failed, falling back to a full clone: {}",
+                    remote,
+                    branch,
+                    e
+                ),
+            }
+        }
+
+        self.clone_from_cache(cwd, remote, directory, shallow_since, depth, None)
+            .await
+    }
 
-        let mut command = Command::new("git");
+    /// Clone `remote` using the cached clone as a reference, only cloning `branch` if given
+    async fn clone_from_cache(
+        &self,
+        cwd: Option<&Path>,
+        remote: &str,
+        directory: Option<&str>,
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+        branch: Option<&str>,
+    ) -> ServiceResult<ClonedRepo> {
+        let path = self.lookup_or_clone(remote).await?;
+
+        let mut command = async_git_command();
         command.env("GIT_TERMINAL_PROMPT", "0");
         command.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes");
         command.arg("clone").arg(remote);
         if let Some(directory) = directory {
-            command.arg(directory.as_ref());
+            command.arg(directory);
         }
 
         command
@@ -52,11 +124,25 @@ impl Pool {
             .arg(path.to_str().unwrap())
             .arg("--dissociate");
 
+        if let Some(branch) = branch {
+            command.arg("--branch").arg(branch).arg("--single-branch");
+        }
+
+        // Note that git ignores these when `remote` is a plain local path; shallow clones of a
+        // local repo need a file:// URL
+        if let Some(shallow_since) = shallow_since {
+            command.arg(format!("--shallow-since={shallow_since}"));
+        }
+        if let Some(depth) = depth {
+            command.arg(format!("--depth={depth}"));
+        }
+
         if let Some(cwd) = cwd {
             command.current_dir(cwd);
         }
 
-        command.output().await.map_err(|e| e.into()).map(|_| ())
+        run_clone(&mut command).await?;
+        resolve_clone(cwd, remote, directory).await
     }
 
     pub async fn lookup<U: AsRef<str>>(&self, uri: U) -> Option<ServiceResult<PathBuf>> {
@@ -127,19 +213,17 @@ async fn clone_repo(
     remote: String,
     dest_dir_name: String,
 ) -> ServiceResult<PathBuf> {
-    let status = Command::new("git")
-        .current_dir(&root)
-        .env("GIT_TERMINAL_PROMPT", "0")
-        .arg("clone")
-        .arg(&remote)
-        .arg(&dest_dir_name)
-        .status()
-        .await?;
-
-    match status.success() {
-        true => Ok(root.join(&dest_dir_name)),
-        false => Err(ServiceError::CloneFailed(format!("{status}"))),
-    }
+    run_clone(
+        async_git_command()
+            .current_dir(&root)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .arg("clone")
+            .arg(&remote)
+            .arg(&dest_dir_name),
+    )
+    .await?;
+
+    Ok(root.join(&dest_dir_name))
 }
 
 #[derive(Debug, Clone)]
@@ -1,6 +1,7 @@
-use crate::{Client, RpcError, ServiceError, ServiceResult};
+use crate::git::{is_commit_sha, resolve_clone, run_clone};
+use crate::trace::async_git_command;
+use crate::{Client, ClonedRepo, RpcError, ServiceResult};
 use std::path::PathBuf;
-use tokio::process::Command;
 
 #[derive(Clone)]
 pub struct PoolHelper {
@@ -19,45 +20,214 @@ impl PoolHelper {
         Ok(Self { inner: None })
     }
 
+    /// Whether clones go through a concurrent_git_pool server (rather than running git locally)
+    pub fn is_pooled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Have the pool clone `uri` into its cache (unless it is already there), returning the path
+    /// of the cached clone. Without a pool there is no cache, so this does nothing.
+    pub async fn lookup_or_clone<U: Into<String>>(
+        &self,
+        uri: U,
+    ) -> Result<ServiceResult<Option<PathBuf>>, RpcError> {
+        match &self.inner {
+            Some(inner) => Ok(inner.lookup_or_clone(uri).await?.map(Some)),
+            None => Ok(Ok(None)),
+        }
+    }
+
+    /// Clone `uri` into `directory` (or the directory git picks), relative to `parent_dir` (or
+    /// the current directory). Returns the absolute path of the clone and the commit it checked
+    /// out.
     pub async fn clone_in<U: Into<String>>(
         &self,
         uri: U,
         parent_dir: Option<PathBuf>,
         directory: Option<String>,
-    ) -> Result<ServiceResult<()>, RpcError> {
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+    ) -> Result<ServiceResult<ClonedRepo>, RpcError> {
+        let uri = uri.into();
         if let Some(inner) = &self.inner {
-            let uri = uri.into();
-            eprintln!("cloning: {}", &uri);
-            let ret = inner.clone_in(uri, parent_dir, directory).await;
-            dbg!(&ret);
-            return ret;
+            return inner
+                .clone_in(uri, parent_dir, directory, shallow_since, depth)
+                .await;
         }
 
-        let mut command = Command::new("git");
-        command.env("GIT_TERMINAL_PROMPT", "0");
-        command.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes");
-        command.arg("clone").arg(uri.into());
-        if let Some(directory) = directory {
-            command.arg(directory);
-        }
-        if let Some(parent_dir) = parent_dir {
-            command.current_dir(parent_dir);
-        }
+        Ok(clone_locally(&uri, parent_dir, directory, shallow_since, depth, None).await)
+    }
 
-        let result = command.output().await;
-        if let Err(e) = result {
-            return Ok(Err(ServiceError::IoError(format!(
-                "failed to call status() on command: {e:?}"
-            ))));
+    /// Like `clone_in`, but only clone `branch` ('git clone --branch <branch> --single-branch'),
+    /// falling back to a full clone if `branch` looks like a commit SHA or the single-branch
+    /// clone fails
+    pub async fn clone_branch_in<U: Into<String>, B: Into<String>>(
+        &self,
+        uri: U,
+        parent_dir: Option<PathBuf>,
+        directory: Option<String>,
+        branch: B,
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+    ) -> Result<ServiceResult<ClonedRepo>, RpcError> {
+        let uri = uri.into();
+        let branch = branch.into();
+        if let Some(inner) = &self.inner {
+            return inner
+                .clone_branch_in(uri, parent_dir, directory, branch, shallow_since, depth)
+                .await;
         }
 
-        let result = result.unwrap();
-        if !result.status.success() {
-            return Ok(Err(ServiceError::CloneFailed(format!(
-                "exit code: {result:?}"
-            ))));
+        if !is_commit_sha(&branch) {
+            match clone_locally(
+                &uri,
+                parent_dir.clone(),
+                directory.clone(),
+                shallow_since.clone(),
+                depth,
+                Some(&branch),
+            )
+            .await
+            {
+                Ok(cloned) => return Ok(Ok(cloned)),
+                Err(e) => tracing::debug!(
+                    "single-branch clone of {}@{} failed, falling back to a full clone: {}",
+                    uri,
+                    branch,
+                    e
+                ),
+            }
         }
 
-        Ok(Ok(()))
+        Ok(clone_locally(&uri, parent_dir, directory, shallow_since, depth, None).await)
+    }
+}
+
+/// Run 'git clone' here rather than in a pool, only cloning `branch` if given
+async fn clone_locally(
+    uri: &str,
+    parent_dir: Option<PathBuf>,
+    directory: Option<String>,
+    shallow_since: Option<String>,
+    depth: Option<u32>,
+    branch: Option<&str>,
+) -> ServiceResult<ClonedRepo> {
+    let mut command = async_git_command();
+    command.env("GIT_TERMINAL_PROMPT", "0");
+    command.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes");
+    command.arg("clone").arg(uri);
+    if let Some(directory) = &directory {
+        command.arg(directory);
+    }
+    if let Some(branch) = branch {
+        command.arg("--branch").arg(branch).arg("--single-branch");
+    }
+    if let Some(shallow_since) = shallow_since {
+        command.arg(format!("--shallow-since={shallow_since}"));
+    }
+    if let Some(depth) = depth {
+        command.arg(format!("--depth={depth}"));
+    }
+    if let Some(parent_dir) = &parent_dir {
+        command.current_dir(parent_dir);
+    }
+
+    run_clone(&mut command).await?;
+    resolve_clone(parent_dir.as_deref(), uri, directory.as_deref()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use crate::PoolHelper;
+
+    fn git(cwd: &std::path::Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .current_dir(cwd)
+            .args(["-c", "user.name=pool", "-c", "user.email=pool@localhost"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    #[tokio::test]
+    async fn clone_in_reports_path_and_head() {
+        let dir = tempfile::tempdir().unwrap();
+        let upstream = dir.path().join("upstream.git");
+        std::fs::create_dir(&upstream).unwrap();
+        git(&upstream, &["init"]);
+        git(&upstream, &["commit", "--allow-empty", "-m", "initial"]);
+
+        let clones_dir = dir.path().join("clones");
+        std::fs::create_dir(&clones_dir).unwrap();
+        let cloned = PoolHelper { inner: None }
+            .clone_in(
+                upstream.to_str().unwrap(),
+                Some(clones_dir.clone()),
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            cloned.path,
+            clones_dir.join("upstream").canonicalize().unwrap()
+        );
+        assert_eq!(cloned.head, Some(git(&cloned.path, &["rev-parse", "HEAD"])));
+    }
+
+    #[tokio::test]
+    async fn clone_branch_in_only_clones_that_branch() {
+        let dir = tempfile::tempdir().unwrap();
+        let upstream = dir.path().join("upstream.git");
+        std::fs::create_dir(&upstream).unwrap();
+        git(&upstream, &["init", "-b", "master"]);
+        git(&upstream, &["commit", "--allow-empty", "-m", "initial"]);
+        git(&upstream, &["branch", "honister"]);
+        git(&upstream, &["branch", "kirkstone"]);
+
+        let clones_dir = dir.path().join("clones");
+        std::fs::create_dir(&clones_dir).unwrap();
+        let helper = PoolHelper { inner: None };
+        let cloned = helper
+            .clone_branch_in(
+                upstream.to_str().unwrap(),
+                Some(clones_dir.clone()),
+                Some("single".to_string()),
+                "honister",
+                None,
+                None,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            git(&cloned.path, &["rev-parse", "--abbrev-ref", "HEAD"]),
+            "honister"
+        );
+        assert_eq!(git(&cloned.path, &["branch", "-r"]), "origin/honister");
+
+        // A SHA can't be passed to '--branch', so it gets a full clone
+        let sha = git(&upstream, &["rev-parse", "HEAD"]);
+        let cloned = helper
+            .clone_branch_in(
+                upstream.to_str().unwrap(),
+                Some(clones_dir),
+                Some("full".to_string()),
+                sha,
+                None,
+                None,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(git(&cloned.path, &["branch", "-r"]).contains("origin/kirkstone"));
     }
 }
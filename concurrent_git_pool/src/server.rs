@@ -1,4 +1,5 @@
 use crate::error::ServiceResult;
+use crate::git::ClonedRepo;
 use crate::pool::Pool;
 use crate::service::Service;
 use std::path::PathBuf;
@@ -26,8 +27,27 @@ impl Service for Server {
         uri: String,
         parent_dir: Option<PathBuf>,
         directory: Option<String>,
-    ) -> ServiceResult<()> {
-        self.cache.clone_in(parent_dir, uri, directory).await
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+    ) -> ServiceResult<ClonedRepo> {
+        self.cache
+            .clone_in(parent_dir, uri, directory, shallow_since, depth)
+            .await
+    }
+
+    async fn clone_branch_in(
+        self,
+        _: Context,
+        uri: String,
+        parent_dir: Option<PathBuf>,
+        directory: Option<String>,
+        branch: String,
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+    ) -> ServiceResult<ClonedRepo> {
+        self.cache
+            .clone_branch_in(parent_dir, uri, directory, branch, shallow_since, depth)
+            .await
     }
 }
 
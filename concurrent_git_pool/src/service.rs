@@ -1,4 +1,5 @@
 use crate::error::ServiceResult;
+use crate::git::ClonedRepo;
 use std::path::PathBuf;
 
 #[tarpc::service]
@@ -9,5 +10,15 @@ pub trait Service {
         uri: String,
         parent_dir: Option<PathBuf>,
         directory: Option<String>,
-    ) -> ServiceResult<()>;
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+    ) -> ServiceResult<ClonedRepo>;
+    async fn clone_branch_in(
+        uri: String,
+        parent_dir: Option<PathBuf>,
+        directory: Option<String>,
+        branch: String,
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+    ) -> ServiceResult<ClonedRepo>;
 }
@@ -0,0 +1,86 @@
+//! Logging of the git subprocesses that are run, for debugging. Each command is logged (with its
+//! arguments and working directory) before it runs and again with its exit status, at INFO under
+//! the `TRACE_GIT_TARGET` target. yb enables that target with `--trace-git`.
+
+use std::io;
+use std::process::{Command, ExitStatus, Output};
+
+/// Set to 1 to log git invocations, like `yb --trace-git`
+pub const TRACE_GIT_ENV_VAR: &str = "YB_TRACE_GIT";
+
+/// The tracing target git invocations are logged under
+pub const TRACE_GIT_TARGET: &str = "git_trace";
+
+/// A new 'git' command. Run it with `TracedCommand::traced_output` so that it gets logged.
+pub fn git_command() -> Command {
+    Command::new("git")
+}
+
+/// Like `git_command`, but for async code. Run it with `traced_output_async`.
+pub fn async_git_command() -> tokio::process::Command {
+    tokio::process::Command::new("git")
+}
+
+/// 'git arg1 arg2 ...'
+fn describe(command: &Command) -> String {
+    let mut ret = command.get_program().to_string_lossy().into_owned();
+    for arg in command.get_args() {
+        ret.push(' ');
+        ret.push_str(&arg.to_string_lossy());
+    }
+    ret
+}
+
+pub(crate) fn trace_start(command: &Command) {
+    tracing::info!(
+        target: TRACE_GIT_TARGET,
+        "running '{}' in {}",
+        describe(command),
+        command.get_current_dir().map_or_else(
+            || "the current directory".to_string(),
+            |dir| dir.display().to_string()
+        )
+    );
+}
+
+pub(crate) fn trace_exit(command: &Command, result: Result<ExitStatus, &io::Error>) {
+    match result {
+        Ok(status) => tracing::info!(
+            target: TRACE_GIT_TARGET,
+            "'{}' finished: {}",
+            describe(command),
+            status
+        ),
+        Err(e) => tracing::info!(
+            target: TRACE_GIT_TARGET,
+            "'{}' could not be run: {}",
+            describe(command),
+            e
+        ),
+    }
+}
+
+pub trait TracedCommand {
+    /// Like `Command::output`, but logs the command and its exit status
+    fn traced_output(&mut self) -> io::Result<Output>;
+}
+
+impl TracedCommand for Command {
+    fn traced_output(&mut self) -> io::Result<Output> {
+        trace_start(self);
+        let output = self.output();
+        trace_exit(self, output.as_ref().map(|output| output.status));
+        output
+    }
+}
+
+/// Like `tokio::process::Command::output`, but logs the command and its exit status
+pub async fn traced_output_async(command: &mut tokio::process::Command) -> io::Result<Output> {
+    trace_start(command.as_std());
+    let output = command.output().await;
+    trace_exit(
+        command.as_std(),
+        output.as_ref().map(|output| output.status),
+    );
+    output
+}
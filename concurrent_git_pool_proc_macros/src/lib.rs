@@ -115,7 +115,7 @@ pub fn clone_repos(input: TokenStream) -> TokenStream {
                 .unwrap_or(quote! {None});
 
             quote! {
-                client.clone_in(#uri, #parent_dir, #directory)
+                client.clone_in(#uri, #parent_dir, #directory, None, None)
             }
         })
         .collect::<Vec<_>>();
@@ -1,21 +1,44 @@
 use async_trait::async_trait;
 use indicatif::MultiProgress;
+use std::path::{Path, PathBuf};
 
+use crate::commands::status::warn_duplicate_workdir_skipped;
+use crate::commands::sync::planner::{plan_sync, SyncPlanEvent, SyncPlanOptions};
+use crate::commands::sync::summary::{format_summary_line, group_actions_by_repo};
 use crate::commands::SubcommandRunner;
 use crate::config::Config;
 use crate::core::tool_context::require_yb_env;
 use crate::errors::YbResult;
+use crate::ops::add_stream::op_add_spec_to_local_stream;
+use crate::spec::Spec;
+use crate::status_calculator::{compute_status, StatusCalculatorEvent, StatusCalculatorOptions};
+use crate::stream_db::StreamKey;
 use crate::ui_ops::check_broken_streams::{
     ui_op_check_broken_streams, UiCheckBrokenStreamsOptions,
 };
 use crate::util::indicatif::MultiProgressHelpers;
+use crate::util::paths::normalize_path;
 use crate::yb_env::YbEnv;
 
+/// Local stream that specs activated with --spec-file are copied into
+const SPEC_FILES_STREAM_NAME: &str = "spec-files";
+
 /// Make the given spec active, but don't actually sync anything
 #[derive(Debug, clap::Parser)]
 pub struct ActivateCommand {
-    /// Name of the spec to activate
-    spec: String,
+    /// Name of the spec to activate, or 'stream/spec' if several streams have a spec by that name
+    #[clap(required_unless_present = "spec-file")]
+    spec: Option<String>,
+
+    /// Activate the spec in this file, which needn't belong to any stream. It is copied into a
+    /// local stream called 'spec-files', which is created if need be.
+    #[clap(long, value_name = "PATH", conflicts_with = "spec")]
+    spec_file: Option<PathBuf>,
+
+    /// Don't activate the spec; instead show what 'yb sync' would do once it is active (repos to
+    /// clone, branches to switch to, layers to add or remove)
+    #[clap(long, conflicts_with = "spec-file")]
+    dry_run: bool,
 }
 
 #[async_trait]
@@ -23,7 +46,16 @@ impl SubcommandRunner for ActivateCommand {
     async fn run(&self, config: &mut Config, mp: &MultiProgress) -> YbResult<()> {
         ui_op_check_broken_streams(UiCheckBrokenStreamsOptions::new(config, mp))?;
 
+        if let Some(spec_file) = &self.spec_file {
+            return activate_spec_file(config, &normalize_path(config.cwd().join(spec_file)));
+        }
+
+        if self.dry_run {
+            return preview_activation(config, mp, self.spec.as_ref().unwrap());
+        }
+
         let mut yb_env = require_yb_env(config)?;
+        let _lock = yb_env.lock()?;
 
         if yb_env.stream_db().is_empty() {
             mp.warn("couldn't activate a spec because there are no streams");
@@ -31,19 +63,69 @@ impl SubcommandRunner for ActivateCommand {
             panic!();
         }
 
-        activate_spec(&mut yb_env, &self.spec)
+        activate_spec(&mut yb_env, self.spec.as_ref().unwrap())
     }
 }
 
 pub fn activate_spec(yb_env: &mut YbEnv, name: &str) -> YbResult<()> {
-    let spec = yb_env.find_spec(name)?.cloned();
-    if let Some(spec) = spec {
-        // TODO don't clone
-        yb_env.activate_spec(spec)?;
-        println!("Activated spec '{}'", &name);
+    // TODO don't clone
+    let spec = yb_env.find_spec(name)?.clone();
+    yb_env.activate_spec(spec)?;
+    println!("Activated spec '{}'", &name);
+
+    Ok(())
+}
+
+/// Print the sync plan for the environment as if spec `name` were active, without activating it
+fn preview_activation(config: &Config, mp: &MultiProgress, name: &str) -> YbResult<()> {
+    let yb_env = require_yb_env(config)?;
+    let spec = yb_env.find_spec(name)?.clone();
+    let active_spec = yb_env.stream_db().make_active_spec(spec)?;
+
+    let mut status_calculator_options = StatusCalculatorOptions::new(config, false, false);
+    status_calculator_options.prospective_spec(active_spec);
+    let status = compute_status(status_calculator_options, |event| {
+        if let StatusCalculatorEvent::DuplicateWorkdirSkipped { path, workdir } = event {
+            warn_duplicate_workdir_skipped(mp, path, workdir);
+        }
+    })?;
+
+    let sync_actions = plan_sync(
+        &status,
+        SyncPlanOptions::new(yb_env.sources_dir()),
+        |event| {
+            if let SyncPlanEvent::RepoSkipped { path, reason } = event {
+                mp.note(format!("would skip {}: {reason}", path.display()));
+            }
+        },
+    )?;
+
+    if sync_actions.is_empty() {
+        println!("Activating spec '{name}' would not change anything");
     } else {
-        eyre::bail!("spec with name '{}' not found", &name);
+        println!("Activating spec '{name}' and syncing would:");
+        for group in group_actions_by_repo(&sync_actions, &yb_env.sources_dir()) {
+            println!("{}", format_summary_line(&group, &sync_actions, None));
+        }
     }
+    mp.note("dry run: the active spec has not been changed");
+
+    Ok(())
+}
+
+fn activate_spec_file(config: &Config, spec_file: &Path) -> YbResult<()> {
+    let spec = Spec::load(spec_file, StreamKey::default())?;
+    let name = spec.name();
+    let _lock = require_yb_env(config)?.lock()?;
+    op_add_spec_to_local_stream(config, SPEC_FILES_STREAM_NAME, &spec)?;
+
+    // Reload so that the spec is found in the local stream
+    let mut yb_env = require_yb_env(config)?;
+    let spec = yb_env
+        .find_spec(format!("{SPEC_FILES_STREAM_NAME}/{name}"))?
+        .clone();
+    yb_env.activate_spec_from(spec, Some(spec_file.to_path_buf()))?;
+    println!("Activated spec '{}' from {}", name, spec_file.display());
 
     Ok(())
 }
@@ -0,0 +1,93 @@
+use std::io;
+
+use async_trait::async_trait;
+use clap::CommandFactory;
+use clap_complete::Shell;
+use indicatif::MultiProgress;
+
+use crate::commands::SubcommandRunner;
+use crate::config::Config;
+use crate::errors::YbResult;
+use crate::yb_env::{try_discover_yb_env, YbEnv};
+use crate::yb_options::YbOptions;
+
+/// Subcommands whose first positional argument is a spec name
+const SPEC_NAME_SUBCOMMANDS: &[&str] = &["activate", "sync"];
+
+/// Print a shell completion script for yb.
+///
+/// For bash and fish the script also completes spec names (of the yb environment in the current
+/// directory) for 'yb activate' and 'yb sync'.
+#[derive(Debug, clap::Parser)]
+pub struct CompletionsCommand {
+    #[clap(value_enum, required_unless_present = "list-specs")]
+    shell: Option<Shell>,
+
+    /// Print the names of the available specs, one per line. Used by the completion scripts.
+    #[clap(long, hide = true, conflicts_with = "shell")]
+    list_specs: bool,
+}
+
+#[async_trait]
+impl SubcommandRunner for CompletionsCommand {
+    async fn run(&self, config: &mut Config, _mp: &MultiProgress) -> YbResult<()> {
+        if self.list_specs {
+            // Completion shouldn't print errors, so outside of a usable environment list nothing
+            if let Ok(Some(yb_env)) = try_discover_yb_env(config.cwd()) {
+                for spec_name in list_spec_names(&yb_env) {
+                    println!("{spec_name}");
+                }
+            }
+            return Ok(());
+        }
+
+        let shell = self.shell.unwrap();
+        clap_complete::generate(shell, &mut YbOptions::command(), "yb", &mut io::stdout());
+        if let Some(script) = spec_name_completion_script(shell) {
+            println!("{script}");
+        }
+
+        Ok(())
+    }
+}
+
+fn list_spec_names(yb_env: &YbEnv) -> Vec<String> {
+    let mut names: Vec<String> = yb_env
+        .stream_db()
+        .streams()
+        .flat_map(|(_, stream)| stream.specs().map(|(name, _)| name.clone()))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Extra completion script that offers spec names (via 'yb completions --list-specs') in place
+/// of the first positional argument of the `SPEC_NAME_SUBCOMMANDS`
+fn spec_name_completion_script(shell: Shell) -> Option<String> {
+    match shell {
+        Shell::Bash => Some(format!(
+            r#"
+_yb_with_spec_names() {{
+    local prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    case "$prev" in
+        {subcommands})
+            if [[ "${{COMP_WORDS[COMP_CWORD]}}" != -* ]]; then
+                COMPREPLY=( $(compgen -W "$(yb completions --list-specs 2>/dev/null)" -- "${{COMP_WORDS[COMP_CWORD]}}") )
+                return 0
+            fi
+            ;;
+    esac
+    _yb "$@"
+}}
+
+complete -F _yb_with_spec_names -o bashdefault -o default yb"#,
+            subcommands = SPEC_NAME_SUBCOMMANDS.join("|")
+        )),
+        Shell::Fish => Some(format!(
+            "complete -c yb -n \"__fish_seen_subcommand_from {}\" -f -a \"(yb completions --list-specs 2>/dev/null)\"",
+            SPEC_NAME_SUBCOMMANDS.join(" ")
+        )),
+        _ => None,
+    }
+}
@@ -0,0 +1,36 @@
+use async_trait::async_trait;
+use indicatif::MultiProgress;
+
+use crate::commands::SubcommandRunner;
+use crate::errors::{YbError, YbResult};
+use crate::yb_conf::YbConfMigration;
+use crate::yb_env::{find_yb_dir, migrate_yb_conf};
+use crate::Config;
+
+/// Upgrade the environment's yb.yaml to the current format version, if it is older. yb also
+/// does this whenever it loads the environment; this reports what was done.
+#[derive(Debug, clap::Parser)]
+pub struct ConfigMigrateCommand {}
+
+#[async_trait]
+impl SubcommandRunner for ConfigMigrateCommand {
+    async fn run(&self, config: &mut Config, _mp: &MultiProgress) -> YbResult<()> {
+        // Not require_yb_env: loading the environment would already migrate the configuration
+        let yb_dir = find_yb_dir(config.cwd())?.ok_or(YbError::NoEnvironment)?;
+
+        let (conf, migration) = migrate_yb_conf(&yb_dir)?;
+        match migration {
+            YbConfMigration::UpToDate => println!(
+                "yb.yaml is already at format version {}",
+                conf.format_version()
+            ),
+            YbConfMigration::Migrated { from_version } => println!(
+                "migrated yb.yaml from format version {} to {}",
+                from_version,
+                conf.format_version()
+            ),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,11 @@
+use enum_dispatch::enum_dispatch;
+
+pub use migrate::ConfigMigrateCommand;
+
+mod migrate;
+
+#[enum_dispatch(SubcommandRunner)]
+#[derive(Debug, clap::Subcommand)]
+pub enum ConfigSubcommands {
+    Migrate(ConfigMigrateCommand),
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+use async_trait::async_trait;
+use color_eyre::Help;
+use eyre::Context;
+use indicatif::MultiProgress;
+
+use crate::commands::SubcommandRunner;
+use crate::core::tool_context::require_tool_context;
+use crate::errors::YbResult;
+use crate::util::dotenv::write_dotenv;
+use crate::util::indicatif::MultiProgressHelpers;
+use crate::Config;
+
+/// Variables that say more about the subshell than about the Yocto environment
+const IGNORED_VARS: &[&str] = &["_", "PWD", "OLDPWD", "SHLVL"];
+
+/// Capture the environment that sourcing poky's oe-init-build-env sets up
+#[derive(Debug, clap::Parser)]
+pub struct EnvCommand {
+    /// Source oe-init-build-env for the build directory in a subshell and write the variables it
+    /// sets or changes to FILE (as NAME="value" lines), e.g. for 'yb run --env-file'
+    #[clap(long, value_name = "FILE")]
+    export: PathBuf,
+}
+
+#[async_trait]
+impl SubcommandRunner for EnvCommand {
+    async fn run(&self, config: &mut Config, mp: &MultiProgress) -> YbResult<()> {
+        let context = require_tool_context(config)?;
+        let poky_dir = context.poky_dir();
+        let init_script = poky_dir.join("oe-init-build-env");
+        if !init_script.is_file() {
+            return Err(eyre::eyre!("{} does not exist", init_script.display())
+                .suggestion("sync poky first, or set poky_dir in yb.yaml")
+                .suppress_backtrace(true));
+        }
+
+        // The script chatters on stdout, so send that to stderr and keep stdout for `env -0`
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(r#". "$1" "$2" >&2 && env -0"#)
+            .arg("bash")
+            .arg(&init_script)
+            .arg(context.build_dir())
+            .current_dir(&poky_dir)
+            .output()
+            .context("failed to run bash")?;
+        if !output.status.success() {
+            return Err(eyre::eyre!(
+                "sourcing {} failed:\n{}",
+                init_script.display(),
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+
+        let vars = sourced_vars(&output.stdout);
+        write_dotenv(&self.export, &vars)?;
+        mp.note(format!(
+            "wrote {} variables to {}",
+            vars.len(),
+            self.export.display()
+        ));
+
+        Ok(())
+    }
+}
+
+/// The variables in `env -0` output that are new or differ from yb's own environment
+fn sourced_vars(env_output: &[u8]) -> BTreeMap<String, String> {
+    env_output
+        .split(|b| *b == 0)
+        .filter_map(|entry| std::str::from_utf8(entry).ok())
+        .filter_map(|entry| entry.split_once('='))
+        .filter(|(name, _)| !IGNORED_VARS.contains(name))
+        .filter(|(name, value)| env::var(name).ok().as_deref() != Some(*value))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
@@ -0,0 +1,46 @@
+use std::fs;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use indicatif::MultiProgress;
+
+use crate::commands::SubcommandRunner;
+use crate::config::Config;
+use crate::core::tool_context::require_yb_env;
+use crate::errors::{YbError, YbResult};
+use crate::ops::repo_manifest::spec_to_repo_manifest;
+use crate::util::paths::{normalize_path, try_diff_paths};
+use crate::yb_env::ActiveSpecStatus;
+
+/// Export the active spec as a Google 'repo' XML manifest
+#[derive(Debug, clap::Parser)]
+pub struct ExportManifestCommand {
+    /// Write the manifest to the given file instead of stdout
+    #[clap(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+#[async_trait]
+impl SubcommandRunner for ExportManifestCommand {
+    async fn run(&self, config: &mut Config, _mp: &MultiProgress) -> YbResult<()> {
+        let yb_env = require_yb_env(config)?;
+
+        let spec = match yb_env.active_spec_status() {
+            Some(ActiveSpecStatus::Active(active_spec)) => &active_spec.spec,
+            Some(ActiveSpecStatus::StreamsBroken(..)) => return Err(YbError::BrokenStreams.into()),
+            None => return Err(YbError::NoActiveSpec.into()),
+        };
+
+        // Project paths are relative to the top of the Yocto environment
+        let root_dir = yb_env.yb_dir().parent().unwrap();
+        let sources_path = try_diff_paths(normalize_path(yb_env.sources_dir()), root_dir)?;
+
+        let manifest = spec_to_repo_manifest(spec, &sources_path);
+        match &self.output {
+            Some(path) => fs::write(path, manifest)?,
+            None => print!("{manifest}"),
+        }
+
+        Ok(())
+    }
+}
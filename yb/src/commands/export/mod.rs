@@ -0,0 +1,11 @@
+use enum_dispatch::enum_dispatch;
+
+pub use manifest::ExportManifestCommand;
+
+mod manifest;
+
+#[enum_dispatch(SubcommandRunner)]
+#[derive(Debug, clap::Subcommand)]
+pub enum ExportSubcommands {
+    Manifest(ExportManifestCommand),
+}
@@ -0,0 +1,80 @@
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use eyre::Context;
+use indicatif::MultiProgress;
+
+use crate::commands::SubcommandRunner;
+use crate::config::Config;
+use crate::errors::YbResult;
+use crate::ops::repo_manifest::repo_manifest_to_spec;
+use crate::spec::Spec;
+use crate::stream_db::StreamKey;
+use crate::util::indicatif::MultiProgressHelpers;
+
+/// Create a spec from a Google 'repo' XML manifest
+#[derive(Debug, clap::Parser)]
+pub struct ImportManifestCommand {
+    /// Path to the manifest XML file
+    manifest: PathBuf,
+
+    /// Name of the spec (defaults to the manifest's file name without extension)
+    #[clap(long)]
+    name: Option<String>,
+
+    /// Write the spec as <name>.yaml into the given stream directory instead of to stdout
+    #[clap(long, value_name = "DIR")]
+    stream_dir: Option<PathBuf>,
+}
+
+#[async_trait]
+impl SubcommandRunner for ImportManifestCommand {
+    async fn run(&self, _config: &mut Config, mp: &MultiProgress) -> YbResult<()> {
+        let xml = fs::read_to_string(&self.manifest)
+            .with_context(|| format!("failed to read manifest {:?}", &self.manifest))?;
+        let name = match &self.name {
+            Some(name) => name.clone(),
+            None => self
+                .manifest
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+                .to_string(),
+        };
+
+        let imported = repo_manifest_to_spec(&name, &xml)?;
+        for warning in &imported.warnings {
+            mp.warn(warning);
+        }
+
+        let yaml = serde_yaml::to_string(&imported.spec)?;
+
+        // Round-trip through Spec::load so that the usual spec validation applies. With
+        // --stream-dir the temp file is created there, so that a valid spec can be renamed into
+        // place and an invalid one never shows up in the stream.
+        let mut f = match &self.stream_dir {
+            Some(stream_dir) => tempfile::NamedTempFile::new_in(stream_dir)
+                .with_context(|| format!("failed to create a temp file in {stream_dir:?}"))?,
+            None => tempfile::NamedTempFile::new()?,
+        };
+        f.write_all(yaml.as_bytes())?;
+        Spec::load(f.path(), StreamKey::default())?;
+
+        match &self.stream_dir {
+            Some(stream_dir) => {
+                let spec_path = stream_dir.join(format!("{name}.yaml"));
+                // Temp files are only readable by their owner
+                fs::set_permissions(f.path(), fs::Permissions::from_mode(0o644))?;
+                f.persist(&spec_path)
+                    .with_context(|| format!("failed to write spec {:?}", &spec_path))?;
+                mp.note(format!("wrote spec '{name}' to {}", spec_path.display()));
+            }
+            None => print!("{yaml}"),
+        }
+
+        Ok(())
+    }
+}
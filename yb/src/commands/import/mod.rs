@@ -0,0 +1,11 @@
+use enum_dispatch::enum_dispatch;
+
+pub use manifest::ImportManifestCommand;
+
+mod manifest;
+
+#[enum_dispatch(SubcommandRunner)]
+#[derive(Debug, clap::Subcommand)]
+pub enum ImportSubcommands {
+    Manifest(ImportManifestCommand),
+}
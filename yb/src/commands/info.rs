@@ -0,0 +1,120 @@
+use async_trait::async_trait;
+use concurrent_git_pool::PoolHelper;
+use indicatif::MultiProgress;
+use serde::Serialize;
+
+use crate::commands::SubcommandRunner;
+use crate::core::tool_context::{determine_tool_context, ToolContext};
+use crate::errors::YbResult;
+use crate::util::output::or_none;
+use crate::yb_env::ActiveSpecStatus;
+use crate::{Config, VERSION};
+
+/// Print yb's version and what it knows about the current environment, e.g. for bug reports
+#[derive(Debug, clap::Parser)]
+pub struct InfoCommand {
+    /// Print the information as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Info {
+    version: String,
+    /// "yb", "yocto-env", "none", or "unknown" if detection failed
+    context: String,
+    context_error: Option<String>,
+    yb_dir: Option<String>,
+    sources_dir: Option<String>,
+    build_dir: Option<String>,
+    active_spec: Option<String>,
+    active_spec_stream: Option<String>,
+    streams: Option<usize>,
+    broken_streams: Option<usize>,
+    git_pool: String,
+}
+
+impl Info {
+    async fn gather(config: &Config) -> Self {
+        let mut info = Info {
+            version: VERSION.to_string(),
+            ..Default::default()
+        };
+
+        match determine_tool_context(config) {
+            Ok(Some(context)) => {
+                info.sources_dir = Some(context.sources_dir().display().to_string());
+                info.build_dir = Some(context.build_dir().display().to_string());
+                match context {
+                    ToolContext::Yb(yb_env) => {
+                        info.context = "yb".to_string();
+                        info.yb_dir = Some(yb_env.yb_dir().display().to_string());
+                        info.streams = Some(yb_env.stream_db().streams().count());
+                        info.broken_streams = Some(yb_env.stream_db().broken_streams().len());
+                        match yb_env.active_spec_status() {
+                            Some(ActiveSpecStatus::Active(active_spec)) => {
+                                info.active_spec = Some(active_spec.name());
+                                info.active_spec_stream = Some(active_spec.from_stream.clone());
+                            }
+                            Some(ActiveSpecStatus::StreamsBroken(..)) => {
+                                info.active_spec = Some("unknown (streams broken)".to_string());
+                            }
+                            None => {}
+                        }
+                    }
+                    ToolContext::YoctoEnv(..) => info.context = "yocto-env".to_string(),
+                }
+            }
+            Ok(None) => info.context = "none".to_string(),
+            Err(e) => {
+                info.context = "unknown".to_string();
+                info.context_error = Some(e.to_string());
+            }
+        }
+
+        info.git_pool = match PoolHelper::connect_or_local().await {
+            Ok(pool) if pool.is_pooled() => "connected".to_string(),
+            Ok(_) => "not configured (cloning locally)".to_string(),
+            Err(e) => format!("unreachable ({e})"),
+        };
+
+        info
+    }
+}
+
+#[async_trait]
+impl SubcommandRunner for InfoCommand {
+    async fn run(&self, config: &mut Config, _mp: &MultiProgress) -> YbResult<()> {
+        let info = Info::gather(config).await;
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&info)?);
+            return Ok(());
+        }
+
+        println!("version: {}", info.version);
+        match &info.context_error {
+            Some(error) => println!("context: {} ({error})", info.context),
+            None => println!("context: {}", info.context),
+        }
+        println!("yb dir: {}", or_none(&info.yb_dir));
+        println!("sources dir: {}", or_none(&info.sources_dir));
+        println!("build dir: {}", or_none(&info.build_dir));
+        match &info.active_spec_stream {
+            Some(stream) => println!(
+                "active spec: {} (from stream '{stream}')",
+                or_none(&info.active_spec)
+            ),
+            None => println!("active spec: {}", or_none(&info.active_spec)),
+        }
+        match (info.streams, info.broken_streams) {
+            (Some(streams), Some(broken)) if broken > 0 => {
+                println!("streams: {streams} ({broken} broken)")
+            }
+            (streams, _) => println!("streams: {}", or_none(&streams)),
+        }
+        println!("git pool: {}", info.git_pool);
+
+        Ok(())
+    }
+}
@@ -2,16 +2,24 @@ use async_trait::async_trait;
 use color_eyre::Help;
 use indicatif::MultiProgress;
 use std::fs;
+use std::path::PathBuf;
 
+use crate::commands::activate::activate_spec;
 use crate::commands::SubcommandRunner;
 use crate::core::tool_context::{
     determine_tool_context, require_yb_env, ToolContext, YoctoEnvironment,
 };
 use crate::errors::YbResult;
-use crate::ops::add_stream::{op_add_stream, AddStreamOptions};
-use crate::yb_env::YbEnv;
+use crate::ops::add_stream::{op_add_local_stream, op_add_stream, AddStreamOptions};
+use crate::ops::derive_spec::derive_spec_from_env;
+use crate::util::indicatif::MultiProgressHelpers;
+use crate::util::paths::normalize_path;
+use crate::yb_env::{YbEnv, YB_ENV_DIRECTORY};
 use crate::Config;
 
+/// Name of the spec (and stream) created by --bare-metadata
+const LOCAL_SPEC_NAME: &str = "local";
+
 /// Initialize a 'yb' environment
 ///
 /// When run in the context of an activated Yocto environment (e.g. you have sourced 'setupsdk'),
@@ -26,6 +34,13 @@ use crate::Config;
 ///     ├── sources
 ///     └── .yb
 ///
+/// Pass '--dir' to initialize into an existing (or new) directory of your choosing instead of 'yocto'.
+/// Any 'build' and 'sources' directories already present there are reused.
+///
+/// To adopt an existing Yocto tree without a stream, combine '--dir' with '--bare-metadata'. A spec
+/// named 'local' is derived from the repos in 'sources' and the layers in build/conf/bblayers.conf,
+/// saved to a local stream, and activated.
+///
 #[derive(Debug, clap::Parser)]
 #[clap(verbatim_doc_comment)]
 pub struct InitCommand {
@@ -37,11 +52,20 @@ pub struct InitCommand {
 
     #[clap(name = "default-spec", short = 'p', long, requires = "default-stream")]
     default_spec: Option<String>,
+
+    /// Directory to initialize instead of creating 'yocto' under the current directory
+    #[clap(long)]
+    dir: Option<PathBuf>,
+
+    /// Derive a spec from the existing repos and bblayers.conf and activate it, instead of using a
+    /// stream
+    #[clap(long, conflicts_with = "default-stream")]
+    bare_metadata: bool,
 }
 
 #[async_trait]
 impl SubcommandRunner for InitCommand {
-    async fn run(&self, config: &mut Config, _mp: &MultiProgress) -> YbResult<()> {
+    async fn run(&self, config: &mut Config, mp: &MultiProgress) -> YbResult<()> {
         let context = determine_tool_context(config)?;
 
         let new_yocto_dir;
@@ -64,14 +88,33 @@ impl SubcommandRunner for InitCommand {
             }
             None => {
                 // No environment, create a skeleton one
-                let yocto_dir = config.cwd().join("yocto");
+                let yocto_dir;
+                match &self.dir {
+                    Some(dir) => {
+                        yocto_dir = normalize_path(config.cwd().join(dir));
+                        if yocto_dir.join(YB_ENV_DIRECTORY).exists() {
+                            return Err(eyre::eyre!(
+                                "a .yb environment already exists at {:?}",
+                                yocto_dir.join(YB_ENV_DIRECTORY)
+                            )
+                            .suppress_backtrace(true));
+                        }
+                        fs::create_dir_all(&yocto_dir)?;
+                    }
+                    None => {
+                        yocto_dir = config.cwd().join("yocto");
+                        fs::create_dir(&yocto_dir)?;
+                    }
+                }
                 new_yocto_dir = yocto_dir.clone();
-                fs::create_dir(&yocto_dir)?;
 
                 let sources_dir = yocto_dir.join("sources");
                 let build_dir = yocto_dir.join("build");
-                fs::create_dir(&sources_dir)?;
-                fs::create_dir(&build_dir)?;
+                for dir in [&sources_dir, &build_dir] {
+                    if !dir.is_dir() {
+                        fs::create_dir(dir)?;
+                    }
+                }
 
                 let new_yocto_env = YoctoEnvironment {
                     build_dir,
@@ -87,6 +130,22 @@ impl SubcommandRunner for InitCommand {
             }
         };
 
+        if self.bare_metadata {
+            let config = config.clone_with_cwd(new_yocto_dir.clone());
+            let yb_env = require_yb_env(&config)?;
+            let _lock = yb_env.lock()?;
+
+            let derived =
+                derive_spec_from_env(LOCAL_SPEC_NAME, &yb_env.sources_dir(), &yb_env.build_dir())?;
+            for reason in &derived.skipped {
+                mp.warn(format!("skipping: {reason}"));
+            }
+            op_add_local_stream(&config, LOCAL_SPEC_NAME, &[derived.spec])?;
+
+            let mut yb_env = require_yb_env(&config)?;
+            activate_spec(&mut yb_env, LOCAL_SPEC_NAME)?;
+        }
+
         if let Some(default_stream_uri) = &self.default_stream {
             let config = config.clone_with_cwd(new_yocto_dir);
 
@@ -98,14 +157,10 @@ impl SubcommandRunner for InitCommand {
                 // TODO deduplicate code
                 let mut yb_env = require_yb_env(&config)?;
 
-                let spec = yb_env.find_spec(default_spec_name)?.cloned();
-                if let Some(spec) = spec {
-                    // TODO don't clone
-                    yb_env.activate_spec(spec)?;
-                    println!("Activated spec '{}'", &default_spec_name);
-                } else {
-                    eyre::bail!("spec with name '{}' not found", &default_spec_name);
-                }
+                // TODO don't clone
+                let spec = yb_env.find_spec(default_spec_name)?.clone();
+                yb_env.activate_spec(spec)?;
+                println!("Activated spec '{}'", &default_spec_name);
             }
         }
 
@@ -0,0 +1,96 @@
+use async_trait::async_trait;
+use git2::{Repository, Sort};
+use indicatif::MultiProgress;
+use serde::Serialize;
+
+use crate::commands::SubcommandRunner;
+use crate::config::Config;
+use crate::core::tool_context::require_tool_context;
+use crate::errors::YbResult;
+use crate::util::paths::list_subdirectories_sorted;
+
+/// List the most recent commits across all source repos, newest first
+#[derive(Debug, clap::Parser)]
+pub struct LogCommand {
+    /// How many commits to list
+    #[clap(long, short = 'n', default_value = "20")]
+    count: usize,
+
+    /// Print the commits as a JSON array
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct LogEntry {
+    repo: String,
+    sha: String,
+    short_sha: String,
+    /// Commit time, in seconds since the epoch
+    time: i64,
+    author: String,
+    summary: String,
+}
+
+/// The (up to) `count` most recent commits reachable from HEAD of `repo`. No more are walked, so
+/// a repo with a long history costs no more than one with a short history.
+fn recent_commits(repo: &Repository, repo_name: &str, count: usize) -> YbResult<Vec<LogEntry>> {
+    let mut walker = repo.revwalk()?;
+    walker.set_sorting(Sort::TIME)?;
+    walker.push_head()?;
+
+    let mut ret = vec![];
+    for oid in walker.take(count) {
+        let commit = repo.find_commit(oid?)?;
+        ret.push(LogEntry {
+            repo: repo_name.to_string(),
+            sha: commit.id().to_string(),
+            short_sha: commit
+                .as_object()
+                .short_id()?
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            time: commit.time().seconds(),
+            author: commit.author().name().unwrap_or_default().to_string(),
+            summary: commit.summary().unwrap_or_default().to_string(),
+        });
+    }
+    Ok(ret)
+}
+
+#[async_trait]
+impl SubcommandRunner for LogCommand {
+    async fn run(&self, config: &mut Config, _mp: &MultiProgress) -> YbResult<()> {
+        let context = require_tool_context(config)?;
+
+        let mut entries = vec![];
+        for subdir in list_subdirectories_sorted(&context.sources_dir())? {
+            let repo = match Repository::open(&subdir) {
+                Ok(repo) => repo,
+                Err(_) => continue,
+            };
+            // Skip repos without any commits yet
+            if repo.head().is_err() {
+                continue;
+            }
+
+            let repo_name = subdir.file_name().unwrap().to_str().unwrap();
+            entries.extend(recent_commits(&repo, repo_name, self.count)?);
+        }
+
+        // Newest first; the sort is stable, so commits from the same second stay in repo order
+        entries.sort_by(|a, b| b.time.cmp(&a.time));
+        entries.truncate(self.count);
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+        } else {
+            for entry in &entries {
+                println!("{} {} {}", entry.repo, entry.short_sha, entry.summary);
+            }
+        }
+
+        Ok(())
+    }
+}
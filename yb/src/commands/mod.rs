@@ -3,10 +3,18 @@ use enum_dispatch::enum_dispatch;
 use indicatif::MultiProgress;
 
 use crate::commands::activate::ActivateCommand;
+use crate::commands::completions::CompletionsCommand;
+use crate::commands::config::ConfigSubcommands;
+use crate::commands::env::EnvCommand;
+use crate::commands::export::ExportSubcommands;
+use crate::commands::import::ImportSubcommands;
+use crate::commands::info::InfoCommand;
 use crate::commands::init::InitCommand;
 use crate::commands::list::ListCommand;
+use crate::commands::log::LogCommand;
 use crate::commands::run::RunCommand;
 use crate::commands::self_update::SelfUpdateCommand;
+use crate::commands::spec::SpecSubcommands;
 use crate::commands::status::*;
 use crate::commands::stream::{
     StreamAddCommand, StreamListCommand, StreamSubcommands, StreamUpdateCommand,
@@ -17,13 +25,21 @@ use crate::errors::YbResult;
 use crate::Config;
 
 mod activate;
+mod completions;
+mod config;
+mod env;
+mod export;
+mod import;
+mod info;
 mod init;
 mod list;
+mod log;
 mod run;
 mod self_update;
+mod spec;
 pub mod status;
 mod stream;
-mod sync;
+pub mod sync;
 mod upgrade;
 
 #[async_trait]
@@ -42,7 +58,19 @@ pub enum Subcommands {
     #[clap(subcommand)]
     Stream(StreamSubcommands),
     Activate(ActivateCommand),
+    #[clap(subcommand)]
+    Spec(SpecSubcommands),
+    #[clap(subcommand)]
+    Export(ExportSubcommands),
+    #[clap(subcommand)]
+    Import(ImportSubcommands),
+    Info(InfoCommand),
     Sync(SyncCommand),
     List(ListCommand),
+    Log(LogCommand),
     Upgrade(UpgradeCommand),
+    Completions(CompletionsCommand),
+    Env(EnvCommand),
+    #[clap(subcommand)]
+    Config(ConfigSubcommands),
 }
@@ -1,5 +1,6 @@
 use async_trait::async_trait;
 use std::ffi::OsString;
+use std::path::PathBuf;
 use std::process::Command;
 
 use console::Style;
@@ -9,6 +10,7 @@ use indicatif::MultiProgress;
 use crate::commands::SubcommandRunner;
 use crate::core::tool_context::require_tool_context;
 use crate::errors::YbResult;
+use crate::util::dotenv::read_dotenv;
 use crate::util::paths::make_relative_to_cwd;
 use crate::Config;
 
@@ -23,6 +25,10 @@ pub struct RunCommand {
     /// Don't print return codes
     #[structopt(name = "no-return-codes", short, long)]
     flag_no_return_codes: bool,
+
+    /// Run the command with the variables from FILE (as written by 'yb env --export') set
+    #[clap(long, value_name = "FILE")]
+    env_file: Option<PathBuf>,
 }
 
 #[async_trait]
@@ -35,6 +41,12 @@ impl SubcommandRunner for RunCommand {
         if self.args.is_empty() {
             return Err(eyre::eyre!("must pass a command"));
         }
+        let envs = self
+            .env_file
+            .as_deref()
+            .map(read_dotenv)
+            .transpose()?
+            .unwrap_or_default();
 
         for repo in &repos {
             let dname_path = repo.workdir().unwrap();
@@ -52,6 +64,7 @@ impl SubcommandRunner for RunCommand {
 
             let result = Command::new(&self.args[0])
                 .args(&self.args[1..])
+                .envs(&envs)
                 .current_dir(repo.workdir().unwrap())
                 .spawn()?
                 .wait()?;
@@ -0,0 +1,11 @@
+use enum_dispatch::enum_dispatch;
+
+pub use repos::SpecReposCommand;
+
+mod repos;
+
+#[enum_dispatch(SubcommandRunner)]
+#[derive(Debug, clap::Subcommand)]
+pub enum SpecSubcommands {
+    Repos(SpecReposCommand),
+}
@@ -0,0 +1,70 @@
+use async_trait::async_trait;
+use indicatif::MultiProgress;
+use serde::Serialize;
+
+use crate::commands::SubcommandRunner;
+use crate::config::Config;
+use crate::core::tool_context::require_yb_env;
+use crate::errors::{YbError, YbResult};
+use crate::spec::Spec;
+use crate::yb_env::ActiveSpecStatus;
+
+/// Print the (enabled) repos of a spec as tab-separated 'name url refspec' lines, sorted by name
+#[derive(Debug, clap::Parser)]
+pub struct SpecReposCommand {
+    /// Name of the spec (defaults to the active spec)
+    spec: Option<String>,
+
+    /// Print a JSON array of objects with 'name', 'url', and 'refspec' keys instead
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct SpecRepoEntry<'a> {
+    name: &'a str,
+    url: &'a str,
+    refspec: &'a str,
+}
+
+fn spec_repo_entries(spec: &Spec) -> Vec<SpecRepoEntry> {
+    let mut entries = spec
+        .enabled_repos()
+        .map(|(name, repo)| SpecRepoEntry {
+            name,
+            url: &repo.url,
+            refspec: &repo.refspec,
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.name.cmp(b.name));
+    entries
+}
+
+#[async_trait]
+impl SubcommandRunner for SpecReposCommand {
+    async fn run(&self, config: &mut Config, _mp: &MultiProgress) -> YbResult<()> {
+        let yb_env = require_yb_env(config)?;
+
+        let spec = match &self.spec {
+            Some(name) => yb_env.find_spec(name)?,
+            None => match yb_env.active_spec_status() {
+                Some(ActiveSpecStatus::Active(active_spec)) => &active_spec.spec,
+                Some(ActiveSpecStatus::StreamsBroken(..)) => {
+                    return Err(YbError::BrokenStreams.into())
+                }
+                None => eyre::bail!("no spec given and no spec is active"),
+            },
+        };
+
+        let entries = spec_repo_entries(spec);
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+        } else {
+            for entry in entries {
+                println!("{}\t{}\t{}", entry.name, entry.url, entry.refspec);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,33 +1,469 @@
 use async_trait::async_trait;
-use std::time::Duration;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use console::{Emoji, Style};
-use git2::StatusOptions;
+use color_eyre::Help;
+use console::{Emoji, Style, Term};
+use eyre::WrapErr;
+use git2::{Repository, StatusOptions};
 use indicatif::{MultiProgress, ProgressBar, ProgressFinish, ProgressStyle};
+use serde::Serialize;
 
+use crate::commands::sync::planner::check_max_behind;
 use crate::commands::SubcommandRunner;
-use crate::data_model::git::{BranchStatus, UpstreamComparison};
-use crate::data_model::status::{ComputedStatusEntry, CorrespondingSpecRepoStatus};
+use crate::core::tool_context::{maybe_yb_env, require_tool_context};
+use crate::data_model::git::{
+    BranchStatus, SubmoduleState, UpstreamBranchStatus, UpstreamComparison,
+};
+use crate::data_model::status::{
+    clone_and_enumerate_ref_revisions, enumerate_revisions_reachable_from, ComputedStatus,
+    ComputedStatusEntry, CorrespondingSpecRepoStatus, MissingRepo, OnDiskNonRepoStatus,
+    OnDiskRepoStatus,
+};
 use crate::errors::YbResult;
-use crate::status_calculator::{compute_status, StatusCalculatorEvent, StatusCalculatorOptions};
+use crate::status_calculator::timings::StatusTimings;
+use crate::status_calculator::{
+    compute_status, StatusCalculatorEvent, StatusCalculatorOptions, MAX_LOG_COMMITS,
+};
 use crate::ui_ops::update_stream::{ui_op_update_stream, UiUpdateStreamOptions};
+use crate::util::bitbake_conf::conf_var_value;
 use crate::util::git::format_short_statuses;
 use crate::util::indicatif::{IndicatifHelpers, MultiProgressHelpers};
+use crate::util::watch::{watch_dir, WatchOutcome};
+use crate::yb_env::{ActiveSpecStatus, YbEnv};
 use crate::Config;
 
 #[derive(Debug, clap::Parser)]
 pub struct StatusCommand {
-    /// Don't run 'git fetch' on source dirs
+    /// Don't run 'git fetch' on source dirs (implies --no-update-stream)
     #[clap(name = "no-fetch", short, long)]
     flag_no_fetch: bool,
 
-    /// Show the most recent 5 'git log' entries
+    /// Don't refresh the active spec's stream first
+    #[clap(long)]
+    no_update_stream: bool,
+
+    /// Don't list the specs that changed when the stream moved since the last run
+    #[clap(long)]
+    no_stream_diff: bool,
+
+    /// Show the most recent 'git log' entries (5 unless --max-commits is given)
     #[clap(name = "log", short, long)]
     flag_log: bool,
 
+    /// With --log, how many 'git log' entries to show per repo (at most 1000)
+    #[clap(long, value_name = "N", requires = "log")]
+    max_commits: Option<usize>,
+
     /// Exclude from the output source dirs for which no differences/suggestions are detected
     #[clap(name = "skip-unremarkable", short, long)]
     skip_unremarkable: bool,
+
+    /// Keep running, re-rendering the status whenever the sources directory changes
+    #[clap(long)]
+    watch: bool,
+
+    /// With --watch, how often (in seconds) to re-render with a 'git fetch'
+    #[clap(long, default_value = "300", requires = "watch")]
+    fetch_interval: u64,
+
+    /// Prefer the given remote when several remotes of a repo match a spec repo (may be repeated;
+    /// earlier takes precedence). Also configurable via 'preferred_remotes' in .yb/config.toml.
+    #[clap(long, value_name = "REMOTE")]
+    prefer_remote: Vec<String>,
+
+    /// Don't count untracked files as local changes (e.g. build artifacts or editor files), so
+    /// they don't make a repo dirty
+    #[clap(long)]
+    ignore_untracked: bool,
+
+    /// Leave out (with a warning) source dirs that resolve to the same git workdir as another
+    /// source dir, e.g. a stray nested clone or a symlink, instead of failing
+    #[clap(long)]
+    skip_duplicate_workdirs: bool,
+
+    /// Also report each submodule of a repo: whether it is checked out at the commit its
+    /// superproject records, on another commit, or not initialized
+    #[clap(long)]
+    include_submodule_status: bool,
+
+    /// Afterwards, list the active spec's repos grouped under its stream, along with the source
+    /// dir satisfying each one (and any source dirs not part of the spec)
+    #[clap(long)]
+    group_by_stream: bool,
+
+    /// Only show source dirs with a problem (dirty, on the wrong branch, diverged) and missing
+    /// repos, followed by the number of problems. With --porcelain, emit just those entries and
+    /// a 'problem_count'.
+    #[clap(long)]
+    only_problems: bool,
+
+    /// Only show repos whose current branch is ahead of its upstream. The '--*-only' filters
+    /// can be combined to show repos matching any of them; they also apply to --porcelain output.
+    #[clap(long, conflicts_with = "only-problems")]
+    ahead_only: bool,
+
+    /// Only show repos whose current branch is behind its upstream
+    #[clap(long, conflicts_with = "only-problems")]
+    behind_only: bool,
+
+    /// Only show repos whose current branch has diverged from its upstream
+    #[clap(long, conflicts_with = "only-problems")]
+    diverged_only: bool,
+
+    /// Only show repos with local changes
+    #[clap(long, conflicts_with = "only-problems")]
+    dirty_only: bool,
+
+    /// Fail if the current branch of a repo is more than this many commits behind its upstream
+    #[clap(long, value_name = "N")]
+    max_behind: Option<usize>,
+
+    /// Print one line per repo, '<codes> <dir> <branch>', instead of the full report. The first
+    /// code column is ' ' if the repo is on a branch tracking its spec repo's branch, 'B' if it is
+    /// on another branch, 'R' if it only shares history with a spec repo (wrong remote) and '?'
+    /// if it isn't part of the spec. The second is 'M' if there are local changes, else ' '.
+    #[clap(
+        long,
+        conflicts_with_all = &["watch", "group-by-stream", "only-problems", "log", "timings"]
+    )]
+    short: bool,
+
+    /// Afterwards, print a breakdown of where the time went (fetching, checking related repos,
+    /// rendering)
+    #[clap(long)]
+    timings: bool,
+
+    /// Warn if the MACHINE that local.conf (and the files it includes) ends up setting differs
+    /// from the MACHINE in the active spec's 'local_conf'
+    #[clap(long)]
+    expected_machine: bool,
+
+    /// Fail instead of warning when --expected-machine finds a mismatch
+    #[clap(long, requires = "expected-machine")]
+    strict: bool,
+
+    /// Instead of the environment's status, report how far the repo at --path (by default the
+    /// current directory) is ahead of/behind a branch of some remote, given as '<url>@<ref>'.
+    /// No spec (or yb environment) is needed.
+    #[clap(
+        long,
+        value_name = "URL@REF",
+        conflicts_with_all = &["watch", "short", "group-by-stream", "only-problems", "expected-machine"]
+    )]
+    remote: Option<String>,
+
+    /// The repo to compare with --remote
+    #[clap(long, value_name = "DIR", requires = "remote")]
+    path: Option<PathBuf>,
+
+    /// Print each source dir's status as a line of JSON as soon as it has been computed, then a
+    /// summary line; useful for environments with many repos. The '--*-only' filters apply.
+    #[clap(
+        long,
+        conflicts_with_all = &["watch", "short", "group-by-stream", "only-problems", "remote", "log", "timings"]
+    )]
+    json_stream: bool,
+}
+
+/// A line of --json-stream output
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StatusStreamLine<'a> {
+    SourceDir {
+        status: &'a ComputedStatusEntry,
+    },
+    /// Always the last line
+    Summary {
+        source_dirs: usize,
+        problem_count: usize,
+        missing_repos: &'a Vec<MissingRepo>,
+    },
+}
+
+impl<'a> StatusStreamLine<'a> {
+    fn emit(&self) {
+        println!(
+            "{}",
+            serde_json::to_string(self).expect("status stream lines are always serializable")
+        );
+    }
+}
+
+/// Porcelain output for --remote
+#[derive(Debug, Serialize)]
+struct RemoteComparison {
+    path: PathBuf,
+    url: String,
+    refspec: String,
+    ahead: usize,
+    behind: usize,
+}
+
+impl RemoteComparison {
+    /// Compare the HEAD of the repo containing `path` with `refspec` of `url`, which is cloned to
+    /// a temporary directory for the purpose
+    fn compute(path: &Path, url: &str, refspec: &str) -> YbResult<Self> {
+        let repo = Repository::discover(path)
+            .wrap_err_with(|| format!("{} is not in a git repository", path.display()))?;
+        let path = repo
+            .workdir()
+            .ok_or_else(|| eyre::eyre!("{} has no working directory", path.display()))?
+            .to_path_buf();
+
+        let local_revs = enumerate_revisions_reachable_from(&path, "HEAD")?;
+        let remote_revs = clone_and_enumerate_ref_revisions(url, refspec)?;
+
+        Ok(Self {
+            path,
+            url: url.to_string(),
+            refspec: refspec.to_string(),
+            ahead: local_revs.difference(&remote_revs).count(),
+            behind: remote_revs.difference(&local_revs).count(),
+        })
+    }
+}
+
+impl Display for RemoteComparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let commits = |n: usize| match n {
+            1 => "1 commit".to_string(),
+            n => format!("{n} commits"),
+        };
+        let target = format!("{}@{}", self.url, self.refspec);
+        match (self.ahead, self.behind) {
+            (0, 0) => write!(f, "{} is up-to-date with {target}", self.path.display()),
+            (ahead, 0) => write!(
+                f,
+                "{} is {} ahead of {target}",
+                self.path.display(),
+                commits(ahead)
+            ),
+            (0, behind) => write!(
+                f,
+                "{} is {} behind {target}",
+                self.path.display(),
+                commits(behind)
+            ),
+            (ahead, behind) => write!(
+                f,
+                "{} has diverged from {target}: {} ahead, {} behind",
+                self.path.display(),
+                commits(ahead),
+                commits(behind)
+            ),
+        }
+    }
+}
+
+/// Split the argument of --remote into URL and ref at the last '@' (URLs like
+/// 'git@host:repo.git' contain one too)
+fn parse_remote_ref(remote: &str) -> YbResult<(&str, &str)> {
+    match remote.rsplit_once('@') {
+        Some((url, refspec))
+            if !url.is_empty() && !refspec.is_empty() && !refspec.contains(':') =>
+        {
+            Ok((url, refspec))
+        }
+        _ => Err(
+            eyre::eyre!("expected '<url>@<ref>' for --remote, got '{}'", remote)
+                .suppress_backtrace(true),
+        ),
+    }
+}
+
+/// Report a source dir left out by --skip-duplicate-workdirs
+pub(crate) fn warn_duplicate_workdir_skipped(mp: &MultiProgress, path: &Path, workdir: &Path) {
+    mp.warn(format!(
+        "skipping {}: it resolves to git workdir {}, which another source dir already covers",
+        path.display(),
+        workdir.display()
+    ));
+}
+
+/// A line naming the active spec, the stream it came from and the stream's current commit
+fn format_active_spec_header(yb_env: &YbEnv) -> YbResult<Option<String>> {
+    let active_spec = match yb_env.active_spec_status() {
+        Some(ActiveSpecStatus::Active(active_spec)) => active_spec,
+        _ => return Ok(None),
+    };
+
+    let mut header = format!(
+        "active spec '{}' from stream '{}'",
+        active_spec.name(),
+        active_spec.from_stream
+    );
+    if let Some(stream) = yb_env.stream_db().stream(active_spec.stream_key()) {
+        if let Some(short_id) = stream.head_short_id()? {
+            header += &format!(" at {short_id}");
+        }
+    }
+
+    Ok(Some(header))
+}
+
+/// List each repo of the active spec under its stream, with the source dir that satisfies it,
+/// followed by source dirs that aren't part of the spec
+fn format_grouped_by_stream(status: &ComputedStatus) -> Vec<String> {
+    let active_spec = match &status.active_spec {
+        Some(active_spec) => active_spec,
+        None => return vec![],
+    };
+
+    let mut satisfied_by = BTreeMap::new();
+    let mut unrelated = vec![];
+    for entry in &status.source_dirs {
+        match entry {
+            ComputedStatusEntry::OnDiskRepo(OnDiskRepoStatus {
+                corresponding_spec_repo: Some(corresponding),
+                path,
+                ..
+            }) => {
+                satisfied_by.insert(corresponding.spec_repo_name(), path.display().to_string());
+            }
+            ComputedStatusEntry::OnDiskRepo(OnDiskRepoStatus { path, .. })
+            | ComputedStatusEntry::OnDiskNonRepo(OnDiskNonRepoStatus { path, .. }) => {
+                unrelated.push(path.display().to_string());
+            }
+        }
+    }
+    for missing in &status.missing_repos {
+        satisfied_by.insert(missing.name.clone(), "missing".to_string());
+    }
+
+    let mut ret = vec![
+        String::new(),
+        format!(
+            "stream '{}' (spec '{}'):",
+            active_spec.from_stream,
+            active_spec.name()
+        ),
+    ];
+    for (spec_repo_name, source_dir) in satisfied_by {
+        ret.push(format!("\t{spec_repo_name}: {source_dir}"));
+    }
+
+    if !unrelated.is_empty() {
+        ret.push("not part of the active spec:".to_string());
+        for source_dir in unrelated {
+            ret.push(format!("\t{source_dir}"));
+        }
+    }
+
+    ret
+}
+
+/// Porcelain output for --only-problems
+#[derive(Serialize)]
+struct ProblemsReport<'a> {
+    problem_count: usize,
+    source_dirs: Vec<&'a ComputedStatusEntry>,
+    missing_repos: &'a Vec<MissingRepo>,
+}
+
+impl<'a> ProblemsReport<'a> {
+    fn new(status: &'a ComputedStatus) -> Self {
+        Self {
+            problem_count: status.problem_count(),
+            source_dirs: status
+                .source_dirs
+                .iter()
+                .filter(|entry| entry.has_problem())
+                .collect(),
+            missing_repos: &status.missing_repos,
+        }
+    }
+}
+
+/// Porcelain output for the '--*-only' state filters
+#[derive(Serialize)]
+struct FilteredReport<'a> {
+    source_dirs: Vec<&'a ComputedStatusEntry>,
+}
+
+fn format_problem_count(problem_count: usize) -> String {
+    match problem_count {
+        1 => "1 problem".to_string(),
+        n => format!("{n} problems"),
+    }
+}
+
+/// The --short line for a repo: two status codes, then its directory and current branch
+fn format_short_line(repo_status: &OnDiskRepoStatus) -> String {
+    let branch_code = match &repo_status.corresponding_spec_repo {
+        Some(CorrespondingSpecRepoStatus::RemoteMatch(_)) => {
+            if repo_status.is_local_branch_tracking_correct_branch() {
+                ' '
+            } else {
+                'B'
+            }
+        }
+        Some(CorrespondingSpecRepoStatus::RelatedRepo { .. }) => 'R',
+        None => '?',
+    };
+    let dirty_code = if repo_status.is_workdir_dirty {
+        'M'
+    } else {
+        ' '
+    };
+
+    format!(
+        "{}{} {} {}",
+        branch_code,
+        dirty_code,
+        repo_status.path.file_name().unwrap().to_string_lossy(),
+        repo_status.current_branch_status.local_branch_name
+    )
+}
+
+/// Compare the MACHINE set by local.conf in `build_dir` with the one the active spec expects,
+/// returning a description of the mismatch (if any)
+fn find_machine_mismatch(status: &ComputedStatus, build_dir: &Path) -> Option<String> {
+    let expected = status
+        .active_spec
+        .as_ref()?
+        .spec
+        .local_conf
+        .get("MACHINE")?;
+    let local_conf = build_dir.join("conf").join("local.conf");
+    match conf_var_value(&local_conf, build_dir, "MACHINE") {
+        Some(machine) if &machine == expected => None,
+        Some(machine) => Some(format!(
+            "{} sets MACHINE to '{}', but the active spec expects '{}'",
+            local_conf.display(),
+            machine,
+            expected
+        )),
+        None => Some(format!(
+            "{} doesn't set MACHINE, but the active spec expects '{}'",
+            local_conf.display(),
+            expected
+        )),
+    }
+}
+
+/// Fail if the current branch of any repo is more than `max_behind` commits behind its upstream
+fn check_status_max_behind(status: &ComputedStatus, max_behind: Option<usize>) -> YbResult<()> {
+    for entry in &status.source_dirs {
+        if let ComputedStatusEntry::OnDiskRepo(repo_status) = entry {
+            if let Some(UpstreamBranchStatus {
+                remote_tracking_branch,
+                upstream_comparison: UpstreamComparison::Behind(behind),
+            }) = &repo_status.current_branch_status.upstream_branch_status
+            {
+                check_max_behind(
+                    &repo_status.path,
+                    *behind,
+                    remote_tracking_branch,
+                    max_behind,
+                )?;
+            }
+        }
+    }
+
+    Ok(())
 }
 
 struct UpstreamStatusMessage {
@@ -71,6 +507,10 @@ fn format_upstream_status_message(branch_status: &BranchStatus) -> Option<Upstre
         UpstreamComparison::UpToDate => {
             format!("up to date with '{remote_tracking_branch_name}'")
         }
+        UpstreamComparison::Shallow => {
+            branch_status_color = Some(Style::from_dotted_str("cyan"));
+            format!("differs from '{remote_tracking_branch_name}' (unknown: shallow clone)")
+        }
     };
 
     Some(UpstreamStatusMessage {
@@ -98,21 +538,209 @@ lazy_static! {
 #[async_trait]
 impl SubcommandRunner for StatusCommand {
     async fn run(&self, config: &mut Config, mp: &MultiProgress) -> YbResult<()> {
+        if let Some(remote) = &self.remote {
+            let (url, refspec) = parse_remote_ref(remote)?;
+            let path = config
+                .cwd()
+                .join(self.path.as_deref().unwrap_or(Path::new(".")));
+            let comparison = RemoteComparison::compute(&path, url, refspec)?;
+            if config.porcelain {
+                println!("{}", config.format.serialize(&comparison)?.trim_end());
+            } else {
+                println!("{comparison}");
+            }
+            return Ok(());
+        }
+
         ui_op_check_broken_streams(UiCheckBrokenStreamsOptions::new(config, mp))?;
 
         // Check the stream (if active) for updates
-        let update_stream_opts = UiUpdateStreamOptions::new(config, mp);
-        ui_op_update_stream(update_stream_opts)?;
+        if !self.no_update_stream && !self.flag_no_fetch {
+            let mut update_stream_opts = UiUpdateStreamOptions::new(config, mp);
+            update_stream_opts.stream_diff(!self.no_stream_diff);
+            ui_op_update_stream(update_stream_opts)?;
+        }
+
+        if !config.porcelain && !self.short && !self.json_stream {
+            if let Some(header) = maybe_yb_env(config)?
+                .map(|yb_env| format_active_spec_header(&yb_env))
+                .transpose()?
+                .flatten()
+            {
+                println!("{}", Style::new().bold().apply_to(header));
+            }
+        }
+
+        let status = if self.json_stream {
+            let status = self.compute_status_quietly(config, mp, |entry| {
+                if self.matches_state_filter(entry) {
+                    StatusStreamLine::SourceDir { status: entry }.emit();
+                }
+            })?;
+            StatusStreamLine::Summary {
+                source_dirs: status.source_dirs.len(),
+                problem_count: status.problem_count(),
+                missing_repos: &status.missing_repos,
+            }
+            .emit();
+            status
+        } else if self.short {
+            let status = self.compute_status_quietly(config, mp, |_| {})?;
+            if !config.porcelain {
+                for entry in &status.source_dirs {
+                    match entry {
+                        ComputedStatusEntry::OnDiskRepo(repo_status)
+                            if self.matches_state_filter(entry) =>
+                        {
+                            println!("{}", format_short_line(repo_status))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            status
+        } else {
+            self.render_status(config, mp, self.flag_no_fetch)?
+        };
 
-        let status_calculator_options =
-            StatusCalculatorOptions::new(config, self.flag_no_fetch, self.flag_log);
+        if self.group_by_stream && !config.porcelain {
+            for line in format_grouped_by_stream(&status) {
+                println!("{line}");
+            }
+        }
+
+        if config.porcelain && !self.json_stream {
+            let output = if self.only_problems {
+                config.format.serialize(&ProblemsReport::new(&status))
+            } else if self.has_state_filter() {
+                config.format.serialize(&FilteredReport {
+                    source_dirs: status
+                        .source_dirs
+                        .iter()
+                        .filter(|entry| self.matches_state_filter(entry))
+                        .collect(),
+                })
+            } else {
+                config.format.serialize(&status)
+            };
+            println!("{}", output?.trim_end());
+        } else if self.only_problems {
+            println!("{}", format_problem_count(status.problem_count()));
+        }
+
+        check_status_max_behind(&status, self.max_behind)?;
+
+        if self.expected_machine {
+            let build_dir = require_tool_context(config)?.build_dir();
+            if let Some(mismatch) = find_machine_mismatch(&status, &build_dir) {
+                if self.strict {
+                    return Err(eyre::eyre!(mismatch).suggestion(
+                        "run 'yb sync --write-local-conf -a' to apply the spec's local_conf settings",
+                    ));
+                }
+                mp.warn(mismatch);
+            }
+        }
+
+        if self.watch {
+            self.watch_and_rerender(config, mp).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl StatusCommand {
+    fn has_state_filter(&self) -> bool {
+        self.ahead_only || self.behind_only || self.diverged_only || self.dirty_only
+    }
+
+    /// Whether `entry` passes the '--*-only' filters (any of them, if several were given).
+    /// Everything passes if there are no filters; non-repos never pass one.
+    fn matches_state_filter(&self, entry: &ComputedStatusEntry) -> bool {
+        if !self.has_state_filter() {
+            return true;
+        }
+
+        let repo_status = match entry {
+            ComputedStatusEntry::OnDiskRepo(repo_status) => repo_status,
+            ComputedStatusEntry::OnDiskNonRepo(_) => return false,
+        };
+        let comparison = repo_status
+            .current_branch_status
+            .upstream_branch_status
+            .as_ref()
+            .map(|status| &status.upstream_comparison);
+
+        (self.ahead_only && matches!(comparison, Some(UpstreamComparison::Ahead(_))))
+            || (self.behind_only && matches!(comparison, Some(UpstreamComparison::Behind(_))))
+            || (self.diverged_only
+                && matches!(comparison, Some(UpstreamComparison::Diverged { .. })))
+            || (self.dirty_only && repo_status.is_workdir_dirty)
+    }
+
+    /// Compute the status without rendering anything along the way (other than warnings),
+    /// passing each source dir's status to `on_entry` as soon as it is known
+    fn compute_status_quietly<F>(
+        &self,
+        config: &Config,
+        mp: &MultiProgress,
+        mut on_entry: F,
+    ) -> YbResult<ComputedStatus>
+    where
+        F: FnMut(&ComputedStatusEntry),
+    {
+        let mut status_calculator_options =
+            StatusCalculatorOptions::new(config, self.flag_no_fetch, false);
+        status_calculator_options
+            .preferred_remotes(self.prefer_remote.clone())
+            .skip_duplicate_workdirs(self.skip_duplicate_workdirs)
+            .ignore_untracked(self.ignore_untracked)
+            .include_submodule_status(self.include_submodule_status);
+        compute_status(status_calculator_options, |event| match event {
+            StatusCalculatorEvent::SubdirStatusComputed(entry) => on_entry(entry),
+            StatusCalculatorEvent::DuplicateWorkdirSkipped { path, workdir } => {
+                warn_duplicate_workdir_skipped(mp, path, workdir)
+            }
+            _ => {}
+        })
+    }
+
+    /// Compute the status, rendering it as it is computed
+    fn render_status(
+        &self,
+        config: &Config,
+        mp: &MultiProgress,
+        no_fetch: bool,
+    ) -> YbResult<ComputedStatus> {
+        let mut status_calculator_options =
+            StatusCalculatorOptions::new(config, no_fetch, self.flag_log);
+        status_calculator_options
+            .preferred_remotes(self.prefer_remote.clone())
+            .skip_duplicate_workdirs(self.skip_duplicate_workdirs)
+            .ignore_untracked(self.ignore_untracked)
+            .include_submodule_status(self.include_submodule_status);
+        if let Some(max_commits) = self.max_commits {
+            if max_commits > MAX_LOG_COMMITS {
+                mp.warn(format!(
+                    "--max-commits {max_commits} is too many; showing {MAX_LOG_COMMITS}"
+                ));
+            }
+            status_calculator_options.max_commits(max_commits);
+        }
 
         let mut overall_progress: Option<ProgressBar> = None;
         let mut subdir_spinner: Option<ProgressBar> = None;
 
         let mut subdir_lines: Vec<ProgressBar> = vec![];
 
+        let mut timings = StatusTimings::new();
+        let mut unversioned_layers = vec![];
+
         let status = compute_status(status_calculator_options, |event| {
+            timings.observe(&event);
+            let render_start = Instant::now();
+
             match event {
                 StatusCalculatorEvent::Start { number_subdirs, .. } => {
                     overall_progress.replace(
@@ -307,7 +935,34 @@ impl SubcommandRunner for StatusCommand {
                                 }
                             }
 
+                            if let Some(submodules) = &repo_status.submodules {
+                                for submodule in submodules {
+                                    let state = match submodule.state {
+                                        SubmoduleState::OnRecordedCommit => {
+                                            Style::default().green().apply_to("on recorded commit")
+                                        }
+                                        SubmoduleState::WrongCommit => Style::default()
+                                            .red()
+                                            .apply_to("not on recorded commit"),
+                                        SubmoduleState::NotInitialized => {
+                                            Style::default().yellow().apply_to("not initialized")
+                                        }
+                                    };
+                                    let line = format!(
+                                        "\tsubmodule {}: {}",
+                                        submodule.path.display(),
+                                        state
+                                    );
+
+                                    let last_message = subdir_lines.last().unwrap();
+                                    subdir_lines.push(mp.println_after(last_message, line));
+                                }
+                            }
+
                             let mut opts = StatusOptions::new();
+                            if self.ignore_untracked {
+                                opts.include_untracked(false).include_ignored(false);
+                            }
                             let statuses = repo_status.repo.statuses(Some(&mut opts)).unwrap(); // TODO YbResult
                             if !statuses.is_empty() {
                                 branch_status_color = Some(Style::from_dotted_str("red.bold"));
@@ -334,16 +989,54 @@ impl SubcommandRunner for StatusCommand {
                                 }
                             }
                         }
-                        // TODO
-                        _ => {}
+                        ComputedStatusEntry::OnDiskNonRepo(non_repo_status) => {
+                            if non_repo_status.looks_like_layer {
+                                subdir_lines.push(mp.println_after(
+                                    subdir_spinner.as_ref().unwrap(),
+                                    Style::new()
+                                        .yellow()
+                                        .apply_to(
+                                            "\tun-versioned layer (not a git repository); yb won't sync it",
+                                        )
+                                        .to_string(),
+                                ));
+                                subdir_spinner
+                                    .as_ref()
+                                    .unwrap()
+                                    .restyle_message(Style::from_dotted_str("yellow.bold"));
+                                unversioned_layers.push(non_repo_status.path.clone());
+                            } else {
+                                subdir_lines.push(mp.println_after(
+                                    subdir_spinner.as_ref().unwrap(),
+                                    "\tnot a git repository",
+                                ));
+                                if self.skip_unremarkable {
+                                    for line in subdir_lines.drain(..) {
+                                        line.finish_and_clear();
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    if (self.only_problems && !status.has_problem())
+                        || !self.matches_state_filter(status)
+                    {
+                        for line in subdir_lines.drain(..) {
+                            line.finish_and_clear();
+                        }
                     }
                 }
                 StatusCalculatorEvent::FinishProcessSubdir => {
                     overall_progress.as_ref().unwrap().inc(1);
                     subdir_spinner.take();
                 }
+                StatusCalculatorEvent::DuplicateWorkdirSkipped { path, workdir } => {
+                    warn_duplicate_workdir_skipped(mp, path, workdir);
+                }
                 StatusCalculatorEvent::MissingReposDetected(missing_repos) => {
-                    if missing_repos.is_empty() {
+                    // Missing repos have no state to filter on
+                    if missing_repos.is_empty() || self.has_state_filter() {
                         return;
                     }
 
@@ -371,11 +1064,49 @@ impl SubcommandRunner for StatusCommand {
                 }
                 _ => {}
             }
+
+            timings.add_rendering_time(render_start.elapsed());
         })?;
 
-        if config.porcelain {
-            let json = serde_json::to_string_pretty(&status);
-            println!("{}", json?);
+        for path in unversioned_layers {
+            mp.warn(format!(
+                "'{}' looks like a layer but is not a git repository; yb won't sync it",
+                path.display()
+            ));
+        }
+
+        if self.timings {
+            mp.suspend(|| eprint!("{}", timings.summary()));
+        }
+
+        Ok(status)
+    }
+
+    /// Re-render the status whenever something changes under the sources directory. Fetches are
+    /// skipped except every `fetch_interval` seconds. Returns on Ctrl-C.
+    async fn watch_and_rerender(&self, config: &Config, mp: &MultiProgress) -> YbResult<()> {
+        let sources_dir = require_tool_context(config)?.sources_dir();
+        let (_watcher, mut changes) = watch_dir(&sources_dir, Duration::from_millis(500))?;
+        let fetch_interval = Duration::from_secs(self.fetch_interval);
+
+        mp.note(format!(
+            "watching {} for changes (Ctrl-C to exit)",
+            sources_dir.display()
+        ));
+
+        loop {
+            let no_fetch = tokio::select! {
+                _ = tokio::signal::ctrl_c() => break,
+                outcome = changes.wait(fetch_interval) => match outcome {
+                    WatchOutcome::Changed => true,
+                    // Periodically fetch (unless fetching is disabled altogether)
+                    WatchOutcome::Timeout => self.flag_no_fetch,
+                    WatchOutcome::Closed => break,
+                },
+            };
+
+            Term::stdout().clear_screen()?;
+            self.render_status(config, mp, no_fetch)?;
         }
 
         Ok(())
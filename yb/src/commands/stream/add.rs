@@ -1,8 +1,10 @@
 use async_trait::async_trait;
+use eyre::WrapErr;
 use indicatif::MultiProgress;
 
 use crate::commands::SubcommandRunner;
-use crate::errors::YbResult;
+use crate::core::tool_context::require_yb_env;
+use crate::errors::{YbError, YbResult};
 use crate::ops::add_stream::{op_add_stream, AddStreamOptions};
 use crate::Config;
 
@@ -13,14 +15,43 @@ pub struct StreamAddCommand {
 
     #[clap(long, short)]
     name: Option<String>,
+
+    /// After adding the stream, activate the given spec from it
+    #[clap(long, value_name = "SPEC")]
+    activate: Option<String>,
 }
 
 #[async_trait]
 impl SubcommandRunner for StreamAddCommand {
     async fn run(&self, config: &mut Config, _mp: &MultiProgress) -> YbResult<()> {
+        let _lock = require_yb_env(config)?.lock()?;
+
         let mut add_stream_opts = AddStreamOptions::new(config);
         add_stream_opts.name(self.name.clone());
         add_stream_opts.uri(self.uri.clone());
-        op_add_stream(add_stream_opts)
+        op_add_stream(add_stream_opts)?;
+
+        if let Some(spec_name) = &self.activate {
+            // Reload the environment so that it knows about the new stream
+            let mut yb_env = require_yb_env(config)?;
+            let stream_name = self.name.as_deref().unwrap_or("default");
+
+            // TODO don't clone
+            let spec = yb_env
+                .stream_db()
+                .get_stream_by_name(stream_name)
+                .and_then(|stream| stream.get_spec_by_name(spec_name))
+                .cloned()
+                .ok_or_else(|| YbError::SpecNotFound {
+                    name: spec_name.clone(),
+                })
+                .wrap_err_with(|| {
+                    format!("stream '{stream_name}' was added, but cannot activate a spec from it")
+                })?;
+            yb_env.activate_spec(spec)?;
+            println!("Activated spec '{}'", &spec_name);
+        }
+
+        Ok(())
     }
 }
@@ -0,0 +1,130 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use indicatif::MultiProgress;
+use serde::Serialize;
+
+use crate::commands::SubcommandRunner;
+use crate::core::tool_context::require_yb_env;
+use crate::errors::YbResult;
+use crate::stream::{Stream, StreamKind};
+use crate::util::output::or_none;
+use crate::Config;
+
+/// Print details about one stream: where it updates from, what commit it is at, and the specs
+/// it provides
+#[derive(Debug, clap::Parser)]
+pub struct StreamInfoCommand {
+    /// Name of the stream
+    #[clap()]
+    stream: String,
+
+    /// Print the information as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct StreamInfo<'a> {
+    name: &'a str,
+    path: &'a Path,
+    kind: &'a StreamKind,
+    /// None for local streams
+    remote_url: Option<String>,
+    /// The remote branch the stream follows, as 'remote/branch'. None for local and pinned
+    /// streams.
+    upstream_branch: Option<String>,
+    pinned_ref: Option<&'a String>,
+    /// None for local streams
+    current_commit: Option<String>,
+    /// Seconds since the Unix epoch; None if the stream was never fetched
+    last_fetched: Option<u64>,
+    /// Why the stream is broken, if it is
+    broken: Option<String>,
+    specs: Vec<&'a str>,
+}
+
+impl<'a> StreamInfo<'a> {
+    fn new(stream: &'a Stream) -> YbResult<Self> {
+        let mut specs: Vec<&str> = stream.specs().map(|(name, _)| name.as_str()).collect();
+        specs.sort_unstable();
+
+        let last_fetched = stream.last_fetched()?.map(|time| {
+            time.duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs())
+        });
+
+        Ok(Self {
+            name: stream.name(),
+            path: stream.path(),
+            kind: stream.kind(),
+            remote_url: stream.remote_url()?,
+            upstream_branch: stream
+                .upstream_branch()?
+                .map(|branch| format!("{}/{}", branch.remote_name, branch.branch_name)),
+            pinned_ref: stream.pinned_ref(),
+            current_commit: stream.current_commit()?,
+            last_fetched,
+            broken: stream.broken_reason().map(|e| e.to_string()),
+            specs,
+        })
+    }
+}
+
+/// Roughly how long ago `timestamp` (seconds since the Unix epoch) was, e.g. "3 hours ago"
+fn format_age(timestamp: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (amount, unit) = match now.saturating_sub(timestamp) {
+        secs if secs < 60 => (secs, "second"),
+        secs if secs < 60 * 60 => (secs / 60, "minute"),
+        secs if secs < 24 * 60 * 60 => (secs / (60 * 60), "hour"),
+        secs => (secs / (24 * 60 * 60), "day"),
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+    format!("{amount} {unit}{plural} ago")
+}
+
+#[async_trait]
+impl SubcommandRunner for StreamInfoCommand {
+    async fn run(&self, config: &mut Config, _mp: &MultiProgress) -> YbResult<()> {
+        let yb_env = require_yb_env(config)?;
+        let stream = yb_env
+            .stream_db()
+            .get_stream_by_name(&self.stream)
+            .ok_or_else(|| eyre::eyre!("no stream named '{}'", self.stream))?;
+        let info = StreamInfo::new(stream)?;
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&info)?);
+            return Ok(());
+        }
+
+        println!("name: {}", info.name);
+        println!("path: {}", info.path.display());
+        println!("kind: {:?}", info.kind);
+        if *info.kind == StreamKind::Git {
+            println!("remote: {}", or_none(&info.remote_url));
+            match info.pinned_ref {
+                Some(pinned_ref) => println!("pinned to: {pinned_ref}"),
+                None => println!("tracking: {}", or_none(&info.upstream_branch)),
+            }
+            println!("commit: {}", or_none(&info.current_commit));
+            match info.last_fetched {
+                Some(timestamp) => println!("last fetched: {}", format_age(timestamp)),
+                None => println!("last fetched: never"),
+            }
+        }
+        if let Some(broken) = &info.broken {
+            println!("broken: {broken}");
+        }
+        println!("specs:");
+        for spec in &info.specs {
+            println!("    {spec}");
+        }
+
+        Ok(())
+    }
+}
@@ -1,20 +1,67 @@
 use async_trait::async_trait;
 use indicatif::MultiProgress;
+use serde::Serialize;
+use std::path::Path;
 
 use crate::commands::SubcommandRunner;
 use crate::core::tool_context::require_yb_env;
 use crate::errors::YbResult;
+use crate::stream::{Stream, StreamKind};
 use crate::util::paths::list_subdirectories_sorted;
 use crate::Config;
 
 #[derive(Debug, clap::Parser)]
-pub struct StreamListCommand {}
+pub struct StreamListCommand {
+    /// Print a JSON array of objects with 'name', 'path', 'kind', 'current_commit', 'specs', and
+    /// 'broken' keys instead
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct StreamEntry<'a> {
+    name: &'a str,
+    path: &'a Path,
+    kind: &'a StreamKind,
+    /// None for local streams
+    current_commit: Option<String>,
+    specs: Vec<&'a str>,
+    /// Why the stream is broken, if it is
+    broken: Option<String>,
+}
+
+impl<'a> StreamEntry<'a> {
+    fn new(stream: &'a Stream) -> YbResult<Self> {
+        let mut specs: Vec<&str> = stream.specs().map(|(name, _)| name.as_str()).collect();
+        specs.sort_unstable();
+
+        Ok(Self {
+            name: stream.name(),
+            path: stream.path(),
+            kind: stream.kind(),
+            current_commit: stream.current_commit()?,
+            specs,
+            broken: stream.broken_reason().map(|e| e.to_string()),
+        })
+    }
+}
 
 #[async_trait]
 impl SubcommandRunner for StreamListCommand {
     async fn run(&self, config: &mut Config, _mp: &MultiProgress) -> YbResult<()> {
         let yb_env = require_yb_env(config)?;
 
+        if self.json {
+            let mut entries: Vec<_> = yb_env
+                .stream_db()
+                .streams()
+                .map(|(_, stream)| StreamEntry::new(stream))
+                .collect::<YbResult<_>>()?;
+            entries.sort_by(|a, b| a.name.cmp(b.name));
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+            return Ok(());
+        }
+
         // TODO: use stream DB instead of iterating through dir
         let streams_dir = yb_env.streams_dir();
 
@@ -1,17 +1,24 @@
 use enum_dispatch::enum_dispatch;
 
 pub use add::StreamAddCommand;
+pub use info::StreamInfoCommand;
 pub use list::StreamListCommand;
+pub use pin::{StreamPinCommand, StreamUnpinCommand};
 pub use update::StreamUpdateCommand;
 
 mod add;
+mod info;
 mod list;
+mod pin;
 mod update;
 
 #[enum_dispatch(SubcommandRunner)]
 #[derive(Debug, clap::Subcommand)]
 pub enum StreamSubcommands {
     Add(StreamAddCommand),
+    Info(StreamInfoCommand),
     List(StreamListCommand),
+    Pin(StreamPinCommand),
+    Unpin(StreamUnpinCommand),
     Update(StreamUpdateCommand),
 }
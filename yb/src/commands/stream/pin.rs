@@ -0,0 +1,75 @@
+use async_trait::async_trait;
+use indicatif::MultiProgress;
+use maplit::hashset;
+
+use crate::commands::SubcommandRunner;
+use crate::core::tool_context::require_yb_env;
+use crate::errors::YbResult;
+use crate::ops::update_stream::{op_update_stream, UpdateStreamOptions};
+use crate::util::indicatif::MultiProgressHelpers;
+use crate::Config;
+
+/// Pin a stream to a branch, tag, or commit so that updating it always checks out that ref
+#[derive(Debug, clap::Parser)]
+pub struct StreamPinCommand {
+    /// Name of the stream to pin
+    #[clap()]
+    stream: String,
+
+    /// Branch, tag, or commit to pin the stream to
+    #[clap(name = "ref")]
+    git_ref: String,
+}
+
+#[async_trait]
+impl SubcommandRunner for StreamPinCommand {
+    async fn run(&self, config: &mut Config, mp: &MultiProgress) -> YbResult<()> {
+        set_stream_pin(config, &self.stream, Some(self.git_ref.clone()))?;
+        mp.note(format!(
+            "stream '{}' pinned to {}",
+            self.stream, self.git_ref
+        ));
+        Ok(())
+    }
+}
+
+/// Unpin a stream so that updating it fast-forwards its branch again
+#[derive(Debug, clap::Parser)]
+pub struct StreamUnpinCommand {
+    /// Name of the stream to unpin
+    #[clap()]
+    stream: String,
+}
+
+#[async_trait]
+impl SubcommandRunner for StreamUnpinCommand {
+    async fn run(&self, config: &mut Config, mp: &MultiProgress) -> YbResult<()> {
+        set_stream_pin(config, &self.stream, None)?;
+        mp.note(format!("stream '{}' unpinned", self.stream));
+        Ok(())
+    }
+}
+
+fn set_stream_pin(config: &Config, stream_name: &str, pinned_ref: Option<String>) -> YbResult<()> {
+    let mut yb_env = require_yb_env(config)?;
+    let _lock = yb_env.lock()?;
+
+    let stream_key = yb_env
+        .stream_db()
+        .get_stream_by_name(stream_name)
+        .ok_or_else(|| eyre::eyre!("no stream named '{}'", stream_name))?
+        .key();
+    let stream = yb_env.stream_db_mut().stream_mut(stream_key).unwrap();
+    let previous_ref = stream.pinned_ref().cloned();
+    stream.set_pinned_ref(pinned_ref)?;
+
+    // Update the stream right away so the pin takes effect (and the active spec is refreshed)
+    let update_opts = UpdateStreamOptions::new(config, hashset! { stream_key });
+    if let Err(err) = op_update_stream(update_opts, |_| {}) {
+        // Don't leave the stream pinned to something we couldn't check out
+        stream.set_pinned_ref(previous_ref)?;
+        return Err(err);
+    }
+
+    Ok(())
+}
@@ -1,19 +1,84 @@
 use async_trait::async_trait;
-use indicatif::MultiProgress;
+use indicatif::{MultiProgress, ProgressBar};
+use std::time::Duration;
 
 use crate::commands::SubcommandRunner;
+use crate::core::tool_context::{maybe_yb_env, require_yb_env};
 use crate::errors::YbResult;
+use crate::ops::update_stream::{
+    op_update_stream, StreamUpdateOutcome, UpdateStreamEvent, UpdateStreamOptions,
+};
 use crate::ui_ops::update_stream::{ui_op_update_stream, UiUpdateStreamOptions};
+use crate::util::indicatif::{IndicatifHelpers, MultiProgressHelpers};
 use crate::Config;
 
 #[derive(Debug, clap::Parser)]
-pub struct StreamUpdateCommand {}
+pub struct StreamUpdateCommand {
+    /// Update every stream, not just the one the active spec belongs to. Streams are fetched
+    /// concurrently.
+    #[clap(long)]
+    all: bool,
+}
 
 #[async_trait]
 impl SubcommandRunner for StreamUpdateCommand {
     async fn run(&self, config: &mut Config, mp: &MultiProgress) -> YbResult<()> {
+        if self.all {
+            return update_all_streams(config, mp);
+        }
+
+        let _lock = maybe_yb_env(config)?
+            .map(|yb_env| yb_env.lock())
+            .transpose()?;
+
         let mut update_stream_opts = UiUpdateStreamOptions::new(config, mp);
         update_stream_opts.fail_if_no_yb_env(true);
         ui_op_update_stream(update_stream_opts)
     }
 }
+
+fn update_all_streams(config: &Config, mp: &MultiProgress) -> YbResult<()> {
+    let yb_env = require_yb_env(config)?;
+    let _lock = yb_env.lock()?;
+
+    let stream_keys = yb_env
+        .stream_db()
+        .streams()
+        .map(|(stream_key, _)| stream_key)
+        .collect();
+    let mut update_opts = UpdateStreamOptions::new(config, stream_keys);
+    update_opts.keep_going(true);
+
+    let spinner = mp.add(
+        ProgressBar::new_spinner()
+            .with_message("refreshing streams")
+            .with_steady_tick(Duration::from_millis(50)),
+    );
+    let result = op_update_stream(update_opts, |event| {
+        if let UpdateStreamEvent::ActiveSpecUpdated = event {
+            mp.note("active spec changed - reloading environment");
+        }
+    });
+    spinner.finish_and_clear();
+    let result = result?;
+
+    let mut failures = 0;
+    for (stream_name, outcome) in &result.stream_outcomes {
+        match outcome {
+            StreamUpdateOutcome::Updated => mp.note(format!("stream '{stream_name}' updated")),
+            StreamUpdateOutcome::Broken(reason) => {
+                mp.warn(format!("stream '{stream_name}' is broken: {reason}"))
+            }
+            StreamUpdateOutcome::Failed(err) => {
+                failures += 1;
+                mp.error(format!("failed to update stream '{stream_name}': {err}"));
+            }
+        }
+    }
+
+    if failures > 0 {
+        eyre::bail!("{} stream(s) failed to update", failures);
+    }
+
+    Ok(())
+}
@@ -1,12 +1,21 @@
 use async_trait::async_trait;
-use std::path::PathBuf;
+use color_eyre::Help;
+use concurrent_git_pool::trace::{git_command, TracedCommand};
+use eyre::WrapErr;
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::time::Duration;
 
-use crate::commands::sync::actions::SyncAction;
+use crate::commands::sync::actions::plan::SyncActionDescriptor;
+use crate::commands::sync::actions::{GitCloner, SyncAction};
+use crate::commands::sync::mirror::Mirror;
 use crate::data_model::git::RemoteTrackingBranch;
 use crate::errors::YbResult;
 use crate::spec::SpecRepo;
-use concurrent_git_pool::PoolHelper;
+use crate::util::expand::expand_url;
 
 #[derive(Debug)]
 pub struct ResetGitWorkdirSyncAction {
@@ -25,14 +34,117 @@ impl SyncAction for ResetGitWorkdirSyncAction {
         true
     }
 
-    async fn apply(&self, _pool: &PoolHelper) -> YbResult<()> {
-        Command::new("git")
+    fn target_path(&self) -> &Path {
+        &self.repo_path
+    }
+
+    fn summary(&self) -> String {
+        "reset".into()
+    }
+
+    fn descriptor(&self) -> SyncActionDescriptor {
+        SyncActionDescriptor::ResetGitWorkdir {
+            repo_path: self.repo_path.clone(),
+        }
+    }
+
+    async fn apply(&self, _cloner: &dyn GitCloner) -> YbResult<()> {
+        git_command()
             .arg("reset")
             .arg("--hard")
             .stdout(Stdio::null())
             .stderr(Stdio::null())
             .current_dir(&self.repo_path)
-            .output()?;
+            .traced_output()?;
+        Ok(())
+    }
+}
+
+/// Deletes all untracked and ignored files in a repo ('git clean -dfx'), e.g. stale generated
+/// files left behind after switching branches
+#[derive(Debug)]
+pub struct CleanRepoSyncAction {
+    repo_path: PathBuf,
+}
+
+impl CleanRepoSyncAction {
+    pub fn new(repo_path: PathBuf) -> Self {
+        Self { repo_path }
+    }
+}
+
+#[async_trait]
+impl SyncAction for CleanRepoSyncAction {
+    fn is_force_required(&self) -> bool {
+        true
+    }
+
+    fn target_path(&self) -> &Path {
+        &self.repo_path
+    }
+
+    fn summary(&self) -> String {
+        "clean".into()
+    }
+
+    fn descriptor(&self) -> SyncActionDescriptor {
+        SyncActionDescriptor::CleanRepo {
+            repo_path: self.repo_path.clone(),
+        }
+    }
+
+    async fn apply(&self, _cloner: &dyn GitCloner) -> YbResult<()> {
+        let output = git_command()
+            .args(["clean", "-dfx"])
+            .current_dir(&self.repo_path)
+            .traced_output()?;
+        if !output.status.success() {
+            eyre::bail!(
+                "failed to clean {}: {}",
+                self.repo_path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Deletes a repo, e.g. so that it can be cloned again from scratch
+#[derive(Debug)]
+pub struct RemoveRepoSyncAction {
+    repo_path: PathBuf,
+}
+
+impl RemoveRepoSyncAction {
+    pub fn new(repo_path: PathBuf) -> Self {
+        Self { repo_path }
+    }
+}
+
+#[async_trait]
+impl SyncAction for RemoveRepoSyncAction {
+    fn is_force_required(&self) -> bool {
+        true
+    }
+
+    fn target_path(&self) -> &Path {
+        &self.repo_path
+    }
+
+    fn summary(&self) -> String {
+        "remove".into()
+    }
+
+    fn descriptor(&self) -> SyncActionDescriptor {
+        SyncActionDescriptor::RemoveRepo {
+            repo_path: self.repo_path.clone(),
+        }
+    }
+
+    async fn apply(&self, _cloner: &dyn GitCloner) -> YbResult<()> {
+        fs::remove_dir_all(&self.repo_path)
+            .wrap_err_with(|| format!("failed to remove {}", self.repo_path.display()))?;
         Ok(())
     }
 }
@@ -58,14 +170,29 @@ impl SyncAction for CheckoutBranchSyncAction {
         false
     }
 
-    async fn apply(&self, _pool: &PoolHelper) -> YbResult<()> {
-        Command::new("git")
+    fn target_path(&self) -> &Path {
+        &self.repo_path
+    }
+
+    fn summary(&self) -> String {
+        format!("checkout {}", self.branch_name)
+    }
+
+    fn descriptor(&self) -> SyncActionDescriptor {
+        SyncActionDescriptor::CheckoutBranch {
+            repo_path: self.repo_path.clone(),
+            branch_name: self.branch_name.clone(),
+        }
+    }
+
+    async fn apply(&self, _cloner: &dyn GitCloner) -> YbResult<()> {
+        git_command()
             .arg("checkout")
             .arg(&self.branch_name)
             //.stdout(Stdio::null())
             //.stderr(Stdio::null())
             .current_dir(&self.repo_path)
-            .output()?;
+            .traced_output()?;
 
         Ok(())
     }
@@ -74,12 +201,15 @@ impl SyncAction for CheckoutBranchSyncAction {
 #[derive(Debug)]
 pub struct FastForwardPullSyncAction {
     repo_path: PathBuf,
-    // TODO number of commits?
+    commits_behind: Option<usize>,
 }
 
 impl FastForwardPullSyncAction {
-    pub fn new(repo_path: PathBuf) -> Self {
-        Self { repo_path }
+    pub fn new(repo_path: PathBuf, commits_behind: Option<usize>) -> Self {
+        Self {
+            repo_path,
+            commits_behind,
+        }
     }
 }
 
@@ -89,14 +219,43 @@ impl SyncAction for FastForwardPullSyncAction {
         false
     }
 
-    async fn apply(&self, _pool: &PoolHelper) -> YbResult<()> {
-        Command::new("git")
+    fn target_path(&self) -> &Path {
+        &self.repo_path
+    }
+
+    fn summary(&self) -> String {
+        match self.commits_behind {
+            Some(commits_behind) => format!("pull {commits_behind}"),
+            None => "pull".into(),
+        }
+    }
+
+    fn descriptor(&self) -> SyncActionDescriptor {
+        SyncActionDescriptor::FastForwardPull {
+            repo_path: self.repo_path.clone(),
+            commits_behind: self.commits_behind,
+        }
+    }
+
+    async fn apply(&self, _cloner: &dyn GitCloner) -> YbResult<()> {
+        let output = git_command()
             .arg("pull")
             .arg("--ff-only")
             .stdout(Stdio::null())
-            .stderr(Stdio::null())
             .current_dir(&self.repo_path)
-            .output()?;
+            .traced_output()?;
+        if !output.status.success() {
+            // Most likely the branch diverged from its upstream after the status was computed
+            return Err(eyre::eyre!(
+                "cannot fast-forward {}: {}",
+                self.repo_path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .suggestion(
+                "merge or rebase the branch onto its upstream manually, then re-run sync",
+            ));
+        }
+
         Ok(())
     }
 }
@@ -128,8 +287,25 @@ impl SyncAction for CreateLocalTrackingBranchSyncAction {
         false
     }
 
-    async fn apply(&self, _pool: &PoolHelper) -> YbResult<()> {
-        Command::new("git")
+    fn target_path(&self) -> &Path {
+        &self.repo_path
+    }
+
+    fn summary(&self) -> String {
+        format!("branch {}", self.local_branch_name)
+    }
+
+    fn descriptor(&self) -> SyncActionDescriptor {
+        SyncActionDescriptor::CreateLocalTrackingBranch {
+            repo_path: self.repo_path.clone(),
+            local_branch_name: self.local_branch_name.clone(),
+            remote_tracking_branch: self.remote_tracking_branch.clone(),
+        }
+    }
+
+    async fn apply(&self, _cloner: &dyn GitCloner) -> YbResult<()> {
+        fetch_remote_branch_if_missing(&self.repo_path, &self.remote_tracking_branch)?;
+        git_command()
             .arg("checkout")
             .arg("-b")
             .arg(&self.local_branch_name)
@@ -138,7 +314,260 @@ impl SyncAction for CreateLocalTrackingBranchSyncAction {
             .stdout(Stdio::null())
             .stderr(Stdio::null())
             .current_dir(&self.repo_path)
-            .output()?;
+            .traced_output()?;
+        Ok(())
+    }
+}
+
+/// Clones of a single branch (see `CloneRepoSyncAction`) only fetch that branch, so if the spec
+/// has since moved to another one, start fetching that too
+fn fetch_remote_branch_if_missing(
+    repo_path: &Path,
+    remote_tracking_branch: &RemoteTrackingBranch,
+) -> YbResult<()> {
+    let repo = Repository::open(repo_path)?;
+    if repo
+        .revparse_single(&remote_tracking_branch.to_string())
+        .is_ok()
+    {
+        return Ok(());
+    }
+
+    let remote_name = remote_tracking_branch.remote_name.as_str();
+    let branch_name = remote_tracking_branch.branch_name.as_str();
+    for args in [
+        &["remote", "set-branches", "--add", remote_name, branch_name][..],
+        &["fetch", remote_name][..],
+    ] {
+        let output = git_command()
+            .args(args)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .current_dir(repo_path)
+            .traced_output()?;
+        if !output.status.success() {
+            eyre::bail!(
+                "couldn't fetch {} into {}: {}",
+                remote_tracking_branch.to_string(),
+                repo_path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// How a spec repo's refspec is checked out (see `yb sync --checkout-strategy`)
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CheckoutStrategy {
+    /// Check out a local branch tracking the spec's branch
+    #[default]
+    Track,
+    /// Check out the spec's branch (or tag) with a detached HEAD
+    Detach,
+    /// Hard-reset the local branch named after the spec's branch to it, dropping any local
+    /// commits on it
+    Reset,
+}
+
+impl CheckoutStrategy {
+    pub fn is_track(&self) -> bool {
+        *self == CheckoutStrategy::Track
+    }
+}
+
+/// Check out `target` (e.g. 'origin/main' or a tag) with a detached HEAD
+#[derive(Debug)]
+pub struct DetachHeadSyncAction {
+    repo_path: PathBuf,
+    target: String,
+}
+
+impl DetachHeadSyncAction {
+    pub fn new(repo_path: PathBuf, target: String) -> Self {
+        Self { repo_path, target }
+    }
+}
+
+#[async_trait]
+impl SyncAction for DetachHeadSyncAction {
+    fn is_force_required(&self) -> bool {
+        false
+    }
+
+    fn target_path(&self) -> &Path {
+        &self.repo_path
+    }
+
+    fn summary(&self) -> String {
+        format!("detach {}", self.target)
+    }
+
+    fn descriptor(&self) -> SyncActionDescriptor {
+        SyncActionDescriptor::DetachHead {
+            repo_path: self.repo_path.clone(),
+            target: self.target.clone(),
+        }
+    }
+
+    async fn apply(&self, _cloner: &dyn GitCloner) -> YbResult<()> {
+        run_checkout(&self.repo_path, &["--detach", &self.target])
+    }
+}
+
+/// Point `branch_name` at `remote_tracking_branch` (creating it if need be), make it track that
+/// and check it out
+#[derive(Debug)]
+pub struct ResetBranchSyncAction {
+    repo_path: PathBuf,
+    branch_name: String,
+    remote_tracking_branch: RemoteTrackingBranch,
+    discards_commits: bool,
+}
+
+impl ResetBranchSyncAction {
+    /// `discards_commits` is whether the branch has commits that resetting it would drop
+    pub fn new(
+        repo_path: PathBuf,
+        branch_name: String,
+        remote_tracking_branch: RemoteTrackingBranch,
+        discards_commits: bool,
+    ) -> Self {
+        Self {
+            repo_path,
+            branch_name,
+            remote_tracking_branch,
+            discards_commits,
+        }
+    }
+}
+
+#[async_trait]
+impl SyncAction for ResetBranchSyncAction {
+    fn is_force_required(&self) -> bool {
+        self.discards_commits
+    }
+
+    fn target_path(&self) -> &Path {
+        &self.repo_path
+    }
+
+    fn summary(&self) -> String {
+        format!("reset-branch {}", self.branch_name)
+    }
+
+    fn descriptor(&self) -> SyncActionDescriptor {
+        SyncActionDescriptor::ResetBranch {
+            repo_path: self.repo_path.clone(),
+            branch_name: self.branch_name.clone(),
+            remote_tracking_branch: self.remote_tracking_branch.clone(),
+            discards_commits: self.discards_commits,
+        }
+    }
+
+    async fn apply(&self, _cloner: &dyn GitCloner) -> YbResult<()> {
+        run_checkout(
+            &self.repo_path,
+            &[
+                "-B",
+                &self.branch_name,
+                "--track",
+                &self.remote_tracking_branch.to_string(),
+            ],
+        )
+    }
+}
+
+/// Run 'git checkout' with `args` in `repo_path`, failing with git's complaint if it fails
+fn run_checkout(repo_path: &Path, args: &[&str]) -> YbResult<()> {
+    let output = git_command()
+        .arg("checkout")
+        .args(args)
+        .current_dir(repo_path)
+        .traced_output()?;
+    if !output.status.success() {
+        eyre::bail!(
+            "failed to check out {} in {}: {}",
+            args.last().unwrap_or(&""),
+            repo_path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct AddRemoteSyncAction {
+    repo_path: PathBuf,
+    remote_name: String,
+    url: String,
+}
+
+impl AddRemoteSyncAction {
+    pub fn new(repo_path: PathBuf, remote_name: String, url: String) -> Self {
+        Self {
+            repo_path,
+            remote_name,
+            url,
+        }
+    }
+}
+
+#[async_trait]
+impl SyncAction for AddRemoteSyncAction {
+    fn is_force_required(&self) -> bool {
+        false
+    }
+
+    fn target_path(&self) -> &Path {
+        &self.repo_path
+    }
+
+    fn summary(&self) -> String {
+        format!("add-remote {}", self.remote_name)
+    }
+
+    fn descriptor(&self) -> SyncActionDescriptor {
+        SyncActionDescriptor::AddRemote {
+            repo_path: self.repo_path.clone(),
+            remote_name: self.remote_name.clone(),
+            url: self.url.clone(),
+        }
+    }
+
+    async fn apply(&self, _cloner: &dyn GitCloner) -> YbResult<()> {
+        let output = git_command()
+            .arg("remote")
+            .arg("add")
+            .arg(&self.remote_name)
+            .arg(expand_url(&self.url)?)
+            .current_dir(&self.repo_path)
+            .traced_output()?;
+        if !output.status.success() {
+            eyre::bail!(
+                "failed to add remote '{}' to {}: {}",
+                self.remote_name,
+                self.repo_path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        let output = git_command()
+            .arg("fetch")
+            .arg(&self.remote_name)
+            .current_dir(&self.repo_path)
+            .traced_output()?;
+        if !output.status.success() {
+            eyre::bail!(
+                "failed to fetch remote '{}' in {}: {}",
+                self.remote_name,
+                self.repo_path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
         Ok(())
     }
 }
@@ -147,6 +576,9 @@ impl SyncAction for CreateLocalTrackingBranchSyncAction {
 pub struct CloneRepoSyncAction {
     dest_repo_path: PathBuf,
     spec_repo: SpecRepo,
+    shallow_since: Option<String>,
+    mirror: Option<Mirror>,
+    checkout_strategy: CheckoutStrategy,
 }
 
 impl CloneRepoSyncAction {
@@ -154,8 +586,29 @@ impl CloneRepoSyncAction {
         Self {
             dest_repo_path,
             spec_repo,
+            shallow_since: None,
+            mirror: None,
+            checkout_strategy: CheckoutStrategy::default(),
         }
     }
+
+    /// Only fetch history after the given date (see `git clone --shallow-since`)
+    pub fn shallow_since(mut self, shallow_since: Option<String>) -> Self {
+        self.shallow_since = shallow_since;
+        self
+    }
+
+    /// Clone from the given mirror, then point 'origin' back at the spec repo URL
+    pub fn mirror(mut self, mirror: Option<Mirror>) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    /// How to check out the spec's refspec once cloned
+    pub fn checkout_strategy(mut self, checkout_strategy: CheckoutStrategy) -> Self {
+        self.checkout_strategy = checkout_strategy;
+        self
+    }
 }
 
 #[async_trait]
@@ -164,53 +617,460 @@ impl SyncAction for CloneRepoSyncAction {
         false
     }
 
-    async fn apply(&self, pool: &PoolHelper) -> YbResult<()> {
-        pool.clone_in(
-            &self.spec_repo.url,
-            None,
-            Some(self.dest_repo_path.to_str().unwrap().to_string()),
-        )
-        .await
-        .unwrap()?;
+    fn target_path(&self) -> &Path {
+        &self.dest_repo_path
+    }
+
+    fn summary(&self) -> String {
+        "clone".into()
+    }
+
+    fn descriptor(&self) -> SyncActionDescriptor {
+        SyncActionDescriptor::CloneRepo {
+            dest_repo_path: self.dest_repo_path.clone(),
+            spec_repo: self.spec_repo.clone(),
+            shallow_since: self.shallow_since.clone(),
+            mirror: self.mirror.clone(),
+            checkout_strategy: self.checkout_strategy,
+        }
+    }
+
+    async fn apply(&self, cloner: &dyn GitCloner) -> YbResult<()> {
+        let url = expand_url(&self.spec_repo.url)?;
+        let clone_url = clone_url(&self.spec_repo, self.mirror.as_ref())?;
+        let mut attempt = 1;
+        loop {
+            // Only the clone or fetch itself is retried; anything wrong with the destination will
+            // still be wrong next time
+            let resume = match inspect_clone_destination(&self.dest_repo_path, &clone_url)? {
+                ExistingCloneDestination::Absent => false,
+                ExistingCloneDestination::Resume => true,
+                ExistingCloneDestination::Restart => {
+                    tracing::warn!(
+                        "removing partial clone at {} and cloning again",
+                        self.dest_repo_path.display()
+                    );
+                    fs::remove_dir_all(&self.dest_repo_path)?;
+                    false
+                }
+            };
+
+            let result = if resume {
+                self.resume_clone()
+            } else {
+                self.clone_from(cloner, &clone_url).await
+            };
+            match result {
+                Ok(()) => break,
+                Err(err) if attempt < CLONE_ATTEMPTS => {
+                    let delay = CLONE_RETRY_DELAY * 2u32.pow(attempt as u32 - 1);
+                    tracing::warn!(
+                        "cloning {} failed (attempt {}/{}), retrying in {}s: {}",
+                        self.spec_repo.url,
+                        attempt,
+                        CLONE_ATTEMPTS,
+                        delay.as_secs(),
+                        err
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        if let Some(shallow_arg) = self.shallow_arg() {
+            fetch_refspec_if_missing(&self.dest_repo_path, &self.spec_repo.refspec, &shallow_arg)?;
+        }
 
-        assert_cmd::Command::new("git")
+        self.checkout_refspec()?;
+
+        if clone_url != url {
+            // Future fetches should go upstream rather than to the mirror
+            let output = git_command()
+                .arg("remote")
+                .arg("set-url")
+                .arg("origin")
+                .arg(&url)
+                .current_dir(&self.dest_repo_path)
+                .traced_output()?;
+            if !output.status.success() {
+                eyre::bail!(
+                    "failed to point 'origin' of {} at {}: {}",
+                    self.dest_repo_path.display(),
+                    url,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+        }
+
+        if self.spec_repo.submodules {
+            let output = git_command()
+                .arg("submodule")
+                .arg("update")
+                .arg("--init")
+                .arg("--recursive")
+                .env("GIT_TERMINAL_PROMPT", "0")
+                .current_dir(&self.dest_repo_path)
+                .traced_output()?;
+            if !output.status.success() {
+                eyre::bail!(
+                    "failed to update submodules in {}: {}",
+                    self.dest_repo_path.display(),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+        }
+
+        for command in &self.spec_repo.post_clone {
+            run_post_clone_command(&self.dest_repo_path, command)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How many times to try cloning a repo before giving up
+const CLONE_ATTEMPTS: usize = 3;
+
+/// How long to wait before retrying a failed clone; doubled for each further attempt, so that
+/// e.g. a dropped VPN connection has a chance to come back
+const CLONE_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// What to do about something already at a clone destination, e.g. left behind by a clone that
+/// was interrupted
+enum ExistingCloneDestination {
+    Absent,
+    /// A repo with the right 'origin': fetch into it
+    Resume,
+    /// A broken or empty repo: delete it and clone from scratch
+    Restart,
+}
+
+impl CloneRepoSyncAction {
+    /// A depth given in the spec overrides --shallow-since
+    fn effective_shallow_since(&self) -> Option<String> {
+        match self.spec_repo.depth {
+            Some(_) => None,
+            None => self.shallow_since.clone(),
+        }
+    }
+
+    /// Check out the spec's refspec in the new clone, according to the checkout strategy. A
+    /// refspec that isn't a branch of 'origin' (e.g. a tag) is checked out as-is, except that
+    /// `Detach` always detaches.
+    fn checkout_refspec(&self) -> YbResult<()> {
+        let refspec = &self.spec_repo.refspec;
+        let repo = Repository::open(&self.dest_repo_path)?;
+
+        // Cloning just the spec's branch usually leaves it checked out already
+        if self.checkout_strategy != CheckoutStrategy::Detach {
+            if let Ok(head) = repo.head() {
+                if head.is_branch() && head.shorthand() == Some(refspec) {
+                    return Ok(());
+                }
+            }
+        }
+
+        let remote_branch = format!("origin/{refspec}");
+        let is_remote_branch = repo.revparse_single(&remote_branch).is_ok();
+
+        match (self.checkout_strategy, is_remote_branch) {
+            (CheckoutStrategy::Detach, true) => {
+                run_checkout(&self.dest_repo_path, &["--detach", &remote_branch])
+            }
+            (CheckoutStrategy::Detach, false) => {
+                run_checkout(&self.dest_repo_path, &["--detach", refspec])
+            }
+            (CheckoutStrategy::Reset, true) => run_checkout(
+                &self.dest_repo_path,
+                &["-B", refspec, "--track", &remote_branch],
+            ),
+            _ => run_checkout(&self.dest_repo_path, &[refspec]),
+        }
+    }
+
+    /// The argument limiting history for fetches into the clone, if it is shallow
+    fn shallow_arg(&self) -> Option<String> {
+        match (self.spec_repo.depth, &self.shallow_since) {
+            (Some(depth), _) => Some(format!("--depth={depth}")),
+            (None, Some(shallow_since)) => Some(format!("--shallow-since={shallow_since}")),
+            (None, None) => None,
+        }
+    }
+
+    /// Fetch into the interrupted clone at the destination
+    fn resume_clone(&self) -> YbResult<()> {
+        tracing::warn!(
+            "resuming interrupted clone in {}",
+            self.dest_repo_path.display()
+        );
+        let output = git_command()
+            .arg("fetch")
+            .arg("origin")
+            .env("GIT_TERMINAL_PROMPT", "0")
             .current_dir(&self.dest_repo_path)
-            .arg("checkout")
-            .arg(&self.spec_repo.refspec)
-            .assert()
-            .success();
+            .traced_output()?;
+        if !output.status.success() {
+            eyre::bail!(
+                "failed to fetch into {}: {}",
+                self.dest_repo_path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
         Ok(())
     }
+
+    /// `url` is the (expanded) URL to clone from
+    async fn clone_from(&self, cloner: &dyn GitCloner, url: &str) -> YbResult<()> {
+        cloner
+            .clone_branch_in(
+                url,
+                None,
+                Some(self.dest_repo_path.to_str().unwrap().to_string()),
+                &self.spec_repo.refspec,
+                self.effective_shallow_since(),
+                self.spec_repo.depth,
+            )
+            .await
+    }
+}
+
+/// The (expanded) URL to clone `spec_repo` from: its own, or the equivalent on `mirror`
+fn clone_url(spec_repo: &SpecRepo, mirror: Option<&Mirror>) -> YbResult<String> {
+    let url = expand_url(&spec_repo.url)?;
+    match mirror {
+        Some(mirror) => mirror.rewrite(&url),
+        None => Ok(url),
+    }
+}
+
+/// Check that `spec_repo` (cloned from `mirror`, if given) can be cloned into `dest`: that
+/// nothing is there yet, or only what an interrupted clone left behind
+pub(crate) fn check_clone_destination(
+    dest: &Path,
+    spec_repo: &SpecRepo,
+    mirror: Option<&Mirror>,
+) -> YbResult<()> {
+    inspect_clone_destination(dest, &clone_url(spec_repo, mirror)?)?;
+    Ok(())
+}
+
+/// A shallow clone only tracks the remote's default branch, so fetch the spec's refspec (as a
+/// branch, or failing that as a tag) if the clone doesn't have it. `shallow_arg` limits the
+/// history fetched, e.g. '--depth=1'.
+fn fetch_refspec_if_missing(repo_path: &Path, refspec: &str, shallow_arg: &str) -> YbResult<()> {
+    let repo = Repository::open(repo_path)?;
+    if repo.revparse_single(refspec).is_ok()
+        || repo.revparse_single(&format!("origin/{refspec}")).is_ok()
+    {
+        return Ok(());
+    }
+
+    let git = |args: &[&str]| -> YbResult<std::process::Output> {
+        Ok(git_command()
+            .args(args)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .current_dir(repo_path)
+            .traced_output()?)
+    };
+
+    let remote_branch = git(&["ls-remote", "--heads", "origin", refspec])?;
+    let output = if !remote_branch.stdout.is_empty() {
+        // Track the branch too, so that checking it out creates a local tracking branch
+        let set_branches = git(&["remote", "set-branches", "--add", "origin", refspec])?;
+        if !set_branches.status.success() {
+            eyre::bail!(
+                "couldn't add '{}' to the branches fetched from origin in {}: {}",
+                refspec,
+                repo_path.display(),
+                String::from_utf8_lossy(&set_branches.stderr).trim()
+            );
+        }
+        git(&["fetch", shallow_arg, "origin"])?
+    } else {
+        git(&["fetch", shallow_arg, "origin", "tag", refspec])?
+    };
+
+    if !output.status.success() {
+        eyre::bail!(
+            "couldn't fetch '{}' into shallow clone at {}: {}",
+            refspec,
+            repo_path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// Work out whether `dest` (the destination for a clone of `url`) is free, holds a clone that can
+/// be resumed, or holds leftovers that can safely be thrown away: nothing at all, or nothing but
+/// a `.git` that isn't a usable repo. Anything else at `dest` is an error, since it may be the
+/// user's work.
+fn inspect_clone_destination(dest: &Path, url: &str) -> YbResult<ExistingCloneDestination> {
+    if !dest.exists() {
+        return Ok(ExistingCloneDestination::Absent);
+    }
+
+    let repo = Repository::open(dest).ok();
+    if let Some(repo) = &repo {
+        let origin_url = repo
+            .find_remote("origin")
+            .ok()
+            .and_then(|remote| remote.url().map(String::from));
+        if origin_url.as_deref() == Some(url) {
+            return Ok(ExistingCloneDestination::Resume);
+        }
+    }
+
+    let entries: Vec<_> = fs::read_dir(dest)?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<Result<_, _>>()?;
+    let is_broken_repo = repo.map_or(true, |repo| repo.head().is_err());
+    if entries.is_empty() || (entries == [".git"] && is_broken_repo) {
+        return Ok(ExistingCloneDestination::Restart);
+    }
+
+    if !is_broken_repo {
+        eyre::bail!(
+            "{} already exists and is not a clone of {}",
+            dest.display(),
+            url
+        );
+    }
+
+    eyre::bail!(
+        "{} already exists and doesn't look like an interrupted clone",
+        dest.display()
+    )
+}
+
+/// Environment variables passed through to post-clone commands; everything else is cleared
+const POST_CLONE_ENV_VARS: &[&str] = &["PATH", "HOME", "USER", "LANG", "TERM", "SSH_AUTH_SOCK"];
+
+/// Run a spec repo's post-clone `command` via `sh -c` in `repo_path`, with a restricted
+/// environment. Output is streamed to the terminal.
+fn run_post_clone_command(repo_path: &Path, command: &str) -> YbResult<()> {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(command)
+        .current_dir(repo_path)
+        .env_clear();
+    for var in POST_CLONE_ENV_VARS {
+        if let Some(value) = std::env::var_os(var) {
+            cmd.env(var, value);
+        }
+    }
+
+    let status = cmd.status()?;
+    if !status.success() {
+        eyre::bail!(
+            "post-clone command '{}' failed in {} ({})",
+            command,
+            repo_path.display(),
+            status
+        );
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::commands::sync::actions::{CloneRepoSyncAction, SyncAction};
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+
+    use assert_cmd::Command;
+    use async_trait::async_trait;
+
+    use crate::commands::sync::actions::{
+        CloneRepoSyncAction, FastForwardPullSyncAction, GitCloner, SyncAction,
+    };
+    use crate::errors::YbResult;
     use crate::spec::SpecRepo;
     use crate::util::debug_temp_dir::DebugTempDir;
-    use assert_cmd::Command;
-    use concurrent_git_pool::PoolHelper;
+
+    /// Clones from a local repository regardless of the requested URI, recording each request
+    struct FakeCloner {
+        source: PathBuf,
+        requested_uris: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl GitCloner for FakeCloner {
+        async fn clone_in(
+            &self,
+            uri: &str,
+            parent_dir: Option<PathBuf>,
+            directory: Option<String>,
+            _shallow_since: Option<String>,
+            _depth: Option<u32>,
+        ) -> YbResult<()> {
+            self.requested_uris.lock().unwrap().push(uri.to_string());
+
+            let mut cmd = Command::new("git");
+            cmd.arg("clone").arg(&self.source);
+            if let Some(directory) = directory {
+                cmd.arg(directory);
+            }
+            if let Some(parent_dir) = parent_dir {
+                cmd.current_dir(parent_dir);
+            }
+            cmd.assert().success();
+            Ok(())
+        }
+    }
+
+    fn git(cwd: &Path, args: &[&str]) {
+        Command::new("git")
+            .current_dir(cwd)
+            .args(["-c", "user.name=yb", "-c", "user.email=yb@localhost"].iter())
+            .args(args)
+            .assert()
+            .success();
+    }
 
     #[tokio::test]
     async fn clone_action_checks_out_correct_refspec() {
         let dir = DebugTempDir::new().unwrap();
-        let dir_path = dir.path().to_path_buf();
 
-        let pool = PoolHelper::connect_or_local().await.unwrap();
+        // Upstream repo whose default branch is not the one the spec wants
+        let upstream = dir.path().join("upstream");
+        std::fs::create_dir(&upstream).unwrap();
+        git(&upstream, &["init", "-b", "master"]);
+        git(&upstream, &["commit", "--allow-empty", "-m", "initial"]);
+        git(&upstream, &["branch", "honister"]);
+
+        let cloner = FakeCloner {
+            source: upstream,
+            requested_uris: Mutex::new(vec![]),
+        };
 
+        let dest = dir.path().join("meta-raspberrypi");
         let spec_repo = SpecRepo {
             url: "https://github.com/agherzan/meta-raspberrypi.git".to_string(),
             refspec: "honister".to_string(),
             extra_remotes: Default::default(),
             layers: None,
+            post_clone: vec![],
+            submodules: false,
+            depth: None,
+            enabled: true,
         };
 
-        let action = CloneRepoSyncAction::new(dir_path.clone(), spec_repo);
-        action.apply(&pool).await.unwrap();
+        let action = CloneRepoSyncAction::new(dest.clone(), spec_repo);
+        action.apply(&cloner).await.unwrap();
+
+        assert_eq!(
+            *cloner.requested_uris.lock().unwrap(),
+            vec!["https://github.com/agherzan/meta-raspberrypi.git"]
+        );
 
         let mut branch_cmd = Command::new("git");
         branch_cmd
-            .current_dir(dir_path)
+            .current_dir(dest)
             .arg("branch")
             .arg("--show-current");
         let branch_cmd_output = branch_cmd.output().unwrap();
@@ -219,4 +1079,131 @@ mod tests {
             .trim();
         assert_eq!(current_branch, "honister");
     }
+
+    #[tokio::test]
+    async fn fast_forward_pull_fails_when_diverged() {
+        let dir = DebugTempDir::new().unwrap();
+
+        let upstream = dir.path().join("upstream");
+        std::fs::create_dir(&upstream).unwrap();
+        git(&upstream, &["init", "-b", "main"]);
+        git(&upstream, &["commit", "--allow-empty", "-m", "initial"]);
+
+        let local = dir.path().join("local");
+        git(
+            dir.path(),
+            &["clone", upstream.to_str().unwrap(), local.to_str().unwrap()],
+        );
+
+        // Both sides gain a commit, so a fast-forward is no longer possible
+        git(&upstream, &["commit", "--allow-empty", "-m", "upstream"]);
+        git(&local, &["commit", "--allow-empty", "-m", "local"]);
+
+        let cloner = FakeCloner {
+            source: upstream,
+            requested_uris: Mutex::new(vec![]),
+        };
+        let action = FastForwardPullSyncAction::new(local, Some(1));
+        let err = action.apply(&cloner).await.unwrap_err();
+        assert!(err.to_string().contains("cannot fast-forward"));
+    }
+
+    fn honister_spec_repo(url: &str) -> SpecRepo {
+        SpecRepo {
+            url: url.to_string(),
+            refspec: "honister".to_string(),
+            extra_remotes: Default::default(),
+            layers: None,
+            post_clone: vec![],
+            submodules: false,
+            depth: None,
+            enabled: true,
+        }
+    }
+
+    fn create_upstream(dir: &Path) -> PathBuf {
+        let upstream = dir.join("upstream");
+        std::fs::create_dir(&upstream).unwrap();
+        git(&upstream, &["init", "-b", "honister"]);
+        git(&upstream, &["commit", "--allow-empty", "-m", "initial"]);
+        upstream
+    }
+
+    #[tokio::test]
+    async fn clone_action_restarts_broken_partial_clone() {
+        let dir = DebugTempDir::new().unwrap();
+        let upstream = create_upstream(dir.path());
+
+        // What an interrupted clone might leave behind: a .git directory that isn't a valid repo
+        let dest = dir.path().join("meta-foo");
+        std::fs::create_dir_all(dest.join(".git")).unwrap();
+        std::fs::write(dest.join(".git").join("config"), "garbage").unwrap();
+
+        let cloner = FakeCloner {
+            source: upstream.clone(),
+            requested_uris: Mutex::new(vec![]),
+        };
+        let action =
+            CloneRepoSyncAction::new(dest.clone(), honister_spec_repo(upstream.to_str().unwrap()));
+        action.apply(&cloner).await.unwrap();
+
+        assert_eq!(cloner.requested_uris.lock().unwrap().len(), 1);
+        git2::Repository::open(&dest).unwrap().head().unwrap();
+    }
+
+    #[tokio::test]
+    async fn clone_action_refuses_dir_with_other_files() {
+        let dir = DebugTempDir::new().unwrap();
+        let upstream = create_upstream(dir.path());
+
+        // A repo without commits whose working tree holds the user's files
+        let dest = dir.path().join("meta-foo");
+        std::fs::create_dir(&dest).unwrap();
+        git(&dest, &["init"]);
+        std::fs::write(dest.join("notes.txt"), "work in progress").unwrap();
+
+        let cloner = FakeCloner {
+            source: upstream.clone(),
+            requested_uris: Mutex::new(vec![]),
+        };
+        let action =
+            CloneRepoSyncAction::new(dest.clone(), honister_spec_repo(upstream.to_str().unwrap()));
+        let err = action.apply(&cloner).await.unwrap_err();
+
+        assert!(
+            err.to_string()
+                .contains("doesn't look like an interrupted clone"),
+            "{err}"
+        );
+        assert!(cloner.requested_uris.lock().unwrap().is_empty());
+        assert!(dest.join("notes.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn clone_action_resumes_partial_clone() {
+        let dir = DebugTempDir::new().unwrap();
+        let upstream = create_upstream(dir.path());
+
+        // A clone that got as far as setting up 'origin' but not fetching anything
+        let dest = dir.path().join("meta-foo");
+        std::fs::create_dir(&dest).unwrap();
+        git(&dest, &["init"]);
+        git(
+            &dest,
+            &["remote", "add", "origin", upstream.to_str().unwrap()],
+        );
+
+        let cloner = FakeCloner {
+            source: upstream.clone(),
+            requested_uris: Mutex::new(vec![]),
+        };
+        let action =
+            CloneRepoSyncAction::new(dest.clone(), honister_spec_repo(upstream.to_str().unwrap()));
+        action.apply(&cloner).await.unwrap();
+
+        // Resumed rather than cloned again
+        assert!(cloner.requested_uris.lock().unwrap().is_empty());
+        let head = git2::Repository::open(&dest).unwrap().head().unwrap();
+        assert_eq!(head.shorthand(), Some("honister"));
+    }
 }
@@ -2,16 +2,18 @@ use async_trait::async_trait;
 use std::fs;
 use std::fs::OpenOptions;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use bytebraise::editor::list_var_editor::ListVarEditor;
+use serde::{Deserialize, Serialize};
 
-use crate::commands::sync::actions::SyncAction;
+use crate::commands::sync::actions::plan::SyncActionDescriptor;
+use crate::commands::sync::actions::{GitCloner, SyncAction};
 use crate::errors::YbResult;
 use crate::util::paths::normalize_path;
-use concurrent_git_pool::PoolHelper;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum BBLayersEditAction {
     AddLayer,
     RemoveLayer,
@@ -40,7 +42,27 @@ impl SyncAction for ModifyBBLayersConfSyncAction {
         false
     }
 
-    async fn apply(&self, _pool: &PoolHelper) -> YbResult<()> {
+    fn target_path(&self) -> &Path {
+        &self.layer_path
+    }
+
+    fn summary(&self) -> String {
+        match self.action {
+            BBLayersEditAction::AddLayer => "add-layer",
+            BBLayersEditAction::RemoveLayer => "remove-layer",
+        }
+        .to_string()
+    }
+
+    fn descriptor(&self) -> SyncActionDescriptor {
+        SyncActionDescriptor::ModifyBBLayersConf {
+            layer_path: self.layer_path.clone(),
+            bblayers_path: self.bblayers_path.clone(),
+            edit: self.action.clone(),
+        }
+    }
+
+    async fn apply(&self, _cloner: &dyn GitCloner) -> YbResult<()> {
         let layer_path = normalize_path(&self.layer_path)
             .to_str()
             .unwrap()
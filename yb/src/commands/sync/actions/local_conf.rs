@@ -0,0 +1,115 @@
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use eyre::WrapErr;
+
+use crate::commands::sync::actions::plan::SyncActionDescriptor;
+use crate::commands::sync::actions::{GitCloner, SyncAction};
+use crate::errors::YbResult;
+
+/// Name of the conf fragment (next to local.conf) that yb manages
+pub const LOCAL_CONF_FRAGMENT: &str = "yb-local.conf";
+
+/// Write the active spec's `local_conf` settings to the managed conf fragment, and make sure
+/// local.conf includes it. Nothing else in local.conf is touched.
+#[derive(Debug)]
+pub struct WriteLocalConfSyncAction {
+    conf_dir: PathBuf,
+    settings: BTreeMap<String, String>,
+}
+
+impl WriteLocalConfSyncAction {
+    pub fn new(conf_dir: PathBuf, settings: BTreeMap<String, String>) -> Self {
+        Self { conf_dir, settings }
+    }
+
+    fn fragment_path(&self) -> PathBuf {
+        self.conf_dir.join(LOCAL_CONF_FRAGMENT)
+    }
+
+    fn local_conf_path(&self) -> PathBuf {
+        self.conf_dir.join("local.conf")
+    }
+
+    fn include_line() -> String {
+        format!("include conf/{LOCAL_CONF_FRAGMENT}")
+    }
+
+    fn fragment_contents(&self) -> String {
+        let mut ret = String::from(
+            "# Managed by yb from the active spec's 'local_conf' - changes will be overwritten\n",
+        );
+        for (name, value) in &self.settings {
+            ret += &format!("{} = \"{}\"\n", name, value.replace('"', "\\\""));
+        }
+        ret
+    }
+
+    fn has_include_line(&self) -> bool {
+        fs::read_to_string(self.local_conf_path()).map_or(false, |local_conf| {
+            local_conf
+                .lines()
+                .any(|line| line.trim() == Self::include_line())
+        })
+    }
+
+    /// Whether the fragment already holds the settings and is included from local.conf (or
+    /// there is nothing to write and never was)
+    pub fn is_up_to_date(&self) -> bool {
+        match fs::read_to_string(self.fragment_path()) {
+            Ok(fragment) => fragment == self.fragment_contents() && self.has_include_line(),
+            Err(_) => self.settings.is_empty(),
+        }
+    }
+}
+
+#[async_trait]
+impl SyncAction for WriteLocalConfSyncAction {
+    fn is_force_required(&self) -> bool {
+        false
+    }
+
+    fn target_path(&self) -> &Path {
+        &self.conf_dir
+    }
+
+    fn summary(&self) -> String {
+        "write-local-conf".to_string()
+    }
+
+    fn descriptor(&self) -> SyncActionDescriptor {
+        SyncActionDescriptor::WriteLocalConf {
+            conf_dir: self.conf_dir.clone(),
+            settings: self.settings.clone(),
+        }
+    }
+
+    async fn apply(&self, _cloner: &dyn GitCloner) -> YbResult<()> {
+        fs::create_dir_all(&self.conf_dir)?;
+
+        let fragment_path = self.fragment_path();
+        let contents = self.fragment_contents();
+        if fs::read_to_string(&fragment_path).ok().as_deref() != Some(contents.as_str()) {
+            fs::write(&fragment_path, contents)
+                .wrap_err_with(|| format!("failed to write {}", fragment_path.display()))?;
+        }
+
+        if !self.has_include_line() {
+            let local_conf_path = self.local_conf_path();
+            let mut local_conf = fs::read_to_string(&local_conf_path).unwrap_or_default();
+            if !local_conf.is_empty() && !local_conf.ends_with('\n') {
+                local_conf.push('\n');
+            }
+            local_conf += &format!(
+                "\n# Settings from the active yb spec\n{}\n",
+                Self::include_line()
+            );
+            fs::write(&local_conf_path, local_conf)
+                .wrap_err_with(|| format!("failed to update {}", local_conf_path.display()))?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,17 +1,180 @@
 use async_trait::async_trait;
 use std::fmt::Debug;
+use std::path::{Path, PathBuf};
 
 pub(crate) use basic::*;
 pub(crate) use bblayers::*;
+pub(crate) use local_conf::*;
 
-use crate::errors::YbResult;
-use concurrent_git_pool::PoolHelper;
+use crate::commands::sync::actions::plan::SyncActionDescriptor;
+use crate::errors::{YbError, YbResult};
+use crate::util::fetch_limit::FetchLimiter;
+use concurrent_git_pool::{ClonedRepo, PoolHelper, ServiceError, ServiceResult};
 
 pub mod basic;
 pub mod bblayers;
+pub mod local_conf;
+pub mod plan;
 
 #[async_trait]
 pub trait SyncAction: Debug + Send + Sync {
     fn is_force_required(&self) -> bool;
-    async fn apply(&self, pool: &PoolHelper) -> YbResult<()>;
+
+    /// Path that the action operates on (a repo, or a layer within one)
+    fn target_path(&self) -> &Path;
+
+    /// Short description of the action, e.g. "checkout main"
+    fn summary(&self) -> String;
+
+    /// Serializable description of the action, used to save sync plans
+    fn descriptor(&self) -> SyncActionDescriptor;
+
+    async fn apply(&self, cloner: &dyn GitCloner) -> YbResult<()>;
+}
+
+/// Clones git repositories on behalf of sync actions. Implemented by `PoolHelper`, but
+/// abstracted so that actions can be tested without a git pool.
+#[async_trait]
+pub trait GitCloner: Send + Sync {
+    /// Clone `uri` into `directory` (relative to `parent_dir`, if given). If `shallow_since` or
+    /// `depth` is given, only history after that date or that many commits is fetched.
+    async fn clone_in(
+        &self,
+        uri: &str,
+        parent_dir: Option<PathBuf>,
+        directory: Option<String>,
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+    ) -> YbResult<()>;
+
+    /// Like `clone_in`, but only clone `branch` of `uri` where that is cheaper. By default this
+    /// is a full clone.
+    async fn clone_branch_in(
+        &self,
+        uri: &str,
+        parent_dir: Option<PathBuf>,
+        directory: Option<String>,
+        _branch: &str,
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+    ) -> YbResult<()> {
+        self.clone_in(uri, parent_dir, directory, shallow_since, depth)
+            .await
+    }
+
+    /// Warm any cache the cloner keeps for `uri`, so that a later clone of it is quick. By
+    /// default there is no cache and this does nothing.
+    async fn prefetch(&self, _uri: &str) -> YbResult<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl GitCloner for PoolHelper {
+    async fn clone_in(
+        &self,
+        uri: &str,
+        parent_dir: Option<PathBuf>,
+        directory: Option<String>,
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+    ) -> YbResult<()> {
+        let result =
+            PoolHelper::clone_in(self, uri, parent_dir, directory, shallow_since, depth).await?;
+        report_clone(uri, result)
+    }
+
+    async fn clone_branch_in(
+        &self,
+        uri: &str,
+        parent_dir: Option<PathBuf>,
+        directory: Option<String>,
+        branch: &str,
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+    ) -> YbResult<()> {
+        let result = PoolHelper::clone_branch_in(
+            self,
+            uri,
+            parent_dir,
+            directory,
+            branch,
+            shallow_since,
+            depth,
+        )
+        .await?;
+        report_clone(uri, result)
+    }
+
+    async fn prefetch(&self, uri: &str) -> YbResult<()> {
+        PoolHelper::lookup_or_clone(self, uri).await??;
+        Ok(())
+    }
+}
+
+/// Log where a clone of `uri` ended up, or convert its failure
+fn report_clone(uri: &str, result: ServiceResult<ClonedRepo>) -> YbResult<()> {
+    match result {
+        Ok(cloned) => {
+            tracing::debug!(
+                "cloned {} into {} at {}",
+                uri,
+                cloned.path.display(),
+                cloned.head.as_deref().unwrap_or("(empty)")
+            );
+            Ok(())
+        }
+        Err(ServiceError::CloneFailed(reason)) => Err(YbError::CloneFailed(reason).into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// A `GitCloner` that waits for a `FetchLimiter` permit before each clone or prefetch, so that
+/// only so many run at once
+pub struct LimitedCloner<C> {
+    inner: C,
+    limiter: FetchLimiter,
+}
+
+impl<C: GitCloner> LimitedCloner<C> {
+    pub fn new(inner: C, limiter: FetchLimiter) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+#[async_trait]
+impl<C: GitCloner> GitCloner for LimitedCloner<C> {
+    async fn clone_in(
+        &self,
+        uri: &str,
+        parent_dir: Option<PathBuf>,
+        directory: Option<String>,
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+    ) -> YbResult<()> {
+        let _permit = self.limiter.acquire().await;
+        self.inner
+            .clone_in(uri, parent_dir, directory, shallow_since, depth)
+            .await
+    }
+
+    async fn clone_branch_in(
+        &self,
+        uri: &str,
+        parent_dir: Option<PathBuf>,
+        directory: Option<String>,
+        branch: &str,
+        shallow_since: Option<String>,
+        depth: Option<u32>,
+    ) -> YbResult<()> {
+        let _permit = self.limiter.acquire().await;
+        self.inner
+            .clone_branch_in(uri, parent_dir, directory, branch, shallow_since, depth)
+            .await
+    }
+
+    async fn prefetch(&self, uri: &str) -> YbResult<()> {
+        let _permit = self.limiter.acquire().await;
+        self.inner.prefetch(uri).await
+    }
 }
@@ -0,0 +1,334 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::sync::actions::{
+    check_clone_destination, AddRemoteSyncAction, BBLayersEditAction, CheckoutBranchSyncAction,
+    CheckoutStrategy, CleanRepoSyncAction, CloneRepoSyncAction,
+    CreateLocalTrackingBranchSyncAction, DetachHeadSyncAction, FastForwardPullSyncAction,
+    ModifyBBLayersConfSyncAction, RemoveRepoSyncAction, ResetBranchSyncAction,
+    ResetGitWorkdirSyncAction, SyncAction, WriteLocalConfSyncAction,
+};
+use crate::commands::sync::mirror::Mirror;
+use crate::data_model::git::RemoteTrackingBranch;
+use crate::errors::YbResult;
+use crate::spec::SpecRepo;
+
+const SYNC_PLAN_FORMAT_VERSION: u32 = 1;
+
+/// Serializable description of a sync action, from which the action can be reconstructed
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum SyncActionDescriptor {
+    ResetGitWorkdir {
+        repo_path: PathBuf,
+    },
+    RemoveRepo {
+        repo_path: PathBuf,
+    },
+    CleanRepo {
+        repo_path: PathBuf,
+    },
+    CheckoutBranch {
+        repo_path: PathBuf,
+        branch_name: String,
+    },
+    FastForwardPull {
+        repo_path: PathBuf,
+        commits_behind: Option<usize>,
+    },
+    CreateLocalTrackingBranch {
+        repo_path: PathBuf,
+        local_branch_name: String,
+        remote_tracking_branch: RemoteTrackingBranch,
+    },
+    AddRemote {
+        repo_path: PathBuf,
+        remote_name: String,
+        url: String,
+    },
+    DetachHead {
+        repo_path: PathBuf,
+        target: String,
+    },
+    ResetBranch {
+        repo_path: PathBuf,
+        branch_name: String,
+        remote_tracking_branch: RemoteTrackingBranch,
+        discards_commits: bool,
+    },
+    CloneRepo {
+        dest_repo_path: PathBuf,
+        spec_repo: SpecRepo,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        shallow_since: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mirror: Option<Mirror>,
+        #[serde(default, skip_serializing_if = "CheckoutStrategy::is_track")]
+        checkout_strategy: CheckoutStrategy,
+    },
+    ModifyBBLayersConf {
+        layer_path: PathBuf,
+        bblayers_path: PathBuf,
+        edit: BBLayersEditAction,
+    },
+    WriteLocalConf {
+        conf_dir: PathBuf,
+        settings: BTreeMap<String, String>,
+    },
+}
+
+impl SyncActionDescriptor {
+    /// Whether the action changes a repo's git state (as opposed to bblayers.conf or local.conf)
+    pub fn changes_repo(&self) -> bool {
+        !matches!(
+            self,
+            SyncActionDescriptor::ModifyBBLayersConf { .. }
+                | SyncActionDescriptor::WriteLocalConf { .. }
+        )
+    }
+
+    pub fn into_action(self) -> Box<dyn SyncAction> {
+        match self {
+            SyncActionDescriptor::ResetGitWorkdir { repo_path } => {
+                Box::new(ResetGitWorkdirSyncAction::new(repo_path))
+            }
+            SyncActionDescriptor::RemoveRepo { repo_path } => {
+                Box::new(RemoveRepoSyncAction::new(repo_path))
+            }
+            SyncActionDescriptor::CleanRepo { repo_path } => {
+                Box::new(CleanRepoSyncAction::new(repo_path))
+            }
+            SyncActionDescriptor::CheckoutBranch {
+                repo_path,
+                branch_name,
+            } => Box::new(CheckoutBranchSyncAction::new(repo_path, branch_name)),
+            SyncActionDescriptor::FastForwardPull {
+                repo_path,
+                commits_behind,
+            } => Box::new(FastForwardPullSyncAction::new(repo_path, commits_behind)),
+            SyncActionDescriptor::CreateLocalTrackingBranch {
+                repo_path,
+                local_branch_name,
+                remote_tracking_branch,
+            } => Box::new(CreateLocalTrackingBranchSyncAction::new(
+                repo_path,
+                local_branch_name,
+                remote_tracking_branch,
+            )),
+            SyncActionDescriptor::AddRemote {
+                repo_path,
+                remote_name,
+                url,
+            } => Box::new(AddRemoteSyncAction::new(repo_path, remote_name, url)),
+            SyncActionDescriptor::DetachHead { repo_path, target } => {
+                Box::new(DetachHeadSyncAction::new(repo_path, target))
+            }
+            SyncActionDescriptor::ResetBranch {
+                repo_path,
+                branch_name,
+                remote_tracking_branch,
+                discards_commits,
+            } => Box::new(ResetBranchSyncAction::new(
+                repo_path,
+                branch_name,
+                remote_tracking_branch,
+                discards_commits,
+            )),
+            SyncActionDescriptor::CloneRepo {
+                dest_repo_path,
+                spec_repo,
+                shallow_since,
+                mirror,
+                checkout_strategy,
+            } => Box::new(
+                CloneRepoSyncAction::new(dest_repo_path, spec_repo)
+                    .shallow_since(shallow_since)
+                    .mirror(mirror)
+                    .checkout_strategy(checkout_strategy),
+            ),
+            SyncActionDescriptor::ModifyBBLayersConf {
+                layer_path,
+                bblayers_path,
+                edit,
+            } => Box::new(ModifyBBLayersConfSyncAction::new(
+                layer_path,
+                bblayers_path,
+                edit,
+            )),
+            SyncActionDescriptor::WriteLocalConf { conf_dir, settings } => {
+                Box::new(WriteLocalConfSyncAction::new(conf_dir, settings))
+            }
+        }
+    }
+
+    /// Check that the environment is still in a state where this action makes sense, e.g. that
+    /// a repo about to be cloned doesn't exist yet (or is only an interrupted clone).
+    pub fn check_preconditions(&self) -> YbResult<()> {
+        match self {
+            SyncActionDescriptor::ResetGitWorkdir { repo_path }
+            | SyncActionDescriptor::RemoveRepo { repo_path }
+            | SyncActionDescriptor::CleanRepo { repo_path }
+            | SyncActionDescriptor::CheckoutBranch { repo_path, .. }
+            | SyncActionDescriptor::FastForwardPull { repo_path, .. }
+            | SyncActionDescriptor::CreateLocalTrackingBranch { repo_path, .. }
+            | SyncActionDescriptor::AddRemote { repo_path, .. }
+            | SyncActionDescriptor::DetachHead { repo_path, .. }
+            | SyncActionDescriptor::ResetBranch { repo_path, .. } => {
+                if !repo_path.join(".git").exists() {
+                    eyre::bail!("expected a git repository at {}", repo_path.display());
+                }
+            }
+            SyncActionDescriptor::CloneRepo {
+                dest_repo_path,
+                spec_repo,
+                mirror,
+                ..
+            } => check_clone_destination(dest_repo_path, spec_repo, mirror.as_ref())?,
+            SyncActionDescriptor::ModifyBBLayersConf { .. }
+            | SyncActionDescriptor::WriteLocalConf { .. } => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// A computed list of sync actions that can be saved and applied later
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncPlan {
+    format_version: u32,
+    actions: Vec<SyncActionDescriptor>,
+}
+
+impl SyncPlan {
+    pub fn new(actions: &[Box<dyn SyncAction>]) -> Self {
+        Self {
+            format_version: SYNC_PLAN_FORMAT_VERSION,
+            actions: actions.iter().map(|action| action.descriptor()).collect(),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> YbResult<()> {
+        let path = path.as_ref();
+        let f = File::create(path)
+            .with_context(|| format!("failed to open {} for writing", path.display()))?;
+        serde_yaml::to_writer(f, self)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> YbResult<Self> {
+        let path = path.as_ref();
+        let f = File::open(path)
+            .with_context(|| format!("failed to open sync plan {}", path.display()))?;
+        let plan: SyncPlan = serde_yaml::from_reader(f)
+            .with_context(|| format!("failed to parse sync plan {}", path.display()))?;
+
+        if plan.format_version != SYNC_PLAN_FORMAT_VERSION {
+            eyre::bail!(
+                "sync plan {} has unsupported format version {}",
+                path.display(),
+                plan.format_version
+            );
+        }
+
+        Ok(plan)
+    }
+
+    /// Validate each action, then convert them back into actions ready to be applied
+    pub fn into_actions(self) -> YbResult<Vec<Box<dyn SyncAction>>> {
+        // A repo may be removed and then cloned again (see `yb sync --reclone`)
+        let mut removed_paths = vec![];
+        for descriptor in &self.actions {
+            match descriptor {
+                SyncActionDescriptor::CloneRepo { dest_repo_path, .. }
+                    if removed_paths.contains(dest_repo_path) =>
+                {
+                    continue
+                }
+                SyncActionDescriptor::RemoveRepo { repo_path } => {
+                    removed_paths.push(repo_path.clone())
+                }
+                _ => {}
+            }
+
+            descriptor
+                .check_preconditions()
+                .wrap_err("sync plan no longer applies to this environment")?;
+        }
+
+        Ok(self
+            .actions
+            .into_iter()
+            .map(SyncActionDescriptor::into_action)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::commands::sync::actions::plan::{SyncActionDescriptor, SyncPlan};
+    use crate::commands::sync::actions::{BBLayersEditAction, CheckoutStrategy, SyncAction};
+    use crate::data_model::git::RemoteTrackingBranch;
+
+    #[test]
+    fn descriptors_round_trip() {
+        let descriptors = vec![
+            SyncActionDescriptor::CheckoutBranch {
+                repo_path: PathBuf::from("/yocto/sources/poky"),
+                branch_name: "kirkstone".to_string(),
+            },
+            SyncActionDescriptor::CreateLocalTrackingBranch {
+                repo_path: PathBuf::from("/yocto/sources/poky"),
+                local_branch_name: "kirkstone".to_string(),
+                remote_tracking_branch: RemoteTrackingBranch {
+                    remote_name: "origin".to_string(),
+                    branch_name: "kirkstone".to_string(),
+                },
+            },
+            SyncActionDescriptor::ModifyBBLayersConf {
+                layer_path: PathBuf::from("/yocto/sources/poky/meta"),
+                bblayers_path: PathBuf::from("/yocto/build/conf/bblayers.conf"),
+                edit: BBLayersEditAction::AddLayer,
+            },
+        ];
+
+        let actions: Vec<Box<dyn SyncAction>> = descriptors
+            .iter()
+            .cloned()
+            .map(SyncActionDescriptor::into_action)
+            .collect();
+        let plan = SyncPlan::new(&actions);
+        assert_eq!(plan.actions, descriptors);
+
+        let yaml = serde_yaml::to_string(&plan).unwrap();
+        let reloaded: SyncPlan = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(reloaded.actions, descriptors);
+    }
+
+    #[test]
+    fn clone_into_existing_dir_is_rejected() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let descriptor = SyncActionDescriptor::CloneRepo {
+            dest_repo_path: tmp.path().to_path_buf(),
+            spec_repo: serde_yaml::from_str(
+                "url: https://example.com/meta-foo.git\nrefspec: main\nlayers: ~\n",
+            )
+            .unwrap(),
+            shallow_since: None,
+            mirror: None,
+            checkout_strategy: CheckoutStrategy::Track,
+        };
+
+        // An empty directory, as an interrupted clone may leave behind, is cloned over
+        descriptor.check_preconditions().unwrap();
+
+        std::fs::write(tmp.path().join("notes.txt"), "work in progress").unwrap();
+        let err = descriptor.check_preconditions().unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+    }
+}
@@ -0,0 +1,127 @@
+use concurrent_git_pool::trace::{git_command, TracedCommand};
+use eyre::WrapErr;
+
+use crate::commands::sync::actions::plan::SyncActionDescriptor;
+use crate::commands::sync::actions::SyncAction;
+use crate::errors::YbResult;
+use crate::util::debug_temp_dir::DebugTempDir;
+use crate::util::expand::expand_url;
+
+/// Parse a size like '500M', '2G', '64K' or a plain number of bytes. Multiples are binary
+/// (1K = 1024 bytes).
+pub(crate) fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((i, unit)) if unit.is_ascii_alphabetic() => {
+            let multiplier: u64 = match unit.to_ascii_uppercase() {
+                'K' => 1 << 10,
+                'M' => 1 << 20,
+                'G' => 1 << 30,
+                _ => return Err(format!("unknown size unit '{unit}' (expected K, M or G)")),
+            };
+            (&s[..i], multiplier)
+        }
+        _ => (s, 1),
+    };
+
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size '{s}'"))
+}
+
+pub(crate) fn format_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 30 => format!("{:.1} GiB", b as f64 / (1u64 << 30) as f64),
+        b if b >= 1 << 20 => format!("{:.1} MiB", b as f64 / (1u64 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KiB", b as f64 / (1u64 << 10) as f64),
+        b => format!("{b} bytes"),
+    }
+}
+
+/// Approximate how much cloning `refspec` of `url` downloads. Git can't ask a remote how big a
+/// repo is, so this makes a depth-1 probe clone (only the objects of the tip commit) and measures
+/// it. That is a lower bound: the history comes on top, which for old repos can be much more.
+pub(crate) fn probe_clone_size(url: &str, refspec: &str) -> YbResult<u64> {
+    let tmp = DebugTempDir::new()?;
+    let output = git_command()
+        .args(["clone", "--bare", "--depth", "1", "--single-branch", "-b"])
+        .arg(refspec)
+        .arg(url)
+        .arg(tmp.path())
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes")
+        .traced_output()?;
+    if !output.status.success() {
+        eyre::bail!(
+            "failed to probe the size of {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    // Sizes are in KiB; count loose objects too, which local clones may leave unpacked
+    let output = git_command()
+        .args(["count-objects", "-v"])
+        .current_dir(tmp.path())
+        .traced_output()?;
+    let counts = String::from_utf8_lossy(&output.stdout);
+    let kib: u64 = counts
+        .lines()
+        .filter_map(|line| {
+            line.strip_prefix("size: ")
+                .or_else(|| line.strip_prefix("size-pack: "))
+        })
+        .filter_map(|size| size.trim().parse::<u64>().ok())
+        .sum();
+
+    Ok(kib * 1024)
+}
+
+/// The URLs that the clone actions among `actions` clone from whose probed size (see
+/// `probe_clone_size`) exceeds `max_size`, along with that size
+pub(crate) fn find_oversized_clones(
+    actions: &[Box<dyn SyncAction>],
+    max_size: u64,
+) -> YbResult<Vec<(String, u64)>> {
+    let mut oversized = vec![];
+    for action in actions {
+        if let SyncActionDescriptor::CloneRepo {
+            spec_repo, mirror, ..
+        } = action.descriptor()
+        {
+            let url = expand_url(&spec_repo.url)?;
+            let url = match &mirror {
+                Some(mirror) => mirror.rewrite(&url)?,
+                None => url,
+            };
+            let size = probe_clone_size(&url, &spec_repo.refspec)
+                .wrap_err("pass a bigger --max-clone-size or leave it out to clone anyway")?;
+            if size > max_size {
+                oversized.push((url, size));
+            }
+        }
+    }
+
+    Ok(oversized)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::sync::clone_size::{format_size, parse_size};
+
+    #[test]
+    fn sizes_parse_with_binary_units() {
+        assert_eq!(parse_size("1048576"), Ok(1 << 20));
+        assert_eq!(parse_size("64K"), Ok(64 << 10));
+        assert_eq!(parse_size("500m"), Ok(500 << 20));
+        assert_eq!(parse_size("2G"), Ok(2 << 30));
+        assert!(parse_size("2T").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("lots").is_err());
+
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(12), "12 bytes");
+    }
+}
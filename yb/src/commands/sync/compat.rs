@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::data_model::Layer;
+
+/// Values assigned to the variables in a bitbake conf file whose names start with `prefix`,
+/// keyed by the rest of the name. Appends (`+=`, `:append` etc.) add to the values of earlier
+/// lines; anything fancier than simple assignments on a single line is ignored.
+fn conf_values_with_prefix(conf: &str, prefix: &str) -> BTreeMap<String, Vec<String>> {
+    let mut ret: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for line in conf.lines() {
+        let rest = match line.trim().strip_prefix(prefix) {
+            Some(rest) => rest,
+            None => continue,
+        };
+        let (name, value) = match rest.split_once('=') {
+            Some((name, value)) => (name, value),
+            None => continue,
+        };
+
+        // Strip the operator (e.g. '?=', '+=') and any override (e.g. ':append')
+        let name = name
+            .trim()
+            .trim_end_matches(['?', ':', '+', '.'])
+            .trim_end();
+        let (name, is_append) = match name.split_once(':') {
+            Some((name, _)) => (name, true),
+            None => (
+                name,
+                ["+=", "=+", ".=", "=."].iter().any(|op| rest.contains(op)),
+            ),
+        };
+        let value = value
+            .trim_start_matches(['+', '.'])
+            .trim()
+            .trim_matches('"');
+
+        let values = ret.entry(name.to_string()).or_default();
+        if !is_append {
+            values.clear();
+        }
+        values.extend(value.split_whitespace().map(str::to_string));
+    }
+    ret
+}
+
+/// Codename(s) of the Yocto release that the poky checkout at `poky_dir` is, from
+/// LAYERSERIES_CORENAMES in meta/conf/layer.conf
+pub(crate) fn poky_release_codenames(poky_dir: &Path) -> Option<Vec<String>> {
+    let layer_conf =
+        fs::read_to_string(poky_dir.join("meta").join("conf").join("layer.conf")).ok()?;
+    let codenames = conf_values_with_prefix(&layer_conf, "LAYERSERIES_CORENAMES")
+        .remove("")
+        .unwrap_or_default();
+    if codenames.is_empty() {
+        None
+    } else {
+        Some(codenames)
+    }
+}
+
+/// Check that each of `layers` declares (via LAYERSERIES_COMPAT_<collection> in its
+/// conf/layer.conf) that it supports one of `codenames`. Layers without a conf/layer.conf are
+/// left to --verify.
+///
+/// Returns a description of each incompatible layer.
+pub(crate) fn check_layers_compat<'a, I>(layers: I, codenames: &[String]) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a Layer>,
+{
+    let mut problems = vec![];
+    for layer in layers {
+        let layer_conf = match fs::read_to_string(layer.path.join("conf").join("layer.conf")) {
+            Ok(layer_conf) => layer_conf,
+            Err(_) => continue,
+        };
+
+        let compat = conf_values_with_prefix(&layer_conf, "LAYERSERIES_COMPAT_");
+        if compat.is_empty() {
+            problems.push(format!(
+                "layer {} does not set LAYERSERIES_COMPAT",
+                layer.path.display()
+            ));
+            continue;
+        }
+
+        for (collection, releases) in compat {
+            if !releases.iter().any(|release| codenames.contains(release)) {
+                problems.push(format!(
+                    "layer {} ({}) is compatible with '{}', not '{}'",
+                    layer.path.display(),
+                    collection,
+                    releases.join(" "),
+                    codenames.join(" ")
+                ));
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::sync::compat::conf_values_with_prefix;
+
+    #[test]
+    fn layer_series_compat_is_parsed() {
+        let layer_conf = r#"
+BBPATH .= ":${LAYERDIR}"
+BBFILE_COLLECTIONS += "foo"
+LAYERSERIES_COMPAT_foo = "dunfell"
+LAYERSERIES_COMPAT_foo += "kirkstone"
+LAYERSERIES_COMPAT_bar ?= "zeus"
+LAYERSERIES_COMPAT_bar = "langdale"
+LAYERSERIES_COMPAT_bar:append = " mickledore"
+"#;
+        let compat = conf_values_with_prefix(layer_conf, "LAYERSERIES_COMPAT_");
+        assert_eq!(compat.len(), 2);
+        assert_eq!(compat["foo"], vec!["dunfell", "kirkstone"]);
+        assert_eq!(compat["bar"], vec!["langdale", "mickledore"]);
+    }
+
+    #[test]
+    fn release_codename_is_parsed() {
+        let layer_conf = "LAYERSERIES_CORENAMES = \"kirkstone\"\n";
+        let codenames = conf_values_with_prefix(layer_conf, "LAYERSERIES_CORENAMES");
+        assert_eq!(codenames[""], vec!["kirkstone"]);
+    }
+}
@@ -0,0 +1,51 @@
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::commands::sync::actions::plan::SyncActionDescriptor;
+use crate::commands::sync::actions::SyncAction;
+
+/// Progress of a sync, emitted as newline-delimited JSON with --json-events
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum SyncEvent<'a> {
+    /// Gathering the status of the source dirs has started
+    StatusStarted { number_subdirs: u64 },
+    /// The status of a source dir is being gathered
+    CheckingSubdir { dirname: &'a str },
+    /// A repo was left out of the plan
+    RepoSkipped { path: &'a Path, reason: &'a str },
+    /// The actions about to be applied (or that would be, without --apply)
+    Plan { actions: Vec<SyncActionDescriptor> },
+    ActionStarted {
+        index: usize,
+        total: usize,
+        action: SyncActionDescriptor,
+    },
+    ActionFinished {
+        index: usize,
+        total: usize,
+        ok: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+impl<'a> SyncEvent<'a> {
+    pub(crate) fn plan(sync_actions: &[Box<dyn SyncAction>]) -> Self {
+        SyncEvent::Plan {
+            actions: sync_actions
+                .iter()
+                .map(|action| action.descriptor())
+                .collect(),
+        }
+    }
+
+    /// Print the event to stdout as a single line of JSON
+    pub(crate) fn emit(&self) {
+        println!(
+            "{}",
+            serde_json::to_string(self).expect("sync events are always serializable")
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::path::Path;
+
+use eyre::WrapErr;
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::sync::actions::SyncAction;
+use crate::commands::sync::summary::group_actions_by_repo;
+use crate::data_model::status::{ComputedStatus, ComputedStatusEntry, CorrespondingSpecRepoStatus};
+use crate::errors::YbResult;
+use crate::spec::Spec;
+
+/// The commit a spec repo is (or would be) at, for build provenance
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct RepoHead {
+    name: String,
+    /// None if it can't be determined, e.g. the repo hasn't been cloned yet
+    head: Option<String>,
+    refspec: String,
+    /// Whether the sync changed (or would change) the repo, e.g. by cloning it, switching its
+    /// branch or pulling commits, as opposed to it already being as the spec wants
+    #[serde(default)]
+    changed: bool,
+}
+
+impl RepoHead {
+    /// 'name <sha> <refspec>', with 'unknown' for an undetermined head
+    pub(crate) fn format_line(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.name,
+            self.head.as_deref().unwrap_or("unknown"),
+            self.refspec
+        )
+    }
+}
+
+fn head_commit(repo: &Repository) -> Option<String> {
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    Some(commit.id().to_string())
+}
+
+/// Names of the repos (subdirectories of `sources_dir`) whose git state `sync_actions` change.
+/// Edits to bblayers.conf or local.conf alone don't count.
+fn changed_repos(sync_actions: &[Box<dyn SyncAction>], sources_dir: &Path) -> HashSet<String> {
+    group_actions_by_repo(sync_actions, sources_dir)
+        .into_iter()
+        .filter(|group| {
+            group
+                .action_indices
+                .iter()
+                .any(|&i| sync_actions[i].descriptor().changes_repo())
+        })
+        .map(|group| group.repo)
+        .collect()
+}
+
+/// Read the HEAD of each enabled repo of `spec` (in `sources_dir`), once `sync_actions` have been
+/// applied, sorted by repo name
+pub(crate) fn current_heads(
+    spec: &Spec,
+    sources_dir: &Path,
+    sync_actions: &[Box<dyn SyncAction>],
+) -> Vec<RepoHead> {
+    let changed = changed_repos(sync_actions, sources_dir);
+    let mut heads: Vec<_> = spec
+        .enabled_repos()
+        .map(|(name, spec_repo)| RepoHead {
+            name: name.clone(),
+            head: Repository::open(sources_dir.join(name))
+                .ok()
+                .and_then(|repo| head_commit(&repo)),
+            refspec: spec_repo.refspec.clone(),
+            changed: changed.contains(name),
+        })
+        .collect();
+    heads.sort_by(|a, b| a.name.cmp(&b.name));
+    heads
+}
+
+/// Work out the HEAD each spec repo would have once `sync_actions` are applied. Repos without
+/// actions stay where they are; repos with actions end up at the spec's branch on the matching
+/// remote, if it has been fetched. Anything else (e.g. repos yet to be cloned) is unknown.
+pub(crate) fn planned_heads(
+    status: &ComputedStatus,
+    sync_actions: &[Box<dyn SyncAction>],
+    sources_dir: &Path,
+) -> Vec<RepoHead> {
+    let active_spec = match &status.active_spec {
+        Some(active_spec) => active_spec,
+        None => return vec![],
+    };
+    let changed = changed_repos(sync_actions, sources_dir);
+
+    let mut heads = vec![];
+    for (name, spec_repo) in active_spec.spec.enabled_repos() {
+        let repo_status = status.source_dirs.iter().find_map(|entry| match entry {
+            ComputedStatusEntry::OnDiskRepo(repo_status)
+                if repo_status
+                    .corresponding_spec_repo
+                    .as_ref()
+                    .map_or(false, |corresponding| {
+                        corresponding.spec_repo_name() == *name
+                    }) =>
+            {
+                Some(repo_status)
+            }
+            _ => None,
+        });
+
+        let head = match repo_status {
+            None => None,
+            Some(repo_status) => {
+                let has_actions = sync_actions
+                    .iter()
+                    .any(|action| action.target_path().starts_with(&repo_status.path));
+                match (&repo_status.corresponding_spec_repo, has_actions) {
+                    (_, false) => head_commit(&repo_status.repo),
+                    (Some(CorrespondingSpecRepoStatus::RemoteMatch(remote_match)), true) => {
+                        repo_status
+                            .repo
+                            .revparse_single(&remote_match.remote_tracking_branch.to_string())
+                            .and_then(|object| object.peel_to_commit())
+                            .ok()
+                            .map(|commit| commit.id().to_string())
+                    }
+                    _ => None,
+                }
+            }
+        };
+
+        heads.push(RepoHead {
+            name: name.clone(),
+            head,
+            refspec: spec_repo.refspec.clone(),
+            changed: changed.contains(name),
+        });
+    }
+
+    heads.sort_by(|a, b| a.name.cmp(&b.name));
+    heads
+}
+
+/// Read a report written by 'yb sync --print-heads --json', mapping each repo name to the commit
+/// it recorded (None if it was unknown)
+pub(crate) fn read_heads_report(path: &Path) -> YbResult<BTreeMap<String, Option<String>>> {
+    let f = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let heads: Vec<RepoHead> = serde_json::from_reader(f)
+        .with_context(|| format!("failed to parse heads report {}", path.display()))?;
+    Ok(heads
+        .into_iter()
+        .map(|head| (head.name, head.head))
+        .collect())
+}
+
+/// Print `heads` one per line, or as a JSON array. With `only_changed`, repos the sync left as
+/// they were are left out.
+pub(crate) fn print_heads(heads: &[RepoHead], json: bool, only_changed: bool) -> YbResult<()> {
+    let heads: Vec<&RepoHead> = heads
+        .iter()
+        .filter(|head| head.changed || !only_changed)
+        .collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&heads)?);
+    } else {
+        for head in heads {
+            println!("{}", head.format_line());
+        }
+    }
+
+    Ok(())
+}
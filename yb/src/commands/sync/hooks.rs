@@ -0,0 +1,29 @@
+use std::process::Command;
+
+use eyre::WrapErr;
+
+use crate::errors::YbResult;
+use crate::yb_env::YbEnv;
+
+/// Run the hook `command` via `sh -c` from the root of `yb_env`, with its output streamed to the
+/// terminal. The yb root, build, and sources directories are exposed to the hook as YB_ROOT,
+/// YB_BUILD_DIR, and YB_SOURCES_DIR.
+pub fn run_hook(yb_env: &YbEnv, hook_name: &str, command: &str) -> YbResult<()> {
+    let root = yb_env.root_dir().parent().unwrap();
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(root)
+        .env("YB_ROOT", root)
+        .env("YB_BUILD_DIR", yb_env.build_dir())
+        .env("YB_SOURCES_DIR", yb_env.sources_dir())
+        .status()
+        .with_context(|| format!("failed to launch {hook_name} hook"))?;
+
+    if !status.success() {
+        eyre::bail!("{} hook '{}' failed ({})", hook_name, command, status);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+
+use crate::errors::YbResult;
+use crate::util::expand::expand_url;
+
+/// How a spec repo URL is mapped onto a mirror's base URL
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MirrorRule {
+    /// https://host/group/repo.git -> <base>/group/repo.git
+    Path,
+    /// https://host/group/repo.git -> <base>/host/group/repo.git
+    HostPath,
+    /// https://host/group/repo.git -> <base>/repo.git
+    Name,
+}
+
+/// A mirror to clone from instead of the spec repo URLs, for a single sync
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Mirror {
+    base: String,
+    rule: MirrorRule,
+}
+
+impl Mirror {
+    pub fn new(base: String, rule: MirrorRule) -> Self {
+        Self { base, rule }
+    }
+
+    /// The URL to clone `url` (already expanded) from
+    pub fn rewrite(&self, url: &str) -> YbResult<String> {
+        let base = expand_url(&self.base)?;
+        let (host, path) = split_host_and_path(url);
+        let path = path.trim_start_matches('/');
+        let relative = match self.rule {
+            MirrorRule::Path => path.to_string(),
+            MirrorRule::HostPath if host.is_empty() => path.to_string(),
+            MirrorRule::HostPath => format!("{host}/{path}"),
+            MirrorRule::Name => path.rsplit('/').next().unwrap_or(path).to_string(),
+        };
+
+        Ok(format!("{}/{}", base.trim_end_matches('/'), relative))
+    }
+}
+
+/// Split a git URL into host (without user or port) and path. Handles 'scheme://' URLs, scp-like
+/// 'user@host:path' URLs and local paths (which have no host).
+fn split_host_and_path(url: &str) -> (&str, &str) {
+    let strip_user_and_port = |authority: &str| -> &str {
+        let host = authority.rsplit('@').next().unwrap_or(authority);
+        host.split(':').next().unwrap_or(host)
+    };
+
+    if let Some((_, rest)) = url.split_once("://") {
+        return match rest.find('/') {
+            Some(slash) => (strip_user_and_port(&rest[..slash]), &rest[slash..]),
+            None => (strip_user_and_port(rest), ""),
+        };
+    }
+
+    match url.split_once(':') {
+        Some((authority, path)) if !authority.contains('/') => {
+            (strip_user_and_port(authority), path)
+        }
+        _ => ("", url),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::sync::mirror::{Mirror, MirrorRule};
+
+    #[test]
+    fn rewrite_rules() {
+        let rewrite = |rule, url| {
+            Mirror::new("/srv/mirror/".to_string(), rule)
+                .rewrite(url)
+                .unwrap()
+        };
+
+        let url = "https://github.com/agherzan/meta-raspberrypi.git";
+        assert_eq!(
+            rewrite(MirrorRule::Path, url),
+            "/srv/mirror/agherzan/meta-raspberrypi.git"
+        );
+        assert_eq!(
+            rewrite(MirrorRule::HostPath, url),
+            "/srv/mirror/github.com/agherzan/meta-raspberrypi.git"
+        );
+        assert_eq!(
+            rewrite(MirrorRule::Name, url),
+            "/srv/mirror/meta-raspberrypi.git"
+        );
+
+        assert_eq!(
+            rewrite(MirrorRule::HostPath, "git@example.com:foo/meta-foo.git"),
+            "/srv/mirror/example.com/foo/meta-foo.git"
+        );
+        assert_eq!(
+            rewrite(
+                MirrorRule::Path,
+                "ssh://git@example.com:2222/foo/meta-foo.git"
+            ),
+            "/srv/mirror/foo/meta-foo.git"
+        );
+        assert_eq!(
+            rewrite(MirrorRule::Name, "/home/yb/upstreams/meta-foo"),
+            "/srv/mirror/meta-foo"
+        );
+    }
+}
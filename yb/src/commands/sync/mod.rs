@@ -1,35 +1,64 @@
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
+use std::path::{Path, PathBuf};
 
 use async_trait::async_trait;
 
+use color_eyre::Help;
 use console::Style;
+use dialoguer::Confirm;
 use git2::Repository;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 
 use crate::commands::activate::activate_spec;
+use crate::commands::status::warn_duplicate_workdir_skipped;
+use crate::commands::sync::actions::plan::{SyncActionDescriptor, SyncPlan};
 use crate::commands::sync::actions::{
-    BBLayersEditAction, CheckoutBranchSyncAction, CloneRepoSyncAction,
-    CreateLocalTrackingBranchSyncAction, FastForwardPullSyncAction, ModifyBBLayersConfSyncAction,
-    ResetGitWorkdirSyncAction, SyncAction,
+    BBLayersEditAction, CheckoutStrategy, LimitedCloner, SyncAction,
 };
+use crate::commands::sync::clone_size::{find_oversized_clones, format_size, parse_size};
+use crate::commands::sync::compat::{check_layers_compat, poky_release_codenames};
+use crate::commands::sync::events::SyncEvent;
+use crate::commands::sync::heads::{current_heads, planned_heads, print_heads, read_heads_report};
+use crate::commands::sync::hooks::run_hook;
+use crate::commands::sync::mirror::{Mirror, MirrorRule};
+use crate::commands::sync::planner::{plan_sync, SyncPlanEvent, SyncPlanOptions};
+use crate::commands::sync::prefetch::{prefetch_urls, spec_clone_urls};
+use crate::commands::sync::repo_filter::{read_repo_list, RepoFilter};
+use crate::commands::sync::summary::{format_summary_line, group_actions_by_repo, RepoActionGroup};
+use crate::commands::sync::verify::verify_status;
 use crate::commands::SubcommandRunner;
 use crate::config::Config;
 use crate::core::tool_context::require_yb_env;
-use crate::data_model::git::{
-    determine_optimal_checkout_branch, RemoteTrackingBranch, UpstreamComparison,
-};
-use crate::data_model::status::{ComputedStatusEntry, CorrespondingSpecRepoStatus};
-use crate::errors::YbResult;
+use crate::data_model::status::{ComputedStatus, ComputedStatusEntry, CorrespondingSpecRepoStatus};
+use crate::errors::{YbError, YbResult};
+use crate::spec::{Spec, SpecDiff};
 use crate::status_calculator::{compute_status, StatusCalculatorEvent, StatusCalculatorOptions};
 use crate::ui_ops::check_broken_streams::{
     ui_op_check_broken_streams, UiCheckBrokenStreamsOptions,
 };
 use crate::ui_ops::update_stream::{ui_op_update_stream, UiUpdateStreamOptions};
-use crate::util::git;
+use crate::util::expand::expand_url;
+use crate::util::fetch_limit::{FetchLimiter, DEFAULT_PARALLEL_FETCH_LIMIT};
+use crate::util::git::{
+    create_backup_ref, get_remote_for_current_branch, validate_shallow_since_date,
+};
 use crate::util::indicatif::MultiProgressHelpers;
+use crate::yb_env::{ActiveSpecStatus, YbEnv};
 use concurrent_git_pool::PoolHelper;
 
-mod actions;
+pub mod actions;
+mod clone_size;
+mod compat;
+mod events;
+mod heads;
+mod hooks;
+pub mod mirror;
+pub mod planner;
+mod prefetch;
+pub mod repo_filter;
+pub(crate) mod summary;
+mod verify;
 
 /// Analyze the yb environment and determine what needs to be done so that it matches the active spec.
 ///
@@ -45,16 +74,289 @@ pub struct SyncCommand {
     #[clap(long, short)]
     force: bool,
 
+    /// Before resetting a repo or switching its branch, save its HEAD in a ref under
+    /// refs/yb/backup/ (with a reflog entry), so it can be recovered afterwards
+    #[clap(long)]
+    reflog_note: bool,
+
+    /// Never reset dirty working directories; skip repos with local changes instead
+    #[clap(long, conflicts_with = "force")]
+    no_reset: bool,
+
+    /// Also remove layers from bblayers.conf that aren't part of the spec. That is confirmed
+    /// interactively first (or with the global --yes).
     #[clap(long, short)]
     exact: bool,
+
+    /// Reconcile repos that share history with a spec repo but don't have its remote, by adding
+    /// the spec remote and switching to a branch tracking it. Otherwise such repos are skipped.
+    /// This is also how to follow a spec repo whose URL changed (e.g. the repo moved).
+    #[clap(long, visible_alias = "create-missing-remotes-from-spec")]
+    allow_unrelated: bool,
+
+    /// For each repo that shares history with a spec repo but has a different remote URL, ask
+    /// whether to accept that URL as an alias of the spec repo's, after which such repos are
+    /// synced like any other. With --apply, accepted aliases are saved in .yb/config.toml
+    /// ('url_aliases') so that later syncs don't ask again.
+    #[clap(long)]
+    prompt_related: bool,
+
+    /// How to check out the spec's branch in repos that are cloned or not on it: 'track' checks
+    /// out a local branch tracking it, 'detach' checks it out with a detached HEAD and 'reset'
+    /// hard-resets the local branch of the same name to it (which requires --force if that
+    /// drops commits). Dirty repos are reset (or skipped with --no-reset) first either way.
+    #[clap(long, value_enum, default_value = "track", value_name = "STRATEGY")]
+    checkout_strategy: CheckoutStrategy,
+
+    /// Once the repos are reconciled, run 'git clean -dfx' in each of them to delete untracked
+    /// and ignored files, e.g. stale generated files that confuse bitbake after switching
+    /// layers. Anything else in such files is lost too, so this requires --force.
+    #[clap(long)]
+    clean_ignored: bool,
+
+    /// Hard-reset branches that are ahead of or diverged from the spec's branch back to it, so
+    /// the tree matches the spec exactly. Local commits are dropped (but saved as with
+    /// --reflog-note), so this requires --force.
+    #[clap(long)]
+    force_refspec: bool,
+
+    /// Once changes are applied (with -a or --apply-plan), check that the environment matches
+    /// the active spec and fail if it doesn't
+    #[clap(long)]
+    verify: bool,
+
+    /// Only sync the given spec repo (may be repeated)
+    #[clap(long, value_name = "REPO")]
+    only: Vec<String>,
+
+    /// Only sync the spec repos listed (one per line) in the given file. Blank lines and lines
+    /// starting with '#' are ignored.
+    #[clap(long, value_name = "FILE")]
+    repos_from: Option<PathBuf>,
+
+    /// Prefer the given remote when several remotes of a repo match a spec repo (may be repeated;
+    /// earlier takes precedence). Also configurable via 'preferred_remotes' in .yb/config.toml.
+    #[clap(long, value_name = "REMOTE")]
+    prefer_remote: Vec<String>,
+
+    /// Print a one-line summary per affected repo instead of the full list of actions
+    #[clap(long)]
+    summary: bool,
+
+    /// Save the computed sync plan to the given file, for later use with --apply-plan
+    #[clap(long, value_name = "FILE")]
+    dump_plan: Option<PathBuf>,
+
+    /// Apply a sync plan previously saved with --dump-plan, instead of computing a new one
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = &[
+            "spec",
+            "dump-plan",
+            "only",
+            "repos-from",
+            "since-spec",
+            "reclone",
+            "prompt-related"
+        ]
+    )]
+    apply_plan: Option<PathBuf>,
+
+    /// Clone missing repos with only the history after the given date (YYYY-MM-DD, optionally
+    /// followed by HH:MM[:SS])
+    #[clap(long, value_name = "DATE")]
+    shallow_since: Option<String>,
+
+    /// Refuse to clone repos bigger than SIZE (e.g. '500M' or '2G'), listing them. Git can't tell
+    /// how big a remote repo is, so each is sized with a depth-1 probe clone, which undercounts
+    /// repos with long histories.
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    max_clone_size: Option<u64>,
+
+    /// Clone missing repos from the given mirror instead of their spec URLs, for this sync only.
+    /// Afterwards 'origin' points at the spec URL, so later fetches go upstream.
+    #[clap(long, value_name = "BASE_URL")]
+    mirror: Option<String>,
+
+    /// How spec repo URLs are mapped onto the --mirror base URL
+    #[clap(long, value_enum, default_value = "path", requires = "mirror")]
+    mirror_rule: MirrorRule,
+
+    /// Fail instead of fast-forwarding a repo that is more than this many commits behind its
+    /// upstream branch
+    #[clap(long, value_name = "N")]
+    max_behind: Option<usize>,
+
+    /// Annotate the plan with how the active spec's repos differ from those of the given spec
+    /// (e.g. the previously active one). Only affects what is reported, not the actions.
+    #[clap(long, value_name = "SPEC")]
+    since_spec: Option<String>,
+
+    /// Remove the given spec repo and clone it again from scratch (may be repeated). Any local
+    /// work in it is lost, so this requires --force.
+    #[clap(long, value_name = "REPO")]
+    reclone: Vec<String>,
+
+    /// Before gathering status, start having the git pool clone every spec repo into its cache,
+    /// so that clones are quicker when actions are applied. Only useful with a git pool.
+    #[clap(long)]
+    prefetch: bool,
+
+    /// Run at most this many fetches and clones at once, e.g. to spare a proxy or stay within a
+    /// server's rate limits. Also configurable via 'parallel_fetch_limit' in .yb/config.toml.
+    #[clap(long, value_name = "N")]
+    parallel_fetch_limit: Option<usize>,
+
+    /// Don't scan working directories for local changes; treat every repo as clean. Faster for
+    /// large trees, but dirty repos are neither reset nor skipped.
+    #[clap(long)]
+    assume_clean: bool,
+
+    /// Don't count untracked files as local changes (e.g. build artifacts or editor files), so
+    /// they don't make a repo dirty and get it reset
+    #[clap(long)]
+    ignore_untracked: bool,
+
+    /// Leave out (with a warning) source dirs that resolve to the same git workdir as another
+    /// source dir, e.g. a stray nested clone or a symlink, instead of failing
+    #[clap(long)]
+    skip_duplicate_workdirs: bool,
+
+    /// Only reconcile bblayers.conf with the spec's layers (see also --exact), taking the repos
+    /// as they are: nothing is cloned, checked out, pulled or reset
+    #[clap(long, conflicts_with_all = &["reclone", "apply-plan"])]
+    layers_only: bool,
+
+    /// Only reconcile the repos with the spec, leaving bblayers.conf alone (e.g. when managing
+    /// it by hand)
+    #[clap(long, conflicts_with = "layers-only")]
+    git_only: bool,
+
+    /// Don't refresh the active spec's stream first; sync to the spec as it currently is
+    #[clap(long)]
+    no_update_stream: bool,
+
+    /// Don't list the specs that changed when the stream moved since the last run
+    #[clap(long)]
+    no_stream_diff: bool,
+
+    /// Write the active spec's 'local_conf' settings (e.g. MACHINE) to conf/yb-local.conf in the
+    /// build directory, and make sure local.conf includes it
+    #[clap(long)]
+    write_local_conf: bool,
+
+    /// Print 'name <sha> <refspec>' for each spec repo: the HEAD it ended up at or, without
+    /// --apply, the HEAD it would end up at (where that can be determined)
+    #[clap(long)]
+    print_heads: bool,
+
+    /// Print --print-heads output as a JSON array instead
+    #[clap(long, requires = "print-heads")]
+    json: bool,
+
+    /// Leave repos that are already as the spec wants out of --print-heads output, listing only
+    /// those the sync clones, switches to another branch or pulls commits into
+    #[clap(long, requires = "print-heads")]
+    report_only_changed: bool,
+
+    /// Reproduce the state recorded in a report written by 'yb sync --print-heads --json': once
+    /// synced (or cloned), each repo in it is checked out at its recorded commit with a detached
+    /// HEAD, whatever the spec's refspec
+    #[clap(long, value_name = "FILE", conflicts_with_all = &["apply-plan", "layers-only"])]
+    from_report: Option<PathBuf>,
+
+    /// Instead of progress bars, print progress to stdout as newline-delimited JSON events, each
+    /// with a 'type' (e.g. 'plan', 'action_started', 'action_finished')
+    #[clap(
+        long,
+        conflicts_with_all = &["summary", "since-spec", "print-heads", "verify"]
+    )]
+    json_events: bool,
+
+    /// Without --apply, exit with status 2 if any actions would be taken, and 0 if the
+    /// environment is already in sync (e.g. to detect drift in CI)
+    #[clap(long, conflicts_with_all = &["apply", "apply-plan"])]
+    dry_run_exit_code: bool,
+
+    /// Afterwards, warn about enabled layers whose LAYERSERIES_COMPAT doesn't include the Yocto
+    /// release of poky (the poky_dir, or else sources/poky)
+    #[clap(long)]
+    check_compat: bool,
+
+    /// Fail instead of warning when --check-compat finds incompatible layers
+    #[clap(long, requires = "check-compat")]
+    strict: bool,
 }
 
 #[async_trait]
 impl SubcommandRunner for SyncCommand {
     async fn run(&self, config: &mut Config, mp: &MultiProgress) -> YbResult<()> {
+        if self.json_events {
+            mp.set_draw_target(ProgressDrawTarget::hidden());
+        }
+
         ui_op_check_broken_streams(UiCheckBrokenStreamsOptions::new(config, mp))?;
 
+        if let Some(shallow_since) = &self.shallow_since {
+            validate_shallow_since_date(shallow_since)?;
+        }
+
+        if self.verify && !self.apply && self.apply_plan.is_none() {
+            return Err(eyre::eyre!(
+                "--verify checks the environment once changes are applied, so it needs -a"
+            )
+            .suggestion("re-run with -a (or --apply-plan)")
+            .suppress_backtrace(true));
+        }
+
         let mut yb_env = require_yb_env(config)?;
+        let fetch_limiter = self.fetch_limiter(&yb_env)?;
+
+        // Hold the lock for the rest of the command if the environment is going to be modified
+        let _lock = if self.apply || self.spec.is_some() || self.apply_plan.is_some() {
+            Some(yb_env.lock()?)
+        } else {
+            None
+        };
+
+        if let Some(plan_path) = &self.apply_plan {
+            // Apply exactly what was planned, without recomputing status
+            let sync_actions = SyncPlan::load(plan_path)?.into_actions()?;
+            self.emit(SyncEvent::plan(&sync_actions));
+            let summary_groups = if self.summary {
+                group_actions_by_repo(&sync_actions, &yb_env.sources_dir())
+            } else {
+                vec![]
+            };
+            self.apply_actions(
+                config,
+                &yb_env,
+                mp,
+                &fetch_limiter,
+                &sync_actions,
+                &summary_groups,
+            )
+            .await?;
+            self.print_applied_heads(&yb_env, &sync_actions)?;
+
+            if self.check_compat {
+                check_compat_env(config, &yb_env, mp, self.strict)?;
+            }
+
+            if self.verify {
+                verify_env(config, mp, &self.prefer_remote)?;
+            }
+
+            return Ok(());
+        }
+
+        // Look up the spec to compare against before activating anything, so a typo fails early
+        let since_spec = self
+            .since_spec
+            .as_ref()
+            .map(|name| yb_env.find_spec(name).cloned())
+            .transpose()?;
 
         if let Some(spec_name) = &self.spec {
             // TODO: don't immediately activate. Use current spec and desired spec to better calculate
@@ -62,12 +364,28 @@ impl SubcommandRunner for SyncCommand {
             activate_spec(&mut yb_env, spec_name)?;
         }
 
-        if yb_env.active_spec_status().is_none() {
-            eyre::bail!("cannot sync unless a spec is active - see the 'yb activate' command");
+        let active_spec = match yb_env.active_spec_status() {
+            Some(ActiveSpecStatus::Active(active_spec)) => Some(active_spec),
+            Some(ActiveSpecStatus::StreamsBroken(..)) => None,
+            None => {
+                eyre::bail!("cannot sync unless a spec is active - see the 'yb activate' command")
+            }
+        };
+
+        if let (true, Some(active_spec)) = (self.prefetch, active_spec) {
+            self.start_prefetch(mp, &fetch_limiter, &active_spec.spec)
+                .await?;
         }
 
-        let update_stream_opts = UiUpdateStreamOptions::new(config, mp);
-        ui_op_update_stream(update_stream_opts)?;
+        if !self.no_update_stream {
+            let mut update_stream_opts = UiUpdateStreamOptions::new(config, mp);
+            update_stream_opts.stream_diff(!self.no_stream_diff);
+            ui_op_update_stream(update_stream_opts)?;
+        }
+
+        if self.assume_clean {
+            mp.warn("--assume-clean was passed: local changes in repos will not be detected");
+        }
 
         if self.apply {
             mp.note("gathering status\n\n");
@@ -75,11 +393,567 @@ impl SubcommandRunner for SyncCommand {
             mp.warn("gathering status only - will not modify environment (pass the -a flag to apply changes)\n\n");
         }
 
+        let mut status = self.gather_status(config, mp, &fetch_limiter, HashMap::new())?;
+        if self.prompt_related {
+            let accepted = resolve_related_repos(mp, &status, config.assume_yes)?;
+            if !accepted.is_empty() {
+                if self.apply {
+                    // The environment's lock is held when applying
+                    for (spec_url, aliases) in &accepted {
+                        for alias in aliases {
+                            yb_env
+                                .user_conf_mut()
+                                .add_url_alias(spec_url.clone(), alias.clone());
+                        }
+                    }
+                    yb_env.save_user_conf()?;
+                } else {
+                    mp.note("accepted aliases are only saved with -a");
+                }
+
+                // Newly accepted aliases change which repos correspond to spec repos
+                status = self.gather_status(config, mp, &fetch_limiter, accepted)?;
+            }
+        }
+
+        let repo_filter = self.repo_filter(&status)?;
+        check_poky_available(&yb_env, &status, repo_filter.as_ref(), self.layers_only)?;
+
+        let pinned_heads = match &self.from_report {
+            Some(report) => pinned_heads_from_report(mp, report, &status)?,
+            None => BTreeMap::new(),
+        };
+
+        let mut plan_options = SyncPlanOptions::new(yb_env.sources_dir());
+        plan_options
+            .repo_filter(repo_filter)
+            .allow_unrelated(self.allow_unrelated)
+            .no_reset(self.no_reset)
+            .exact(self.exact)
+            .shallow_since(self.shallow_since.clone())
+            .max_behind(self.max_behind)
+            .reclone(self.reclone.clone())
+            .write_local_conf(self.write_local_conf)
+            .layers_only(self.layers_only)
+            .git_only(self.git_only)
+            .checkout_strategy(self.checkout_strategy)
+            .clean_ignored(self.clean_ignored)
+            .force_refspec(self.force_refspec)
+            .pinned_heads(pinned_heads)
+            .mirror(self.mirror());
+        let mut reclone_discards_changes = false;
+        let sync_actions = plan_sync(&status, plan_options, |event| match event {
+            SyncPlanEvent::UnknownRepoSkipped(path) => {
+                if self.json_events {
+                    self.emit(SyncEvent::RepoSkipped {
+                        path,
+                        reason: "not a spec repo",
+                    });
+                } else {
+                    mp.note(format!("skipped {}: not a spec repo", path.display()));
+                }
+            }
+            SyncPlanEvent::RepoSkipped { path, reason } => {
+                self.emit(SyncEvent::RepoSkipped {
+                    path,
+                    reason: &reason,
+                });
+                mp.warn(format!("{} {reason}", path.display()))
+            }
+            SyncPlanEvent::RepoRecloned {
+                path,
+                has_local_changes,
+            } => {
+                let lost = if has_local_changes {
+                    "its local changes will be lost"
+                } else {
+                    "any local work in it (e.g. unpushed commits) will be lost"
+                };
+                mp.warn(format!(
+                    "{} will be removed and cloned again; {lost}",
+                    path.display()
+                ));
+                reclone_discards_changes |= has_local_changes;
+            }
+        })?;
+
+        if self.apply && !self.reclone.is_empty() && !self.force {
+            let what = if reclone_discards_changes {
+                "repos with local changes"
+            } else {
+                "repos"
+            };
+            return Err(eyre::eyre!("refusing to reclone {what} without --force")
+                .suggestion("re-run with --force if losing local work is OK")
+                .suppress_backtrace(true));
+        }
+
+        if let Some(max_clone_size) = self.max_clone_size {
+            let oversized = find_oversized_clones(&sync_actions, max_clone_size)?;
+            for (url, size) in &oversized {
+                mp.warn(format!(
+                    "{url} is at least {}, more than --max-clone-size ({})",
+                    format_size(*size),
+                    format_size(max_clone_size)
+                ));
+            }
+            if !oversized.is_empty() {
+                return Err(eyre::eyre!(
+                    "refusing to clone {} repo(s) bigger than --max-clone-size",
+                    oversized.len()
+                )
+                .suggestion("raise --max-clone-size, or leave it out to clone them anyway")
+                .suppress_backtrace(true));
+            }
+        }
+
+        if self.clean_ignored {
+            mp.warn(
+                "--clean-ignored deletes all untracked and ignored files in the synced repos, \
+                 including any work kept in them",
+            );
+            if self.apply && !self.force {
+                return Err(eyre::eyre!("refusing to clean repos without --force")
+                    .suggestion("re-run with --force if losing untracked files is OK")
+                    .suppress_backtrace(true));
+            }
+        }
+
+        if let (Some(since_spec), Some(active_spec)) = (&since_spec, &status.active_spec) {
+            // On stderr, so that it doesn't get mixed up with a --print-heads report
+            for line in format_spec_transitions(since_spec, &active_spec.spec) {
+                mp.suspend(|| eprintln!("{line}"));
+            }
+        }
+
+        // TODO backup bblayers.conf before apply
+
+        let summary_groups = if self.summary {
+            group_actions_by_repo(&sync_actions, &yb_env.sources_dir())
+        } else {
+            if self.json_events {
+                self.emit(SyncEvent::plan(&sync_actions));
+            } else if !(self.print_heads && self.json) {
+                // Leave stdout to the heads report, so that it can be saved for --from-report
+                println!("actions: {sync_actions:#?}");
+            }
+            vec![]
+        };
+
+        if let Some(plan_path) = &self.dump_plan {
+            SyncPlan::new(&sync_actions).save(plan_path)?;
+            mp.note(format!("sync plan written to {}", plan_path.display()));
+        }
+
+        if self.apply {
+            self.apply_actions(
+                config,
+                &yb_env,
+                mp,
+                &fetch_limiter,
+                &sync_actions,
+                &summary_groups,
+            )
+            .await?;
+            self.print_applied_heads(&yb_env, &sync_actions)?;
+        } else {
+            if self.print_heads {
+                print_heads(
+                    &planned_heads(&status, &sync_actions, &yb_env.sources_dir()),
+                    self.json,
+                    self.report_only_changed,
+                )?;
+            }
+
+            for group in &summary_groups {
+                println!("{}", format_summary_line(group, &sync_actions, None));
+            }
+
+            if !sync_actions.is_empty() {
+                mp.warn("none of these changes have been applied - re-run with -a to apply")
+            }
+        }
+
+        if self.check_compat {
+            check_compat_env(config, &yb_env, mp, self.strict)?;
+        }
+
+        if self.verify {
+            verify_env(config, mp, &self.prefer_remote)?;
+        }
+
+        if self.dry_run_exit_code && !sync_actions.is_empty() {
+            return Err(YbError::OutOfSync {
+                actions: sync_actions.len(),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Describe how the repos of `to` differ from those of `from`, one line per transition
+fn format_spec_transitions(from: &Spec, to: &Spec) -> Vec<String> {
+    let diff = SpecDiff::new(from, to);
+    if diff.is_empty() {
+        return vec![format!("no repo changes since spec '{}'", from.name())];
+    }
+
+    let mut ret = vec![format!("changes since spec '{}':", from.name())];
+    ret.extend(
+        diff.transitions()
+            .iter()
+            .map(|transition| format!("\t{transition}")),
+    );
+    ret
+}
+
+/// The URL of the remote a related repo was cloned from: the current branch's remote, or else
+/// 'origin'
+fn related_repo_remote_url(repo: &Repository) -> YbResult<Option<String>> {
+    let remote = match get_remote_for_current_branch(repo)? {
+        Some(remote) => Some(remote),
+        None => repo.find_remote("origin").ok(),
+    };
+    Ok(remote.and_then(|remote| remote.url().map(str::to_string)))
+}
+
+/// Ask, for each related repo in `status`, whether its remote URL should be accepted as an alias
+/// of the spec repo's URL. With `assume_yes` every alias is accepted; otherwise, without a
+/// terminal to ask on, nothing is asked. Returns the accepted aliases, keyed by spec repo URL.
+fn resolve_related_repos(
+    mp: &MultiProgress,
+    status: &ComputedStatus,
+    assume_yes: bool,
+) -> YbResult<HashMap<String, Vec<String>>> {
+    let mut accepted_aliases: HashMap<String, Vec<String>> = HashMap::new();
+    for entry in &status.source_dirs {
+        let repo_status = match entry {
+            ComputedStatusEntry::OnDiskRepo(repo_status) => repo_status,
+            ComputedStatusEntry::OnDiskNonRepo(_) => continue,
+        };
+        let spec_repo = match &repo_status.corresponding_spec_repo {
+            Some(CorrespondingSpecRepoStatus::RelatedRepo { spec_repo, .. }) => spec_repo,
+            _ => continue,
+        };
+        let remote_url = match related_repo_remote_url(&repo_status.repo)? {
+            Some(remote_url) => remote_url,
+            None => continue,
+        };
+        let spec_url = expand_url(&spec_repo.url)?;
+
+        if assume_yes {
+            mp.note(format!(
+                "treating {remote_url} as an alias for {spec_url} (--yes)"
+            ));
+            accepted_aliases
+                .entry(spec_url)
+                .or_default()
+                .push(remote_url);
+            continue;
+        }
+
+        if !console::user_attended_stderr() {
+            mp.note(format!(
+                "not asking about {} without a terminal to ask on",
+                repo_status.path.display()
+            ));
+            continue;
+        }
+
+        let accepted = mp.suspend(|| -> YbResult<bool> {
+            Confirm::new()
+                .with_prompt(format!("treat {remote_url} as an alias for {spec_url}?"))
+                .default(false)
+                .wait_for_newline(true)
+                .interact()
+                .map_err(|e| e.into())
+        })?;
+        if accepted {
+            accepted_aliases
+                .entry(spec_url)
+                .or_default()
+                .push(remote_url);
+        }
+    }
+
+    Ok(accepted_aliases)
+}
+
+/// The commits recorded in the heads report at `report` for the active spec's enabled repos.
+/// Repos the report has no commit for, or that aren't enabled in the spec, are warned about and
+/// left out.
+fn pinned_heads_from_report(
+    mp: &MultiProgress,
+    report: &Path,
+    status: &ComputedStatus,
+) -> YbResult<BTreeMap<String, String>> {
+    let mut pinned_heads = BTreeMap::new();
+    for (name, head) in read_heads_report(report)? {
+        let in_spec = status.active_spec.as_ref().map_or(false, |active_spec| {
+            active_spec
+                .spec
+                .enabled_repos()
+                .any(|(repo_name, _)| *repo_name == name)
+        });
+        match head {
+            _ if !in_spec => mp.warn(format!(
+                "{} has repo '{name}', which the active spec doesn't have enabled; ignoring it",
+                report.display()
+            )),
+            None => mp.warn(format!(
+                "{} has no commit for repo '{name}'; leaving it at the spec's refspec",
+                report.display()
+            )),
+            Some(head) => {
+                pinned_heads.insert(name, head);
+            }
+        }
+    }
+
+    Ok(pinned_heads)
+}
+
+/// Layers may have been added to bblayers.conf by hand for local work, so make sure the user
+/// really wants them gone. Fails if the user declines or there is no terminal to ask on.
+fn confirm_layer_removal(mp: &MultiProgress, layers: &[PathBuf]) -> YbResult<()> {
+    mp.warn("the following layers will be removed from bblayers.conf:");
+    for layer in layers {
+        mp.suspend(|| eprintln!("    {}", layer.display()));
+    }
+
+    if !console::user_attended_stderr() {
+        return Err(
+            eyre::eyre!("refusing to remove layers without confirmation")
+                .suggestion("re-run with --yes to remove them non-interactively")
+                .suppress_backtrace(true),
+        );
+    }
+
+    let confirmed = mp.suspend(|| -> YbResult<bool> {
+        Confirm::new()
+            .with_prompt("Remove these layers?")
+            .wait_for_newline(true)
+            .interact()
+            .map_err(|e| e.into())
+    })?;
+    if !confirmed {
+        eyre::bail!("not removing layers; nothing was applied");
+    }
+
+    Ok(())
+}
+
+impl SyncCommand {
+    /// Apply `sync_actions` in order, running the sync hooks around them.
+    async fn apply_actions(
+        &self,
+        config: &Config,
+        yb_env: &YbEnv,
+        mp: &MultiProgress,
+        fetch_limiter: &FetchLimiter,
+        sync_actions: &[Box<dyn SyncAction>],
+        summary_groups: &[RepoActionGroup],
+    ) -> YbResult<()> {
+        let forced_actions = sync_actions
+            .iter()
+            .filter(|action| action.is_force_required())
+            .map(|action| format!("{} in {}", action.summary(), action.target_path().display()))
+            .collect::<Vec<_>>();
+        if !forced_actions.is_empty() && !self.force {
+            return Err(
+                eyre::eyre!("refusing to {} without --force", forced_actions.join(", "))
+                    .suggestion("re-run with --force if losing local work is OK")
+                    .suppress_backtrace(true),
+            );
+        }
+
+        let removed_layers = sync_actions
+            .iter()
+            .filter_map(|action| match action.descriptor() {
+                SyncActionDescriptor::ModifyBBLayersConf {
+                    layer_path,
+                    edit: BBLayersEditAction::RemoveLayer,
+                    ..
+                } => Some(layer_path),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if !removed_layers.is_empty() && !config.assume_yes {
+            confirm_layer_removal(mp, &removed_layers)?;
+        }
+
+        if let Some(pre_sync) = &yb_env.user_conf().hooks().pre_sync {
+            mp.note("running pre_sync hook");
+            run_hook(yb_env, "pre_sync", pre_sync)?;
+        }
+
+        if !self.json_events {
+            println!();
+        }
+        let progress = mp.add(
+            ProgressBar::new(sync_actions.len() as u64).with_style(
+                ProgressStyle::with_template("{msg} [{wide_bar}] {pos}/{len}")
+                    .unwrap()
+                    .progress_chars("##-"),
+            ),
+        );
+        progress.set_message("applying actions");
+
+        let client = LimitedCloner::new(
+            PoolHelper::connect_or_local().await.unwrap(),
+            fetch_limiter.clone(),
+        );
+        let mut applied = 0;
+        let mut apply_result = Ok(());
+        let total = sync_actions.len();
+        for (index, action) in sync_actions.iter().enumerate() {
+            self.emit(SyncEvent::ActionStarted {
+                index,
+                total,
+                action: action.descriptor(),
+            });
+            apply_result = match self.back_up_before(mp, &action.descriptor()) {
+                Ok(()) => action.apply(&client).await,
+                Err(err) => Err(err),
+            };
+            self.emit(SyncEvent::ActionFinished {
+                index,
+                total,
+                ok: apply_result.is_ok(),
+                error: apply_result.as_ref().err().map(|err| err.to_string()),
+            });
+            if apply_result.is_err() {
+                break;
+            }
+            applied += 1;
+            progress.inc(1);
+        }
+
+        for group in summary_groups {
+            let outcome = if group.action_indices.iter().all(|&i| i < applied) {
+                Some(true)
+            } else if group.action_indices.contains(&applied) && apply_result.is_err() {
+                Some(false)
+            } else {
+                None
+            };
+            println!("{}", format_summary_line(group, sync_actions, outcome));
+        }
+
+        apply_result?;
+
+        // The sync has been applied at this point even if the hook fails
+        if let Some(post_sync) = &yb_env.user_conf().hooks().post_sync {
+            mp.note("running post_sync hook");
+            run_hook(yb_env, "post_sync", post_sync)?;
+        }
+
+        Ok(())
+    }
+
+    /// Start warming the git pool's cache with the repos of `spec`, in the background. The
+    /// actions applied later clone through the same pool, which waits for in-flight clones.
+    async fn start_prefetch(
+        &self,
+        mp: &MultiProgress,
+        fetch_limiter: &FetchLimiter,
+        spec: &Spec,
+    ) -> YbResult<()> {
+        let pool = PoolHelper::connect_or_local().await.unwrap();
+        if !pool.is_pooled() {
+            mp.warn("--prefetch has no effect without a git pool (see CONCURRENT_GIT_POOL)");
+            return Ok(());
+        }
+
+        let urls = spec_clone_urls(spec, self.mirror().as_ref());
+        mp.note(format!("prefetching {} spec repos", urls.len()));
+        let cloner = LimitedCloner::new(pool, fetch_limiter.clone());
+        tokio::spawn(async move { prefetch_urls(&cloner, &urls).await });
+
+        Ok(())
+    }
+
+    /// Limit the number of concurrent fetches and clones to --parallel-fetch-limit, or else
+    /// 'parallel_fetch_limit' from .yb/config.toml
+    fn fetch_limiter(&self, yb_env: &YbEnv) -> YbResult<FetchLimiter> {
+        let limit = self
+            .parallel_fetch_limit
+            .or_else(|| yb_env.user_conf().parallel_fetch_limit())
+            .unwrap_or(DEFAULT_PARALLEL_FETCH_LIMIT);
+        if limit == 0 {
+            eyre::bail!("the parallel fetch limit must be at least 1");
+        }
+        Ok(FetchLimiter::new(limit))
+    }
+
+    /// With --reflog-note (or --force-refspec), save the HEAD of the repo that `action` is about
+    /// to reset or switch away from in a backup ref
+    fn back_up_before(&self, mp: &MultiProgress, action: &SyncActionDescriptor) -> YbResult<()> {
+        if !self.reflog_note && !self.force_refspec {
+            return Ok(());
+        }
+
+        let (repo_path, what) = match action {
+            SyncActionDescriptor::ResetGitWorkdir { repo_path } => (repo_path, "reset"),
+            SyncActionDescriptor::CheckoutBranch { repo_path, .. }
+            | SyncActionDescriptor::DetachHead { repo_path, .. } => (repo_path, "checkout"),
+            SyncActionDescriptor::ResetBranch { repo_path, .. } => (repo_path, "reset-branch"),
+            _ => return Ok(()),
+        };
+        if let Some(backup_ref) = create_backup_ref(repo_path, what)? {
+            mp.note(format!(
+                "saved the HEAD of {} as {backup_ref}",
+                repo_path.display()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// With --print-heads, print the HEAD each spec repo ended up at after `sync_actions`
+    fn print_applied_heads(
+        &self,
+        yb_env: &YbEnv,
+        sync_actions: &[Box<dyn SyncAction>],
+    ) -> YbResult<()> {
+        if !self.print_heads {
+            return Ok(());
+        }
+
+        match yb_env.active_spec_status() {
+            Some(ActiveSpecStatus::Active(active_spec)) => print_heads(
+                &current_heads(&active_spec.spec, &yb_env.sources_dir(), sync_actions),
+                self.json,
+                self.report_only_changed,
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Compute the status of the environment, showing progress. `url_aliases` are accepted on top
+    /// of those in .yb/config.toml.
+    fn gather_status(
+        &self,
+        config: &Config,
+        mp: &MultiProgress,
+        fetch_limiter: &FetchLimiter,
+        url_aliases: HashMap<String, Vec<String>>,
+    ) -> YbResult<ComputedStatus> {
         let mut overall_progress: Option<ProgressBar> = None;
 
-        let status_calculator_options = StatusCalculatorOptions::new(config, false, false);
+        let mut status_calculator_options = StatusCalculatorOptions::new(config, false, false);
+        status_calculator_options
+            .preferred_remotes(self.prefer_remote.clone())
+            .url_aliases(url_aliases)
+            .assume_clean(self.assume_clean)
+            .skip_duplicate_workdirs(self.skip_duplicate_workdirs)
+            .ignore_untracked(self.ignore_untracked)
+            .fetch_limiter(fetch_limiter.clone());
         let status = compute_status(status_calculator_options, |event| match event {
             StatusCalculatorEvent::Start { number_subdirs, .. } => {
+                self.emit(SyncEvent::StatusStarted { number_subdirs });
                 overall_progress.replace(
                     mp.add(
                         ProgressBar::new(number_subdirs)
@@ -92,220 +966,215 @@ impl SubcommandRunner for SyncCommand {
                     ),
                 );
             }
-            StatusCalculatorEvent::StartProcessSubdir { dirname } => overall_progress
-                .as_ref()
-                .unwrap()
-                .set_message(format!("checking {dirname}")),
+            StatusCalculatorEvent::StartProcessSubdir { dirname } => {
+                self.emit(SyncEvent::CheckingSubdir { dirname: &dirname });
+                overall_progress
+                    .as_ref()
+                    .unwrap()
+                    .set_message(format!("checking {dirname}"))
+            }
             StatusCalculatorEvent::FinishProcessSubdir => overall_progress.as_ref().unwrap().inc(1),
+            StatusCalculatorEvent::DuplicateWorkdirSkipped { path, workdir } => {
+                warn_duplicate_workdir_skipped(mp, path, workdir)
+            }
             _ => {}
         })?;
 
         drop(overall_progress);
 
-        let mut sync_actions: Vec<Box<dyn SyncAction>> = vec![];
-
-        for status_data in status.source_dirs.iter() {
-            let subdir = status_data.path();
+        Ok(status)
+    }
 
-            if let ComputedStatusEntry::OnDiskRepo(status_data) = status_data {
-                if !status_data.has_corresponding_spec_repo() {
-                    println!("skipped {:?}", &subdir);
-                    continue;
-                }
+    /// With --json-events, print `event`
+    fn emit(&self, event: SyncEvent) {
+        if self.json_events {
+            event.emit();
+        }
+    }
 
-                if status_data.is_workdir_dirty {
-                    sync_actions.push(Box::new(ResetGitWorkdirSyncAction::new(
-                        status_data.path.clone(),
-                    )))
-                }
+    fn mirror(&self) -> Option<Mirror> {
+        self.mirror
+            .clone()
+            .map(|base| Mirror::new(base, self.mirror_rule))
+    }
 
-                match &status_data.corresponding_spec_repo {
-                    Some(corresponding_spec_repo_status) => match &corresponding_spec_repo_status {
-                        CorrespondingSpecRepoStatus::RelatedRepo { spec_repo, .. } => {
-                            println!(
-                                "{}",
-                                Style::new().red().on_white().apply_to(format!(
-                                    "{} shares commits with spec repo {}",
-                                    status_data.path.display(),
-                                    spec_repo.url
-                                ))
-                            );
-                            panic!();
-                        }
-                        CorrespondingSpecRepoStatus::RemoteMatch(remote_match) => {
-                            if status_data.is_local_branch_tracking_correct_branch() {
-                                let upstream_comparison = status_data
-                                    .current_branch_status
-                                    .upstream_branch_status
-                                    .as_ref()
-                                    .unwrap()
-                                    .upstream_comparison;
-                                match upstream_comparison {
-                                    UpstreamComparison::UpToDate => {}
-                                    UpstreamComparison::Behind(_) => {
-                                        sync_actions.push(Box::new(
-                                            FastForwardPullSyncAction::new(
-                                                status_data.path.clone(),
-                                            ),
-                                        ));
-                                    }
-                                    UpstreamComparison::Ahead(_) => {
-                                        let msg = format!("{} is ahead of remote and I don't know what to do about it", status_data.path.display());
-                                        mp.error(msg);
-                                        panic!();
-                                    }
-                                    UpstreamComparison::Diverged { .. } => unimplemented!(),
-                                }
-                            } else if remote_match.local_branches_tracking_remote.is_empty() {
-                                let new_local_branch_name =
-                                    determine_local_branch_name_for_checkout(
-                                        &status_data.repo,
-                                        &remote_match.spec_repo.refspec,
-                                    )?;
-
-                                sync_actions.push(Box::new(
-                                    CreateLocalTrackingBranchSyncAction::new(
-                                        status_data.path.clone(),
-                                        new_local_branch_name.clone(),
-                                        RemoteTrackingBranch {
-                                            branch_name: remote_match.spec_repo.refspec.clone(),
-                                            remote_name: remote_match.matching_remote_name.clone(),
-                                        },
-                                    ),
-                                ));
-
-                                sync_actions.push(Box::new(CheckoutBranchSyncAction::new(
-                                    status_data.path.clone(),
-                                    new_local_branch_name.clone(),
-                                )));
-
-                                sync_actions.push(Box::new(FastForwardPullSyncAction::new(
-                                    status_data.path.clone(),
-                                )));
-                            } else {
-                                let optimal_branch = determine_optimal_checkout_branch(
-                                    &remote_match.local_branches_tracking_remote,
-                                )
-                                .unwrap();
-
-                                sync_actions.push(Box::new(CheckoutBranchSyncAction::new(
-                                    status_data.path.clone(),
-                                    optimal_branch.local_tracking_branch.branch_name.clone(),
-                                )));
-
-                                match optimal_branch.upstream_comparison {
-                                    UpstreamComparison::UpToDate => {}
-                                    UpstreamComparison::Behind(_) => {
-                                        sync_actions.push(Box::new(
-                                            FastForwardPullSyncAction::new(
-                                                status_data.path.clone(),
-                                            ),
-                                        ));
-                                    }
-                                    UpstreamComparison::Ahead(_ahead) => {
-                                        // TODO: suggest pushing changes?
-                                    }
-                                    UpstreamComparison::Diverged { .. } => unimplemented!(),
-                                }
-                            }
-                        }
-                    },
-                    None => {
-                        // TODO
-                    }
-                }
-            }
+    /// Build the filter from --only and --repos-from, or None if neither was given.
+    fn repo_filter(&self, status: &ComputedStatus) -> YbResult<Option<RepoFilter>> {
+        if self.only.is_empty() && self.repos_from.is_none() {
+            return Ok(None);
         }
 
-        for repo in &status.missing_repos {
-            let dest = yb_env.sources_dir().join(repo.name.clone());
-            sync_actions.push(Box::new(CloneRepoSyncAction::new(
-                dest.clone(),
-                repo.spec_repo.clone(),
-            )));
-
-            // TODO add action to temporary clone the repo and precheck that the expected layers
-            //  actually exist?
-            for layer in repo.spec_repo.resolved_layers(dest) {
-                for layer in layer {
-                    sync_actions.push(Box::new(ModifyBBLayersConfSyncAction::new(
-                        layer.path,
-                        status.bblayers_path.clone(),
-                        BBLayersEditAction::AddLayer,
-                    )));
-                }
-            }
+        let mut repos = self.only.clone();
+        if let Some(repos_from) = &self.repos_from {
+            repos.extend(read_repo_list(repos_from)?);
         }
 
-        // This doesn't include layers for missing spec repos - that is handled above
-        for layer in status.missing_bblayers_layers_for_extant_spec_repos() {
-            sync_actions.push(Box::new(ModifyBBLayersConfSyncAction::new(
-                layer.path,
-                status.bblayers_path.clone(),
-                BBLayersEditAction::AddLayer,
-            )));
-        }
+        let valid_repos = status
+            .active_spec
+            .as_ref()
+            .map(|active_spec| {
+                active_spec
+                    .spec
+                    .enabled_repos()
+                    .map(|(name, _)| name)
+                    .collect()
+            })
+            .unwrap_or_default();
 
-        if self.exact {
-            for layer in status.extraneous_bblayers_layers() {
-                sync_actions.push(Box::new(ModifyBBLayersConfSyncAction::new(
-                    layer.path,
-                    status.bblayers_path.clone(),
-                    BBLayersEditAction::RemoveLayer,
-                )));
-            }
+        RepoFilter::new(repos, &valid_repos).map(Some)
+    }
+}
 
-            // TODO workspace layer
-        }
+/// Whether a spec repo looks like poky, going by its name or the last component of its URL
+fn is_poky_like(name: &str, url: &str) -> bool {
+    let url_name = url
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    name == "poky" || url_name.trim_end_matches(".git") == "poky"
+}
 
-        // TODO backup bblayers.conf before apply
+/// Fail early if the active spec has poky, but poky is neither at the poky_dir of yb.yaml nor in
+/// the sources dir, and this sync isn't going to clone it either. Syncing the rest of the spec
+/// without poky would leave a confusingly half-usable environment.
+fn check_poky_available(
+    yb_env: &YbEnv,
+    status: &ComputedStatus,
+    repo_filter: Option<&RepoFilter>,
+    layers_only: bool,
+) -> YbResult<()> {
+    if yb_env
+        .poky_dir()
+        .map_or(false, |poky_dir| poky_dir.is_dir())
+    {
+        return Ok(());
+    }
 
-        println!("actions: {sync_actions:#?}");
+    let missing_poky = match status
+        .missing_repos
+        .iter()
+        .find(|missing| is_poky_like(&missing.name, &missing.spec_repo.url))
+    {
+        Some(missing_poky) => missing_poky,
+        None => return Ok(()),
+    };
 
-        if self.apply {
-            if sync_actions.iter().any(|action| action.is_force_required()) && !self.force {
-                mp.warn("need to pass --force flag to apply one or more actions");
-                panic!();
-            }
+    let will_clone =
+        !layers_only && repo_filter.map_or(true, |filter| filter.contains(&missing_poky.name));
+    if will_clone {
+        return Ok(());
+    }
 
-            println!();
-            let progress = mp.add(
-                ProgressBar::new(sync_actions.len() as u64).with_style(
-                    ProgressStyle::with_template("{msg} [{wide_bar}] {pos}/{len}")
-                        .unwrap()
-                        .progress_chars("##-"),
-                ),
-            );
-            progress.set_message("applying actions");
+    let why = if layers_only {
+        "--layers-only was passed"
+    } else {
+        "it isn't selected"
+    };
+    Err(eyre::eyre!(
+        "the active spec needs poky (spec repo '{}'), but it isn't in {} and yb.yaml has no \
+         poky_dir; it won't be cloned because {why}",
+        missing_poky.name,
+        yb_env.sources_dir().display()
+    )
+    .suggestion(format!(
+        "run a sync that includes '{}' first (e.g. 'yb sync -a --only {}')",
+        missing_poky.name, missing_poky.name
+    ))
+    .suppress_backtrace(true))
+}
 
-            let client = PoolHelper::connect_or_local().await.unwrap();
-            for action in sync_actions {
-                action.apply(&client).await?;
-                progress.inc(1);
-            }
-        } else if !sync_actions.is_empty() {
-            mp.warn("none of these changes have been applied - re-run with -a to apply")
+/// Check that the layers enabled in the environment (or requested by the active spec) support
+/// poky's Yocto release, warning about those that don't or, if `strict`, failing
+fn check_compat_env(
+    config: &Config,
+    yb_env: &YbEnv,
+    mp: &MultiProgress,
+    strict: bool,
+) -> YbResult<()> {
+    let poky_dir = yb_env
+        .poky_dir()
+        .unwrap_or_else(|| yb_env.sources_dir().join("poky"));
+    let codenames = match poky_release_codenames(&poky_dir) {
+        Some(codenames) => codenames,
+        None => {
+            mp.warn(format!(
+                "not checking layer compatibility: couldn't determine the Yocto release of {}",
+                poky_dir.display()
+            ));
+            return Ok(());
         }
+    };
 
-        Ok(())
+    let status = compute_status(StatusCalculatorOptions::new(config, true, false), |_| {})?;
+    let mut layers: Vec<_> = status
+        .enabled_layers
+        .union(&status.spec_requested_layers())
+        .cloned()
+        .collect();
+    layers.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let problems = check_layers_compat(&layers, &codenames);
+    for problem in &problems {
+        mp.warn(problem);
+    }
+
+    if strict && !problems.is_empty() {
+        eyre::bail!(
+            "{} layer(s) are not compatible with the '{}' release",
+            problems.len(),
+            codenames.join(" ")
+        );
     }
+
+    Ok(())
 }
 
-fn determine_local_branch_name_for_checkout(
-    repo: &Repository,
-    local_branch_name: &str,
-) -> YbResult<String> {
-    if !git::local_branch_exists(repo, local_branch_name)? {
-        return Ok(local_branch_name.to_string());
+/// Check that the environment matches the active spec, failing if it doesn't
+fn verify_env(config: &Config, mp: &MultiProgress, preferred_remotes: &[String]) -> YbResult<()> {
+    mp.note("verifying environment");
+    let mut status_calculator_options = StatusCalculatorOptions::new(config, true, false);
+    status_calculator_options.preferred_remotes(preferred_remotes.to_vec());
+    let status = compute_status(status_calculator_options, |_| {})?;
+    let problems = verify_status(&status);
+    for problem in &problems {
+        println!(
+            "{}: {}",
+            Style::from_dotted_str("red.bold").apply_to("FAIL"),
+            problem
+        );
     }
 
-    // TODO smarter way
-    for i in 2..10 {
-        let next_try = format!("{local_branch_name}-{i}");
-        if !git::local_branch_exists(repo, &next_try)? {
-            return Ok(next_try);
-        }
+    if !problems.is_empty() {
+        eyre::bail!("verification failed with {} problem(s)", problems.len());
     }
 
-    unimplemented!("exhausted possible local branch candidates");
+    println!(
+        "{}",
+        Style::from_dotted_str("green.bold").apply_to("verification passed")
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::sync::is_poky_like;
+
+    #[test]
+    fn poky_like_repos_are_recognized() {
+        assert!(is_poky_like("poky", "https://example.com/whatever.git"));
+        assert!(is_poky_like(
+            "yocto",
+            "https://git.yoctoproject.org/git/poky.git"
+        ));
+        assert!(is_poky_like(
+            "yocto",
+            "https://github.com/yoctoproject/poky/"
+        ));
+        assert!(!is_poky_like(
+            "meta-poky-extras",
+            "https://example.com/meta-poky-extras"
+        ));
+    }
 }
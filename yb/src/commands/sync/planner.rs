@@ -0,0 +1,1087 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use color_eyre::Help;
+use git2::{BranchType, ErrorCode, Repository};
+
+use crate::commands::sync::actions::{
+    AddRemoteSyncAction, BBLayersEditAction, CheckoutBranchSyncAction, CheckoutStrategy,
+    CleanRepoSyncAction, CloneRepoSyncAction, CreateLocalTrackingBranchSyncAction,
+    DetachHeadSyncAction, FastForwardPullSyncAction, ModifyBBLayersConfSyncAction,
+    RemoveRepoSyncAction, ResetBranchSyncAction, ResetGitWorkdirSyncAction, SyncAction,
+    WriteLocalConfSyncAction,
+};
+use crate::commands::sync::mirror::Mirror;
+use crate::commands::sync::repo_filter::RepoFilter;
+use crate::data_model::git::{
+    determine_optimal_checkout_branch, RemoteTrackingBranch, UpstreamComparison,
+};
+use crate::data_model::status::{ComputedStatus, ComputedStatusEntry, CorrespondingSpecRepoStatus};
+use crate::errors::{YbError, YbResult};
+use crate::util::git;
+
+/// Options controlling how `plan_sync` reconciles the environment with the active spec
+#[derive(Debug)]
+pub struct SyncPlanOptions {
+    sources_dir: PathBuf,
+    repo_filter: Option<RepoFilter>,
+    allow_unrelated: bool,
+    no_reset: bool,
+    exact: bool,
+    shallow_since: Option<String>,
+    mirror: Option<Mirror>,
+    max_behind: Option<usize>,
+    reclone: Vec<String>,
+    write_local_conf: bool,
+    layers_only: bool,
+    git_only: bool,
+    checkout_strategy: CheckoutStrategy,
+    clean_ignored: bool,
+    force_refspec: bool,
+    pinned_heads: BTreeMap<String, String>,
+}
+
+impl SyncPlanOptions {
+    /// `sources_dir` is where missing repos get cloned to
+    pub fn new(sources_dir: PathBuf) -> Self {
+        Self {
+            sources_dir,
+            repo_filter: None,
+            allow_unrelated: false,
+            no_reset: false,
+            exact: false,
+            shallow_since: None,
+            mirror: None,
+            max_behind: None,
+            reclone: vec![],
+            write_local_conf: false,
+            layers_only: false,
+            git_only: false,
+            checkout_strategy: CheckoutStrategy::default(),
+            clean_ignored: false,
+            force_refspec: false,
+            pinned_heads: BTreeMap::new(),
+        }
+    }
+
+    /// Only plan actions for the selected spec repos (and their layers)
+    pub fn repo_filter(&mut self, repo_filter: Option<RepoFilter>) -> &mut Self {
+        self.repo_filter = repo_filter;
+        self
+    }
+
+    /// Reconcile repos that share history with a spec repo but lack its remote
+    pub fn allow_unrelated(&mut self, val: bool) -> &mut Self {
+        self.allow_unrelated = val;
+        self
+    }
+
+    /// Skip repos with local changes rather than resetting them
+    pub fn no_reset(&mut self, val: bool) -> &mut Self {
+        self.no_reset = val;
+        self
+    }
+
+    /// Also remove layers from bblayers.conf that the spec doesn't ask for
+    pub fn exact(&mut self, val: bool) -> &mut Self {
+        self.exact = val;
+        self
+    }
+
+    /// Clone missing repos with only the history after the given date
+    pub fn shallow_since(&mut self, shallow_since: Option<String>) -> &mut Self {
+        self.shallow_since = shallow_since;
+        self
+    }
+
+    /// Clone missing repos from the given mirror rather than from their spec URLs
+    pub fn mirror(&mut self, mirror: Option<Mirror>) -> &mut Self {
+        self.mirror = mirror;
+        self
+    }
+
+    /// Fail rather than fast-forward a repo that is more than this many commits behind
+    pub fn max_behind(&mut self, max_behind: Option<usize>) -> &mut Self {
+        self.max_behind = max_behind;
+        self
+    }
+
+    /// Remove the given spec repos and clone them again from scratch, whatever their state
+    pub fn reclone(&mut self, reclone: Vec<String>) -> &mut Self {
+        self.reclone = reclone;
+        self
+    }
+
+    /// Write the spec's `local_conf` settings to the managed fragment included from local.conf
+    pub fn write_local_conf(&mut self, val: bool) -> &mut Self {
+        self.write_local_conf = val;
+        self
+    }
+
+    /// Take the repos as they are and only reconcile bblayers.conf (and local.conf); no git
+    /// actions are planned
+    pub fn layers_only(&mut self, val: bool) -> &mut Self {
+        self.layers_only = val;
+        self
+    }
+
+    /// Only reconcile the repos; bblayers.conf is left alone
+    pub fn git_only(&mut self, val: bool) -> &mut Self {
+        self.git_only = val;
+        self
+    }
+
+    /// How to check out the spec's refspec in repos that are cloned or on the wrong branch
+    pub fn checkout_strategy(&mut self, checkout_strategy: CheckoutStrategy) -> &mut Self {
+        self.checkout_strategy = checkout_strategy;
+        self
+    }
+
+    /// Reset branches that are ahead of or diverged from the spec's branch to it, dropping the
+    /// local commits, rather than leaving them be (ahead) or failing (diverged)
+    pub fn force_refspec(&mut self, val: bool) -> &mut Self {
+        self.force_refspec = val;
+        self
+    }
+
+    /// Once the named spec repos are reconciled (or cloned), check out the given commit in each
+    /// of them with a detached HEAD, whatever the spec's refspec
+    pub fn pinned_heads(&mut self, pinned_heads: BTreeMap<String, String>) -> &mut Self {
+        self.pinned_heads = pinned_heads;
+        self
+    }
+
+    /// Once the repos are reconciled, delete the untracked and ignored files in each of them
+    pub fn clean_ignored(&mut self, val: bool) -> &mut Self {
+        self.clean_ignored = val;
+        self
+    }
+}
+
+/// Fail if a repo is more than `max_behind` (if given) commits behind `upstream`. Such a big gap
+/// more likely means a misconfiguration than something to blindly fast-forward.
+pub(crate) fn check_max_behind(
+    path: &Path,
+    behind: usize,
+    upstream: &RemoteTrackingBranch,
+    max_behind: Option<usize>,
+) -> YbResult<()> {
+    match max_behind {
+        Some(max_behind) if behind > max_behind => Err(eyre::eyre!(
+            "{} is {} commits behind '{}', more than the maximum of {}",
+            path.display(),
+            behind,
+            upstream.to_string(),
+            max_behind
+        )
+        .suggestion("check that the spec and remote are correct, or raise --max-behind")),
+        _ => Ok(()),
+    }
+}
+
+pub enum SyncPlanEvent<'a> {
+    /// A repo that doesn't correspond to any spec repo was left alone
+    UnknownRepoSkipped(&'a Path),
+    /// A spec repo was left alone; `reason` says why (and how to change that)
+    RepoSkipped { path: &'a Path, reason: String },
+    /// A repo will be removed and cloned again, losing any local work in it
+    RepoRecloned {
+        path: &'a Path,
+        has_local_changes: bool,
+    },
+}
+
+/// Work out the actions needed to make the environment described by `status` match its active
+/// spec. Nothing is applied.
+pub fn plan_sync<F>(
+    status: &ComputedStatus,
+    opts: SyncPlanOptions,
+    mut c: F,
+) -> YbResult<Vec<Box<dyn SyncAction>>>
+where
+    F: FnMut(SyncPlanEvent),
+{
+    let repo_filter = &opts.repo_filter;
+    let is_selected = |repo_name: &str| {
+        repo_filter
+            .as_ref()
+            .map_or(true, |filter| filter.contains(repo_name))
+    };
+    // Layers are selected by the repo (i.e. sources subdirectory) they live in. Repo paths in
+    // the status are canonicalized but bblayers.conf paths may not be, so check both.
+    let sources_dir = opts.sources_dir.clone();
+    let sources_dirs = vec![
+        sources_dir
+            .canonicalize()
+            .unwrap_or_else(|_| sources_dir.clone()),
+        sources_dir,
+    ];
+    let is_layer_selected = |layer_path: &PathBuf| {
+        repo_filter.is_none()
+            || sources_dirs.iter().any(|sources_dir| {
+                layer_path
+                    .strip_prefix(sources_dir)
+                    .ok()
+                    .and_then(|relative| relative.iter().next())
+                    .and_then(|repo_name| repo_name.to_str())
+                    .map_or(false, |repo_name| is_selected(repo_name))
+            })
+    };
+
+    let spec_repos = status
+        .active_spec
+        .as_ref()
+        .map(|active_spec| &active_spec.spec.repos);
+    for name in &opts.reclone {
+        let is_enabled = status.active_spec.as_ref().map_or(false, |active_spec| {
+            active_spec
+                .spec
+                .enabled_repos()
+                .any(|(repo_name, _)| repo_name == name)
+        });
+        if !is_enabled {
+            return Err(eyre::eyre!(
+                "cannot reclone '{}': no such spec repo, or it is disabled",
+                name
+            )
+            .suppress_backtrace(true));
+        }
+    }
+
+    let mut sync_actions: Vec<Box<dyn SyncAction>> = vec![];
+    let mut reconciled_repos = vec![];
+    let mut pin_actions: Vec<Box<dyn SyncAction>> = vec![];
+    let mut pin = |name: &str, path: &Path| {
+        if let Some(commit) = opts.pinned_heads.get(name) {
+            pin_actions.push(Box::new(DetachHeadSyncAction::new(
+                path.to_path_buf(),
+                commit.clone(),
+            )));
+        }
+    };
+
+    let source_dirs: &[ComputedStatusEntry] = if opts.layers_only {
+        &[]
+    } else {
+        &status.source_dirs
+    };
+    for status_data in source_dirs.iter() {
+        let subdir = status_data.path();
+
+        if let ComputedStatusEntry::OnDiskRepo(status_data) = status_data {
+            // Recloning goes by directory name rather than by remote, since the repo may be too
+            // wedged for its remote to be recognized
+            let reclone_name = subdir
+                .file_name()
+                .and_then(|name| name.to_str())
+                .filter(|name| opts.reclone.iter().any(|reclone| reclone == name));
+            if let (Some(name), Some(spec_repos)) = (reclone_name, spec_repos) {
+                if !is_selected(name) {
+                    continue;
+                }
+
+                c(SyncPlanEvent::RepoRecloned {
+                    path: &status_data.path,
+                    has_local_changes: status_data.is_workdir_dirty,
+                });
+                sync_actions.push(Box::new(RemoveRepoSyncAction::new(
+                    status_data.path.clone(),
+                )));
+                sync_actions.push(Box::new(
+                    CloneRepoSyncAction::new(status_data.path.clone(), spec_repos[name].clone())
+                        .shallow_since(opts.shallow_since.clone())
+                        .mirror(opts.mirror.clone())
+                        .checkout_strategy(opts.checkout_strategy),
+                ));
+                pin(name, &status_data.path);
+                continue;
+            }
+
+            if !status_data.has_corresponding_spec_repo() {
+                c(SyncPlanEvent::UnknownRepoSkipped(subdir));
+                continue;
+            }
+
+            if !is_selected(
+                &status_data
+                    .corresponding_spec_repo
+                    .as_ref()
+                    .unwrap()
+                    .spec_repo_name(),
+            ) {
+                continue;
+            }
+
+            if let Some(CorrespondingSpecRepoStatus::RelatedRepo { spec_repo, .. }) =
+                &status_data.corresponding_spec_repo
+            {
+                if !opts.allow_unrelated {
+                    c(SyncPlanEvent::RepoSkipped {
+                        path: &status_data.path,
+                        reason: format!("shares commits with spec repo {}, but the remote is wrong - skipping (pass --allow-unrelated to reconcile it)", spec_repo.url),
+                    });
+                    continue;
+                }
+            }
+
+            if status_data.is_workdir_dirty {
+                if opts.no_reset {
+                    c(SyncPlanEvent::RepoSkipped {
+                        path: &status_data.path,
+                        reason: "has local changes - skipping (--no-reset was passed)".to_string(),
+                    });
+                    continue;
+                }
+
+                sync_actions.push(Box::new(ResetGitWorkdirSyncAction::new(
+                    status_data.path.clone(),
+                )))
+            }
+            reconciled_repos.push(status_data.path.clone());
+            pin(
+                &status_data
+                    .corresponding_spec_repo
+                    .as_ref()
+                    .unwrap()
+                    .spec_repo_name(),
+                &status_data.path,
+            );
+
+            if let (false, Some(CorrespondingSpecRepoStatus::RemoteMatch(remote_match))) = (
+                opts.checkout_strategy.is_track(),
+                &status_data.corresponding_spec_repo,
+            ) {
+                sync_actions.extend(plan_strategy_checkout(
+                    &status_data.repo,
+                    &status_data.path,
+                    &remote_match.remote_tracking_branch,
+                    opts.checkout_strategy,
+                )?);
+                continue;
+            }
+
+            match &status_data.corresponding_spec_repo {
+                Some(corresponding_spec_repo_status) => match &corresponding_spec_repo_status {
+                    CorrespondingSpecRepoStatus::RelatedRepo {
+                        spec_repo,
+                        spec_repo_name,
+                    } => {
+                        // Add the spec's remote and switch to a branch tracking it
+                        let remote_name =
+                            determine_remote_name_for_spec_repo(&status_data.repo, spec_repo_name)?;
+                        sync_actions.push(Box::new(AddRemoteSyncAction::new(
+                            status_data.path.clone(),
+                            remote_name.clone(),
+                            spec_repo.url.clone(),
+                        )));
+
+                        let remote_tracking_branch = RemoteTrackingBranch {
+                            branch_name: spec_repo.refspec.clone(),
+                            remote_name,
+                        };
+                        if opts.checkout_strategy == CheckoutStrategy::Detach {
+                            // The remote hasn't been fetched yet, so there's nothing to compare
+                            sync_actions.push(Box::new(DetachHeadSyncAction::new(
+                                status_data.path.clone(),
+                                remote_tracking_branch.to_string(),
+                            )));
+                            continue;
+                        }
+
+                        let new_local_branch_name = determine_local_branch_name_for_checkout(
+                            &status_data.repo,
+                            &spec_repo.refspec,
+                        )?;
+
+                        sync_actions.push(Box::new(CreateLocalTrackingBranchSyncAction::new(
+                            status_data.path.clone(),
+                            new_local_branch_name.clone(),
+                            remote_tracking_branch,
+                        )));
+
+                        sync_actions.push(Box::new(CheckoutBranchSyncAction::new(
+                            status_data.path.clone(),
+                            new_local_branch_name,
+                        )));
+                    }
+                    CorrespondingSpecRepoStatus::RemoteMatch(remote_match) => {
+                        if remote_match.local_branches_tracking_remote.is_empty() {
+                            let new_local_branch_name = determine_local_branch_name_for_checkout(
+                                &status_data.repo,
+                                &remote_match.spec_repo.refspec,
+                            )?;
+
+                            sync_actions.push(Box::new(CreateLocalTrackingBranchSyncAction::new(
+                                status_data.path.clone(),
+                                new_local_branch_name.clone(),
+                                RemoteTrackingBranch {
+                                    branch_name: remote_match.spec_repo.refspec.clone(),
+                                    remote_name: remote_match.matching_remote_name.clone(),
+                                },
+                            )));
+
+                            sync_actions.push(Box::new(CheckoutBranchSyncAction::new(
+                                status_data.path.clone(),
+                                new_local_branch_name.clone(),
+                            )));
+
+                            sync_actions.push(Box::new(FastForwardPullSyncAction::new(
+                                status_data.path.clone(),
+                                None,
+                            )));
+                        } else {
+                            let current_branch =
+                                &status_data.current_branch_status.local_branch_name;
+                            let optimal_branch = determine_optimal_checkout_branch(
+                                &remote_match.local_branches_tracking_remote,
+                                Some(current_branch.as_str()),
+                            )
+                            .unwrap();
+                            let stays_on_current_branch =
+                                optimal_branch.local_tracking_branch.branch_name == *current_branch;
+
+                            if !stays_on_current_branch {
+                                sync_actions.push(Box::new(CheckoutBranchSyncAction::new(
+                                    status_data.path.clone(),
+                                    optimal_branch.local_tracking_branch.branch_name.clone(),
+                                )));
+                            }
+
+                            match optimal_branch.upstream_comparison {
+                                UpstreamComparison::UpToDate => {}
+                                UpstreamComparison::Ahead(_)
+                                | UpstreamComparison::Diverged { .. }
+                                    if opts.force_refspec =>
+                                {
+                                    let local_tracking_branch =
+                                        &optimal_branch.local_tracking_branch;
+                                    sync_actions.push(Box::new(ResetBranchSyncAction::new(
+                                        status_data.path.clone(),
+                                        local_tracking_branch.branch_name.clone(),
+                                        local_tracking_branch.remote_tracking_branch.clone(),
+                                        true,
+                                    )));
+                                }
+                                UpstreamComparison::Behind(behind) => {
+                                    check_max_behind(
+                                        &status_data.path,
+                                        behind,
+                                        &optimal_branch
+                                            .local_tracking_branch
+                                            .remote_tracking_branch,
+                                        opts.max_behind,
+                                    )?;
+                                    sync_actions.push(Box::new(FastForwardPullSyncAction::new(
+                                        status_data.path.clone(),
+                                        Some(behind),
+                                    )));
+                                }
+                                UpstreamComparison::Ahead(_) if stays_on_current_branch => {
+                                    eyre::bail!("{} is ahead of remote and I don't know what to do about it", status_data.path.display());
+                                }
+                                UpstreamComparison::Ahead(_ahead) => {
+                                    // TODO: suggest pushing changes?
+                                }
+                                UpstreamComparison::Diverged { .. } => {
+                                    return Err(YbError::RepoDiverged {
+                                        path: status_data.path.clone(),
+                                    }
+                                    .into());
+                                }
+                                UpstreamComparison::Shallow => {
+                                    // Can't tell how far behind (if at all) a shallow repo is; a
+                                    // fast-forward pull fails if it isn't simply behind
+                                    sync_actions.push(Box::new(FastForwardPullSyncAction::new(
+                                        status_data.path.clone(),
+                                        None,
+                                    )));
+                                }
+                            }
+                        }
+                    }
+                },
+                None => {
+                    // TODO
+                }
+            }
+        }
+    }
+
+    if opts.clean_ignored {
+        sync_actions.extend(
+            reconciled_repos
+                .into_iter()
+                .map(|path| Box::new(CleanRepoSyncAction::new(path)) as Box<dyn SyncAction>),
+        );
+    }
+
+    for repo in &status.missing_repos {
+        if !is_selected(&repo.name) {
+            continue;
+        }
+
+        let dest = opts.sources_dir.join(repo.name.clone());
+        if opts.layers_only {
+            c(SyncPlanEvent::RepoSkipped {
+                path: &dest,
+                reason: "is missing - not cloning it (--layers-only was passed)".to_string(),
+            });
+            continue;
+        }
+
+        sync_actions.push(Box::new(
+            CloneRepoSyncAction::new(dest.clone(), repo.spec_repo.clone())
+                .shallow_since(opts.shallow_since.clone())
+                .mirror(opts.mirror.clone())
+                .checkout_strategy(opts.checkout_strategy),
+        ));
+        pin(&repo.name, &dest);
+
+        if opts.git_only {
+            continue;
+        }
+
+        // TODO add action to temporary clone the repo and precheck that the expected layers
+        //  actually exist?
+        for layer in repo.spec_repo.resolved_layers(dest) {
+            for layer in layer {
+                sync_actions.push(Box::new(ModifyBBLayersConfSyncAction::new(
+                    layer.path,
+                    status.bblayers_path.clone(),
+                    BBLayersEditAction::AddLayer,
+                )));
+            }
+        }
+    }
+
+    // Only once the repos they apply to are reconciled or cloned
+    sync_actions.extend(pin_actions);
+
+    if !opts.git_only {
+        // This doesn't include layers for missing spec repos - that is handled above
+        for layer in status.missing_bblayers_layers_for_extant_spec_repos() {
+            if !is_layer_selected(&layer.path) {
+                continue;
+            }
+
+            sync_actions.push(Box::new(ModifyBBLayersConfSyncAction::new(
+                layer.path,
+                status.bblayers_path.clone(),
+                BBLayersEditAction::AddLayer,
+            )));
+        }
+
+        if opts.exact {
+            for layer in status.extraneous_bblayers_layers() {
+                if !is_layer_selected(&layer.path) {
+                    continue;
+                }
+
+                sync_actions.push(Box::new(ModifyBBLayersConfSyncAction::new(
+                    layer.path,
+                    status.bblayers_path.clone(),
+                    BBLayersEditAction::RemoveLayer,
+                )));
+            }
+
+            // TODO workspace layer
+        }
+    }
+
+    if let (true, Some(active_spec)) = (opts.write_local_conf, &status.active_spec) {
+        // local.conf lives next to bblayers.conf
+        let conf_dir = status.bblayers_path.parent().unwrap().to_path_buf();
+        let action = WriteLocalConfSyncAction::new(conf_dir, active_spec.spec.local_conf.clone());
+        if !action.is_up_to_date() {
+            sync_actions.push(Box::new(action));
+        }
+    }
+
+    Ok(sync_actions)
+}
+
+/// The actions (if any) that check out `upstream` in `repo` according to `checkout_strategy`,
+/// which is either `Detach` or `Reset`. If `upstream` doesn't exist (e.g. the spec's refspec is a
+/// tag) `Detach` checks out the refspec itself.
+fn plan_strategy_checkout(
+    repo: &Repository,
+    path: &Path,
+    upstream: &RemoteTrackingBranch,
+    checkout_strategy: CheckoutStrategy,
+) -> YbResult<Vec<Box<dyn SyncAction>>> {
+    let head = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+    let upstream_commit = repo
+        .revparse_single(&upstream.to_string())
+        .and_then(|object| object.peel_to_commit())
+        .ok();
+
+    match checkout_strategy {
+        CheckoutStrategy::Track => unreachable!("tracking branches are planned by plan_sync"),
+        CheckoutStrategy::Detach => {
+            let (target, target_commit) = match upstream_commit {
+                Some(commit) => (upstream.to_string(), commit),
+                None => (
+                    upstream.branch_name.clone(),
+                    repo.revparse_single(&upstream.branch_name)?
+                        .peel_to_commit()?,
+                ),
+            };
+            if repo.head_detached()? && head.map_or(false, |head| head.id() == target_commit.id()) {
+                return Ok(vec![]);
+            }
+
+            Ok(vec![Box::new(DetachHeadSyncAction::new(
+                path.to_path_buf(),
+                target,
+            ))])
+        }
+        CheckoutStrategy::Reset => {
+            let upstream_commit = upstream_commit.ok_or_else(|| {
+                eyre::eyre!(
+                    "cannot reset a branch of {} to '{}': no such remote branch",
+                    path.display(),
+                    upstream.to_string()
+                )
+                .suggestion("use another --checkout-strategy for specs that name tags or commits")
+            })?;
+            let branch_name = &upstream.branch_name;
+
+            let local_branch = match repo.find_branch(branch_name, BranchType::Local) {
+                Ok(local_branch) => Some(local_branch),
+                Err(err) if err.code() == ErrorCode::NotFound => None,
+                Err(err) => return Err(err.into()),
+            };
+            let discards_commits = match &local_branch {
+                Some(local_branch) => {
+                    let local_commit = local_branch.get().peel_to_commit()?;
+                    local_commit.id() != upstream_commit.id()
+                        && !repo.graph_descendant_of(upstream_commit.id(), local_commit.id())?
+                }
+                None => false,
+            };
+
+            if let Some(local_branch) = &local_branch {
+                let is_current = local_branch.is_head() && !repo.head_detached()?;
+                let is_at_upstream = head.map_or(false, |head| head.id() == upstream_commit.id());
+                let tracks_upstream =
+                    git::get_remote_tracking_branch(repo, local_branch)?.as_ref() == Some(upstream);
+                if is_current && is_at_upstream && tracks_upstream {
+                    return Ok(vec![]);
+                }
+            }
+
+            Ok(vec![Box::new(ResetBranchSyncAction::new(
+                path.to_path_buf(),
+                branch_name.clone(),
+                upstream.clone(),
+                discards_commits,
+            ))])
+        }
+    }
+}
+
+fn determine_remote_name_for_spec_repo(
+    repo: &Repository,
+    spec_repo_name: &str,
+) -> YbResult<String> {
+    if !git::remote_exists(repo, spec_repo_name)? {
+        return Ok(spec_repo_name.to_string());
+    }
+
+    for i in 2..10 {
+        let next_try = format!("{spec_repo_name}-{i}");
+        if !git::remote_exists(repo, &next_try)? {
+            return Ok(next_try);
+        }
+    }
+
+    Err(eyre::eyre!(
+        "couldn't pick a name for the '{}' remote to add to {}: '{}' and '{}-2' through '{}-9' \
+         are all taken",
+        spec_repo_name,
+        repo_display_path(repo).display(),
+        spec_repo_name,
+        spec_repo_name,
+        spec_repo_name
+    )
+    .suggestion("remove some of those remotes, or add the spec's remote yourself")
+    .suppress_backtrace(true))
+}
+
+fn repo_display_path(repo: &Repository) -> &Path {
+    repo.workdir().unwrap_or_else(|| repo.path())
+}
+
+fn determine_local_branch_name_for_checkout(
+    repo: &Repository,
+    local_branch_name: &str,
+) -> YbResult<String> {
+    if !git::local_branch_exists(repo, local_branch_name)? {
+        return Ok(local_branch_name.to_string());
+    }
+
+    // TODO smarter way
+    for i in 2..10 {
+        let next_try = format!("{local_branch_name}-{i}");
+        if !git::local_branch_exists(repo, &next_try)? {
+            return Ok(next_try);
+        }
+    }
+
+    Err(eyre::eyre!(
+        "couldn't pick a name for a local '{}' branch in {}: '{}' and '{}-2' through '{}-9' are \
+         all taken",
+        local_branch_name,
+        repo_display_path(repo).display(),
+        local_branch_name,
+        local_branch_name,
+        local_branch_name
+    )
+    .suggestion("delete some of those branches")
+    .suppress_backtrace(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::path::{Path, PathBuf};
+
+    use git2::Repository;
+
+    use crate::commands::sync::actions::plan::SyncActionDescriptor;
+    use crate::commands::sync::actions::CheckoutStrategy;
+    use crate::commands::sync::planner::{
+        determine_remote_name_for_spec_repo, plan_sync, SyncPlanOptions,
+    };
+    use crate::data_model::git::{
+        BranchStatus, LocalTrackingBranch, LocalTrackingBranchWithUpstreamComparison,
+        RemoteTrackingBranch, UpstreamBranchStatus, UpstreamComparison,
+    };
+    use crate::data_model::status::{
+        ComputedStatus, ComputedStatusEntry, CorrespondingSpecRepoStatus, MissingRepo,
+        OnDiskRepoStatus, RemoteMatchStatus,
+    };
+    use crate::spec::SpecRepo;
+    use crate::util::debug_temp_dir::DebugTempDir;
+
+    fn spec_repo() -> SpecRepo {
+        SpecRepo {
+            url: "https://example.com/meta-foo.git".to_string(),
+            refspec: "main".to_string(),
+            extra_remotes: Default::default(),
+            layers: None,
+            post_clone: vec![],
+            submodules: false,
+            depth: None,
+            enabled: true,
+        }
+    }
+
+    fn origin_main() -> RemoteTrackingBranch {
+        RemoteTrackingBranch {
+            remote_name: "origin".to_string(),
+            branch_name: "main".to_string(),
+        }
+    }
+
+    fn status_with(
+        source_dirs: Vec<ComputedStatusEntry>,
+        missing_repos: Vec<MissingRepo>,
+    ) -> ComputedStatus {
+        ComputedStatus {
+            source_dirs,
+            enabled_layers: HashSet::new(),
+            missing_repos,
+            active_spec: None,
+            bblayers_path: PathBuf::from("/nonexistent/conf/bblayers.conf"),
+        }
+    }
+
+    /// An on-disk repo at `path` whose 'origin' matches `spec_repo()`. It is on `current_branch`,
+    /// which compares to its upstream (if any) as given by `upstream_comparison`;
+    /// `tracking_branches` are the local branches tracking origin/main.
+    fn matched_repo(
+        path: &Path,
+        current_branch: &str,
+        upstream_comparison: Option<UpstreamComparison>,
+        tracking_branches: Vec<(&str, UpstreamComparison)>,
+    ) -> ComputedStatusEntry {
+        ComputedStatusEntry::OnDiskRepo(OnDiskRepoStatus {
+            repo: Repository::init(path).unwrap(),
+            path: path.to_path_buf(),
+            is_workdir_dirty: false,
+            recent_commits: None,
+            submodules: None,
+            current_branch_status: BranchStatus {
+                local_branch_name: current_branch.to_string(),
+                upstream_branch_status: upstream_comparison.map(|upstream_comparison| {
+                    UpstreamBranchStatus {
+                        remote_tracking_branch: origin_main(),
+                        upstream_comparison,
+                    }
+                }),
+            },
+            corresponding_spec_repo: Some(CorrespondingSpecRepoStatus::RemoteMatch(
+                RemoteMatchStatus {
+                    is_extra_remote: false,
+                    spec_repo: spec_repo(),
+                    spec_repo_name: "meta-foo".to_string(),
+                    remote_tracking_branch: origin_main(),
+                    local_branches_tracking_remote: tracking_branches
+                        .into_iter()
+                        .map(|(branch_name, upstream_comparison)| {
+                            LocalTrackingBranchWithUpstreamComparison {
+                                local_tracking_branch: LocalTrackingBranch {
+                                    branch_name: branch_name.to_string(),
+                                    remote_tracking_branch: origin_main(),
+                                },
+                                upstream_comparison,
+                            }
+                        })
+                        .collect(),
+                    matching_remote_name: "origin".to_string(),
+                },
+            )),
+            layers: HashSet::new(),
+        })
+    }
+
+    fn planned_descriptors_with(
+        status: &ComputedStatus,
+        opts: SyncPlanOptions,
+    ) -> Vec<SyncActionDescriptor> {
+        plan_sync(status, opts, |_| {})
+            .unwrap()
+            .iter()
+            .map(|action| action.descriptor())
+            .collect()
+    }
+
+    fn planned_descriptors(
+        status: &ComputedStatus,
+        sources_dir: &Path,
+    ) -> Vec<SyncActionDescriptor> {
+        planned_descriptors_with(status, SyncPlanOptions::new(sources_dir.to_path_buf()))
+    }
+
+    #[test]
+    fn missing_repo_is_cloned() {
+        let sources_dir = PathBuf::from("/yocto/sources");
+        let status = status_with(
+            vec![],
+            vec![MissingRepo {
+                name: "meta-foo".to_string(),
+                spec_repo: spec_repo(),
+            }],
+        );
+
+        assert_eq!(
+            planned_descriptors(&status, &sources_dir),
+            vec![SyncActionDescriptor::CloneRepo {
+                dest_repo_path: sources_dir.join("meta-foo"),
+                spec_repo: spec_repo(),
+                shallow_since: None,
+                mirror: None,
+                checkout_strategy: CheckoutStrategy::Track,
+            }]
+        );
+    }
+
+    #[test]
+    fn behind_branch_is_pulled() {
+        let dir = DebugTempDir::new().unwrap();
+        let repo_path = dir.path().join("meta-foo");
+        let status = status_with(
+            vec![matched_repo(
+                &repo_path,
+                "main",
+                Some(UpstreamComparison::Behind(2)),
+                vec![("main", UpstreamComparison::Behind(2))],
+            )],
+            vec![],
+        );
+
+        assert_eq!(
+            planned_descriptors(&status, dir.path()),
+            vec![SyncActionDescriptor::FastForwardPull {
+                repo_path,
+                commits_behind: Some(2),
+            }]
+        );
+    }
+
+    #[test]
+    fn wrong_branch_gets_tracking_branch() {
+        let dir = DebugTempDir::new().unwrap();
+        let repo_path = dir.path().join("meta-foo");
+        let status = status_with(
+            vec![matched_repo(&repo_path, "feature", None, vec![])],
+            vec![],
+        );
+
+        assert_eq!(
+            planned_descriptors(&status, dir.path()),
+            vec![
+                SyncActionDescriptor::CreateLocalTrackingBranch {
+                    repo_path: repo_path.clone(),
+                    local_branch_name: "main".to_string(),
+                    remote_tracking_branch: origin_main(),
+                },
+                SyncActionDescriptor::CheckoutBranch {
+                    repo_path: repo_path.clone(),
+                    branch_name: "main".to_string(),
+                },
+                SyncActionDescriptor::FastForwardPull {
+                    repo_path,
+                    commits_behind: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn current_branch_kept_when_equally_up_to_date() {
+        let dir = DebugTempDir::new().unwrap();
+        let repo_path = dir.path().join("meta-foo");
+        let status = status_with(
+            vec![matched_repo(
+                &repo_path,
+                "work",
+                Some(UpstreamComparison::UpToDate),
+                vec![
+                    ("main", UpstreamComparison::UpToDate),
+                    ("work", UpstreamComparison::UpToDate),
+                ],
+            )],
+            vec![],
+        );
+
+        assert!(planned_descriptors(&status, dir.path()).is_empty());
+    }
+
+    #[test]
+    fn current_branch_kept_and_fast_forwarded_when_behind() {
+        let dir = DebugTempDir::new().unwrap();
+        let repo_path = dir.path().join("meta-foo");
+        let status = status_with(
+            vec![matched_repo(
+                &repo_path,
+                "work",
+                Some(UpstreamComparison::Behind(1)),
+                vec![
+                    ("main", UpstreamComparison::UpToDate),
+                    ("work", UpstreamComparison::Behind(1)),
+                ],
+            )],
+            vec![],
+        );
+
+        assert_eq!(
+            planned_descriptors(&status, dir.path()),
+            vec![SyncActionDescriptor::FastForwardPull {
+                repo_path,
+                commits_behind: Some(1),
+            }]
+        );
+    }
+
+    #[test]
+    fn most_up_to_date_branch_checked_out_when_current_is_unrelated() {
+        let dir = DebugTempDir::new().unwrap();
+        let repo_path = dir.path().join("meta-foo");
+        let status = status_with(
+            vec![matched_repo(
+                &repo_path,
+                "scratch",
+                None,
+                vec![
+                    ("work", UpstreamComparison::Behind(1)),
+                    ("main", UpstreamComparison::UpToDate),
+                ],
+            )],
+            vec![],
+        );
+
+        assert_eq!(
+            planned_descriptors(&status, dir.path()),
+            vec![SyncActionDescriptor::CheckoutBranch {
+                repo_path,
+                branch_name: "main".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn layers_only_plans_no_git_actions() {
+        let dir = DebugTempDir::new().unwrap();
+        let repo_path = dir.path().join("meta-foo");
+        let status = status_with(
+            vec![matched_repo(
+                &repo_path,
+                "main",
+                Some(UpstreamComparison::Behind(2)),
+                vec![("main", UpstreamComparison::Behind(2))],
+            )],
+            vec![MissingRepo {
+                name: "meta-bar".to_string(),
+                spec_repo: spec_repo(),
+            }],
+        );
+
+        let mut opts = SyncPlanOptions::new(dir.path().to_path_buf());
+        opts.layers_only(true);
+        assert!(planned_descriptors_with(&status, opts).is_empty());
+    }
+
+    #[test]
+    fn git_only_plans_no_layer_actions() {
+        let sources_dir = PathBuf::from("/yocto/sources");
+        let spec_repo = SpecRepo {
+            layers: Some([(".".to_string(), ())].into_iter().collect()),
+            ..spec_repo()
+        };
+        let status = status_with(
+            vec![],
+            vec![MissingRepo {
+                name: "meta-foo".to_string(),
+                spec_repo: spec_repo.clone(),
+            }],
+        );
+
+        let mut opts = SyncPlanOptions::new(sources_dir.clone());
+        opts.git_only(true);
+        assert_eq!(
+            planned_descriptors_with(&status, opts),
+            vec![SyncActionDescriptor::CloneRepo {
+                dest_repo_path: sources_dir.join("meta-foo"),
+                spec_repo,
+                shallow_since: None,
+                mirror: None,
+                checkout_strategy: CheckoutStrategy::Track,
+            }]
+        );
+    }
+
+    #[test]
+    fn exhausted_remote_names_is_an_error() {
+        let dir = DebugTempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        assert_eq!(
+            determine_remote_name_for_spec_repo(&repo, "meta-foo").unwrap(),
+            "meta-foo"
+        );
+
+        repo.remote("meta-foo", "https://example.com/meta-foo.git")
+            .unwrap();
+        for i in 2..10 {
+            repo.remote(&format!("meta-foo-{i}"), "https://example.com/meta-foo.git")
+                .unwrap();
+        }
+        let err = determine_remote_name_for_spec_repo(&repo, "meta-foo")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'meta-foo' remote"), "{err}");
+        let dir_name = dir.path().file_name().unwrap().to_str().unwrap();
+        assert!(err.contains(dir_name), "{err}");
+    }
+}
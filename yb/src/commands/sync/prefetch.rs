@@ -0,0 +1,162 @@
+use futures::future::join_all;
+
+use crate::commands::sync::actions::GitCloner;
+use crate::commands::sync::mirror::Mirror;
+use crate::spec::Spec;
+use crate::util::expand::expand_url;
+
+/// The URLs that cloning the enabled repos of `spec` would clone from, sorted and deduplicated. URLs
+/// that can't be expanded are left out; cloning them reports the problem.
+pub(crate) fn spec_clone_urls(spec: &Spec, mirror: Option<&Mirror>) -> Vec<String> {
+    let mut urls: Vec<String> = spec
+        .enabled_repos()
+        .filter_map(|(_, spec_repo)| {
+            let url = expand_url(&spec_repo.url).ok()?;
+            match mirror {
+                Some(mirror) => mirror.rewrite(&url).ok(),
+                None => Some(url),
+            }
+        })
+        .collect();
+    urls.sort();
+    urls.dedup();
+    urls
+}
+
+/// Ask `cloner` to warm its cache for each of `urls`, concurrently. Failures are ignored: they
+/// will come up again if the repo actually needs to be cloned.
+pub(crate) async fn prefetch_urls(cloner: &dyn GitCloner, urls: &[String]) {
+    let results = join_all(urls.iter().map(|url| cloner.prefetch(url))).await;
+    for (url, result) in urls.iter().zip(results) {
+        if let Err(err) = result {
+            tracing::debug!("prefetching {} failed: {}", url, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+
+    use crate::commands::sync::actions::{GitCloner, LimitedCloner};
+    use crate::commands::sync::prefetch::{prefetch_urls, spec_clone_urls};
+    use crate::errors::YbResult;
+    use crate::spec::{Spec, SpecRepo};
+    use crate::util::fetch_limit::FetchLimiter;
+
+    /// Records prefetch requests; fails for URLs containing "broken"
+    #[derive(Default)]
+    struct RecordingCloner {
+        prefetched: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl GitCloner for RecordingCloner {
+        async fn clone_in(
+            &self,
+            _uri: &str,
+            _parent_dir: Option<PathBuf>,
+            _directory: Option<String>,
+            _shallow_since: Option<String>,
+            _depth: Option<u32>,
+        ) -> YbResult<()> {
+            unreachable!("prefetching shouldn't clone anything into the environment")
+        }
+
+        async fn prefetch(&self, uri: &str) -> YbResult<()> {
+            self.prefetched.lock().unwrap().push(uri.to_string());
+            if uri.contains("broken") {
+                eyre::bail!("can't reach {}", uri);
+            }
+            Ok(())
+        }
+    }
+
+    /// Tracks how many prefetches are in flight at once, each taking a little while
+    #[derive(Default)]
+    struct ConcurrencyTrackingCloner {
+        in_flight: AtomicUsize,
+        max_in_flight: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl GitCloner for ConcurrencyTrackingCloner {
+        async fn clone_in(
+            &self,
+            _uri: &str,
+            _parent_dir: Option<PathBuf>,
+            _directory: Option<String>,
+            _shallow_since: Option<String>,
+            _depth: Option<u32>,
+        ) -> YbResult<()> {
+            unreachable!("prefetching shouldn't clone anything into the environment")
+        }
+
+        async fn prefetch(&self, _uri: &str) -> YbResult<()> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn spec_repo(url: &str) -> SpecRepo {
+        serde_yaml::from_str(&format!("url: {url}\nrefspec: main\nlayers: ~\n")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn every_spec_url_is_prefetched() {
+        let spec = Spec::new(
+            "default".to_string(),
+            HashMap::from([
+                (
+                    "poky".to_string(),
+                    spec_repo("https://example.com/poky.git"),
+                ),
+                (
+                    "meta-broken".to_string(),
+                    spec_repo("https://example.com/meta-broken.git"),
+                ),
+                (
+                    "meta-foo".to_string(),
+                    spec_repo("https://example.com/meta-foo.git"),
+                ),
+            ]),
+        );
+
+        let cloner = RecordingCloner::default();
+        prefetch_urls(&cloner, &spec_clone_urls(&spec, None)).await;
+
+        let mut prefetched = cloner.prefetched.into_inner().unwrap();
+        prefetched.sort();
+        assert_eq!(
+            prefetched,
+            vec![
+                "https://example.com/meta-broken.git",
+                "https://example.com/meta-foo.git",
+                "https://example.com/poky.git",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn prefetches_respect_the_fetch_limit() {
+        let urls: Vec<String> = (0..6)
+            .map(|i| format!("https://example.com/meta-{i}.git"))
+            .collect();
+
+        let tracker = ConcurrencyTrackingCloner::default();
+        let max_in_flight = tracker.max_in_flight.clone();
+        let cloner = LimitedCloner::new(tracker, FetchLimiter::new(2));
+        prefetch_urls(&cloner, &urls).await;
+
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+    }
+}
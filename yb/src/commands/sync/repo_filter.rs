@@ -0,0 +1,88 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use eyre::WrapErr;
+use itertools::Itertools;
+
+use crate::errors::YbResult;
+
+/// Restricts a sync to a subset of the active spec's repos
+#[derive(Debug)]
+pub struct RepoFilter {
+    repos: HashSet<String>,
+}
+
+impl RepoFilter {
+    /// Create a filter selecting `repos`, which must all be found in `valid_repos`.
+    pub fn new<I>(repos: I, valid_repos: &HashSet<&String>) -> YbResult<Self>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let repos: HashSet<String> = repos.into_iter().collect();
+
+        let unknown = repos
+            .iter()
+            .filter(|repo| !valid_repos.contains(repo))
+            .sorted()
+            .collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            eyre::bail!(
+                "unknown repo(s): {}; valid options are: {}",
+                unknown.iter().join(", "),
+                valid_repos.iter().sorted().join(", ")
+            );
+        }
+
+        Ok(Self { repos })
+    }
+
+    pub fn contains(&self, repo_name: &str) -> bool {
+        self.repos.contains(repo_name)
+    }
+}
+
+/// Read a list of repo names from the file at `path`. See `parse_repo_list`.
+pub fn read_repo_list<P: AsRef<Path>>(path: P) -> YbResult<Vec<String>> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("failed to read repo list {}", path.display()))?;
+    Ok(parse_repo_list(&contents))
+}
+
+/// Parse newline-separated repo names. Blank lines and lines starting with '#' are ignored.
+pub fn parse_repo_list(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::commands::sync::repo_filter::{parse_repo_list, RepoFilter};
+
+    #[test]
+    fn parse_skips_comments_and_blank_lines() {
+        let contents = "# staged rollout\npoky\n\n  meta-openembedded  \n# meta-foo\n";
+        assert_eq!(parse_repo_list(contents), vec!["poky", "meta-openembedded"]);
+    }
+
+    #[test]
+    fn unknown_repo_is_an_error() {
+        let poky = "poky".to_string();
+        let valid: HashSet<&String> = vec![&poky].into_iter().collect();
+
+        let filter = RepoFilter::new(vec!["poky".to_string()], &valid).unwrap();
+        assert!(filter.contains("poky"));
+
+        let err = RepoFilter::new(vec!["meta-foo".to_string()], &valid).unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("meta-foo"));
+        assert!(msg.contains("valid options are: poky"));
+    }
+}
@@ -0,0 +1,76 @@
+use std::path::{Component, Path};
+
+use console::Style;
+use itertools::Itertools;
+
+use crate::commands::sync::actions::SyncAction;
+
+/// The sync actions affecting a single repo, in the order they will be applied
+#[derive(Debug, Eq, PartialEq)]
+pub struct RepoActionGroup {
+    pub repo: String,
+    pub action_indices: Vec<usize>,
+}
+
+/// Group `actions` by the repo (i.e. subdirectory of `sources_dir`) they affect. Groups are in the
+/// order their first action appears.
+pub fn group_actions_by_repo(
+    actions: &[Box<dyn SyncAction>],
+    sources_dir: &Path,
+) -> Vec<RepoActionGroup> {
+    let canonical_sources_dir = sources_dir.canonicalize().ok();
+
+    let mut groups: Vec<RepoActionGroup> = vec![];
+    for (i, action) in actions.iter().enumerate() {
+        let target_path = action.target_path();
+        let repo = [Some(sources_dir), canonical_sources_dir.as_deref()]
+            .iter()
+            .flatten()
+            .find_map(|dir| target_path.strip_prefix(dir).ok())
+            .and_then(|relative| relative.components().next())
+            .and_then(|component| match component {
+                Component::Normal(name) => name.to_str().map(String::from),
+                _ => None,
+            })
+            .unwrap_or_else(|| target_path.display().to_string());
+
+        match groups.iter_mut().find(|group| group.repo == repo) {
+            Some(group) => group.action_indices.push(i),
+            None => groups.push(RepoActionGroup {
+                repo,
+                action_indices: vec![i],
+            }),
+        }
+    }
+
+    groups
+}
+
+/// Render a one-line summary of `group`. `outcome` is None if the actions have not been (fully)
+/// applied, otherwise whether applying them succeeded.
+pub fn format_summary_line(
+    group: &RepoActionGroup,
+    actions: &[Box<dyn SyncAction>],
+    outcome: Option<bool>,
+) -> String {
+    let verbs = group
+        .action_indices
+        .iter()
+        .map(|&i| actions[i].summary())
+        .join(", ");
+
+    let mut line = format!(
+        "{} {}: {}",
+        Style::new().cyan().apply_to("●"),
+        Style::new().bold().apply_to(&group.repo),
+        verbs
+    );
+
+    match outcome {
+        Some(true) => line += &format!(" {}", Style::new().green().apply_to("✓")),
+        Some(false) => line += &format!(" {}", Style::new().red().apply_to("✗")),
+        None => {}
+    }
+
+    line
+}
@@ -0,0 +1,124 @@
+use itertools::Itertools;
+
+use crate::data_model::status::{ComputedStatus, ComputedStatusEntry, CorrespondingSpecRepoStatus};
+
+/// Checks that the environment described by `status` matches its active spec. Every spec repo
+/// must be present, on a branch tracking the correct remote branch, and have a clean working
+/// directory. Every layer requested by the spec must be enabled and contain a conf/layer.conf.
+///
+/// Returns a description of each discrepancy found (empty if verification passed).
+pub fn verify_status(status: &ComputedStatus) -> Vec<String> {
+    let mut problems = vec![];
+
+    for missing in &status.missing_repos {
+        problems.push(format!(
+            "spec repo '{}' is missing (remote: {})",
+            missing.name, missing.spec_repo.url
+        ));
+    }
+
+    for entry in &status.source_dirs {
+        if let ComputedStatusEntry::OnDiskRepo(repo_status) = entry {
+            match &repo_status.corresponding_spec_repo {
+                Some(CorrespondingSpecRepoStatus::RemoteMatch(remote_match)) => {
+                    if !repo_status.is_local_branch_tracking_correct_branch() {
+                        problems.push(format!(
+                            "{} is not on a branch tracking '{}'",
+                            repo_status.path.display(),
+                            remote_match.remote_tracking_branch.to_string()
+                        ));
+                    }
+                }
+                Some(CorrespondingSpecRepoStatus::RelatedRepo { spec_repo, .. }) => {
+                    problems.push(format!(
+                        "{} shares commits with spec repo {}, but the remote is wrong",
+                        repo_status.path.display(),
+                        spec_repo.url
+                    ));
+                }
+                None => continue,
+            }
+
+            if repo_status.is_workdir_dirty {
+                problems.push(format!(
+                    "{} has a dirty working directory",
+                    repo_status.path.display()
+                ));
+            }
+        }
+    }
+
+    for layer in status
+        .missing_bblayers_layers_for_extant_spec_repos()
+        .into_iter()
+        .sorted_by(|a, b| a.path.cmp(&b.path))
+    {
+        problems.push(format!(
+            "layer {} is not enabled in {}",
+            layer.path.display(),
+            status.bblayers_path.display()
+        ));
+    }
+
+    for layer in status
+        .spec_requested_layers()
+        .into_iter()
+        .sorted_by(|a, b| a.path.cmp(&b.path))
+    {
+        if !layer.path.join("conf").join("layer.conf").is_file() {
+            problems.push(format!(
+                "layer {} does not contain conf/layer.conf",
+                layer.path.display()
+            ));
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+
+    use crate::commands::sync::verify::verify_status;
+    use crate::data_model::status::{ComputedStatus, MissingRepo};
+    use crate::spec::SpecRepo;
+
+    fn empty_status() -> ComputedStatus {
+        ComputedStatus {
+            source_dirs: vec![],
+            enabled_layers: HashSet::new(),
+            missing_repos: vec![],
+            active_spec: None,
+            bblayers_path: PathBuf::from("/nonexistent/conf/bblayers.conf"),
+        }
+    }
+
+    #[test]
+    fn empty_env_passes_verification() {
+        assert!(verify_status(&empty_status()).is_empty());
+    }
+
+    #[test]
+    fn missing_repo_fails_verification() {
+        let mut status = empty_status();
+        status.missing_repos.push(MissingRepo {
+            name: "poky".to_string(),
+            spec_repo: SpecRepo {
+                url: "https://github.com/yoctoproject/poky.git".to_string(),
+                refspec: "zeus".to_string(),
+                extra_remotes: Default::default(),
+                layers: None,
+                post_clone: vec![],
+                submodules: false,
+                depth: None,
+                enabled: true,
+            },
+        });
+
+        let problems = verify_status(&status);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("'poky' is missing"));
+    }
+}
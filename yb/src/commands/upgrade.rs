@@ -80,14 +80,10 @@ impl SubcommandRunner for UpgradeCommand {
                 // TODO deduplicate code
                 let mut yb_env = require_yb_env(config)?;
 
-                let spec = yb_env.find_spec(default_spec_name)?.cloned();
-                if let Some(spec) = spec {
-                    // TODO don't clone
-                    yb_env.activate_spec(spec)?;
-                    println!("Activated spec '{}'", &default_spec_name);
-                } else {
-                    eyre::bail!("spec with name '{}' not found", &default_spec_name);
-                }
+                // TODO don't clone
+                let spec = yb_env.find_spec(default_spec_name)?.clone();
+                yb_env.activate_spec(spec)?;
+                println!("Activated spec '{}'", &default_spec_name);
             }
         }
 
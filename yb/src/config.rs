@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 
+use crate::util::output::OutputFormat;
 use crate::yb_options::YbOptions;
 
 /// Application-scope context
@@ -8,13 +9,19 @@ pub struct Config {
     /// The current working directory
     pub(crate) cwd: PathBuf,
     pub(crate) porcelain: bool,
+    /// How porcelain output is serialized
+    pub(crate) format: OutputFormat,
+    /// Answer yes to confirmation prompts instead of asking (--yes)
+    pub(crate) assume_yes: bool,
 }
 
 impl Config {
     pub fn new(cwd: PathBuf, options: &YbOptions) -> Config {
         Config {
             cwd,
-            porcelain: options.porcelain,
+            porcelain: options.porcelain || options.format.is_some(),
+            format: options.format.unwrap_or_default(),
+            assume_yes: options.yes,
         }
     }
 
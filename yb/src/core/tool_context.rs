@@ -6,7 +6,7 @@ use std::path::{Path, PathBuf};
 use git2::Repository;
 
 use crate::config::Config;
-use crate::errors::YbResult;
+use crate::errors::{YbError, YbResult};
 use crate::util::paths::{list_subdirectories_sorted, run_which};
 use crate::yb_env::{try_discover_yb_env, YbEnv};
 
@@ -44,6 +44,20 @@ impl ToolContext {
             ToolContext::YoctoEnv(yocto_env) => yocto_env.build_dir.clone(),
         }
     }
+
+    /// The poky layer: yb.yaml's poky_dir (or else sources/poky) in a yb environment, or the
+    /// one that was activated in a Yocto environment
+    pub fn poky_dir(&self) -> PathBuf {
+        match self {
+            ToolContext::Yb(yb_env) => yb_env
+                .poky_dir()
+                .unwrap_or_else(|| yb_env.sources_dir().join("poky")),
+            ToolContext::YoctoEnv(yocto_env) => yocto_env
+                .poky_layer
+                .clone()
+                .unwrap_or_else(|| yocto_env.sources_dir.join("poky")),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -53,9 +67,7 @@ pub struct YoctoEnvironment {
     pub(crate) sources_dir: PathBuf,
 }
 
-pub fn determine_tool_context(
-    config: &Config,
-) -> YbResult<Option<ToolContext>> {
+pub fn determine_tool_context(config: &Config) -> YbResult<Option<ToolContext>> {
     if run_which("petalinux-build")?.is_some() {
         eyre::bail!("PetaLinux is not supported, but an active PetaLinux environment was detected");
     }
@@ -106,9 +118,7 @@ pub fn determine_tool_context(
     Ok(None)
 }
 
-pub fn require_tool_context(
-    config: &Config,
-) -> YbResult<ToolContext> {
+pub fn require_tool_context(config: &Config) -> YbResult<ToolContext> {
     determine_tool_context(config).and_then(|c| {
         c.ok_or_else(|| {
             tracing::error!("expected a yb or Yocto environment");
@@ -119,11 +129,9 @@ pub fn require_tool_context(
     })
 }
 
-pub fn require_yb_env(
-    config: &Config,
-) -> YbResult<YbEnv> {
+pub fn require_yb_env(config: &Config) -> YbResult<YbEnv> {
     determine_tool_context(config).and_then(|c| match c {
-        None => eyre::bail!("expected a yb environment; no environment was found"),
+        None => Err(YbError::NoEnvironment.into()),
         Some(ToolContext::Yb(yb_env)) => Ok(yb_env),
         Some(ToolContext::YoctoEnv(_)) => {
             eyre::bail!("expected a yb environment; a Yocto environment was found")
@@ -131,9 +139,7 @@ pub fn require_yb_env(
     })
 }
 
-pub fn maybe_yb_env(
-    config: &Config,
-) -> YbResult<Option<YbEnv>> {
+pub fn maybe_yb_env(config: &Config) -> YbResult<Option<YbEnv>> {
     let ret = determine_tool_context(config).map(|c| {
         if let Some(ToolContext::Yb(yb_env)) = c {
             Some(yb_env)
@@ -1,12 +1,19 @@
-use itertools::Itertools;
-use serde::Serialize;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Serialize)]
 pub enum UpstreamComparison {
     UpToDate,
     Behind(usize),
     Ahead(usize),
-    Diverged { ahead: usize, behind: usize },
+    Diverged {
+        ahead: usize,
+        behind: usize,
+    },
+    /// The branch and its upstream differ, but the repo is shallow so its history may be
+    /// truncated and ahead/behind counts can't be trusted
+    Shallow,
 }
 
 impl UpstreamComparison {
@@ -15,15 +22,23 @@ impl UpstreamComparison {
     }
 }
 
-// It just so happens we can use the ordering of the `UpstreamComparison` enum to determine the
-// optimal branch.
-pub fn determine_optimal_checkout_branch(
-    candidates: &Vec<LocalTrackingBranchWithUpstreamComparison>,
-) -> Option<&LocalTrackingBranchWithUpstreamComparison> {
+// The current branch is kept whenever it is a candidate, so that sync doesn't needlessly switch
+// branches. Otherwise, it just so happens we can use the ordering of the `UpstreamComparison`
+// enum to determine the optimal branch.
+pub fn determine_optimal_checkout_branch<'a>(
+    candidates: &'a [LocalTrackingBranchWithUpstreamComparison],
+    current_branch: Option<&str>,
+) -> Option<&'a LocalTrackingBranchWithUpstreamComparison> {
     candidates
         .iter()
-        .sorted_by(|a, b| a.upstream_comparison.cmp(&b.upstream_comparison))
-        .next()
+        .find(|candidate| {
+            current_branch == Some(candidate.local_tracking_branch.branch_name.as_str())
+        })
+        .or_else(|| {
+            candidates
+                .iter()
+                .min_by_key(|candidate| candidate.upstream_comparison)
+        })
 }
 
 #[cfg(test)]
@@ -44,6 +59,99 @@ mod test {
         );
         assert!(UpstreamComparison::Behind(1) < UpstreamComparison::Ahead(1));
     }
+
+    fn candidate(
+        branch_name: &str,
+        upstream_comparison: UpstreamComparison,
+    ) -> LocalTrackingBranchWithUpstreamComparison {
+        LocalTrackingBranchWithUpstreamComparison {
+            local_tracking_branch: LocalTrackingBranch {
+                branch_name: branch_name.to_string(),
+                remote_tracking_branch: RemoteTrackingBranch {
+                    remote_name: "origin".to_string(),
+                    branch_name: "main".to_string(),
+                },
+            },
+            upstream_comparison,
+        }
+    }
+
+    #[test]
+    fn optimal_checkout_branch_keeps_current() {
+        let candidates = vec![
+            candidate("a", UpstreamComparison::UpToDate),
+            candidate("b", UpstreamComparison::UpToDate),
+            candidate("c", UpstreamComparison::Behind(1)),
+        ];
+
+        let optimal = |current| {
+            determine_optimal_checkout_branch(&candidates, current)
+                .unwrap()
+                .local_tracking_branch
+                .branch_name
+                .as_str()
+        };
+        assert_eq!(optimal(None), "a");
+        assert_eq!(optimal(Some("b")), "b");
+        assert_eq!(optimal(Some("c")), "c");
+        assert_eq!(optimal(Some("d")), "a");
+    }
+
+    #[test]
+    fn remote_tracking_branch_parse_branch_with_slash() {
+        let parsed = RemoteTrackingBranch::parse("origin/feature/foo", "origin").unwrap();
+        assert_eq!(parsed.remote_name, "origin");
+        assert_eq!(parsed.branch_name, "feature/foo");
+        assert_eq!(parsed.to_string(), "origin/feature/foo");
+    }
+
+    #[test]
+    fn remote_tracking_branch_parse_nested_branch() {
+        let parsed =
+            RemoteTrackingBranch::parse("refs/remotes/upstream/a/b/c/d", "upstream").unwrap();
+        assert_eq!(parsed.remote_name, "upstream");
+        assert_eq!(parsed.branch_name, "a/b/c/d");
+    }
+
+    #[test]
+    fn remote_tracking_branch_parse_remote_with_slash() {
+        let parsed = RemoteTrackingBranch::parse("team/mirror/kirkstone", "team/mirror").unwrap();
+        assert_eq!(parsed.remote_name, "team/mirror");
+        assert_eq!(parsed.branch_name, "kirkstone");
+    }
+
+    #[test]
+    fn remote_tracking_branch_parse_wrong_remote() {
+        assert_eq!(
+            RemoteTrackingBranch::parse("origin/master", "upstream"),
+            None
+        );
+        assert_eq!(RemoteTrackingBranch::parse("origin/", "origin"), None);
+        assert_eq!(
+            RemoteTrackingBranch::parse("originx/master", "origin"),
+            None
+        );
+    }
+}
+
+/// Where a submodule's checkout is relative to the commit its superproject records
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize)]
+pub enum SubmoduleState {
+    OnRecordedCommit,
+    WrongCommit,
+    /// The submodule hasn't been checked out ('git submodule update --init')
+    NotInitialized,
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize)]
+pub struct SubmoduleStatus {
+    /// Relative to the superproject's working directory
+    pub path: PathBuf,
+    /// The commit recorded in the superproject's HEAD
+    pub recorded_commit: Option<String>,
+    /// The commit checked out in the submodule
+    pub checked_out_commit: Option<String>,
+    pub state: SubmoduleState,
 }
 
 #[derive(Debug, Eq, PartialEq, Serialize)]
@@ -80,13 +188,30 @@ pub struct LocalTrackingBranch {
     pub remote_tracking_branch: RemoteTrackingBranch,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RemoteTrackingBranch {
     pub remote_name: String,
     pub branch_name: String,
 }
 
 impl RemoteTrackingBranch {
+    /// Split the name of a remote tracking branch belonging to remote `remote_name` into its remote
+    /// and branch parts. Accepts both the short (`origin/feature/foo`) and full
+    /// (`refs/remotes/origin/feature/foo`) forms. Returns None if the name doesn't belong to the
+    /// remote.
+    pub fn parse(name: &str, remote_name: &str) -> Option<RemoteTrackingBranch> {
+        let name = name.strip_prefix("refs/remotes/").unwrap_or(name);
+        let branch_name = name.strip_prefix(remote_name)?.strip_prefix('/')?;
+        if branch_name.is_empty() {
+            return None;
+        }
+
+        Some(RemoteTrackingBranch {
+            remote_name: remote_name.to_string(),
+            branch_name: branch_name.to_string(),
+        })
+    }
+
     // TODO better name
     pub fn to_string(&self) -> String {
         format!("{}/{}", self.remote_name, self.branch_name)
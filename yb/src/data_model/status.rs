@@ -1,4 +1,3 @@
-use assert_cmd::Command;
 use core::fmt;
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
@@ -6,19 +5,22 @@ use std::path::{Path, PathBuf};
 
 use crate::data_model::git::{
     BranchStatus, LocalTrackingBranch, LocalTrackingBranchWithUpstreamComparison,
-    RemoteTrackingBranch,
+    RemoteTrackingBranch, SubmoduleStatus,
 };
 use crate::data_model::Layer;
+use concurrent_git_pool::trace::{git_command, TracedCommand};
 use git2::{Branch, BranchType, Oid, Repository};
 use itertools::Itertools;
 use serde::Serialize;
-use tempfile::TempDir;
 
 use crate::errors::YbResult;
 use crate::spec::{ActiveSpec, SpecRepo};
 use crate::status_calculator::{compare_branch_to_remote_tracking_branch, StatusCalculatorEvent};
 
-use crate::util::git::get_remote_tracking_branch;
+use crate::util::debug_temp_dir::DebugTempDir;
+use crate::util::expand::expand_url;
+use crate::util::fetch_limit::FetchLimiter;
+use crate::util::git::{get_remote_tracking_branch, lossy_name, utf8_name};
 
 /// The status of the Yocto environment
 #[derive(Debug, Serialize)]
@@ -67,6 +69,16 @@ impl ComputedStatus {
             .collect()
     }
 
+    /// Number of source dirs that need attention (see `ComputedStatusEntry::has_problem`) plus
+    /// the number of missing spec repos
+    pub fn problem_count(&self) -> usize {
+        self.source_dirs
+            .iter()
+            .filter(|entry| entry.has_problem())
+            .count()
+            + self.missing_repos.len()
+    }
+
     pub fn extraneous_bblayers_layers(&self) -> HashSet<Layer> {
         self.enabled_layers
             .difference(&self.spec_requested_layers())
@@ -93,20 +105,22 @@ impl<'a> Iterator for ActiveSpecRepos<'a> {
     type Item = ActiveSpecRepoStatus<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.active_spec_repos.next().map(|a| {
-            if let Some(extant) = self
-                .source_dirs
-                .iter()
-                .find(|entry| (*entry).spec_repo() == Some(a.1))
-            {
-                ActiveSpecRepoStatus::Extant {
-                    spec_repo: a.1,
-                    path: extant.path(),
+        self.active_spec_repos
+            .find(|(_, spec_repo)| spec_repo.enabled)
+            .map(|a| {
+                if let Some(extant) = self
+                    .source_dirs
+                    .iter()
+                    .find(|entry| (*entry).spec_repo() == Some(a.1))
+                {
+                    ActiveSpecRepoStatus::Extant {
+                        spec_repo: a.1,
+                        path: extant.path(),
+                    }
+                } else {
+                    ActiveSpecRepoStatus::Missing(a.1)
                 }
-            } else {
-                ActiveSpecRepoStatus::Missing(a.1)
-            }
-        })
+            })
     }
 }
 
@@ -133,11 +147,21 @@ impl ComputedStatusEntry {
             ComputedStatusEntry::OnDiskRepo(repo) => repo.spec_repo(),
         }
     }
+
+    /// Whether the source dir needs attention. Directories that aren't repos never do.
+    pub fn has_problem(&self) -> bool {
+        match &self {
+            ComputedStatusEntry::OnDiskNonRepo(_) => false,
+            ComputedStatusEntry::OnDiskRepo(repo) => repo.has_problem(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
 pub struct OnDiskNonRepoStatus {
     pub(crate) path: PathBuf,
+    /// Whether the directory contains conf/layer.conf, i.e. is probably a hand-copied layer
+    pub(crate) looks_like_layer: bool,
 }
 
 #[derive(Serialize)]
@@ -148,8 +172,16 @@ pub struct OnDiskRepoStatus {
     /// Path to the directory
     pub path: PathBuf,
     pub is_workdir_dirty: bool,
-    #[serde(skip)]
+    /// With `yb status --log`, the most recent commits on HEAD (as many as were requested)
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_commit_ids"
+    )]
     pub recent_commits: Option<Vec<Oid>>,
+    /// With `yb status --include-submodule-status`, how each submodule's checkout compares with
+    /// the commit the repo records for it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub submodules: Option<Vec<SubmoduleStatus>>,
     /// Not necessarily the correct branch as far as any active spec is concerned
     pub current_branch_status: BranchStatus,
     /// Status information pertaining to corresponding spec repo, or None if no matching spec repo
@@ -158,6 +190,15 @@ pub struct OnDiskRepoStatus {
     pub layers: HashSet<Layer>,
 }
 
+fn serialize_commit_ids<S>(ids: &Option<Vec<Oid>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    ids.as_ref()
+        .map(|ids| ids.iter().map(Oid::to_string).collect::<Vec<_>>())
+        .serialize(serializer)
+}
+
 impl OnDiskRepoStatus {
     pub fn has_corresponding_spec_repo(&self) -> bool {
         self.corresponding_spec_repo.is_some()
@@ -167,6 +208,20 @@ impl OnDiskRepoStatus {
         self.corresponding_spec_repo.as_ref().map(|c| c.spec_repo())
     }
 
+    /// Whether the repo has a dirty working directory, a current branch that has diverged from
+    /// its upstream, or is a spec repo on the wrong branch (or with the wrong remote)
+    pub fn has_problem(&self) -> bool {
+        let wrong_branch = match &self.corresponding_spec_repo {
+            Some(CorrespondingSpecRepoStatus::RemoteMatch(_)) => {
+                !self.is_local_branch_tracking_correct_branch()
+            }
+            Some(CorrespondingSpecRepoStatus::RelatedRepo { .. }) => true,
+            None => false,
+        };
+
+        self.is_workdir_dirty || self.current_branch_status.is_diverged() || wrong_branch
+    }
+
     pub fn is_local_branch_tracking_correct_branch(&self) -> bool {
         assert!(
             self.has_corresponding_spec_repo(),
@@ -206,20 +261,42 @@ pub fn find_local_branches_tracking_remote_branch(
     repo: &Repository,
     remote_tracking_branch: &RemoteTrackingBranch,
 ) -> YbResult<Vec<LocalTrackingBranchWithUpstreamComparison>> {
+    let local_branch_upstreams = enumerate_local_branch_upstreams(repo)?;
+    compare_local_branches_tracking_remote_branch(
+        repo,
+        &local_branch_upstreams,
+        remote_tracking_branch,
+    )
+}
+
+/// Returns (name, upstream) for each local branch of `repo` that has an upstream branch.
+fn enumerate_local_branch_upstreams(
+    repo: &Repository,
+) -> YbResult<Vec<(String, RemoteTrackingBranch)>> {
     let branches: YbResult<Vec<Branch>> = repo
         .branches(Some(BranchType::Local))?
         .map(|branch| -> YbResult<_> { Ok(branch?.0) })
         .collect();
 
-    let filtered = branches?
-        .into_iter()
-        .filter(|branch| {
-            get_remote_tracking_branch(branch)
-                .unwrap()
-                .map_or(false, |b| b == *remote_tracking_branch)
-        })
-        .map(|branch| {
-            let branch_name = branch.name().unwrap().unwrap().to_string();
+    let mut ret = vec![];
+    for branch in branches? {
+        if let Some(upstream) = get_remote_tracking_branch(repo, &branch)? {
+            ret.push((lossy_name(branch.name_bytes()?, "branch"), upstream));
+        }
+    }
+
+    Ok(ret)
+}
+
+fn compare_local_branches_tracking_remote_branch(
+    repo: &Repository,
+    local_branch_upstreams: &[(String, RemoteTrackingBranch)],
+    remote_tracking_branch: &RemoteTrackingBranch,
+) -> YbResult<Vec<LocalTrackingBranchWithUpstreamComparison>> {
+    local_branch_upstreams
+        .iter()
+        .filter(|(_, upstream)| upstream == remote_tracking_branch)
+        .map(|(branch_name, _)| {
             compare_branch_to_remote_tracking_branch(
                 repo,
                 branch_name.clone(),
@@ -233,9 +310,7 @@ pub fn find_local_branches_tracking_remote_branch(
                 upstream_comparison: comparison,
             })
         })
-        .try_collect()?;
-
-    Ok(filtered)
+        .try_collect()
 }
 
 #[derive(Debug, Eq, PartialEq, Serialize)]
@@ -294,31 +369,31 @@ pub fn enumerate_repo_remotes(repo: &Repository) -> YbResult<HashMap<String, Str
     let remote_names = repo.remotes()?;
 
     let remotes: Vec<_> = remote_names
-        .iter()
-        .map(|remote_name| -> YbResult<_> {
-            let remote_name = remote_name.unwrap(); // assume utf-8
-            Ok((remote_name, repo.find_remote(remote_name)?))
-        })
+        .iter_bytes()
+        .filter_map(|remote_name| utf8_name(remote_name, "remote"))
+        .map(|remote_name| -> YbResult<_> { Ok((remote_name, repo.find_remote(remote_name)?)) })
         .try_collect()?;
 
     Ok(remotes
         .into_iter()
         .filter_map(|(remote_name, remote)| {
-            remote
-                .url()
-                .map(|remote_url| (remote_name.to_string(), remote_url.to_string()))
+            utf8_name(
+                remote.url_bytes(),
+                &format!("URL of remote '{remote_name}'"),
+            )
+            .map(|remote_url| (remote_name.to_string(), remote_url.to_string()))
         })
         .collect())
 }
 
 pub fn enumerate_revisions<P: AsRef<Path>>(repo_path: P) -> YbResult<HashSet<String>> {
     // git rev-list --all --full-history
-    let revs = Command::new("git")
+    let revs = git_command()
         .arg("rev-list")
         .arg("--all")
         .arg("--full-history")
         .current_dir(repo_path)
-        .output()?
+        .traced_output()?
         .stdout;
 
     Ok(std::str::from_utf8(revs.as_slice())
@@ -329,47 +404,238 @@ pub fn enumerate_revisions<P: AsRef<Path>>(repo_path: P) -> YbResult<HashSet<Str
 }
 
 pub fn clone_and_enumerate_revisions(spec_repo: &SpecRepo) -> YbResult<HashSet<String>> {
-    let tmp = TempDir::new().unwrap();
+    let tmp = DebugTempDir::new().unwrap();
 
-    let mut cmd = Command::new("git");
+    let mut cmd = git_command();
     cmd.arg("clone")
-        .arg(&spec_repo.url)
+        .arg(expand_url(&spec_repo.url)?)
         .arg("-b")
         .arg(&spec_repo.refspec)
         .arg(tmp.path());
     cmd.env("GIT_TERMINAL_PROMPT", "0");
     cmd.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes");
-    cmd.assert().success();
+    let output = cmd.traced_output()?;
+    if !output.status.success() {
+        eyre::bail!(
+            "failed to clone {}: {}",
+            spec_repo.url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
 
     enumerate_revisions(tmp.path())
 }
 
+/// The commits reachable from `rev` in the repo at `repo_path`
+pub fn enumerate_revisions_reachable_from<P: AsRef<Path>>(
+    repo_path: P,
+    rev: &str,
+) -> YbResult<HashSet<String>> {
+    let output = git_command()
+        .arg("rev-list")
+        .arg(rev)
+        .current_dir(repo_path.as_ref())
+        .traced_output()?;
+    if !output.status.success() {
+        eyre::bail!(
+            "failed to list the commits of {} in {}: {}",
+            rev,
+            repo_path.as_ref().display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
+/// Like `clone_and_enumerate_revisions`, but only clones `refspec` of `url` and only lists the
+/// commits reachable from it
+pub fn clone_and_enumerate_ref_revisions(url: &str, refspec: &str) -> YbResult<HashSet<String>> {
+    let tmp = DebugTempDir::new().unwrap();
+
+    let output = git_command()
+        .arg("clone")
+        .arg("--single-branch")
+        .arg("--no-checkout")
+        .arg("-b")
+        .arg(refspec)
+        .arg(expand_url(url)?)
+        .arg(tmp.path())
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes")
+        .traced_output()?;
+    if !output.status.success() {
+        eyre::bail!(
+            "failed to clone {}@{}: {}",
+            url,
+            refspec,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    enumerate_revisions_reachable_from(tmp.path(), "HEAD")
+}
+
+/// Order `remotes` (name -> URL) so that those named in `preferred_remotes` come first, in that
+/// order, followed by the rest sorted by name. This makes remote matching deterministic when
+/// several remotes share a URL.
+fn order_remotes_by_preference(
+    remotes: HashMap<String, String>,
+    preferred_remotes: &[String],
+) -> Vec<(String, String)> {
+    let rank = |name: &String| {
+        preferred_remotes
+            .iter()
+            .position(|preferred| preferred == name)
+            .unwrap_or(preferred_remotes.len())
+    };
+
+    remotes
+        .into_iter()
+        .sorted_by(|(a, _), (b, _)| rank(a).cmp(&rank(b)).then_with(|| a.cmp(b)))
+        .collect()
+}
+
+/// The name of the directory of `repo`'s working tree, e.g. 'meta-foo'
+fn repo_subdir_name(repo: &Repository) -> &str {
+    repo.path()
+        .parent()
+        .unwrap()
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap()
+}
+
+/// The commits of spec repos that on-disk repos may be related to (see
+/// `find_corresponding_spec_repo_for_repo`), cloned ahead of time and in parallel. Spec repos with
+/// the same URL and refspec are only cloned once.
+#[derive(Debug, Default)]
+pub struct RelatedRepoRevisions {
+    revisions: HashMap<(String, String), HashSet<String>>,
+}
+
+impl RelatedRepoRevisions {
+    /// Clone each spec repo that one of `repos` would be checked against for shared history: the
+    /// spec repo named like the repo's directory, if none of the repo's remotes match any spec
+    /// repo. Clones that fail are left out, so that the error is reported if and when the
+    /// revisions turn out to be needed.
+    pub fn compute<'a, I>(
+        repos: I,
+        spec_repos: &HashMap<String, SpecRepo>,
+        url_aliases: &HashMap<String, Vec<String>>,
+        fetch_limiter: Option<&FetchLimiter>,
+    ) -> YbResult<Self>
+    where
+        I: IntoIterator<Item = &'a Repository>,
+    {
+        let mut spec_repo_urls = HashSet::new();
+        for spec_repo in spec_repos.values() {
+            let url = expand_url(&spec_repo.url)?;
+            if let Some(aliases) = url_aliases.get(&url) {
+                spec_repo_urls.extend(aliases.iter().cloned());
+            }
+            spec_repo_urls.insert(url);
+            for extra_remote in spec_repo.extra_remotes.values() {
+                spec_repo_urls.insert(expand_url(&extra_remote.url)?);
+            }
+        }
+
+        let mut candidates = HashMap::new();
+        for repo in repos {
+            let spec_repo = match spec_repos.get(repo_subdir_name(repo)) {
+                Some(spec_repo) => spec_repo,
+                None => continue,
+            };
+            let has_remote_match = enumerate_repo_remotes(repo)?
+                .values()
+                .any(|url| spec_repo_urls.contains(url));
+            if !has_remote_match {
+                let key = (expand_url(&spec_repo.url)?, spec_repo.refspec.clone());
+                candidates.entry(key).or_insert(spec_repo);
+            }
+        }
+
+        let revisions = std::thread::scope(|scope| {
+            let clones: Vec<_> = candidates
+                .into_iter()
+                .map(|(key, spec_repo)| {
+                    scope.spawn(move || {
+                        let _permit = fetch_limiter.map(FetchLimiter::blocking_acquire);
+                        (key, clone_and_enumerate_revisions(spec_repo))
+                    })
+                })
+                .collect();
+            clones
+                .into_iter()
+                .filter_map(|clone| match clone.join().expect("clone thread panicked") {
+                    (key, Ok(revisions)) => Some((key, revisions)),
+                    (_, Err(_)) => None,
+                })
+                .collect()
+        });
+
+        Ok(Self { revisions })
+    }
+
+    /// The commits of `spec_repo`, if it was cloned up-front
+    fn get(&self, spec_repo: &SpecRepo) -> YbResult<Option<&HashSet<String>>> {
+        let key = (expand_url(&spec_repo.url)?, spec_repo.refspec.clone());
+        Ok(self.revisions.get(&key))
+    }
+
+    /// How many spec repos were cloned
+    pub fn len(&self) -> usize {
+        self.revisions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.revisions.is_empty()
+    }
+}
+
 /// For the on-disk repository `repo`, try to find corresponding spec repo using these methods:
 ///     1. Check if the repos share a remote (either primary or extra)
 ///     2. See if the on-disk repo and the spec repo remote has any common commits (by cloning the
 ///         latter to a temporary directory)
+/// If several remotes match, the first according to `preferred_remotes` wins (see
+/// `order_remotes_by_preference`). A remote also matches if its URL is one of the `url_aliases`
+/// of the spec repo's URL. Spec repos already cloned into `related_revisions` aren't cloned again.
 /// TODO document does not validate refspec
 pub fn find_corresponding_spec_repo_for_repo<F>(
     repo: &Repository,
     spec_repos: &HashMap<String, SpecRepo>,
+    preferred_remotes: &[String],
+    url_aliases: &HashMap<String, Vec<String>>,
+    related_revisions: &RelatedRepoRevisions,
     c: &mut F,
 ) -> YbResult<Option<CorrespondingSpecRepoStatus>>
 where
     F: FnMut(StatusCalculatorEvent),
 {
-    let repo_subdir_name = repo
-        .path()
-        .parent()
-        .unwrap()
-        .file_name()
-        .unwrap()
-        .to_str()
-        .unwrap();
+    let repo_subdir_name = repo_subdir_name(repo);
 
-    let remote_names_with_urls = enumerate_repo_remotes(repo)?;
+    // Enumerate remotes and branch upstreams once up-front, rather than for every spec repo
+    let remote_names_with_urls =
+        order_remotes_by_preference(enumerate_repo_remotes(repo)?, preferred_remotes);
+    let local_branch_upstreams = enumerate_local_branch_upstreams(repo)?;
 
     // Iterate through each spec repo
     for (spec_repo_subdir_name, spec_repo) in spec_repos {
+        // Remotes were created from the expanded URLs
+        let spec_repo_url = expand_url(&spec_repo.url)?;
+        let extra_remote_urls: Vec<String> = spec_repo
+            .extra_remotes
+            .values()
+            .map(|extra_remote| expand_url(&extra_remote.url))
+            .try_collect()?;
+        let aliases = url_aliases
+            .get(&spec_repo_url)
+            .map_or(&[] as &[String], |aliases| aliases.as_slice());
+
         // Iterate through each of the on-disk repo's remotes
         for (remote_name, remote_url) in &remote_names_with_urls {
             let tracking_branch = RemoteTrackingBranch {
@@ -377,17 +643,19 @@ where
                 remote_name: remote_name.clone(),
             };
 
-            if *remote_url == spec_repo.url {
-                // The remote URL exactly matches what the spec expects
+            if *remote_url == spec_repo_url || aliases.contains(remote_url) {
+                // The remote URL matches what the spec expects (or an accepted alias of it)
                 return Ok(Some(CorrespondingSpecRepoStatus::RemoteMatch(
                     RemoteMatchStatus {
                         spec_repo: spec_repo.clone(),
                         spec_repo_name: spec_repo_subdir_name.clone(),
                         is_extra_remote: false,
-                        local_branches_tracking_remote: find_local_branches_tracking_remote_branch(
-                            repo,
-                            &tracking_branch,
-                        )?,
+                        local_branches_tracking_remote:
+                            compare_local_branches_tracking_remote_branch(
+                                repo,
+                                &local_branch_upstreams,
+                                &tracking_branch,
+                            )?,
                         remote_tracking_branch: tracking_branch,
                         matching_remote_name: remote_name.clone(),
                     },
@@ -402,11 +670,7 @@ where
                 remote_name: remote_name.clone(),
             };
 
-            if spec_repo
-                .extra_remotes
-                .iter()
-                .any(|(_, extra_remote)| *remote_url == extra_remote.url)
-            {
+            if extra_remote_urls.contains(remote_url) {
                 // The remote URL matches one of the extra remotes in the spec
                 // TODO revisit assertion
                 assert_eq!(
@@ -418,10 +682,12 @@ where
                         spec_repo: spec_repo.clone(),
                         spec_repo_name: spec_repo_subdir_name.clone(),
                         is_extra_remote: true,
-                        local_branches_tracking_remote: find_local_branches_tracking_remote_branch(
-                            repo,
-                            &tracking_branch,
-                        )?,
+                        local_branches_tracking_remote:
+                            compare_local_branches_tracking_remote_branch(
+                                repo,
+                                &local_branch_upstreams,
+                                &tracking_branch,
+                            )?,
                         remote_tracking_branch: tracking_branch,
                         matching_remote_name: remote_name.clone(),
                     },
@@ -435,7 +701,14 @@ where
         if repo_subdir_name == spec_repo_subdir_name {
             let op = format!("checking possible upstream {}", spec_repo.url);
             c(StatusCalculatorEvent::StartSubdirOperation { operation_name: op });
-            let spec_repo_revs = clone_and_enumerate_revisions(spec_repo)?;
+            let cloned_revs;
+            let spec_repo_revs = match related_revisions.get(spec_repo)? {
+                Some(revs) => revs,
+                None => {
+                    cloned_revs = clone_and_enumerate_revisions(spec_repo)?;
+                    &cloned_revs
+                }
+            };
             let on_disk_revs = enumerate_revisions(repo.path())?;
             c(StatusCalculatorEvent::StartSubdirOperation {
                 operation_name: "".into(),
@@ -454,3 +727,235 @@ where
 
     Ok(None)
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::process::Command;
+
+    use git2::{BranchType, Repository, Signature};
+    use tempfile::TempDir;
+
+    use crate::data_model::git::UpstreamComparison;
+    use crate::data_model::status::{
+        find_corresponding_spec_repo_for_repo, repo_subdir_name, CorrespondingSpecRepoStatus,
+        RelatedRepoRevisions,
+    };
+    use crate::spec::SpecRepo;
+
+    fn spec_repo(url: &str) -> SpecRepo {
+        SpecRepo {
+            url: url.to_string(),
+            refspec: "main".to_string(),
+            extra_remotes: Default::default(),
+            layers: None,
+            post_clone: vec![],
+            submodules: false,
+            depth: None,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn remote_and_tracking_branches_matched_among_many_spec_repos() {
+        let tmp = TempDir::new().unwrap();
+        let repo_path = tmp.path().join("meta-foo");
+        let repo = Repository::init(&repo_path).unwrap();
+        repo.remote("origin", "https://example.com/meta-foo.git")
+            .unwrap();
+        repo.remote("other", "https://example.com/other.git")
+            .unwrap();
+
+        // 'main' tracks the matching remote, 'work' the other one
+        let sig = Signature::now("yb", "yb@localhost").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let commit_id = repo
+            .commit(Some("refs/heads/main"), &sig, &sig, "initial", &tree, &[])
+            .unwrap();
+        let commit = repo.find_commit(commit_id).unwrap();
+        for remote_ref in ["refs/remotes/origin/main", "refs/remotes/other/main"] {
+            repo.reference(remote_ref, commit_id, false, "").unwrap();
+        }
+        repo.find_branch("main", BranchType::Local)
+            .unwrap()
+            .set_upstream(Some("origin/main"))
+            .unwrap();
+        repo.branch("work", &commit, false)
+            .unwrap()
+            .set_upstream(Some("other/main"))
+            .unwrap();
+
+        let mut spec_repos = HashMap::new();
+        for i in 0..10 {
+            spec_repos.insert(
+                format!("meta-unrelated-{i}"),
+                spec_repo(&format!("https://example.com/unrelated-{i}.git")),
+            );
+        }
+        spec_repos.insert(
+            "meta-foo".to_string(),
+            spec_repo("https://example.com/meta-foo.git"),
+        );
+
+        let status = find_corresponding_spec_repo_for_repo(
+            &repo,
+            &spec_repos,
+            &[],
+            &HashMap::new(),
+            &RelatedRepoRevisions::default(),
+            &mut |_| {},
+        )
+        .unwrap()
+        .unwrap();
+
+        match status {
+            CorrespondingSpecRepoStatus::RemoteMatch(remote_match) => {
+                assert_eq!(remote_match.spec_repo_name, "meta-foo");
+                assert_eq!(remote_match.matching_remote_name, "origin");
+                assert!(!remote_match.is_extra_remote);
+                let tracking = &remote_match.local_branches_tracking_remote;
+                assert_eq!(tracking.len(), 1);
+                assert_eq!(tracking[0].local_tracking_branch.branch_name, "main");
+                assert_eq!(
+                    tracking[0].upstream_comparison,
+                    UpstreamComparison::UpToDate
+                );
+            }
+            _ => panic!("expected a remote match"),
+        }
+    }
+
+    #[test]
+    fn url_alias_matches_spec_repo() {
+        let tmp = TempDir::new().unwrap();
+        let repo_path = tmp.path().join("meta-foo");
+        let repo = Repository::init(&repo_path).unwrap();
+        repo.remote("origin", "https://mirror.example.com/meta-foo.git")
+            .unwrap();
+
+        let mut spec_repos = HashMap::new();
+        spec_repos.insert(
+            "meta-foo".to_string(),
+            spec_repo("https://example.com/meta-foo.git"),
+        );
+        let url_aliases = HashMap::from([(
+            "https://example.com/meta-foo.git".to_string(),
+            vec!["https://mirror.example.com/meta-foo.git".to_string()],
+        )]);
+
+        let status = find_corresponding_spec_repo_for_repo(
+            &repo,
+            &spec_repos,
+            &[],
+            &url_aliases,
+            &RelatedRepoRevisions::default(),
+            &mut |_| {},
+        )
+        .unwrap()
+        .unwrap();
+        match status {
+            CorrespondingSpecRepoStatus::RemoteMatch(remote_match) => {
+                assert_eq!(remote_match.spec_repo_name, "meta-foo");
+                assert_eq!(remote_match.matching_remote_name, "origin");
+            }
+            _ => panic!("expected a remote match"),
+        }
+    }
+
+    fn git(cwd: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .current_dir(cwd)
+            .args(["-c", "user.name=yb", "-c", "user.email=yb@localhost"])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {args:?} failed");
+    }
+
+    #[test]
+    fn related_repos_share_one_up_front_clone() {
+        let tmp = TempDir::new().unwrap();
+        let upstream = tmp.path().join("upstream");
+        std::fs::create_dir(&upstream).unwrap();
+        git(&upstream, &["init", "-b", "main"]);
+        git(&upstream, &["commit", "--allow-empty", "-m", "initial"]);
+        let upstream_url = upstream.to_str().unwrap();
+
+        // Two repos with history in common with the upstream, but no remote pointing at it, in
+        // directories named like spec repos that both clone it
+        let sources = tmp.path().join("sources");
+        std::fs::create_dir(&sources).unwrap();
+        let mut spec_repos = HashMap::new();
+        let mut repos = vec![];
+        for name in ["meta-foo", "meta-bar"] {
+            git(&sources, &["clone", upstream_url, name]);
+            git(&sources.join(name), &["remote", "remove", "origin"]);
+            repos.push(Repository::open(sources.join(name)).unwrap());
+            spec_repos.insert(name.to_string(), spec_repo(upstream_url));
+        }
+
+        let related_revisions =
+            RelatedRepoRevisions::compute(&repos, &spec_repos, &HashMap::new(), None).unwrap();
+        assert_eq!(related_revisions.len(), 1);
+
+        for repo in &repos {
+            let status = find_corresponding_spec_repo_for_repo(
+                repo,
+                &spec_repos,
+                &[],
+                &HashMap::new(),
+                &related_revisions,
+                &mut |_| {},
+            )
+            .unwrap()
+            .unwrap();
+            match status {
+                CorrespondingSpecRepoStatus::RelatedRepo { spec_repo_name, .. } => {
+                    assert_eq!(spec_repo_name, repo_subdir_name(repo));
+                }
+                _ => panic!("expected a related repo"),
+            }
+        }
+    }
+
+    #[test]
+    fn preferred_remote_wins_when_urls_match() {
+        let tmp = TempDir::new().unwrap();
+        let repo_path = tmp.path().join("meta-foo");
+        let repo = Repository::init(&repo_path).unwrap();
+        repo.remote("mirror", "https://example.com/meta-foo.git")
+            .unwrap();
+        repo.remote("origin", "https://example.com/meta-foo.git")
+            .unwrap();
+
+        let mut spec_repos = HashMap::new();
+        spec_repos.insert(
+            "meta-foo".to_string(),
+            spec_repo("https://example.com/meta-foo.git"),
+        );
+
+        for preferred in ["origin", "mirror"] {
+            let status = find_corresponding_spec_repo_for_repo(
+                &repo,
+                &spec_repos,
+                &[preferred.to_string()],
+                &HashMap::new(),
+                &RelatedRepoRevisions::default(),
+                &mut |_| {},
+            )
+            .unwrap()
+            .unwrap();
+
+            match status {
+                CorrespondingSpecRepoStatus::RemoteMatch(remote_match) => {
+                    assert_eq!(remote_match.matching_remote_name, preferred);
+                    assert_eq!(remote_match.remote_tracking_branch.remote_name, preferred);
+                }
+                _ => panic!("expected a remote match"),
+            }
+        }
+    }
+}
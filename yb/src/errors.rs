@@ -1,3 +1,29 @@
+use std::path::PathBuf;
+
 use color_eyre::eyre;
+use thiserror::Error;
 
 pub type YbResult<T> = eyre::Result<T>;
+
+/// Process exit code for `YbError::OutOfSync`, distinct from the 1 used for other failures
+pub const OUT_OF_SYNC_EXIT_CODE: i32 = 2;
+
+/// Failures that library users may want to handle specifically. They are returned wrapped in an
+/// `eyre::Report`; use `downcast_ref::<YbError>()` to match on them.
+#[derive(Debug, Error)]
+pub enum YbError {
+    #[error("expected a yb environment; no environment was found")]
+    NoEnvironment,
+    #[error("no spec is active")]
+    NoActiveSpec,
+    #[error("cannot determine the active spec because a stream is broken")]
+    BrokenStreams,
+    #[error("spec with name '{name}' not found")]
+    SpecNotFound { name: String },
+    #[error("{} has diverged from its upstream branch", path.display())]
+    RepoDiverged { path: PathBuf },
+    #[error("The git clone operation failed: {0}")]
+    CloneFailed(String),
+    #[error("the environment is not in sync with the active spec: {actions} action(s) needed")]
+    OutOfSync { actions: usize },
+}
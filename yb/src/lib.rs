@@ -19,6 +19,7 @@ pub mod status_calculator;
 pub mod stream;
 pub mod stream_db;
 pub mod ui_ops;
+pub mod user_conf;
 pub mod util;
 pub mod yb_conf;
 pub mod yb_env;
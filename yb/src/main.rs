@@ -1,17 +1,32 @@
 use std::{env, io};
 
 use clap::Parser;
+use concurrent_git_pool::trace::{TRACE_GIT_ENV_VAR, TRACE_GIT_TARGET};
 use eyre::Context;
 use indicatif::MultiProgress;
 
 use yb::commands::*;
 use yb::config::Config;
-use yb::errors::YbResult;
+use yb::errors::{YbError, YbResult, OUT_OF_SYNC_EXIT_CODE};
+use yb::util::debug_temp_dir::keep_temp_dirs;
+use yb::util::paths::normalize_path;
 use yb::yb_options::{Level, YbOptions};
 
 fn parse_args_and_create_config() -> YbResult<(Config, YbOptions)> {
     let opt: YbOptions = YbOptions::parse();
-    let cwd = env::current_dir().context("couldn't get the current directory of the process")?;
+    let mut cwd =
+        env::current_dir().context("couldn't get the current directory of the process")?;
+    if let Some(directory) = &opt.directory {
+        let directory = normalize_path(cwd.join(directory));
+        if !directory.is_dir() {
+            eyre::bail!(
+                "cannot change to '{}': no such directory",
+                directory.display()
+            );
+        }
+        cwd = directory;
+    }
+    keep_temp_dirs(opt.keep_temp || env::var("YB_KEEP_TEMP").as_deref() == Ok("1"));
     let config = Config::new(cwd, &opt);
     Ok((config, opt))
 }
@@ -50,10 +65,16 @@ async fn real_main() -> Result<(), i32> {
         Ok((mut config, opt)) => {
             let mp = MultiProgress::new();
 
-            install_tracing(opt.level, mp.clone());
+            let trace_git = opt.trace_git || env::var(TRACE_GIT_ENV_VAR).as_deref() == Ok("1");
+            install_tracing(opt.level, trace_git, mp.clone());
 
             // Run the subcommand
             if let Err(err) = opt.command.run(&mut config, &mp).await {
+                if let Some(YbError::OutOfSync { .. }) = err.downcast_ref::<YbError>() {
+                    // Not a failure as such, just the answer to 'sync --dry-run-exit-code'
+                    eprintln!("{err}");
+                    return Err(OUT_OF_SYNC_EXIT_CODE);
+                }
                 eprintln!("internal error: {err:?}");
                 return Err(1);
             }
@@ -63,7 +84,7 @@ async fn real_main() -> Result<(), i32> {
     Ok(())
 }
 
-fn install_tracing(level: Level, mp: MultiProgress) {
+fn install_tracing(level: Level, trace_git: bool, mp: MultiProgress) {
     use tracing_error::ErrorLayer;
     use tracing_subscriber::fmt;
     use tracing_subscriber::prelude::*;
@@ -71,15 +92,19 @@ fn install_tracing(level: Level, mp: MultiProgress) {
 
     let fmt_layer = fmt::layer()
         .with_target(false)
-        .with_writer(move || MultiProgressWriteWrapper::new(mp.clone()));
+        .with_writer(move || MultiProgressWriteWrapper::new(mp.clone(), io::stderr()));
     let level = tracing::Level::from(level);
-    let filter_layer = EnvFilter::try_from_default_env()
+    let mut filter_layer = EnvFilter::try_from_default_env()
         .or_else(|_| {
             EnvFilter::builder()
                 .with_default_directive(level.into())
                 .parse("")
         })
         .unwrap();
+    if trace_git {
+        filter_layer =
+            filter_layer.add_directive(format!("{TRACE_GIT_TARGET}=info").parse().unwrap());
+    }
 
     tracing_subscriber::registry()
         .with(filter_layer)
@@ -88,20 +113,89 @@ fn install_tracing(level: Level, mp: MultiProgress) {
         .init();
 }
 
-struct MultiProgressWriteWrapper(MultiProgress);
+/// Writes log output to `W` (stderr) with the progress bars out of the way. Output is buffered
+/// until flushed (or dropped, which tracing does after each event), so that each log line is
+/// written in one go rather than interleaved with progress bar redraws.
+struct MultiProgressWriteWrapper<W: io::Write> {
+    mp: MultiProgress,
+    buf: Vec<u8>,
+    out: W,
+}
 
-impl MultiProgressWriteWrapper {
-    fn new(mp: MultiProgress) -> Self {
-        Self(mp)
+impl<W: io::Write> MultiProgressWriteWrapper<W> {
+    fn new(mp: MultiProgress, out: W) -> Self {
+        Self {
+            mp,
+            buf: vec![],
+            out,
+        }
     }
 }
 
-impl io::Write for MultiProgressWriteWrapper {
+impl<W: io::Write> io::Write for MultiProgressWriteWrapper<W> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.0.suspend(|| io::stderr().lock().write(buf))
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        Ok(())
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+
+        let buf = std::mem::take(&mut self.buf);
+        let out = &mut self.out;
+        self.mp.suspend(|| {
+            out.write_all(&buf)?;
+            out.flush()
+        })
+    }
+}
+
+impl<W: io::Write> Drop for MultiProgressWriteWrapper<W> {
+    fn drop(&mut self) {
+        let _ = io::Write::flush(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    use indicatif::{MultiProgress, ProgressDrawTarget};
+
+    use crate::MultiProgressWriteWrapper;
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn log_emitted_right_before_exit_is_not_lost() {
+        let out = SharedBuf::default();
+        let writer_out = out.clone();
+        let mp = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || MultiProgressWriteWrapper::new(mp.clone(), writer_out.clone()))
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::error!("giving up");
+        });
+
+        // Nothing else is written or flushed after the event, as when yb exits right after it
+        let logged = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        assert!(logged.contains("giving up"), "{logged:?}");
     }
 }
@@ -1,20 +1,22 @@
-use assert_cmd::Command;
 use eyre::WrapErr;
 use std::fs;
 use std::fs::OpenOptions;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
 use git2::build::RepoBuilder;
 use git2::FetchOptions;
-use tempfile::Builder;
 
 use crate::config::Config;
 use crate::core::tool_context::require_yb_env;
 use crate::errors::YbResult;
+use crate::spec::Spec;
 use crate::stream::{
     Stream, StreamConfig, StreamKind, STREAM_CONFIG_FILE, STREAM_CONTENT_ROOT_SUBDIR,
 };
 use crate::stream_db::StreamKey;
+use crate::util::debug_temp_dir::DebugTempDir;
+use crate::util::expand::expand_url;
 use crate::util::git::ssh_agent_remote_callbacks;
 
 pub struct AddStreamOptions<'cfg> {
@@ -56,7 +58,7 @@ pub fn op_add_stream(options: AddStreamOptions) -> YbResult<()> {
 
     let stream_name = options.name.clone().unwrap_or_else(|| "default".into());
 
-    let tmpdir = Builder::new().prefix("yb").tempdir()?;
+    let tmpdir = DebugTempDir::prefixed("yb")?;
     let tmp_contents_dir = tmpdir.path().join(STREAM_CONTENT_ROOT_SUBDIR);
 
     let mut fetch_options = FetchOptions::new();
@@ -65,7 +67,7 @@ pub fn op_add_stream(options: AddStreamOptions) -> YbResult<()> {
     // Clone the stream
     RepoBuilder::new()
         .fetch_options(fetch_options)
-        .clone(&options.uri, tmp_contents_dir.as_ref())?;
+        .clone(&expand_url(&options.uri)?, tmp_contents_dir.as_ref())?;
 
     // Write the config file
     // TODO: when other stream types are added, don't hardcode git
@@ -94,15 +96,90 @@ pub fn op_add_stream(options: AddStreamOptions) -> YbResult<()> {
 
     // Just fake a key for now
     let key = StreamKey::default();
-    // Try to load stream
-    Stream::load(PathBuf::from(tmpdir.path()), stream_name, key)?;
+    // Try to load stream. Any error from here on drops `tmpdir`, which cleans it up (unless
+    // --keep-temp was given).
+    let stream = Stream::load(PathBuf::from(tmpdir.path()), stream_name, key)?;
+    if let Some(reason) = stream.broken_reason() {
+        eyre::bail!("stream {} is broken: {:?}", &options.uri, reason);
+    }
+    drop(stream);
+
+    // Everything was OK, so move into stream directory. `mv` rather than a rename since the temp
+    // dir may be on another filesystem.
+    let output = Command::new("mv")
+        .arg(tmpdir.path())
+        .arg(&stream_root_dir)
+        .output()?;
+    if !output.status.success() {
+        // A failed cross-filesystem move can leave a partial copy behind
+        if stream_root_dir.exists() {
+            fs::remove_dir_all(&stream_root_dir)?;
+        }
+        eyre::bail!(
+            "failed to move stream into {}: {}",
+            stream_root_dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
 
-    // Everything was OK, so move into stream directory
-    let mut mv_cmd = Command::new("mv");
-    mv_cmd.arg(tmpdir.into_path()).arg(&stream_root_dir);
-    mv_cmd.assert().success();
+    // The directory has been moved, so there is nothing left for `tmpdir` to clean up
+    let _ = tmpdir.into_path();
 
     println!("yb {:?}", &yb_env);
 
     Ok(())
 }
+
+/// Create a local stream (see `StreamKind::Local`) called `name` containing `specs`. Local
+/// streams have no upstream; their specs are written straight into the stream directory.
+pub fn op_add_local_stream(config: &Config, name: &str, specs: &[Spec]) -> YbResult<()> {
+    let yb_env = require_yb_env(config)?;
+
+    let stream_root_dir = yb_env.streams_dir().join(name);
+    if stream_root_dir.exists() {
+        eyre::bail!("a stream with name {} already exists", name);
+    }
+
+    let contents_dir = stream_root_dir.join(STREAM_CONTENT_ROOT_SUBDIR);
+    fs::create_dir_all(&contents_dir)?;
+
+    let config_file_path = stream_root_dir.join(STREAM_CONFIG_FILE);
+    let f = fs::File::create(&config_file_path)
+        .with_context(|| format!("failed to open file {:?} for writing", &config_file_path))?;
+    serde_yaml::to_writer(f, &StreamConfig::new(StreamKind::Local))?;
+
+    for spec in specs {
+        write_spec(&contents_dir, spec)?;
+    }
+
+    Ok(())
+}
+
+/// Write `spec` into the local stream called `name` (replacing any spec of the same name),
+/// creating the stream if it doesn't exist yet
+pub fn op_add_spec_to_local_stream(config: &Config, name: &str, spec: &Spec) -> YbResult<()> {
+    let yb_env = require_yb_env(config)?;
+    match yb_env.stream_db().get_stream_by_name(name) {
+        None => return op_add_local_stream(config, name, std::slice::from_ref(spec)),
+        Some(stream) if *stream.kind() != StreamKind::Local => {
+            eyre::bail!("stream '{}' already exists and is not a local stream", name);
+        }
+        Some(_) => {}
+    }
+
+    write_spec(
+        &yb_env
+            .streams_dir()
+            .join(name)
+            .join(STREAM_CONTENT_ROOT_SUBDIR),
+        spec,
+    )
+}
+
+fn write_spec(contents_dir: &Path, spec: &Spec) -> YbResult<()> {
+    let spec_path = contents_dir.join(format!("{}.yaml", spec.name()));
+    let f = fs::File::create(&spec_path)
+        .with_context(|| format!("failed to open file {:?} for writing", &spec_path))?;
+    serde_yaml::to_writer(f, spec)?;
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::Path;
+
+use git2::Repository;
+
+use crate::data_model::status::enumerate_repo_remotes;
+use crate::errors::YbResult;
+use crate::spec::{Spec, SpecRepo};
+use crate::status_calculator::bblayers_manager::read_bblayers;
+use crate::util::git::{get_current_local_branch_name, get_remote_name_for_current_branch};
+use crate::util::paths::{list_subdirectories_sorted, normalize_path};
+
+pub struct DerivedSpec {
+    pub spec: Spec,
+    /// Why each source directory that didn't make it into the spec was skipped
+    pub skipped: Vec<String>,
+}
+
+/// Derive a spec named `name` from what is currently on disk: one spec repo per git repository in
+/// `sources_dir` (using the URL of the remote it tracks and its current branch), with layers taken
+/// from the build directory's bblayers.conf.
+pub fn derive_spec_from_env(
+    name: &str,
+    sources_dir: &Path,
+    build_dir: &Path,
+) -> YbResult<DerivedSpec> {
+    let enabled_layers = read_bblayers(&build_dir.to_path_buf())?;
+
+    let mut repos = HashMap::new();
+    let mut skipped = vec![];
+    for repo_path in list_subdirectories_sorted(sources_dir)? {
+        let repo_path = normalize_path(repo_path);
+        let repo_name = match repo_path.file_name().and_then(OsStr::to_str) {
+            Some(repo_name) => repo_name.to_string(),
+            None => {
+                skipped.push(format!(
+                    "the name of {} is not valid UTF-8",
+                    repo_path.display()
+                ));
+                continue;
+            }
+        };
+
+        let repo = match Repository::open(&repo_path) {
+            Ok(repo) => repo,
+            Err(_) => {
+                skipped.push(format!("{} is not a git repository", repo_path.display()));
+                continue;
+            }
+        };
+
+        let refspec = match get_current_local_branch_name(&repo) {
+            Ok(branch_name) => branch_name,
+            Err(_) => {
+                skipped.push(format!("{} is not on a branch", repo_path.display()));
+                continue;
+            }
+        };
+
+        // Prefer the remote the current branch tracks, then 'origin', then the only remote
+        let remotes = enumerate_repo_remotes(&repo)?;
+        let remote_name = get_remote_name_for_current_branch(&repo)?.or_else(|| {
+            if remotes.contains_key("origin") {
+                Some("origin".to_string())
+            } else if remotes.len() == 1 {
+                remotes.keys().next().cloned()
+            } else {
+                None
+            }
+        });
+        let url = match remote_name.and_then(|remote_name| remotes.get(&remote_name)) {
+            Some(url) => url.clone(),
+            None => {
+                skipped.push(format!(
+                    "couldn't determine the remote URL for {}",
+                    repo_path.display()
+                ));
+                continue;
+            }
+        };
+
+        let layers = enabled_layers
+            .iter()
+            .filter_map(|layer| layer.path.strip_prefix(&repo_path).ok())
+            .filter_map(|relative_path| match relative_path.to_str() {
+                Some("") => Some((".".to_string(), ())),
+                Some(relative_path) => Some((relative_path.to_string(), ())),
+                None => {
+                    tracing::warn!(
+                        "leaving layer {} out of the spec: its path is not valid UTF-8",
+                        repo_path.join(relative_path).display()
+                    );
+                    None
+                }
+            })
+            .collect::<HashMap<_, _>>();
+
+        repos.insert(
+            repo_name,
+            SpecRepo {
+                url,
+                refspec,
+                extra_remotes: Default::default(),
+                layers: if layers.is_empty() {
+                    None
+                } else {
+                    Some(layers)
+                },
+                post_clone: vec![],
+                submodules: false,
+                depth: None,
+                enabled: true,
+            },
+        );
+    }
+
+    Ok(DerivedSpec {
+        spec: Spec::new(name.to_string(), repos),
+        skipped,
+    })
+}
@@ -1,2 +1,4 @@
 pub mod add_stream;
+pub mod derive_spec;
+pub mod repo_manifest;
 pub mod update_stream;
@@ -0,0 +1,405 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use itertools::Itertools;
+
+use crate::errors::YbResult;
+use crate::spec::{Spec, SpecRepo};
+
+pub struct ImportedManifest {
+    pub spec: Spec,
+    /// Describes each part of the manifest that couldn't be converted
+    pub warnings: Vec<String>,
+}
+
+/// Split a git URL into the part a manifest `<remote>` fetches from and the project name relative
+/// to it, e.g. "https://github.com/yoctoproject/poky.git" becomes
+/// ("https://github.com/yoctoproject", "poky.git").
+fn split_url(url: &str) -> (&str, &str) {
+    let url = url.trim_end_matches('/');
+    url.rsplit_once('/').unwrap_or((".", url))
+}
+
+/// Pick a name for the remote fetching from `fetch`, based on its last path component
+fn remote_name_for_fetch(fetch: &str) -> String {
+    let name = fetch
+        .rsplit(|c| c == '/' || c == ':')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        .collect::<String>();
+    if name.is_empty() || name == "." {
+        "origin".to_string()
+    } else {
+        name
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Convert `spec` into a Google `repo` XML manifest. Each spec repo becomes a `<project>` checked
+/// out at `<sources_path>/<repo name>` at the spec's refspec. One `<remote>` is emitted per
+/// distinct fetch location; the most common one is made the `<default>`. Extra remotes of spec
+/// repos are emitted as additional `<remote>` entries (a `repo` project can only track one remote,
+/// so they are not referenced by any project).
+pub fn spec_to_repo_manifest(spec: &Spec, sources_path: &Path) -> String {
+    let repos = spec
+        .enabled_repos()
+        .sorted_by(|a, b| a.0.cmp(b.0))
+        .collect_vec();
+
+    // Fetch location -> remote name, in the order the remotes are emitted
+    let mut remotes: Vec<(String, String)> = vec![];
+    let mut assign_remote = |fetch: &str, candidate: String| -> String {
+        if let Some((name, _)) = remotes.iter().find(|(_, f)| f == fetch) {
+            return name.clone();
+        }
+        let mut name = candidate.clone();
+        let mut suffix = 2;
+        while remotes.iter().any(|(n, _)| *n == name) {
+            name = format!("{candidate}-{suffix}");
+            suffix += 1;
+        }
+        remotes.push((name.clone(), fetch.to_string()));
+        name
+    };
+
+    let mut projects = vec![];
+    let mut usage: HashMap<String, usize> = HashMap::new();
+    for (repo_name, spec_repo) in &repos {
+        let (fetch, project_name) = split_url(&spec_repo.url);
+        let remote = assign_remote(fetch, remote_name_for_fetch(fetch));
+        *usage.entry(remote.clone()).or_default() += 1;
+        projects.push((repo_name, project_name, remote, &spec_repo.refspec));
+    }
+
+    for (_, spec_repo) in &repos {
+        let extra_remotes = spec_repo.extra_remotes.iter().collect::<BTreeMap<_, _>>();
+        for (remote_name, remote) in extra_remotes {
+            let (fetch, _) = split_url(&remote.url);
+            assign_remote(fetch, remote_name.clone());
+        }
+    }
+
+    // Most used remote wins; ties go to the one emitted first
+    let default_remote = remotes
+        .iter()
+        .map(|(name, _)| name)
+        .rev()
+        .max_by_key(|name| usage.get(*name).copied().unwrap_or_default())
+        .cloned();
+
+    let mut ret = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<manifest>\n");
+    for (name, fetch) in &remotes {
+        ret += &format!(
+            "  <remote name=\"{}\" fetch=\"{}\"/>\n",
+            xml_escape(name),
+            xml_escape(fetch)
+        );
+    }
+    if let Some(default_remote) = &default_remote {
+        ret += &format!("  <default remote=\"{}\"/>\n", xml_escape(default_remote));
+    }
+    for (repo_name, project_name, remote, refspec) in projects {
+        let path = sources_path.join(repo_name);
+        ret += &format!(
+            "  <project name=\"{}\" path=\"{}\"",
+            xml_escape(project_name),
+            xml_escape(&path.to_string_lossy())
+        );
+        if Some(&remote) != default_remote.as_ref() {
+            ret += &format!(" remote=\"{}\"", xml_escape(&remote));
+        }
+        ret += &format!(" revision=\"{}\"/>\n", xml_escape(refspec));
+    }
+    ret += "</manifest>\n";
+    ret
+}
+
+/// Convert a Google `repo` XML manifest into a spec named `name`. Each `<project>` becomes a spec
+/// repo named after the last component of its path, whose URL is the project name appended to
+/// its remote's fetch location and whose refspec is its revision (falling back to the remote's
+/// and then the `<default>` revision). Projects that can't be converted, and `<include>`s, are
+/// skipped with a warning.
+pub fn repo_manifest_to_spec(name: &str, xml: &str) -> YbResult<ImportedManifest> {
+    let doc = roxmltree::Document::parse(xml)?;
+    let manifest = doc.root_element();
+    if !manifest.has_tag_name("manifest") {
+        eyre::bail!(
+            "expected a <manifest> root element, found <{}>",
+            manifest.tag_name().name()
+        );
+    }
+
+    let mut warnings = vec![];
+    let mut remotes = HashMap::new();
+    let mut default_remote = None;
+    let mut default_revision = None;
+    for node in manifest.children().filter(|n| n.is_element()) {
+        match node.tag_name().name() {
+            "remote" => {
+                if let (Some(name), Some(fetch)) = (node.attribute("name"), node.attribute("fetch"))
+                {
+                    remotes.insert(name, (fetch, node.attribute("revision")));
+                }
+            }
+            "default" => {
+                default_remote = node.attribute("remote");
+                default_revision = node.attribute("revision");
+            }
+            "include" => warnings.push(format!(
+                "includes are not supported; projects from '{}' were not imported",
+                node.attribute("name").unwrap_or_default()
+            )),
+            _ => {}
+        }
+    }
+
+    let mut repos = HashMap::new();
+    for project in manifest.children().filter(|n| n.has_tag_name("project")) {
+        let project_name = match project.attribute("name") {
+            Some(project_name) => project_name,
+            None => {
+                warnings.push("skipping a project without a name".to_string());
+                continue;
+            }
+        };
+
+        let path = project.attribute("path").unwrap_or(project_name);
+        let repo_name = match Path::new(path).file_name() {
+            Some(repo_name) => repo_name.to_string_lossy().to_string(),
+            None => {
+                warnings.push(format!(
+                    "skipping project '{project_name}': bad path '{path}'"
+                ));
+                continue;
+            }
+        };
+
+        let remote = match project.attribute("remote").or(default_remote) {
+            Some(remote) => remote,
+            None => {
+                warnings.push(format!(
+                    "skipping project '{project_name}': no remote given and no default remote"
+                ));
+                continue;
+            }
+        };
+        let (fetch, remote_revision) = match remotes.get(remote) {
+            Some(remote) => remote,
+            None => {
+                warnings.push(format!(
+                    "skipping project '{project_name}': remote '{remote}' is not defined"
+                ));
+                continue;
+            }
+        };
+        if fetch.starts_with('.') {
+            warnings.push(format!(
+                "skipping project '{project_name}': remote '{remote}' has a fetch location relative to the manifest URL ('{fetch}')"
+            ));
+            continue;
+        }
+
+        let revision = match project
+            .attribute("revision")
+            .or(*remote_revision)
+            .or(default_revision)
+        {
+            Some(revision) => revision,
+            None => {
+                warnings.push(format!(
+                    "skipping project '{project_name}': no revision given and no default revision"
+                ));
+                continue;
+            }
+        };
+
+        if repos.contains_key(&repo_name) {
+            warnings.push(format!(
+                "skipping project '{project_name}': another project is also checked out to a directory named '{repo_name}'"
+            ));
+            continue;
+        }
+
+        repos.insert(
+            repo_name,
+            SpecRepo {
+                url: format!("{}/{}", fetch.trim_end_matches('/'), project_name),
+                refspec: revision.trim_start_matches("refs/heads/").to_string(),
+                extra_remotes: HashMap::new(),
+                layers: None,
+                post_clone: vec![],
+                submodules: false,
+                depth: None,
+                enabled: true,
+            },
+        );
+    }
+
+    Ok(ImportedManifest {
+        spec: Spec::new(name.to_string(), repos),
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    use maplit::hashmap;
+
+    use crate::ops::repo_manifest::{repo_manifest_to_spec, spec_to_repo_manifest};
+    use crate::spec::{Spec, SpecRemote, SpecRepo};
+
+    fn spec_repo(url: &str, refspec: &str) -> SpecRepo {
+        SpecRepo {
+            url: url.to_string(),
+            refspec: refspec.to_string(),
+            extra_remotes: HashMap::new(),
+            layers: None,
+            post_clone: vec![],
+            submodules: false,
+            depth: None,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn manifest_has_project_per_spec_repo() {
+        let mut meta_foo = spec_repo("https://example.com/foo/meta-foo.git", "main");
+        meta_foo.extra_remotes.insert(
+            "fork".to_string(),
+            SpecRemote {
+                url: "https://example.com/fork/meta-foo.git".to_string(),
+            },
+        );
+        let spec = Spec::new(
+            "myspec".to_string(),
+            hashmap! {
+                "poky".to_string() => spec_repo("https://git.yoctoproject.org/poky", "kirkstone"),
+                "meta-oe".to_string() => spec_repo("https://git.openembedded.org/meta-openembedded", "kirkstone"),
+                "meta-foo".to_string() => meta_foo,
+                "meta-bar".to_string() => spec_repo("https://example.com/foo/meta-bar.git", "v1.0"),
+            },
+        );
+
+        let manifest = spec_to_repo_manifest(&spec, Path::new("sources"));
+        let doc = roxmltree::Document::parse(&manifest).unwrap();
+        let root = doc.root_element();
+        assert_eq!(root.tag_name().name(), "manifest");
+
+        let remotes = root
+            .children()
+            .filter(|n| n.has_tag_name("remote"))
+            .map(|n| (n.attribute("name").unwrap(), n.attribute("fetch").unwrap()))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(remotes["foo"], "https://example.com/foo");
+        assert_eq!(remotes["fork"], "https://example.com/fork");
+
+        let default = root.children().find(|n| n.has_tag_name("default")).unwrap();
+        assert_eq!(default.attribute("remote"), Some("foo"));
+
+        let projects = root
+            .children()
+            .filter(|n| n.has_tag_name("project"))
+            .collect::<Vec<_>>();
+        assert_eq!(projects.len(), 4);
+        for (path, name, revision) in [
+            ("sources/meta-bar", "meta-bar.git", "v1.0"),
+            ("sources/meta-foo", "meta-foo.git", "main"),
+            ("sources/meta-oe", "meta-openembedded", "kirkstone"),
+            ("sources/poky", "poky", "kirkstone"),
+        ] {
+            let project = projects
+                .iter()
+                .find(|p| p.attribute("path") == Some(path))
+                .unwrap();
+            assert_eq!(project.attribute("name"), Some(name));
+            assert_eq!(project.attribute("revision"), Some(revision));
+            let remote = project.attribute("remote").unwrap_or("foo");
+            assert_eq!(
+                format!("{}/{}", remotes[remote], name),
+                spec.repos[path.trim_start_matches("sources/")].url
+            );
+        }
+    }
+
+    #[test]
+    fn disabled_repos_left_out_of_manifest() {
+        let mut meta_bar = spec_repo("https://example.com/foo/meta-bar.git", "main");
+        meta_bar.enabled = false;
+        let spec = Spec::new(
+            "myspec".to_string(),
+            hashmap! {
+                "meta-foo".to_string() => spec_repo("https://example.com/foo/meta-foo.git", "main"),
+                "meta-bar".to_string() => meta_bar,
+            },
+        );
+
+        let manifest = spec_to_repo_manifest(&spec, Path::new("sources"));
+        let doc = roxmltree::Document::parse(&manifest).unwrap();
+        let paths = doc
+            .root_element()
+            .children()
+            .filter(|n| n.has_tag_name("project"))
+            .map(|n| n.attribute("path").unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["sources/meta-foo"]);
+    }
+
+    #[test]
+    fn import_manifest() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+  <remote name="yocto" fetch="https://git.yoctoproject.org/"/>
+  <remote name="oe" fetch="https://git.openembedded.org" revision="kirkstone"/>
+  <default remote="yocto" revision="refs/heads/kirkstone"/>
+  <include name="other.xml"/>
+  <project name="poky" path="sources/poky"/>
+  <project name="meta-openembedded" path="sources/meta-oe" remote="oe"/>
+  <project name="meta-bar" revision="v1.0"/>
+  <project name="meta-baz" remote="nope"/>
+</manifest>
+"#;
+
+        let imported = repo_manifest_to_spec("imported", xml).unwrap();
+        assert_eq!(imported.spec.name(), "imported");
+
+        let repos = imported
+            .spec
+            .repos
+            .iter()
+            .map(|(name, repo)| (name.as_str(), (repo.url.as_str(), repo.refspec.as_str())))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(
+            repos,
+            HashMap::from([
+                ("poky", ("https://git.yoctoproject.org/poky", "kirkstone")),
+                (
+                    "meta-oe",
+                    (
+                        "https://git.openembedded.org/meta-openembedded",
+                        "kirkstone"
+                    )
+                ),
+                (
+                    "meta-bar",
+                    ("https://git.yoctoproject.org/meta-bar", "v1.0")
+                ),
+            ])
+        );
+
+        assert_eq!(imported.warnings.len(), 2);
+        assert!(imported.warnings[0].contains("other.xml"));
+        assert!(imported.warnings[1].contains("remote 'nope' is not defined"));
+    }
+}
@@ -1,14 +1,26 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 use crate::config::Config;
 use crate::core::tool_context::require_yb_env;
 use crate::errors::YbResult;
-use crate::stream_db::StreamKey;
+use crate::stream_db::{StreamDb, StreamKey};
 use crate::yb_env::ActiveSpecStatus;
 
+#[derive(Debug)]
+pub enum StreamUpdateOutcome {
+    Updated,
+    /// The stream was updated, but one or more of its specs failed to load
+    Broken(Arc<eyre::Report>),
+    /// The stream could not be updated (only recorded when `keep_going` is set)
+    Failed(eyre::Report),
+}
+
 #[derive(Default)]
 pub struct UpdateStreamResult {
     pub active_spec_updated: bool,
+    /// Outcome for each stream, by stream name
+    pub stream_outcomes: Vec<(String, StreamUpdateOutcome)>,
 }
 
 pub enum UpdateStreamEvent<'a> {
@@ -20,6 +32,7 @@ pub enum UpdateStreamEvent<'a> {
 pub struct UpdateStreamOptions<'cfg> {
     pub(crate) config: &'cfg Config,
     stream_keys: HashSet<StreamKey>,
+    keep_going: bool,
 }
 
 impl<'cfg> UpdateStreamOptions<'cfg> {
@@ -27,8 +40,38 @@ impl<'cfg> UpdateStreamOptions<'cfg> {
         Self {
             config,
             stream_keys,
+            keep_going: false,
         }
     }
+
+    /// If a stream fails to update, record the failure in the result and carry on with the
+    /// remaining streams instead of returning an error.
+    pub fn keep_going(&mut self, val: bool) -> &mut Self {
+        self.keep_going = val;
+        self
+    }
+}
+
+/// Fetch each of the given streams on its own thread. Each `Stream` guards its repository with a
+/// mutex, so the fetches only ever contend on different repositories.
+fn fetch_streams(
+    stream_db: &StreamDb,
+    stream_keys: &HashSet<StreamKey>,
+) -> HashMap<StreamKey, YbResult<()>> {
+    std::thread::scope(|scope| {
+        let handles = stream_keys
+            .iter()
+            .map(|&stream_key| {
+                let stream = stream_db.stream(stream_key).unwrap();
+                (stream_key, scope.spawn(move || stream.fetch()))
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|(stream_key, handle)| (stream_key, handle.join().unwrap()))
+            .collect()
+    })
 }
 
 pub fn op_update_stream<F>(options: UpdateStreamOptions, mut c: F) -> YbResult<UpdateStreamResult>
@@ -46,6 +89,8 @@ where
 
     c(UpdateStreamEvent::Start);
 
+    let mut fetch_results = fetch_streams(yb_env.stream_db(), &options.stream_keys);
+
     for stream_key in options.stream_keys {
         let is_active_stream = active_spec_stream
             .map(|key| key == stream_key)
@@ -53,7 +98,34 @@ where
 
         {
             let stream = yb_env.stream_db_mut().stream_mut(stream_key).unwrap();
-            stream.pull()?;
+            let stream_name = stream.name().clone();
+            let update_result = fetch_results
+                .remove(&stream_key)
+                .unwrap()
+                .and_then(|_| stream.merge_fetched());
+
+            match update_result {
+                Ok(()) => {}
+                Err(err) if options.keep_going => {
+                    result
+                        .stream_outcomes
+                        .push((stream_name, StreamUpdateOutcome::Failed(err)));
+                    continue;
+                }
+                Err(err) => return Err(err),
+            }
+
+            if let Some(reason) = stream.broken_reason() {
+                // Nothing to reload the active spec from
+                result
+                    .stream_outcomes
+                    .push((stream_name, StreamUpdateOutcome::Broken(reason)));
+                continue;
+            }
+
+            result
+                .stream_outcomes
+                .push((stream_name, StreamUpdateOutcome::Updated));
         }
 
         if is_active_stream {
@@ -73,6 +145,8 @@ where
         }
     }
 
+    result.stream_outcomes.sort_by(|(a, _), (b, _)| a.cmp(b));
+
     c(UpdateStreamEvent::Finish(&result));
 
     Ok(result)
@@ -1,9 +1,10 @@
 use crate::data_model::Layer;
 use color_eyre::Help;
-use eyre::Report;
+use eyre::{Report, WrapErr};
 use itertools::Itertools;
 use serde::{Deserialize, Deserializer, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::path::{Path, PathBuf};
 
@@ -16,10 +17,28 @@ const fn default_format_version() -> u32 {
     SPEC_FORMAT_VERSION
 }
 
+const fn default_enabled() -> bool {
+    true
+}
+
+fn is_enabled(enabled: &bool) -> bool {
+    *enabled
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Spec {
     header: SpecHeader,
     pub(crate) repos: HashMap<String, SpecRepo>,
+    /// Variables (e.g. MACHINE) for `yb sync --write-local-conf` to set in the build's conf
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) local_conf: BTreeMap<String, String>,
+    /// Another spec in the same directory (by file name, without '.yaml') whose repos and
+    /// `local_conf` settings this one inherits. Its own take precedence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base: Option<String>,
+    /// Repos to drop from the inherited set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    exclude: Vec<String>,
 
     #[serde(skip)]
     pub(crate) stream_key: StreamKey,
@@ -27,16 +46,32 @@ pub struct Spec {
 
 impl PartialEq for Spec {
     fn eq(&self, other: &Self) -> bool {
-        self.header == other.header && self.repos == other.repos
+        self.header == other.header
+            && self.repos == other.repos
+            && self.local_conf == other.local_conf
     }
 }
 
 impl Eq for Spec {}
 
 impl Spec {
+    pub fn new(name: String, repos: HashMap<String, SpecRepo>) -> Self {
+        Self {
+            header: SpecHeader {
+                format_version: SPEC_FORMAT_VERSION,
+                name,
+                default_refspec: None,
+            },
+            repos,
+            local_conf: BTreeMap::new(),
+            base: None,
+            exclude: vec![],
+            stream_key: StreamKey::default(),
+        }
+    }
+
     pub fn load(path: &Path, stream_key: StreamKey) -> YbResult<Self> {
-        let f = File::open(path)?;
-        let mut ret = serde_yaml::from_reader::<_, Self>(f).map_err(Report::from)?;
+        let mut ret = Self::load_resolving_base(path, &mut vec![])?;
         ret.stream_key = stream_key;
 
         // Validation: ensure no overlap between repo URLs
@@ -65,9 +100,165 @@ impl Spec {
         Ok(ret)
     }
 
+    /// Load the spec at `path`, merged with its base spec (if any) minus its excluded repos.
+    /// `seen` holds the specs being loaded further down the chain, to catch cycles.
+    fn load_resolving_base(path: &Path, seen: &mut Vec<PathBuf>) -> YbResult<Self> {
+        let f = File::open(path)?;
+        let mut ret = serde_yaml::from_reader::<_, Self>(f).map_err(Report::from)?;
+
+        // Repos without a refspec of their own get the spec's default
+        for (repo_name, spec_repo) in &mut ret.repos {
+            if spec_repo.refspec.is_empty() {
+                spec_repo.refspec = ret.header.default_refspec.clone().ok_or_else(|| {
+                    eyre::eyre!(
+                        "spec repo '{}' has no refspec and the spec has no default_refspec",
+                        repo_name
+                    )
+                    .suppress_backtrace(true)
+                })?;
+            }
+        }
+
+        if let Some(base) = ret.base.take() {
+            seen.push(path.to_path_buf());
+            let base_path = path.with_file_name(format!("{base}.yaml"));
+            if seen.contains(&base_path) {
+                return Err(eyre::eyre!(
+                    "spec {} inherits from itself (via base '{}')",
+                    path.display(),
+                    base
+                )
+                .suppress_backtrace(true));
+            }
+            let base_spec = Self::load_resolving_base(&base_path, seen).wrap_err_with(|| {
+                format!("failed to load base spec '{base}' of {}", path.display())
+            })?;
+
+            let mut repos = base_spec.repos;
+            repos.extend(ret.repos);
+            ret.repos = repos;
+            let mut local_conf = base_spec.local_conf;
+            local_conf.extend(ret.local_conf);
+            ret.local_conf = local_conf;
+        }
+
+        for name in std::mem::take(&mut ret.exclude) {
+            if ret.repos.remove(&name).is_none() {
+                return Err(eyre::eyre!(
+                    "spec {} excludes repo '{}', which it doesn't have",
+                    path.display(),
+                    name
+                )
+                .suppress_backtrace(true));
+            }
+        }
+
+        Ok(ret)
+    }
+
     pub fn name(&self) -> String {
         self.header.name.clone()
     }
+
+    /// The spec's repos, minus those disabled with `enabled: false`
+    pub fn enabled_repos(&self) -> impl Iterator<Item = (&String, &SpecRepo)> {
+        self.repos.iter().filter(|(_, spec_repo)| spec_repo.enabled)
+    }
+}
+
+/// How a single spec repo differs between two specs
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SpecRepoTransition {
+    Added {
+        name: String,
+    },
+    Removed {
+        name: String,
+    },
+    RefspecChanged {
+        name: String,
+        from: String,
+        to: String,
+    },
+    UrlChanged {
+        name: String,
+        from: String,
+        to: String,
+    },
+}
+
+impl Display for SpecRepoTransition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpecRepoTransition::Added { name } => write!(f, "{name}: added"),
+            SpecRepoTransition::Removed { name } => write!(f, "{name}: removed"),
+            SpecRepoTransition::RefspecChanged { name, from, to } => {
+                write!(f, "{name}: {from} → {to}")
+            }
+            SpecRepoTransition::UrlChanged { name, from, to } => {
+                write!(f, "{name}: url {from} → {to}")
+            }
+        }
+    }
+}
+
+/// The repo-level differences between two specs
+#[derive(Debug)]
+pub struct SpecDiff {
+    pub(crate) transitions: Vec<SpecRepoTransition>,
+}
+
+impl SpecDiff {
+    /// Compare the repos of `from` against those of `to`. Transitions are sorted by repo name. A
+    /// disabled repo counts as absent, as it does for sync.
+    pub fn new(from: &Spec, to: &Spec) -> Self {
+        let from_repos = from.enabled_repos().collect::<HashMap<_, _>>();
+        let to_repos = to.enabled_repos().collect::<HashMap<_, _>>();
+        let names = from_repos
+            .keys()
+            .chain(to_repos.keys())
+            .copied()
+            .collect::<BTreeSet<_>>();
+
+        let mut transitions = vec![];
+        for name in names {
+            match (from_repos.get(name), to_repos.get(name)) {
+                (None, Some(_)) => {
+                    transitions.push(SpecRepoTransition::Added { name: name.clone() })
+                }
+                (Some(_), None) => {
+                    transitions.push(SpecRepoTransition::Removed { name: name.clone() })
+                }
+                (Some(from_repo), Some(to_repo)) => {
+                    if from_repo.url != to_repo.url {
+                        transitions.push(SpecRepoTransition::UrlChanged {
+                            name: name.clone(),
+                            from: from_repo.url.clone(),
+                            to: to_repo.url.clone(),
+                        });
+                    }
+                    if from_repo.refspec != to_repo.refspec {
+                        transitions.push(SpecRepoTransition::RefspecChanged {
+                            name: name.clone(),
+                            from: from_repo.refspec.clone(),
+                            to: to_repo.refspec.clone(),
+                        });
+                    }
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+
+        Self { transitions }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transitions.is_empty()
+    }
+
+    pub fn transitions(&self) -> &[SpecRepoTransition] {
+        &self.transitions
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
@@ -75,6 +266,13 @@ pub struct SpecHeader {
     #[serde(alias = "version", default = "default_format_version")]
     format_version: u32,
     name: String,
+    /// Refspec for repos that don't give one
+    #[serde(
+        alias = "default_branch",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    default_refspec: Option<String>,
 }
 
 // https://github.com/serde-rs/serde/issues/1098#issuecomment-760711617
@@ -90,6 +288,8 @@ where
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
 pub struct SpecRepo {
     pub(crate) url: String,
+    /// May be omitted in favor of the spec's `default_refspec`, which `Spec::load` fills in
+    #[serde(default)]
     pub(crate) refspec: String,
     #[serde(
         rename = "extra-remotes",
@@ -99,6 +299,19 @@ pub struct SpecRepo {
     pub(crate) extra_remotes: HashMap<String, SpecRemote>,
     // each entry is a layer name
     pub(crate) layers: Option<HashMap<String, ()>>,
+    /// Shell commands run in the repo directory after it is cloned, e.g. 'git lfs pull'
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) post_clone: Vec<String>,
+    /// Whether to initialize and update submodules (recursively) after cloning
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) submodules: bool,
+    /// Clone only this many commits of history (overriding `yb sync --shallow-since`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) depth: Option<u32>,
+    /// Set to false to leave the repo out (it is neither synced nor reported as missing) without
+    /// deleting its declaration
+    #[serde(default = "default_enabled", skip_serializing_if = "is_enabled")]
+    pub(crate) enabled: bool,
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -149,6 +362,9 @@ pub struct SpecRemote {
 pub struct ActiveSpec {
     pub(crate) spec: Spec,
     pub(crate) from_stream: String,
+    /// The file the spec was activated from with 'yb activate --spec-file', if it was
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) spec_file: Option<PathBuf>,
 
     #[serde(skip)]
     pub(crate) stream_key: StreamKey,
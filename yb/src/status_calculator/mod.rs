@@ -2,35 +2,54 @@ use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::{fs, io};
 
-use git2::{Branch, FetchOptions, Repository, StatusOptions};
+use git2::{Branch, ErrorCode, FetchOptions, Repository, StatusOptions};
 use maplit::hashset;
 
 use crate::config::Config;
 use crate::core::tool_context::{require_tool_context, ToolContext};
 use crate::data_model::git::{
-    BranchStatus, RemoteTrackingBranch, UpstreamBranchStatus, UpstreamComparison,
+    BranchStatus, RemoteTrackingBranch, SubmoduleState, SubmoduleStatus, UpstreamBranchStatus,
+    UpstreamComparison,
 };
 use crate::data_model::status::{
     find_corresponding_spec_repo_for_repo, ComputedStatus, ComputedStatusEntry, MissingRepo,
-    OnDiskNonRepoStatus, OnDiskRepoStatus,
+    OnDiskNonRepoStatus, OnDiskRepoStatus, RelatedRepoRevisions,
 };
 use crate::data_model::Layer;
 use crate::errors::YbResult;
-use crate::spec::SpecRepo;
+use crate::spec::{ActiveSpec, SpecRepo};
 use crate::status_calculator::bblayers_manager::read_bblayers;
+use crate::util::fetch_limit::FetchLimiter;
 use crate::util::git::{
-    check_repository_workdirs_unique, create_revwalk, get_current_local_branch,
-    get_remote_for_current_branch, get_remote_tracking_branch, ssh_agent_remote_callbacks,
+    check_repository_workdirs_unique, create_revwalk, find_duplicate_workdirs,
+    get_current_local_branch, get_remote_for_current_branch, get_remote_tracking_branch,
+    lossy_name, ssh_agent_remote_callbacks,
 };
 use crate::util::paths::list_subdirectories_sorted;
 use crate::yb_env::ActiveSpecStatus;
 
 pub mod bblayers_manager;
+pub mod timings;
+
+/// How many recent commits `log` collects per repo by default
+pub const DEFAULT_LOG_COMMITS: usize = 5;
+
+/// Upper bound on `max_commits`, so that a typo'd count doesn't walk the whole history of e.g. poky
+pub const MAX_LOG_COMMITS: usize = 1000;
 
 pub struct StatusCalculatorOptions<'cfg> {
     config: &'cfg Config,
     no_fetch: bool,
     log: bool,
+    max_commits: usize,
+    preferred_remotes: Vec<String>,
+    url_aliases: HashMap<String, Vec<String>>,
+    assume_clean: bool,
+    skip_duplicate_workdirs: bool,
+    ignore_untracked: bool,
+    fetch_limiter: Option<FetchLimiter>,
+    include_submodule_status: bool,
+    prospective_spec: Option<ActiveSpec>,
 }
 
 impl<'cfg> StatusCalculatorOptions<'cfg> {
@@ -39,18 +58,101 @@ impl<'cfg> StatusCalculatorOptions<'cfg> {
             config,
             no_fetch,
             log,
+            max_commits: DEFAULT_LOG_COMMITS,
+            preferred_remotes: vec![],
+            url_aliases: HashMap::new(),
+            assume_clean: false,
+            skip_duplicate_workdirs: false,
+            ignore_untracked: false,
+            fetch_limiter: None,
+            include_submodule_status: false,
+            prospective_spec: None,
         }
     }
+
+    /// Remote names to prefer, in order, when several remotes of a repo match a spec repo. These
+    /// take precedence over the `preferred_remotes` in .yb/config.toml.
+    pub fn preferred_remotes(&mut self, preferred_remotes: Vec<String>) -> &mut Self {
+        self.preferred_remotes = preferred_remotes;
+        self
+    }
+
+    /// Treat every repo as clean rather than scanning working directories for changes, which
+    /// can be slow for large trees
+    pub fn assume_clean(&mut self, val: bool) -> &mut Self {
+        self.assume_clean = val;
+        self
+    }
+
+    /// Leave out source dirs that resolve to the same git workdir as another source dir (see
+    /// `find_duplicate_workdirs`), rather than failing
+    pub fn skip_duplicate_workdirs(&mut self, val: bool) -> &mut Self {
+        self.skip_duplicate_workdirs = val;
+        self
+    }
+
+    /// Don't count untracked (or ignored) files as local changes, so that e.g. build artifacts
+    /// don't make a repo dirty
+    pub fn ignore_untracked(&mut self, val: bool) -> &mut Self {
+        self.ignore_untracked = val;
+        self
+    }
+
+    /// With `log`, how many recent commits to collect per repo (at most `MAX_LOG_COMMITS`)
+    pub fn max_commits(&mut self, val: usize) -> &mut Self {
+        self.max_commits = val.min(MAX_LOG_COMMITS);
+        self
+    }
+
+    /// Compare each submodule's checkout with the commit its repo records for it
+    pub fn include_submodule_status(&mut self, val: bool) -> &mut Self {
+        self.include_submodule_status = val;
+        self
+    }
+
+    /// Compare the environment with `active_spec` rather than the spec that is actually active,
+    /// e.g. to preview what activating it would change
+    pub fn prospective_spec(&mut self, active_spec: ActiveSpec) -> &mut Self {
+        self.prospective_spec = Some(active_spec);
+        self
+    }
+
+    /// Other remote URLs to accept as a spec repo's URL, keyed by the spec repo's URL. These are
+    /// in addition to the `url_aliases` in .yb/config.toml.
+    pub fn url_aliases(&mut self, url_aliases: HashMap<String, Vec<String>>) -> &mut Self {
+        self.url_aliases = url_aliases;
+        self
+    }
+
+    /// Wait for a permit from `limiter` before each fetch, so that fetches count towards the
+    /// same limit as any clones happening meanwhile
+    pub fn fetch_limiter(&mut self, limiter: FetchLimiter) -> &mut Self {
+        self.fetch_limiter = Some(limiter);
+        self
+    }
 }
 
 /// Compares a local branch (identified by `local_branch_name`) and remote tracking branch (`tracking_branch`)
-/// to determine if the former is up-to-date, ahead, behind, or diverged from the latter.
+/// to determine if the former is up-to-date, ahead, behind, or diverged from the latter. In a shallow
+/// repo, branches that differ are compared as `UpstreamComparison::Shallow`.
 pub fn compare_branch_to_remote_tracking_branch(
     repo: &Repository,
     local_branch_name: String,
     tracking_branch: &RemoteTrackingBranch,
 ) -> YbResult<UpstreamComparison> {
     let remote_branch_name = tracking_branch.to_string();
+    if repo.is_shallow() {
+        let local_commit = repo.revparse_single(&local_branch_name)?.peel_to_commit()?;
+        let remote_commit = repo
+            .revparse_single(&remote_branch_name)?
+            .peel_to_commit()?;
+        return Ok(if local_commit.id() == remote_commit.id() {
+            UpstreamComparison::UpToDate
+        } else {
+            UpstreamComparison::Shallow
+        });
+    }
+
     let ahead_count =
         create_revwalk(repo, &format!("{remote_branch_name}..{local_branch_name}"))?.count();
     let behind_count =
@@ -72,9 +174,9 @@ fn compare_branch_to_upstream(
     repo: &Repository,
     local_branch: &Branch,
 ) -> YbResult<Option<UpstreamBranchStatus>> {
-    let local_branch_name = local_branch.name()?.unwrap().to_string();
+    let local_branch_name = lossy_name(local_branch.name_bytes()?, "branch");
 
-    get_remote_tracking_branch(local_branch)?
+    get_remote_tracking_branch(repo, local_branch)?
         .map(|tracking_branch| -> YbResult<_> {
             compare_branch_to_remote_tracking_branch(repo, local_branch_name, &tracking_branch).map(
                 |comparison| UpstreamBranchStatus {
@@ -122,6 +224,7 @@ fn compute_repo_status<F>(
     path: &PathBuf,
     options: &mut StatusCalculatorOptions,
     active_spec_repos: &HashMap<String, SpecRepo>,
+    related_revisions: &RelatedRepoRevisions,
     c: &mut F,
 ) -> YbResult<ComputedStatusEntry>
 where
@@ -134,11 +237,16 @@ where
         // If the current branch is tracking an upstream branch, fetch it to check for updates
         if let Some(remote) = repo_remote.as_mut() {
             c(StatusCalculatorEvent::StartFetch);
+            let permit = options
+                .fetch_limiter
+                .as_ref()
+                .map(FetchLimiter::blocking_acquire);
             let mut fetch_options = FetchOptions::new();
             fetch_options.remote_callbacks(ssh_agent_remote_callbacks());
             // TODO: this is really slow
             //fetch_options.download_tags(AutotagOption::All);
             remote.fetch(&[] as &[&str], Some(&mut fetch_options), None)?;
+            drop(permit);
             c(StatusCalculatorEvent::FinishFetch);
         }
 
@@ -146,12 +254,19 @@ where
     }
 
     // See if we can map the repo to a spec repo
-    let spec_repo_status = find_corresponding_spec_repo_for_repo(&repo, active_spec_repos, c)?;
+    let spec_repo_status = find_corresponding_spec_repo_for_repo(
+        &repo,
+        active_spec_repos,
+        &options.preferred_remotes,
+        &options.url_aliases,
+        related_revisions,
+        c,
+    )?;
 
     let current_branch_status = {
         // TODO: gracefully handle detached HEAD and repos without a tracked branch
         let local_branch = get_current_local_branch(&repo)?;
-        let local_branch_name = local_branch.name()?.unwrap().to_string();
+        let local_branch_name = lossy_name(local_branch.name_bytes()?, "branch");
 
         BranchStatus {
             local_branch_name,
@@ -165,7 +280,7 @@ where
         walker.set_sorting(git2::Sort::TOPOLOGICAL)?;
         walker.push_head()?;
         let mut commit_v = vec![];
-        for commit in walker.take(5) {
+        for commit in walker.take(options.max_commits) {
             commit_v.push(commit?);
         }
         Some(commit_v)
@@ -173,7 +288,20 @@ where
         None
     };
 
-    let is_workdir_dirty = !repo.statuses(Some(&mut StatusOptions::new()))?.is_empty();
+    let submodules = if options.include_submodule_status {
+        Some(compute_submodule_statuses(&repo)?)
+    } else {
+        None
+    };
+
+    let mut status_options = StatusOptions::new();
+    if options.ignore_untracked {
+        status_options
+            .include_untracked(false)
+            .include_ignored(false);
+    }
+    let is_workdir_dirty =
+        !options.assume_clean && !repo.statuses(Some(&mut status_options))?.is_empty();
 
     Ok(ComputedStatusEntry::OnDiskRepo(OnDiskRepoStatus {
         current_branch_status,
@@ -182,10 +310,33 @@ where
         corresponding_spec_repo: spec_repo_status,
         path: path.clone(),
         recent_commits: commits,
+        submodules,
         layers: detect_layers(path)?,
     }))
 }
 
+fn compute_submodule_statuses(repo: &Repository) -> YbResult<Vec<SubmoduleStatus>> {
+    let mut ret = vec![];
+    for submodule in repo.submodules()? {
+        let recorded_commit = submodule.head_id();
+        let checked_out_commit = submodule.workdir_id();
+        let state = match checked_out_commit {
+            None => SubmoduleState::NotInitialized,
+            Some(id) if Some(id) == recorded_commit => SubmoduleState::OnRecordedCommit,
+            Some(_) => SubmoduleState::WrongCommit,
+        };
+        ret.push(SubmoduleStatus {
+            path: submodule.path().to_path_buf(),
+            recorded_commit: recorded_commit.map(|id| id.to_string()),
+            checked_out_commit: checked_out_commit.map(|id| id.to_string()),
+            state,
+        });
+    }
+    ret.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(ret)
+}
+
 pub fn compute_status<F>(mut options: StatusCalculatorOptions, mut c: F) -> YbResult<ComputedStatus>
 where
     F: FnMut(StatusCalculatorEvent),
@@ -212,26 +363,74 @@ where
         }
     };
 
-    let active_spec_maybe = match &context {
-        ToolContext::Yb(yb_env) => yb_env.active_spec_status(),
+    let prospective_spec_status = options
+        .prospective_spec
+        .clone()
+        .map(ActiveSpecStatus::Active);
+    let active_spec_maybe = match (&prospective_spec_status, &context) {
+        (Some(prospective_spec_status), _) => Some(prospective_spec_status),
+        (None, ToolContext::Yb(yb_env)) => yb_env.active_spec_status(),
         _ => None,
     };
 
-    let sources_subdirs_with_repo = sources_subdirs
+    if let ToolContext::Yb(yb_env) = &context {
+        options
+            .preferred_remotes
+            .extend(yb_env.user_conf().preferred_remotes().iter().cloned());
+        for (spec_url, aliases) in yb_env.user_conf().url_aliases() {
+            options
+                .url_aliases
+                .entry(spec_url.clone())
+                .or_default()
+                .extend(aliases.iter().cloned());
+        }
+    }
+
+    let mut sources_subdirs_with_repo = sources_subdirs
         .iter()
         // TODO: this throws out all errors from `discover`
         .map(|d| (d, Repository::discover(d).ok()))
         .collect::<Vec<_>>();
 
-    let repos = sources_subdirs_with_repo
-        .iter()
-        .filter_map(|v| v.1.as_ref());
+    let subdir_repos = || {
+        sources_subdirs_with_repo
+            .iter()
+            .filter_map(|(subdir, repo)| repo.as_ref().map(|repo| (*subdir, repo)))
+    };
+
+    if options.skip_duplicate_workdirs {
+        // How many times to skip each path; a symlinked source dir has the same (canonical)
+        // path as the one it links to
+        let mut skipped: HashMap<PathBuf, usize> = HashMap::new();
+        for duplicate in find_duplicate_workdirs(subdir_repos())? {
+            for subdir in duplicate.extraneous_subdirs() {
+                c(StatusCalculatorEvent::DuplicateWorkdirSkipped {
+                    path: subdir,
+                    workdir: &duplicate.workdir,
+                });
+                *skipped.entry(subdir.clone()).or_default() += 1;
+            }
+        }
+        sources_subdirs_with_repo.retain(|(subdir, _)| match skipped.get_mut(*subdir) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        });
+    } else {
+        check_repository_workdirs_unique(subdir_repos())?;
+    }
 
-    check_repository_workdirs_unique(repos.clone())?;
+    let number_subdirs = sources_subdirs_with_repo.len() as u64;
+    let number_repos = sources_subdirs_with_repo
+        .iter()
+        .filter(|(_, repo)| repo.is_some())
+        .count() as u64;
 
     c(StatusCalculatorEvent::Start {
-        number_subdirs: sources_subdirs.len() as u64,
-        number_repos: repos.count() as u64,
+        number_subdirs,
+        number_repos,
     });
 
     // If a spec is active, get the expected set of repos, otherwise empty.
@@ -240,36 +439,73 @@ where
     let mut active_spec_repos = active_spec_maybe
         .map(|s| match s {
             ActiveSpecStatus::StreamsBroken(..) => unimplemented!(),
-            ActiveSpecStatus::Active(active_spec) => active_spec.spec.repos.clone(),
+            ActiveSpecStatus::Active(active_spec) => active_spec
+                .spec
+                .enabled_repos()
+                .map(|(name, spec_repo)| (name.clone(), spec_repo.clone()))
+                .collect(),
         })
         .unwrap_or_default();
 
-    let mut status_entries: Vec<ComputedStatusEntry> = Vec::with_capacity(sources_subdirs.len());
+    // Clone the spec repos that on-disk repos might share history with all at once, rather than
+    // one after another as each repo is checked
+    let related_revisions = RelatedRepoRevisions::compute(
+        sources_subdirs_with_repo
+            .iter()
+            .filter_map(|(_, repo)| repo.as_ref())
+            .filter(|repo| !is_interrupted_clone(repo)),
+        &active_spec_repos,
+        &options.url_aliases,
+        options.fetch_limiter.as_ref(),
+    )?;
+
+    let mut status_entries: Vec<ComputedStatusEntry> =
+        Vec::with_capacity(sources_subdirs_with_repo.len());
     for (subdir, repo_maybe) in sources_subdirs_with_repo {
         let subdir_name = subdir.file_name().unwrap().to_str().unwrap().to_string();
         c(StatusCalculatorEvent::StartProcessSubdir {
             dirname: subdir_name.clone(),
         });
 
-        if let Some(repo) = repo_maybe {
-            let status =
-                compute_repo_status(repo, subdir, &mut options, &active_spec_repos, &mut c)?;
-            if let ComputedStatusEntry::OnDiskRepo(OnDiskRepoStatus {
-                corresponding_spec_repo: Some(c),
-                ..
-            }) = &status
+        match repo_maybe {
+            Some(repo)
+                if is_interrupted_clone(&repo) && active_spec_repos.contains_key(&subdir_name) =>
             {
-                active_spec_repos.remove(&c.spec_repo_name());
+                // Leave the spec repo missing, so that sync plans a clone (which resumes or
+                // restarts this one)
+                tracing::warn!(
+                    "{} looks like an interrupted clone, treating it as missing",
+                    subdir.display()
+                );
+            }
+            Some(repo) => {
+                let status = compute_repo_status(
+                    repo,
+                    subdir,
+                    &mut options,
+                    &active_spec_repos,
+                    &related_revisions,
+                    &mut c,
+                )?;
+                if let ComputedStatusEntry::OnDiskRepo(OnDiskRepoStatus {
+                    corresponding_spec_repo: Some(c),
+                    ..
+                }) = &status
+                {
+                    active_spec_repos.remove(&c.spec_repo_name());
+                }
+
+                c(StatusCalculatorEvent::SubdirStatusComputed(&status));
+                status_entries.push(status);
+            }
+            None => {
+                let status = ComputedStatusEntry::OnDiskNonRepo(OnDiskNonRepoStatus {
+                    path: subdir.clone(),
+                    looks_like_layer: looks_like_layer_dir(&subdir),
+                });
+                c(StatusCalculatorEvent::SubdirStatusComputed(&status));
+                status_entries.push(status);
             }
-
-            c(StatusCalculatorEvent::SubdirStatusComputed(&status));
-            status_entries.push(status);
-        } else {
-            let status = ComputedStatusEntry::OnDiskNonRepo(OnDiskNonRepoStatus {
-                path: subdir.clone(),
-            });
-            c(StatusCalculatorEvent::SubdirStatusComputed(&status));
-            status_entries.push(status);
         }
 
         c(StatusCalculatorEvent::FinishProcessSubdir);
@@ -301,6 +537,15 @@ where
     Ok(ret)
 }
 
+/// Whether `repo` looks like what a clone that was interrupted before checking anything out leaves
+/// behind: a repo whose HEAD is unborn (or missing)
+fn is_interrupted_clone(repo: &Repository) -> bool {
+    match repo.head() {
+        Ok(_) => false,
+        Err(e) => matches!(e.code(), ErrorCode::UnbornBranch | ErrorCode::NotFound),
+    }
+}
+
 pub enum StatusCalculatorEvent<'a> {
     Start {
         number_repos: u64,
@@ -317,5 +562,10 @@ pub enum StatusCalculatorEvent<'a> {
     SubdirStatusComputed(&'a ComputedStatusEntry),
     FinishProcessSubdir,
     MissingReposDetected(&'a Vec<MissingRepo>),
+    /// A source dir was left out because it resolves to the same git workdir as another
+    DuplicateWorkdirSkipped {
+        path: &'a Path,
+        workdir: &'a Path,
+    },
     Finish(&'a ComputedStatus),
 }
@@ -0,0 +1,162 @@
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use crate::status_calculator::StatusCalculatorEvent;
+
+/// Tracks where the time went while computing a status, driven entirely by the
+/// `StatusCalculatorEvent`s emitted by `compute_status`.
+#[derive(Debug, Default)]
+pub struct StatusTimings {
+    started: Option<Instant>,
+    total: Option<Duration>,
+    current_subdir: Option<String>,
+    fetch_started: Option<Instant>,
+    operation_started: Option<(String, Instant)>,
+    /// (subdir, duration) for each fetch
+    fetches: Vec<(String, Duration)>,
+    /// (subdir, operation, duration) for other per-subdir operations, e.g. cloning a spec repo to
+    /// check whether it is related
+    operations: Vec<(String, String, Duration)>,
+    rendering: Duration,
+}
+
+impl StatusTimings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(&mut self, event: &StatusCalculatorEvent) {
+        let now = Instant::now();
+        match event {
+            StatusCalculatorEvent::Start { .. } => {
+                self.started = Some(now);
+            }
+            StatusCalculatorEvent::StartProcessSubdir { dirname } => {
+                self.current_subdir = Some(dirname.clone());
+            }
+            StatusCalculatorEvent::StartFetch => {
+                self.fetch_started = Some(now);
+            }
+            StatusCalculatorEvent::FinishFetch => {
+                if let Some(started) = self.fetch_started.take() {
+                    self.fetches
+                        .push((self.subdir_name(), now.duration_since(started)));
+                }
+            }
+            StatusCalculatorEvent::StartSubdirOperation { operation_name } => {
+                // An empty operation name marks the end of the previous operation
+                if let Some((name, started)) = self.operation_started.take() {
+                    self.operations
+                        .push((self.subdir_name(), name, now.duration_since(started)));
+                }
+                if !operation_name.is_empty() {
+                    self.operation_started = Some((operation_name.clone(), now));
+                }
+            }
+            StatusCalculatorEvent::FinishProcessSubdir => {
+                self.current_subdir = None;
+            }
+            StatusCalculatorEvent::Finish(..) => {
+                self.total = self.started.map(|started| now.duration_since(started));
+            }
+            _ => {}
+        }
+    }
+
+    /// Account time spent rendering (i.e. in the event callback) separately from the status
+    /// calculation itself.
+    pub fn add_rendering_time(&mut self, duration: Duration) {
+        self.rendering += duration;
+    }
+
+    pub fn total_fetch_time(&self) -> Duration {
+        self.fetches.iter().map(|(_, duration)| *duration).sum()
+    }
+
+    fn subdir_name(&self) -> String {
+        self.current_subdir.clone().unwrap_or_default()
+    }
+
+    /// Format a table summarizing the timings
+    pub fn summary(&self) -> String {
+        let mut rows = vec![("total".to_string(), self.total.unwrap_or_default())];
+        rows.push(("fetch".to_string(), self.total_fetch_time()));
+        for (subdir, duration) in &self.fetches {
+            rows.push((format!("  {subdir}"), *duration));
+        }
+        for (subdir, operation, duration) in &self.operations {
+            rows.push((format!("{subdir}: {operation}"), *duration));
+        }
+        rows.push(("rendering".to_string(), self.rendering));
+
+        let width = rows.iter().map(|(label, _)| label.len()).max().unwrap();
+        let mut ret = String::from("timings:\n");
+        for (label, duration) in rows {
+            writeln!(ret, "  {label:width$}  {:>8.3}s", duration.as_secs_f64()).unwrap();
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use crate::data_model::status::ComputedStatus;
+    use crate::status_calculator::timings::StatusTimings;
+    use crate::status_calculator::StatusCalculatorEvent;
+
+    /// The seconds shown in `summary` for the row labelled `label`
+    fn summary_seconds(summary: &str, label: &str) -> f64 {
+        let line = summary
+            .lines()
+            .find(|line| line.trim_start().starts_with(&format!("{label} ")))
+            .unwrap_or_else(|| panic!("no '{label}' row in {summary}"));
+        line.split_whitespace()
+            .last()
+            .unwrap()
+            .trim_end_matches('s')
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn fetch_and_total_times_cover_the_fetch() {
+        let status = ComputedStatus {
+            source_dirs: vec![],
+            enabled_layers: HashSet::new(),
+            missing_repos: vec![],
+            active_spec: None,
+            bblayers_path: PathBuf::from("/yocto/build/conf/bblayers.conf"),
+        };
+        let fetch_duration = Duration::from_millis(200);
+
+        let mut timings = StatusTimings::new();
+        timings.observe(&StatusCalculatorEvent::Start {
+            number_repos: 1,
+            number_subdirs: 1,
+        });
+        timings.observe(&StatusCalculatorEvent::StartProcessSubdir {
+            dirname: "meta-foo".to_string(),
+        });
+        timings.observe(&StatusCalculatorEvent::StartFetch);
+        sleep(fetch_duration);
+        timings.observe(&StatusCalculatorEvent::FinishFetch);
+        timings.observe(&StatusCalculatorEvent::FinishProcessSubdir);
+        timings.observe(&StatusCalculatorEvent::Finish(&status));
+        timings.add_rendering_time(Duration::from_millis(1500));
+
+        assert!(timings.total_fetch_time() >= fetch_duration);
+
+        let summary = timings.summary();
+        let fetch = summary_seconds(&summary, "fetch");
+        // Generous upper bound, in case the machine running the tests is busy
+        assert!((0.2..2.0).contains(&fetch), "{summary}");
+        assert_eq!(summary_seconds(&summary, "meta-foo"), fetch);
+        assert!(summary_seconds(&summary, "total") >= fetch, "{summary}");
+        assert_eq!(summary_seconds(&summary, "rendering"), 1.5);
+    }
+}
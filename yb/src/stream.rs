@@ -1,21 +1,27 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::fmt::{Debug, Formatter};
-use std::fs::File;
+use std::fs::{self, File};
+use std::io;
 use std::path::PathBuf;
+use std::time::SystemTime;
 
 use std::sync::{Arc, Mutex};
 
-use git2::{FetchOptions, Repository};
+use color_eyre::Help;
+use eyre::WrapErr;
+use git2::build::CheckoutBuilder;
+use git2::{BranchType, FetchOptions, Oid, Repository};
 use serde::{Deserialize, Serialize};
 use walkdir::WalkDir;
 
+use crate::data_model::git::RemoteTrackingBranch;
 use crate::errors::YbResult;
-use crate::spec::Spec;
+use crate::spec::{Spec, SpecDiff};
 use crate::stream_db::StreamKey;
 use crate::util::git::{
     do_merge, get_current_local_branch_name, get_remote_name_for_current_branch,
-    ssh_agent_remote_callbacks,
+    get_remote_tracking_branch_for_current_local_branch, ssh_agent_remote_callbacks,
 };
 use crate::util::paths::{is_hidden, is_yaml_file};
 
@@ -23,16 +29,24 @@ use crate::util::paths::{is_hidden, is_yaml_file};
 const STREAM_CONFIG_FILE_VERSION: u32 = 1;
 pub const STREAM_CONTENT_ROOT_SUBDIR: &str = "contents";
 pub const STREAM_CONFIG_FILE: &str = "stream.yaml";
+/// The commit and specs of the stream as of the last time yb looked, to report what changed
+const LAST_SEEN_FILE: &str = "last_seen.yaml";
 
 #[derive(Debug, Deserialize, Serialize, Eq, PartialEq)]
 pub enum StreamKind {
     Git,
+    /// Specs live directly in the stream's contents directory; there is no upstream to fetch from
+    Local,
 }
 
 #[derive(Debug, Deserialize, Serialize, Eq, PartialEq)]
 pub struct StreamConfig {
     kind: StreamKind,
     format_version: u32,
+    /// Branch, tag, or commit the stream is pinned to. When set, updating the stream checks out
+    /// this ref instead of fast-forwarding the current branch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pinned_ref: Option<String>,
 }
 
 impl StreamConfig {
@@ -40,6 +54,7 @@ impl StreamConfig {
         StreamConfig {
             kind,
             format_version: STREAM_CONFIG_FILE_VERSION,
+            pinned_ref: None,
         }
     }
 }
@@ -47,7 +62,8 @@ impl StreamConfig {
 pub struct Stream {
     path: PathBuf,
     name: String,
-    repo: Mutex<Repository>,
+    /// None for local streams
+    repo: Option<Mutex<Repository>>,
     config: StreamConfig,
     specs: StreamSpecs,
     key: StreamKey,
@@ -82,13 +98,16 @@ impl Stream {
         let config: StreamConfig = serde_yaml::from_reader(&f)?;
 
         let stream_contents_dir = path.join(STREAM_CONTENT_ROOT_SUBDIR);
-        let repo = Repository::discover(&stream_contents_dir)?;
+        let repo = match config.kind {
+            StreamKind::Git => Some(Mutex::new(Repository::discover(&stream_contents_dir)?)),
+            StreamKind::Local => None,
+        };
 
         Ok(Stream {
             path,
             name,
             specs: Self::load_specs(stream_contents_dir, stream_key)?,
-            repo: Mutex::new(repo),
+            repo,
             config,
             key: stream_key,
         })
@@ -119,27 +138,61 @@ impl Stream {
     }
 
     pub fn fetch(&self) -> YbResult<()> {
-        let repo = self.repo.lock().unwrap();
+        let repo = match &self.repo {
+            Some(repo) => repo.lock().unwrap(),
+            None => return Ok(()),
+        };
 
-        let upstream_name = get_remote_name_for_current_branch(&repo)?.unwrap();
-
-        let mut remote = repo.find_remote(&upstream_name)?;
         let mut fetch_options = FetchOptions::new();
         fetch_options.remote_callbacks(ssh_agent_remote_callbacks());
-        remote.fetch(&[] as &[&str], Some(&mut fetch_options), None)?;
+
+        if self.config.pinned_ref.is_some() || repo.head_detached()? {
+            // The pinned ref may be any branch or tag, so fetch everything
+            let upstream_name = self.upstream_remote_name(&repo)?;
+            let mut remote = repo.find_remote(&upstream_name)?;
+            remote.fetch(&[] as &[&str], Some(&mut fetch_options), None)?;
+        } else {
+            // Fetch the tracked branch explicitly, since the remote's default refspec may not
+            // cover it (e.g. a stream added from a non-default branch of a single-branch clone)
+            let tracked = self.tracked_branch(&repo)?;
+            let mut remote = repo.find_remote(&tracked.remote_name)?;
+            let refspec = format!(
+                "+refs/heads/{}:refs/remotes/{}/{}",
+                tracked.branch_name, tracked.remote_name, tracked.branch_name
+            );
+            remote.fetch(&[refspec.as_str()], Some(&mut fetch_options), None)?;
+        }
+
         Ok(())
     }
 
+    /// The remote branch the stream's current branch tracks
+    fn tracked_branch(&self, repo: &Repository) -> YbResult<RemoteTrackingBranch> {
+        let branch_name = get_current_local_branch_name(repo)?;
+        get_remote_tracking_branch_for_current_local_branch(repo)?.ok_or_else(|| {
+            eyre::eyre!(
+                "stream '{}' is on branch '{}', which has no upstream branch to update from",
+                self.name,
+                branch_name
+            )
+            .suggestion(format!(
+                "set one with 'git branch --set-upstream-to=<remote>/<branch>' in {}",
+                self.path.join(STREAM_CONTENT_ROOT_SUBDIR).display()
+            ))
+        })
+    }
+
     pub fn pull(&mut self) -> YbResult<()> {
         self.fetch()?;
+        self.merge_fetched()
+    }
 
-        let repo = self.repo.lock().unwrap();
-        let current_branch_name = get_current_local_branch_name(&repo)?;
-
-        let fetch_head = repo.find_reference("FETCH_HEAD")?;
-        let fetch_commit = repo.reference_to_annotated_commit(&fetch_head)?;
-
-        do_merge(&repo, &current_branch_name, fetch_commit)?;
+    /// Bring the stream up to date with what was last fetched (see `fetch`) and reload its specs.
+    /// Split out from `pull` so that several streams can be fetched concurrently.
+    pub fn merge_fetched(&mut self) -> YbResult<()> {
+        if let Some(repo) = &self.repo {
+            self.checkout_fetched(&repo.lock().unwrap())?;
+        }
 
         let stream_contents_dir = self.path.join(STREAM_CONTENT_ROOT_SUBDIR);
         self.specs = Self::load_specs(stream_contents_dir, self.key)?;
@@ -147,6 +200,181 @@ impl Stream {
         Ok(())
     }
 
+    fn checkout_fetched(&self, repo: &Repository) -> YbResult<()> {
+        if let Some(pinned_ref) = &self.config.pinned_ref {
+            let upstream_name = self.upstream_remote_name(repo)?;
+            let oid = resolve_pinned_ref(repo, &upstream_name, pinned_ref)?;
+            let commit = repo.find_commit(oid)?;
+            repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().force()))?;
+            repo.set_head_detached(oid)?;
+        } else {
+            if repo.head_detached()? {
+                // The stream was previously pinned; go back to the branch it was tracking
+                reattach_to_tracking_branch(repo)?;
+            }
+
+            let current_branch_name = get_current_local_branch_name(repo)?;
+
+            let tracked = self.tracked_branch(repo)?;
+            let tracked_ref = repo.find_reference(&format!(
+                "refs/remotes/{}/{}",
+                tracked.remote_name, tracked.branch_name
+            ))?;
+            let fetch_commit = repo.reference_to_annotated_commit(&tracked_ref)?;
+
+            do_merge(repo, &current_branch_name, fetch_commit)?;
+        }
+
+        Ok(())
+    }
+
+    /// Pin the stream to `pinned_ref` (or unpin it if None) and save the stream config. The
+    /// change takes effect the next time the stream is pulled.
+    pub fn set_pinned_ref(&mut self, pinned_ref: Option<String>) -> YbResult<()> {
+        if self.config.kind == StreamKind::Local {
+            eyre::bail!("stream '{}' is local and cannot be pinned", self.name);
+        }
+
+        self.config.pinned_ref = pinned_ref;
+
+        let config_file_path = self.path.join(STREAM_CONFIG_FILE);
+        let f = File::create(&config_file_path).with_context(|| {
+            format!(
+                "failed to open file {} for writing",
+                config_file_path.display()
+            )
+        })?;
+        serde_yaml::to_writer(f, &self.config)?;
+
+        Ok(())
+    }
+
+    /// Short ID of the commit the stream is currently at, or None for local streams
+    pub fn head_short_id(&self) -> YbResult<Option<String>> {
+        let repo = match &self.repo {
+            Some(repo) => repo.lock().unwrap(),
+            None => return Ok(None),
+        };
+
+        let head = repo.head()?.peel_to_commit()?;
+        let short_id = head.as_object().short_id()?;
+        Ok(short_id.as_str().map(String::from))
+    }
+
+    /// Full ID of the commit the stream is currently at, or None for local streams
+    pub fn current_commit(&self) -> YbResult<Option<String>> {
+        let repo = match &self.repo {
+            Some(repo) => repo.lock().unwrap(),
+            None => return Ok(None),
+        };
+
+        let head = repo.head()?.peel_to_commit()?;
+        Ok(Some(head.id().to_string()))
+    }
+
+    pub fn pinned_ref(&self) -> Option<&String> {
+        self.config.pinned_ref.as_ref()
+    }
+
+    /// URL of the remote the stream updates from, or None for local streams
+    pub fn remote_url(&self) -> YbResult<Option<String>> {
+        let repo = match &self.repo {
+            Some(repo) => repo.lock().unwrap(),
+            None => return Ok(None),
+        };
+
+        let remote = repo.find_remote(&self.upstream_remote_name(&repo)?)?;
+        Ok(remote.url().map(String::from))
+    }
+
+    /// The remote branch the stream follows, or None for local streams and pinned streams (whose
+    /// HEAD is detached)
+    pub fn upstream_branch(&self) -> YbResult<Option<RemoteTrackingBranch>> {
+        let repo = match &self.repo {
+            Some(repo) => repo.lock().unwrap(),
+            None => return Ok(None),
+        };
+
+        if repo.head_detached()? {
+            return Ok(None);
+        }
+        get_remote_tracking_branch_for_current_local_branch(&repo)
+    }
+
+    /// When the stream was last fetched, going by FETCH_HEAD. None for local streams and streams
+    /// that haven't been fetched since they were cloned.
+    pub fn last_fetched(&self) -> YbResult<Option<SystemTime>> {
+        let repo = match &self.repo {
+            Some(repo) => repo.lock().unwrap(),
+            None => return Ok(None),
+        };
+
+        match fs::metadata(repo.path().join("FETCH_HEAD")) {
+            Ok(metadata) => Ok(Some(metadata.modified()?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The remote to fetch from. A pinned stream has a detached HEAD, so in that case fall back
+    /// to 'origin' (or the only remote).
+    fn upstream_remote_name(&self, repo: &Repository) -> YbResult<String> {
+        if !repo.head_detached()? {
+            if let Some(name) = get_remote_name_for_current_branch(repo)? {
+                return Ok(name);
+            }
+        }
+
+        let remotes = repo.remotes()?;
+        if remotes.iter().any(|remote| remote == Some("origin")) {
+            return Ok("origin".into());
+        }
+
+        remotes
+            .get(0)
+            .map(String::from)
+            .ok_or_else(|| eyre::eyre!("stream '{}' has no remotes", self.name))
+    }
+
+    /// Compare the stream's commit and specs with what the previous call recorded, then record
+    /// the current ones. Returns None the first time, if the stream hasn't moved since, and for
+    /// local or broken streams.
+    pub fn take_changes_since_last_seen(&self) -> YbResult<Option<StreamSpecChanges>> {
+        let commit = match self.current_commit()? {
+            Some(commit) => commit,
+            None => return Ok(None),
+        };
+        let specs = match &self.specs {
+            StreamSpecs::Loaded(specs) => specs,
+            StreamSpecs::Broken(..) => return Ok(None),
+        };
+
+        let last_seen_path = self.path.join(LAST_SEEN_FILE);
+        // A record that can't be read is simply replaced
+        let last_seen = File::open(&last_seen_path)
+            .ok()
+            .and_then(|f| serde_yaml::from_reader::<_, LastSeen>(f).ok());
+        if last_seen
+            .as_ref()
+            .map_or(false, |last_seen| last_seen.commit == commit)
+        {
+            return Ok(None);
+        }
+
+        let current = LastSeen {
+            commit,
+            specs: specs
+                .iter()
+                .map(|(name, spec)| (name.clone(), spec.clone()))
+                .collect(),
+        };
+        let f = File::create(&last_seen_path)
+            .wrap_err_with(|| format!("failed to open {} for writing", last_seen_path.display()))?;
+        serde_yaml::to_writer(f, &current)?;
+
+        Ok(last_seen.map(|last_seen| StreamSpecChanges::new(&last_seen, &current)))
+    }
+
     pub fn get_spec_by_name<S: AsRef<str>>(&self, name: S) -> Option<&Spec> {
         match &self.specs {
             StreamSpecs::Loaded(specs) => specs.get(name.as_ref()),
@@ -158,6 +386,14 @@ impl Stream {
         &self.name
     }
 
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn kind(&self) -> &StreamKind {
+        &self.config.kind
+    }
+
     pub fn key(&self) -> StreamKey {
         self.key
     }
@@ -181,8 +417,96 @@ impl Stream {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct LastSeen {
+    commit: String,
+    specs: BTreeMap<String, Spec>,
+}
+
+/// How the specs of a stream changed from one commit to another
+#[derive(Debug)]
+pub struct StreamSpecChanges {
+    pub from_commit: String,
+    pub to_commit: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Specs that differ in any way, along with how their repos differ (which may be not at all,
+    /// e.g. if only `local_conf` changed)
+    pub modified: Vec<(String, SpecDiff)>,
+}
+
+impl StreamSpecChanges {
+    fn new(from: &LastSeen, to: &LastSeen) -> Self {
+        let mut changes = Self {
+            from_commit: from.commit.clone(),
+            to_commit: to.commit.clone(),
+            added: vec![],
+            removed: vec![],
+            modified: vec![],
+        };
+
+        for (name, spec) in &to.specs {
+            match from.specs.get(name) {
+                None => changes.added.push(name.clone()),
+                Some(old_spec) if old_spec != spec => changes
+                    .modified
+                    .push((name.clone(), SpecDiff::new(old_spec, spec))),
+                Some(_) => {}
+            }
+        }
+        changes.removed = from
+            .specs
+            .keys()
+            .filter(|name| !to.specs.contains_key(*name))
+            .cloned()
+            .collect();
+
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
 #[derive(Debug)]
 pub enum StreamSpecs {
     Loaded(HashMap<String, Spec>),
     Broken(Arc<eyre::Report>),
 }
+
+/// Resolve `pinned_ref` to a commit, preferring a branch of the remote `remote_name`, then a tag,
+/// then any other revision (e.g. a commit hash).
+fn resolve_pinned_ref(repo: &Repository, remote_name: &str, pinned_ref: &str) -> YbResult<Oid> {
+    let candidates = [
+        format!("refs/remotes/{remote_name}/{pinned_ref}"),
+        format!("refs/tags/{pinned_ref}"),
+        pinned_ref.to_string(),
+    ];
+
+    for candidate in &candidates {
+        if let Ok(object) = repo.revparse_single(candidate) {
+            return Ok(object.peel_to_commit()?.id());
+        }
+    }
+
+    eyre::bail!("pinned ref '{}' not found", pinned_ref)
+}
+
+/// Check out the first local branch that has an upstream branch
+fn reattach_to_tracking_branch(repo: &Repository) -> YbResult<()> {
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        if branch.upstream().is_ok() {
+            let refname = branch
+                .get()
+                .name()
+                .ok_or_else(|| eyre::eyre!("branch has no name"))?;
+            repo.set_head(refname)?;
+            repo.checkout_head(Some(CheckoutBuilder::new().force()))?;
+            return Ok(());
+        }
+    }
+
+    eyre::bail!("no local branch with an upstream found to return to after unpinning")
+}
@@ -1,7 +1,8 @@
-use crate::errors::YbResult;
+use crate::errors::{YbError, YbResult};
 use crate::spec::{ActiveSpec, Spec};
 use crate::stream::Stream;
 use crate::util::paths::is_hidden;
+use color_eyre::Help;
 use eyre::Context;
 use slotmap::{new_key_type, SlotMap};
 use std::collections::HashMap;
@@ -78,20 +79,48 @@ impl StreamDb {
             .map(|item| item.1)
     }
 
-    pub fn find_spec_by_name<N: AsRef<str>>(&self, name: N) -> YbResult<Option<&Spec>> {
-        let mut ret = None;
-
-        for stream in self.streams.values() {
-            let s = stream.get_spec_by_name(&name);
-            if s.is_some() {
-                if ret.is_some() {
-                    eyre::bail!("spec '{}' found in multiple streams", name.as_ref());
-                }
-                ret = s;
+    /// Find a spec by name across all streams. `name` may be qualified as `stream/spec` to pick
+    /// the spec from a particular stream; an unqualified name must be unique across streams.
+    pub fn find_spec_by_name<N: AsRef<str>>(&self, name: N) -> YbResult<&Spec> {
+        let name = name.as_ref();
+        let not_found = || -> eyre::Report {
+            YbError::SpecNotFound {
+                name: name.to_string(),
             }
+            .into()
+        };
+
+        if let Some((stream_name, spec_name)) = name.split_once('/') {
+            let stream = self.get_stream_by_name(stream_name).ok_or_else(|| {
+                eyre::eyre!(
+                    "spec '{}' names a stream '{}' that doesn't exist",
+                    name,
+                    stream_name
+                )
+            })?;
+            return stream.get_spec_by_name(spec_name).ok_or_else(not_found);
         }
 
-        Ok(ret)
+        let mut matches: Vec<(&Stream, &Spec)> = self
+            .streams
+            .values()
+            .filter_map(|stream| stream.get_spec_by_name(name).map(|spec| (stream, spec)))
+            .collect();
+        if matches.len() > 1 {
+            matches.sort_by(|a, b| a.0.name().cmp(b.0.name()));
+            let qualified: Vec<_> = matches
+                .iter()
+                .map(|(stream, _)| format!("'{}/{}'", stream.name(), name))
+                .collect();
+            return Err(eyre::eyre!("spec '{}' found in multiple streams", name)
+                .suggestion(format!(
+                    "qualify the name with its stream: {}",
+                    qualified.join(" or ")
+                ))
+                .suppress_backtrace(true));
+        }
+
+        matches.pop().map(|(_, spec)| spec).ok_or_else(not_found)
     }
 
     pub fn stream(&self, stream_key: StreamKey) -> Option<&Stream> {
@@ -119,11 +148,18 @@ impl StreamDb {
 
             active_spec.stream_key = stream.key();
         } else {
-            eyre::bail!(
+            let err = eyre::eyre!(
                 "active spec '{}' refers to non-existent stream '{}'",
                 active_spec.name(),
                 active_spec.from_stream
             );
+            return Err(match &active_spec.spec_file {
+                Some(spec_file) => err.suggestion(format!(
+                    "it was activated from {}; re-run 'yb activate --spec-file' with it",
+                    spec_file.display()
+                )),
+                None => err,
+            });
         }
 
         Ok(active_spec)
@@ -135,6 +171,7 @@ impl StreamDb {
             Ok(ActiveSpec {
                 spec,
                 from_stream: stream.name().clone(),
+                spec_file: None,
                 stream_key: key,
             })
         } else {
@@ -45,16 +45,21 @@ pub fn ui_op_check_broken_streams(options: UiCheckBrokenStreamsOptions) -> YbRes
         options.mp.note("error information follows below:");
         options.mp.suspend(|| eprintln!("{:?}", &broken));
         options.mp.println("")?;
-        options
-            .mp
-            .note("would you like to try refresh the broken streams?");
-        let confirm_result = options.mp.suspend(|| -> YbResult<bool> {
-            Confirm::new()
-                .with_prompt("Refresh streams?")
-                .wait_for_newline(true)
-                .interact()
-                .map_err(|e| e.into())
-        })?;
+        let confirm_result = if options.config.assume_yes {
+            options.mp.note("refreshing the broken streams (--yes)");
+            true
+        } else {
+            options
+                .mp
+                .note("would you like to try refresh the broken streams?");
+            options.mp.suspend(|| -> YbResult<bool> {
+                Confirm::new()
+                    .with_prompt("Refresh streams?")
+                    .wait_for_newline(true)
+                    .interact()
+                    .map_err(|e| e.into())
+            })?
+        };
 
         if !confirm_result {
             options
@@ -6,6 +6,7 @@ use crate::config::Config;
 use crate::core::tool_context::maybe_yb_env;
 use crate::errors::YbResult;
 use crate::ops::update_stream::{op_update_stream, UpdateStreamEvent, UpdateStreamOptions};
+use crate::stream::StreamSpecChanges;
 use crate::util::indicatif::{IndicatifHelpers, MultiProgressHelpers};
 
 use crate::yb_env::ActiveSpecStatus;
@@ -16,6 +17,7 @@ pub struct UiUpdateStreamOptions<'cfg> {
     mp: &'cfg MultiProgress,
     verbose: bool,
     fail_if_no_yb_env: bool,
+    stream_diff: bool,
 }
 
 impl<'cfg> UiUpdateStreamOptions<'cfg> {
@@ -25,6 +27,7 @@ impl<'cfg> UiUpdateStreamOptions<'cfg> {
             mp,
             verbose: false,
             fail_if_no_yb_env: false,
+            stream_diff: true,
         }
     }
 
@@ -37,6 +40,36 @@ impl<'cfg> UiUpdateStreamOptions<'cfg> {
         self.verbose = val;
         self
     }
+
+    /// Note which specs changed if a stream moved since the last time yb looked at it
+    pub fn stream_diff(&mut self, val: bool) -> &mut Self {
+        self.stream_diff = val;
+        self
+    }
+}
+
+/// A note describing how the specs of `stream_name` changed
+fn format_stream_changes(stream_name: &str, changes: &StreamSpecChanges) -> String {
+    let short = |commit: &str| commit.chars().take(7).collect::<String>();
+    let mut lines = vec![format!(
+        "stream '{}' changed since the last run ({} → {}):",
+        stream_name,
+        short(&changes.from_commit),
+        short(&changes.to_commit)
+    )];
+    for name in &changes.added {
+        lines.push(format!("    spec '{name}' added"));
+    }
+    for name in &changes.removed {
+        lines.push(format!("    spec '{name}' removed"));
+    }
+    for (name, diff) in &changes.modified {
+        lines.push(format!("    spec '{name}' modified"));
+        for transition in diff.transitions() {
+            lines.push(format!("        {transition}"));
+        }
+    }
+    lines.join("\n")
 }
 
 pub fn ui_op_update_stream(options: UiUpdateStreamOptions) -> YbResult<()> {
@@ -75,6 +108,11 @@ pub fn ui_op_update_stream(options: UiUpdateStreamOptions) -> YbResult<()> {
         }
     }
 
+    let stream_names = streams
+        .iter()
+        .filter_map(|key| yb_env.stream_db().stream(*key))
+        .map(|stream| stream.name().clone())
+        .collect::<Vec<_>>();
     let update_opts = UpdateStreamOptions::new(options.config, streams);
 
     // TODO report result in porcelain
@@ -102,5 +140,21 @@ pub fn ui_op_update_stream(options: UiUpdateStreamOptions) -> YbResult<()> {
         }
     })?;
 
+    // Reload to see the streams as updated. This records what was seen even with the diff
+    // turned off, so that turning it back on doesn't report stale changes.
+    if let Some(yb_env) = maybe_yb_env(options.config)? {
+        for stream in stream_names
+            .iter()
+            .filter_map(|name| yb_env.stream_db().get_stream_by_name(name))
+        {
+            match stream.take_changes_since_last_seen()? {
+                Some(changes) if options.stream_diff && !changes.is_empty() => options
+                    .mp
+                    .note(format_stream_changes(stream.name(), &changes)),
+                _ => {}
+            }
+        }
+    }
+
     Ok(())
 }
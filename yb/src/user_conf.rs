@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::YbResult;
+
+/// Settings of a yb environment that users edit themselves, kept in .yb/config.toml (unlike
+/// yb.yaml, which yb manages). The file is optional; a missing one means all defaults.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UserConf {
+    /// Default for `yb sync --parallel-fetch-limit`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parallel_fetch_limit: Option<usize>,
+
+    /// Remote names to prefer, in order, when several remotes of a repo match a spec repo (e.g.
+    /// 'origin' and a mirror with the same URL)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    preferred_remotes: Vec<String>,
+
+    /// User commands to run at certain points, e.g. around `yb sync --apply`
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    hooks: Hooks,
+
+    /// Other remote URLs to accept as a spec repo's URL (e.g. an internal mirror of it), keyed by
+    /// the spec repo's URL. See `yb sync --prompt-related`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    url_aliases: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Hooks {
+    /// Run (via `sh -c`) before sync actions are applied. If it fails the sync is aborted.
+    pub pre_sync: Option<String>,
+
+    /// Run (via `sh -c`) after sync actions have been applied
+    pub post_sync: Option<String>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.pre_sync.is_none() && self.post_sync.is_none()
+    }
+}
+
+impl UserConf {
+    /// Load `path`, or the defaults if it doesn't exist
+    pub fn load(path: &Path) -> YbResult<Self> {
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+
+        toml::from_str(&data).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// Write the settings to `path`. Any comments in an existing file are lost.
+    pub fn save(&self, path: &Path) -> YbResult<()> {
+        fs::write(path, toml::to_string(self)?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
+    pub fn parallel_fetch_limit(&self) -> Option<usize> {
+        self.parallel_fetch_limit
+    }
+
+    pub fn preferred_remotes(&self) -> &[String] {
+        &self.preferred_remotes
+    }
+
+    pub fn url_aliases(&self) -> &HashMap<String, Vec<String>> {
+        &self.url_aliases
+    }
+
+    pub fn add_url_alias(&mut self, spec_url: String, alias: String) {
+        let aliases = self.url_aliases.entry(spec_url).or_default();
+        if !aliases.contains(&alias) {
+            aliases.push(alias);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::user_conf::UserConf;
+    use crate::util::debug_temp_dir::DebugTempDir;
+
+    #[test]
+    fn hooks_handling() {
+        let conf = r#"
+[hooks]
+post_sync = "./regenerate-local-conf.sh"
+"#;
+
+        let user_conf: UserConf = toml::from_str(conf).unwrap();
+        assert!(user_conf.hooks().pre_sync.is_none());
+        assert_eq!(
+            user_conf.hooks().post_sync.as_deref(),
+            Some("./regenerate-local-conf.sh")
+        );
+    }
+
+    #[test]
+    fn preferred_remotes_handling() {
+        let conf = r#"
+preferred_remotes = ["origin", "mirror"]
+"#;
+
+        let user_conf: UserConf = toml::from_str(conf).unwrap();
+        assert_eq!(user_conf.preferred_remotes(), ["origin", "mirror"]);
+    }
+
+    #[test]
+    fn url_aliases_round_trip() {
+        let dir = DebugTempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            r#"
+parallel_fetch_limit = 2
+preferred_remotes = ["origin"]
+
+[url_aliases]
+"https://example.com/meta-foo.git" = ["https://mirror.example.com/meta-foo.git"]
+"#,
+        )
+        .unwrap();
+
+        let mut user_conf = UserConf::load(&path).unwrap();
+        user_conf.add_url_alias(
+            "https://example.com/meta-foo.git".to_string(),
+            "https://mirror.example.com/meta-foo.git".to_string(),
+        );
+        user_conf.add_url_alias(
+            "https://example.com/meta-bar.git".to_string(),
+            "https://mirror.example.com/meta-bar.git".to_string(),
+        );
+        user_conf.save(&path).unwrap();
+
+        let user_conf = UserConf::load(&path).unwrap();
+        assert_eq!(user_conf.parallel_fetch_limit(), Some(2));
+        assert_eq!(user_conf.preferred_remotes(), ["origin"]);
+        assert_eq!(
+            user_conf.url_aliases()["https://example.com/meta-foo.git"],
+            ["https://mirror.example.com/meta-foo.git"]
+        );
+        assert_eq!(
+            user_conf.url_aliases()["https://example.com/meta-bar.git"],
+            ["https://mirror.example.com/meta-bar.git"]
+        );
+    }
+
+    #[test]
+    fn missing_file_means_defaults() {
+        let dir = DebugTempDir::new().unwrap();
+        let user_conf = UserConf::load(&dir.path().join("config.toml")).unwrap();
+        assert!(user_conf.hooks().is_empty());
+        assert!(user_conf.preferred_remotes().is_empty());
+        assert_eq!(user_conf.parallel_fetch_limit(), None);
+    }
+}
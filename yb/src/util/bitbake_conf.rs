@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::Path;
+
+/// The value `name` ends up with after parsing `conf_path` (e.g. local.conf), following
+/// `include`/`require` lines that name files relative to `base_dir` (the build directory, which
+/// bitbake searches first). Only plain, soft (`?=`) and weak (`??=`) single-line assignments are
+/// understood; anything fancier is ignored.
+pub fn conf_var_value(conf_path: &Path, base_dir: &Path, name: &str) -> Option<String> {
+    let mut value = None;
+    let mut weak_default = None;
+    read_conf_var(conf_path, base_dir, name, &mut value, &mut weak_default, 0);
+    value.or(weak_default)
+}
+
+fn read_conf_var(
+    conf_path: &Path,
+    base_dir: &Path,
+    name: &str,
+    value: &mut Option<String>,
+    weak_default: &mut Option<String>,
+    depth: usize,
+) {
+    // Guard against include cycles
+    if depth > 10 {
+        return;
+    }
+    let conf = match fs::read_to_string(conf_path) {
+        Ok(conf) => conf,
+        Err(_) => return,
+    };
+
+    for line in conf.lines() {
+        let line = line.trim();
+        if let Some(included) = line
+            .strip_prefix("include ")
+            .or_else(|| line.strip_prefix("require "))
+        {
+            let included = Path::new(included.trim());
+            read_conf_var(
+                &base_dir.join(included),
+                base_dir,
+                name,
+                value,
+                weak_default,
+                depth + 1,
+            );
+            continue;
+        }
+
+        let rest = match line.strip_prefix(name) {
+            Some(rest) => rest.trim_start(),
+            None => continue,
+        };
+        let (op, assigned) = match rest.split_once('=') {
+            Some((op, assigned)) => (op.trim(), assigned.trim().trim_matches('"').to_string()),
+            None => continue,
+        };
+        match op {
+            "" => *value = Some(assigned),
+            "?" if value.is_none() => *value = Some(assigned),
+            "??" => *weak_default = Some(assigned),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::util::bitbake_conf::conf_var_value;
+    use crate::util::debug_temp_dir::DebugTempDir;
+
+    #[test]
+    fn machine_is_read_through_includes() {
+        let build_dir = DebugTempDir::new().unwrap();
+        let conf_dir = build_dir.path().join("conf");
+        fs::create_dir(&conf_dir).unwrap();
+        fs::write(
+            conf_dir.join("local.conf"),
+            "MACHINE ??= \"qemux86-64\"\nMACHINEOVERRIDES = \"foo\"\ninclude conf/extra.conf\n",
+        )
+        .unwrap();
+        let local_conf = conf_dir.join("local.conf");
+
+        assert_eq!(
+            conf_var_value(&local_conf, build_dir.path(), "MACHINE").as_deref(),
+            Some("qemux86-64")
+        );
+
+        fs::write(
+            conf_dir.join("extra.conf"),
+            "MACHINE ?= \"raspberrypi4\"\nMACHINE ?= \"beaglebone\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            conf_var_value(&local_conf, build_dir.path(), "MACHINE").as_deref(),
+            Some("raspberrypi4")
+        );
+    }
+}
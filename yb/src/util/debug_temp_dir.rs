@@ -1,10 +1,19 @@
 use ::std::io::Result;
 use ::std::path::{Path, PathBuf};
+use ::std::sync::atomic::{AtomicBool, Ordering};
 
 use tempfile::{Builder, TempDir};
 
 // Based on https://gist.github.com/ExpHP/facc0dcbf4399aac7af87dcebae03f7c
 
+static KEEP_TEMP_DIRS: AtomicBool = AtomicBool::new(false);
+
+/// Retain every DebugTempDir when it is dropped, not just while unwinding, so that intermediate
+/// results (e.g. clones) can be inspected after a failure.
+pub fn keep_temp_dirs(keep: bool) {
+    KEEP_TEMP_DIRS.store(keep, Ordering::Relaxed);
+}
+
 #[derive(Debug)]
 pub struct DebugTempDir(Option<TempDir>);
 
@@ -48,12 +57,12 @@ impl AsRef<Path> for DebugTempDir {
     }
 }
 
-/// Leaks the inner TempDir if we are unwinding.
+/// Leaks the inner TempDir if we are unwinding or temp dirs are being kept.
 impl Drop for DebugTempDir {
     fn drop(&mut self) {
-        if ::std::thread::panicking() {
+        if ::std::thread::panicking() || KEEP_TEMP_DIRS.load(Ordering::Relaxed) {
             if let Some(d) = self.0.as_ref() {
-                eprintln!("retaining temporary directory at: {d:?}")
+                eprintln!("retaining temporary directory at: {}", d.path().display())
             }
             ::std::mem::forget(self.0.take())
         }
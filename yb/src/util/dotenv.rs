@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use eyre::WrapErr;
+
+use crate::errors::YbResult;
+
+/// Write `vars` to `path` as `NAME="value"` lines. Values are double-quoted with anything the
+/// shell would expand escaped, so the file can also be sourced by sh.
+pub fn write_dotenv(path: &Path, vars: &BTreeMap<String, String>) -> YbResult<()> {
+    let mut contents = String::new();
+    for (name, value) in vars {
+        contents.push_str(name);
+        contents.push('=');
+        contents.push_str(&quote(value));
+        contents.push('\n');
+    }
+    fs::write(path, contents).wrap_err_with(|| format!("failed to write {}", path.display()))
+}
+
+/// Read a file written by `write_dotenv`. Blank lines and `#` comments are skipped, and
+/// unquoted values are taken verbatim.
+pub fn read_dotenv(path: &Path) -> YbResult<BTreeMap<String, String>> {
+    let contents =
+        fs::read_to_string(path).wrap_err_with(|| format!("failed to read {}", path.display()))?;
+
+    let mut ret = BTreeMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (name, value) = line
+            .split_once('=')
+            .ok_or_else(|| eyre::eyre!("{}:{}: expected NAME=value", path.display(), i + 1))?;
+        ret.insert(name.trim().to_string(), unquote(value.trim()));
+    }
+    Ok(ret)
+}
+
+fn quote(value: &str) -> String {
+    let mut ret = String::from('"');
+    for c in value.chars() {
+        match c {
+            '\\' | '"' | '$' | '`' => {
+                ret.push('\\');
+                ret.push(c);
+            }
+            '\n' => ret.push_str("\\n"),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+fn unquote(value: &str) -> String {
+    let value = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(value) => value,
+        None => return value.to_string(),
+    };
+
+    let mut ret = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            ret.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => ret.push('\n'),
+            Some(c) => ret.push(c),
+            None => ret.push('\\'),
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::util::debug_temp_dir::DebugTempDir;
+    use crate::util::dotenv::{read_dotenv, write_dotenv};
+
+    #[test]
+    fn values_round_trip() {
+        let dir = DebugTempDir::new().unwrap();
+        let path = dir.path().join("env");
+
+        let mut vars = BTreeMap::new();
+        vars.insert("BBPATH".to_string(), "/work/build".to_string());
+        vars.insert(
+            "TRICKY".to_string(),
+            "say \"hi\" to $USER\\`x`\nbye".to_string(),
+        );
+        vars.insert("EMPTY".to_string(), String::new());
+
+        write_dotenv(&path, &vars).unwrap();
+        assert_eq!(read_dotenv(&path).unwrap(), vars);
+    }
+}
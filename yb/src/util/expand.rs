@@ -0,0 +1,102 @@
+use std::env;
+
+use color_eyre::Help;
+
+use crate::errors::YbResult;
+
+/// Expand a leading `~` (also right after `file://`) to the home directory, and `$VAR` or
+/// `${VAR}` to the value of the environment variable. Specs and stream configs keep the
+/// unexpanded form; call this right before handing a URL to git.
+pub fn expand_url(url: &str) -> YbResult<String> {
+    expand_url_with(url, |name| env::var(name).ok())
+}
+
+fn expand_url_with<F>(url: &str, lookup: F) -> YbResult<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let lookup_var = |name: &str| {
+        lookup(name).ok_or_else(|| {
+            eyre::eyre!(
+                "environment variable '{}' used in '{}' is not set",
+                name,
+                url
+            )
+            .suppress_backtrace(true)
+        })
+    };
+
+    let mut ret = String::with_capacity(url.len());
+    let mut rest = url;
+    let (prefix, after_prefix) = match url.strip_prefix("file://") {
+        Some(after_prefix) => ("file://", after_prefix),
+        None => ("", url),
+    };
+    if after_prefix == "~" || after_prefix.starts_with("~/") {
+        ret.push_str(prefix);
+        ret.push_str(&lookup_var("HOME")?);
+        rest = &after_prefix[1..];
+    }
+
+    while let Some(dollar) = rest.find('$') {
+        ret.push_str(&rest[..dollar]);
+        let after_dollar = &rest[dollar + 1..];
+
+        let (name, remainder) = if let Some(braced) = after_dollar.strip_prefix('{') {
+            let close = braced
+                .find('}')
+                .ok_or_else(|| eyre::eyre!("unterminated '${{' in '{}'", url))?;
+            (&braced[..close], &braced[close + 1..])
+        } else {
+            let end = after_dollar
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after_dollar.len());
+            (&after_dollar[..end], &after_dollar[end..])
+        };
+
+        if name.is_empty() {
+            // A lone '$' isn't a variable reference
+            ret.push('$');
+        } else {
+            ret.push_str(&lookup_var(name)?);
+        }
+        rest = remainder;
+    }
+    ret.push_str(rest);
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::util::expand::expand_url_with;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/yb".to_string()),
+            "MIRROR" => Some("/srv/mirrors".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn expands_tilde_and_variables() {
+        let expand = |url| expand_url_with(url, lookup).unwrap();
+        assert_eq!(expand("~/poky.git"), "/home/yb/poky.git");
+        assert_eq!(expand("file://~/poky.git"), "file:///home/yb/poky.git");
+        assert_eq!(expand("${MIRROR}/poky.git"), "/srv/mirrors/poky.git");
+        assert_eq!(expand("$MIRROR/poky.git"), "/srv/mirrors/poky.git");
+        assert_eq!(
+            expand("https://git.yoctoproject.org/poky~1"),
+            "https://git.yoctoproject.org/poky~1"
+        );
+    }
+
+    #[test]
+    fn undefined_variable_is_an_error() {
+        let err = expand_url_with("${NOPE}/poky.git", lookup).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("environment variable 'NOPE' used in '${NOPE}/poky.git' is not set"));
+    }
+}
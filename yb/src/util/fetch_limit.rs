@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// How many fetches/clones may run at once unless configured otherwise
+pub const DEFAULT_PARALLEL_FETCH_LIMIT: usize = 4;
+
+/// Bounds the number of network operations (fetches and clones) that run at the same time.
+/// Clones of a limiter share its limit.
+#[derive(Debug, Clone)]
+pub struct FetchLimiter {
+    semaphore: Arc<Semaphore>,
+}
+
+impl FetchLimiter {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+        }
+    }
+
+    /// Wait until another network operation may start. It counts as running until the returned
+    /// permit is dropped.
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        self.semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed")
+    }
+
+    /// Like `acquire`, but for synchronous code (e.g. fetches done while computing status)
+    pub fn blocking_acquire(&self) -> OwnedSemaphorePermit {
+        futures::executor::block_on(self.acquire())
+    }
+}
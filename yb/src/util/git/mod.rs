@@ -1,19 +1,51 @@
 // Some functions below (where noted) are from git2-rs which is dual-licensed MIT and Apache 2.0.
 // Those portions are Copyright (c) 2014 Alex Crichton
 
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::data_model::git::RemoteTrackingBranch;
+use color_eyre::Help;
 use eyre::eyre;
 use git2::ErrorCode::NotFound;
 use git2::{
     Branch, BranchType, Cred, ErrorCode, ObjectType, Remote, RemoteCallbacks, Repository, Revwalk,
     SubmoduleIgnore,
 };
+use itertools::Itertools;
 
 use crate::errors::YbResult;
 
+/// `name` (of a remote, branch etc., as described by `what`) as a str, or None (with a warning)
+/// if it isn't valid UTF-8. Such names can't be passed back to git2, so whatever they name is
+/// best skipped.
+pub fn utf8_name<'a>(name: &'a [u8], what: &str) -> Option<&'a str> {
+    match std::str::from_utf8(name) {
+        Ok(name) => Some(name),
+        Err(_) => {
+            tracing::warn!(
+                "skipping {} '{}': its name is not valid UTF-8",
+                what,
+                String::from_utf8_lossy(name)
+            );
+            None
+        }
+    }
+}
+
+/// `name` as a String, replacing any invalid UTF-8 (with a warning). Only suitable for display.
+pub fn lossy_name(name: &[u8], what: &str) -> String {
+    match std::str::from_utf8(name) {
+        Ok(name) => name.to_string(),
+        Err(_) => {
+            let lossy = String::from_utf8_lossy(name).into_owned();
+            tracing::warn!("the name of {} '{}' is not valid UTF-8", what, lossy);
+            lossy
+        }
+    }
+}
+
 pub fn get_current_local_branch(repo: &Repository) -> YbResult<Branch> {
     match repo.head() {
         Ok(head) => Ok(Branch::wrap(head)),
@@ -34,22 +66,45 @@ pub fn get_current_local_branch_name(repo: &Repository) -> YbResult<String> {
 pub fn get_remote_tracking_branch_for_current_local_branch(
     repo: &Repository,
 ) -> YbResult<Option<RemoteTrackingBranch>> {
-    get_remote_tracking_branch(&get_current_local_branch(repo)?)
+    get_remote_tracking_branch(repo, &get_current_local_branch(repo)?)
 }
 
-pub fn get_remote_tracking_branch(branch: &Branch) -> YbResult<Option<RemoteTrackingBranch>> {
-    match branch.upstream() {
-        Ok(upstream_branch) => {
-            let tracking_branch_name = upstream_branch.name()?.unwrap().to_string();
-            let tracking_branch_parts = tracking_branch_name.split_once('/').unwrap();
-            Ok(Some(RemoteTrackingBranch {
-                remote_name: tracking_branch_parts.0.to_string(),
-                branch_name: tracking_branch_parts.1.to_string(),
-            }))
-        }
-        Err(err) if err.code() == NotFound => Ok(None),
-        Err(err) => Err(err.into()),
-    }
+/// Returns the remote tracking branch that `branch` is tracking, or None if it has no upstream
+/// (or its upstream is a local branch).
+pub fn get_remote_tracking_branch(
+    repo: &Repository,
+    branch: &Branch,
+) -> YbResult<Option<RemoteTrackingBranch>> {
+    let upstream_branch = match branch.upstream() {
+        Ok(upstream_branch) => upstream_branch,
+        Err(err) if err.code() == NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    // Ask libgit2 for the real remote name rather than guessing based on slashes, since both remote
+    // and branch names may contain them. Repository::branch_upstream_remote needs the
+    // 'refs/heads/blah' name.
+    // Names that aren't UTF-8 can't be handled, so treat such branches as not tracking anything
+    let branch_ref_name = match utf8_name(branch.get().name_bytes(), "branch") {
+        Some(branch_ref_name) => branch_ref_name,
+        None => return Ok(None),
+    };
+    let remote_name = match repo.branch_upstream_remote(branch_ref_name) {
+        Ok(remote_name) => remote_name,
+        Err(err) if err.code() == NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let remote_name = match utf8_name(&remote_name, "remote") {
+        Some(remote_name) => remote_name,
+        None => return Ok(None),
+    };
+
+    let upstream_ref_name = match utf8_name(upstream_branch.get().name_bytes(), "branch") {
+        Some(upstream_ref_name) => upstream_ref_name,
+        None => return Ok(None),
+    };
+
+    Ok(RemoteTrackingBranch::parse(upstream_ref_name, remote_name))
 }
 
 pub fn get_remote_name_for_current_branch(repo: &Repository) -> YbResult<Option<String>> {
@@ -63,11 +118,7 @@ pub fn get_remote_name_for_current_branch(repo: &Repository) -> YbResult<Option<
 
     match repo.branch_upstream_remote(&branch_ref_name) {
         Err(ref e) if e.code() == ErrorCode::NotFound => Ok(None),
-        Ok(name) => Ok(Some(
-            name.as_str()
-                .ok_or_else(|| eyre!("couldn't get branch name from reference"))?
-                .to_string(),
-        )),
+        Ok(name) => Ok(utf8_name(&name, "remote").map(str::to_string)),
         Err(e) => Err(e.into()),
     }
 }
@@ -329,29 +380,117 @@ pub fn do_merge<'a>(
     Ok(())
 }
 
-pub fn check_repository_workdirs_unique<'a, I>(repos: I) -> YbResult<()>
+/// Source dirs that resolve to the same git workdir, e.g. a symlink to another source dir or
+/// plain directories inside a stray repo
+#[derive(Debug)]
+pub struct DuplicateWorkdir {
+    pub workdir: PathBuf,
+    pub subdirs: Vec<PathBuf>,
+}
+
+impl DuplicateWorkdir {
+    /// The subdirs to get rid of: all but one that is the workdir itself (all of them, if none
+    /// is)
+    pub fn extraneous_subdirs(&self) -> impl Iterator<Item = &PathBuf> {
+        let workdir = self
+            .workdir
+            .canonicalize()
+            .unwrap_or_else(|_| self.workdir.clone());
+        let mut kept = false;
+        self.subdirs.iter().filter(move |subdir| {
+            if !kept && **subdir == workdir {
+                kept = true;
+                false
+            } else {
+                true
+            }
+        })
+    }
+}
+
+/// Group the source dirs in `repos` by git workdir, returning the workdirs that more than one
+/// source dir resolves to
+pub fn find_duplicate_workdirs<'a, I>(repos: I) -> YbResult<Vec<DuplicateWorkdir>>
 where
-    I: Iterator<Item = &'a Repository>,
+    I: Iterator<Item = (&'a PathBuf, &'a Repository)>,
 {
-    let mut workdir_to_repo: HashMap<PathBuf, Vec<&Repository>> = HashMap::new();
-    for repo in repos {
+    let mut workdir_to_subdirs: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for (subdir, repo) in repos {
         let workdir = repo
             .workdir()
             .ok_or_else(|| eyre!("bare repositories not supported"))?;
-        let r = workdir_to_repo.entry(PathBuf::from(workdir)).or_default();
-        r.push(repo);
+        workdir_to_subdirs
+            .entry(PathBuf::from(workdir))
+            .or_default()
+            .push(subdir.clone());
     }
 
-    for (workdir, workdir_repos) in workdir_to_repo {
-        if workdir_repos.len() > 1 {
-            return Err(eyre::eyre!(
-                "multiple layer repositories are rooted at git workdir {}",
-                workdir.display()
-            ));
-        }
+    Ok(workdir_to_subdirs
+        .into_iter()
+        .filter(|(_, subdirs)| subdirs.len() > 1)
+        .map(|(workdir, subdirs)| DuplicateWorkdir { workdir, subdirs })
+        .collect())
+}
+
+pub fn check_repository_workdirs_unique<'a, I>(repos: I) -> YbResult<()>
+where
+    I: Iterator<Item = (&'a PathBuf, &'a Repository)>,
+{
+    let duplicates = find_duplicate_workdirs(repos)?;
+    if duplicates.is_empty() {
+        return Ok(());
     }
 
-    Ok(())
+    let mut message =
+        String::from("multiple source directories are rooted at the same git workdir:");
+    for duplicate in &duplicates {
+        message += &format!(
+            "\n\t{}: {}",
+            duplicate.workdir.display(),
+            duplicate
+                .subdirs
+                .iter()
+                .map(|subdir| subdir.display().to_string())
+                .join(", ")
+        );
+    }
+    let extraneous = duplicates
+        .iter()
+        .flat_map(DuplicateWorkdir::extraneous_subdirs)
+        .map(|subdir| subdir.display().to_string())
+        .join(", ");
+
+    Err(eyre!(message).suggestion(format!(
+        "remove {extraneous} (e.g. a stray nested clone or a symlink), or pass \
+         --skip-duplicate-workdirs to leave them out"
+    )))
+}
+
+/// Namespace of the refs `create_backup_ref` creates
+pub const BACKUP_REF_PREFIX: &str = "refs/yb/backup/";
+
+/// Record the current HEAD of the repo at `repo_path` in a new ref under `BACKUP_REF_PREFIX`
+/// (with a reflog entry), so that it can be recovered after `what` (e.g. "reset") loses track of
+/// it. Returns the name of the ref, or None if HEAD doesn't point at a commit yet.
+pub fn create_backup_ref(repo_path: &Path, what: &str) -> YbResult<Option<String>> {
+    let repo = Repository::open(repo_path)?;
+    let head = match repo.head().ok().and_then(|head| head.target()) {
+        Some(head) => head,
+        None => return Ok(None),
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let ref_name = format!("{BACKUP_REF_PREFIX}{timestamp}-{what}");
+    repo.reference_ensure_log(&ref_name)?;
+    repo.reference(
+        &ref_name,
+        head,
+        true,
+        &format!("yb sync: pre-{what} @ {head}"),
+    )?;
+    Ok(Some(ref_name))
 }
 
 pub fn ssh_agent_remote_callbacks<'a>() -> RemoteCallbacks<'a> {
@@ -369,3 +508,42 @@ pub fn local_branch_exists(repo: &Repository, local_branch_name: &str) -> YbResu
         Err(err) => Err(err.into()),
     }
 }
+
+pub fn remote_exists(repo: &Repository, remote_name: &str) -> YbResult<bool> {
+    match repo.find_remote(remote_name) {
+        Ok(_) => Ok(true),
+        Err(err) if err.code() == ErrorCode::NotFound => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Check that `date` is something we're happy to hand to `git clone --shallow-since`: a date
+/// (YYYY-MM-DD), optionally followed by a time (HH:MM or HH:MM:SS). git itself accepts almost
+/// anything and silently misinterprets what it doesn't understand.
+pub fn validate_shallow_since_date(date: &str) -> YbResult<()> {
+    let is_number = |s: &str, len: usize| s.len() == len && s.chars().all(|c| c.is_ascii_digit());
+
+    let (day, time) = match date.split_once(['T', ' ']) {
+        Some((day, time)) => (day, Some(time)),
+        None => (date, None),
+    };
+
+    let day_parts = day.split('-').collect::<Vec<_>>();
+    let day_ok = day_parts.len() == 3
+        && is_number(day_parts[0], 4)
+        && is_number(day_parts[1], 2)
+        && is_number(day_parts[2], 2);
+    let time_ok = time.map_or(true, |time| {
+        let time_parts = time.split(':').collect::<Vec<_>>();
+        (2..=3).contains(&time_parts.len()) && time_parts.iter().all(|part| is_number(part, 2))
+    });
+
+    if !day_ok || !time_ok {
+        eyre::bail!(
+            "invalid --shallow-since date '{}': expected YYYY-MM-DD, optionally followed by HH:MM[:SS]",
+            date
+        );
+    }
+
+    Ok(())
+}
@@ -1,10 +1,16 @@
 use std::collections::HashSet;
 use std::hash::Hash;
 
+pub mod bitbake_conf;
 pub mod debug_temp_dir;
+pub mod dotenv;
+pub mod expand;
+pub mod fetch_limit;
 pub mod git;
 pub mod indicatif;
+pub mod output;
 pub mod paths;
+pub mod watch;
 
 // https://stackoverflow.com/a/46767732
 pub fn has_unique_elements<T>(iter: T) -> bool
@@ -0,0 +1,86 @@
+use serde::Serialize;
+
+use crate::errors::YbResult;
+
+/// How machine-readable output (--format, or --porcelain) is serialized
+#[derive(clap::ValueEnum, Clone, Debug, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// JSON on a single line
+    Json,
+    /// Indented JSON
+    #[default]
+    JsonPretty,
+    /// YAML
+    Yaml,
+}
+
+impl OutputFormat {
+    pub fn serialize<T: Serialize + ?Sized>(self, value: &T) -> YbResult<String> {
+        Ok(match self {
+            OutputFormat::Json => serde_json::to_string(value)?,
+            OutputFormat::JsonPretty => serde_json::to_string_pretty(value)?,
+            OutputFormat::Yaml => serde_yaml::to_string(value)?,
+        })
+    }
+}
+
+/// `value`, or "none" if there isn't one, for human-readable output
+pub fn or_none<T: ToString>(value: &Option<T>) -> String {
+    value
+        .as_ref()
+        .map_or_else(|| "none".to_string(), |v| v.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+
+    use crate::data_model::status::{ComputedStatus, MissingRepo};
+    use crate::spec::SpecRepo;
+    use crate::util::output::OutputFormat;
+
+    #[test]
+    fn status_round_trips_through_each_format() {
+        let status = ComputedStatus {
+            source_dirs: vec![],
+            enabled_layers: HashSet::new(),
+            missing_repos: vec![MissingRepo {
+                name: "poky".to_string(),
+                spec_repo: SpecRepo {
+                    url: "https://github.com/yoctoproject/poky.git".to_string(),
+                    refspec: "zeus".to_string(),
+                    extra_remotes: Default::default(),
+                    layers: None,
+                    post_clone: vec![],
+                    submodules: false,
+                    depth: None,
+                    enabled: true,
+                },
+            }],
+            active_spec: None,
+            bblayers_path: PathBuf::from("/yocto/build/conf/bblayers.conf"),
+        };
+        let expected = serde_json::to_value(&status).unwrap();
+
+        let json = OutputFormat::Json.serialize(&status).unwrap();
+        assert_eq!(json.lines().count(), 1);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json).unwrap(),
+            expected
+        );
+
+        let json_pretty = OutputFormat::JsonPretty.serialize(&status).unwrap();
+        assert!(json_pretty.lines().count() > 1);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json_pretty).unwrap(),
+            expected
+        );
+
+        let yaml = OutputFormat::Yaml.serialize(&status).unwrap();
+        assert_eq!(
+            serde_yaml::from_str::<serde_json::Value>(&yaml).unwrap(),
+            expected
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use std::path::Path;
+use std::time::Duration;
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+use crate::errors::YbResult;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum WatchOutcome {
+    /// One or more changes happened (and then things were quiet for the debounce period)
+    Changed,
+    /// Nothing changed before the timeout elapsed
+    Timeout,
+    /// The watcher went away
+    Closed,
+}
+
+/// Receives change notifications, coalescing bursts of them (e.g. from a 'git checkout') into one.
+pub struct DebouncedChanges {
+    rx: UnboundedReceiver<()>,
+    debounce: Duration,
+}
+
+impl DebouncedChanges {
+    pub fn new(rx: UnboundedReceiver<()>, debounce: Duration) -> Self {
+        Self { rx, debounce }
+    }
+
+    /// Wait up to `timeout` for a change.
+    pub async fn wait(&mut self, timeout: Duration) -> WatchOutcome {
+        match tokio::time::timeout(timeout, self.rx.recv()).await {
+            Err(..) => WatchOutcome::Timeout,
+            Ok(None) => WatchOutcome::Closed,
+            Ok(Some(())) => {
+                // Swallow further changes until things settle down
+                while let Ok(Some(())) = tokio::time::timeout(self.debounce, self.rx.recv()).await {
+                }
+                WatchOutcome::Changed
+            }
+        }
+    }
+}
+
+/// Recursively watch `path` for changes. The returned watcher must be kept alive for as long as
+/// changes are wanted.
+pub fn watch_dir<P: AsRef<Path>>(
+    path: P,
+    debounce: Duration,
+) -> YbResult<(RecommendedWatcher, DebouncedChanges)> {
+    let (tx, rx) = unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if event.is_ok() {
+            // The receiver may have been dropped already; nothing to do about it
+            let _ = tx.send(());
+        }
+    })?;
+    watcher.watch(path.as_ref(), RecursiveMode::Recursive)?;
+
+    Ok((watcher, DebouncedChanges::new(rx, debounce)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use crate::util::watch::{watch_dir, DebouncedChanges, WatchOutcome};
+
+    #[tokio::test]
+    async fn injected_events_are_debounced() {
+        let (tx, rx) = unbounded_channel();
+        let mut changes = DebouncedChanges::new(rx, Duration::from_millis(50));
+
+        assert_eq!(
+            changes.wait(Duration::from_millis(50)).await,
+            WatchOutcome::Timeout
+        );
+
+        for _ in 0..10 {
+            tx.send(()).unwrap();
+        }
+        assert_eq!(
+            changes.wait(Duration::from_millis(50)).await,
+            WatchOutcome::Changed
+        );
+        // The burst was coalesced into a single change
+        assert_eq!(
+            changes.wait(Duration::from_millis(50)).await,
+            WatchOutcome::Timeout
+        );
+
+        drop(tx);
+        assert_eq!(
+            changes.wait(Duration::from_millis(50)).await,
+            WatchOutcome::Closed
+        );
+    }
+
+    #[tokio::test]
+    async fn workdir_change_is_detected() {
+        let tmp = TempDir::new().unwrap();
+        let repo_dir = tmp.path().join("meta-foo");
+        fs::create_dir(&repo_dir).unwrap();
+
+        let (_watcher, mut changes) = watch_dir(tmp.path(), Duration::from_millis(50)).unwrap();
+        fs::write(repo_dir.join("local.conf"), "MACHINE = \"qemux86-64\"").unwrap();
+
+        assert_eq!(
+            changes.wait(Duration::from_secs(5)).await,
+            WatchOutcome::Changed
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use std::path::{Path, PathBuf};
 
+use color_eyre::Help;
 use serde::{Deserialize, Serialize};
 
 use crate::core::tool_context::YoctoEnvironment;
@@ -23,6 +24,35 @@ pub struct YbConf {
     poky_dir_relative: Option<PathBuf>,
 }
 
+/// Whether `load_yb_conf_with_migrations` had to upgrade a configuration
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum YbConfMigration {
+    UpToDate,
+    Migrated { from_version: u32 },
+}
+
+/// Parse the contents of a yb.yaml, upgrading it to `YB_CONF_FORMAT_VERSION` if it is older.
+/// The caller is responsible for writing back a migrated configuration.
+pub fn load_yb_conf_with_migrations(data: &[u8]) -> YbResult<(YbConf, YbConfMigration)> {
+    let mut conf: YbConf = serde_yaml::from_slice(data)?;
+    match conf.format_version {
+        YB_CONF_FORMAT_VERSION => Ok((conf, YbConfMigration::UpToDate)),
+        1 => {
+            // Version 1 called sources_dir_relative 'repos_dir_relative', which the serde alias
+            // already takes care of
+            conf.format_version = YB_CONF_FORMAT_VERSION;
+            Ok((conf, YbConfMigration::Migrated { from_version: 1 }))
+        }
+        version => Err(eyre::eyre!(
+            "yb.yaml has format version {}, but this yb only understands up to {}",
+            version,
+            YB_CONF_FORMAT_VERSION
+        )
+        .suggestion("upgrade yb (see 'yb self-update')")
+        .suppress_backtrace(true)),
+    }
+}
+
 impl YbConf {
     pub fn new_from_yocto_env(yb_dir: &Path, yocto_env: &YoctoEnvironment) -> YbResult<Self> {
         // There may not be a poky directory (or any layers) yet
@@ -40,6 +70,10 @@ impl YbConf {
         })
     }
 
+    pub fn format_version(&self) -> u32 {
+        self.format_version
+    }
+
     pub fn build_dir_relative(&self) -> &PathBuf {
         &self.build_dir_relative
     }
@@ -55,7 +89,9 @@ impl YbConf {
 
 #[cfg(test)]
 mod test {
-    use crate::yb_conf::{YbConf, YB_CONF_FORMAT_VERSION};
+    use crate::yb_conf::{
+        load_yb_conf_with_migrations, YbConf, YbConfMigration, YB_CONF_FORMAT_VERSION,
+    };
 
     #[test]
     fn fake_version_1_handling() {
@@ -84,6 +120,25 @@ poky_dir_relative: "../sources/poky"
         assert_eq!(yb_conf.format_version, 1);
     }
 
+    #[test]
+    fn version_1_migration() {
+        let conf = r#"---
+format_version: 1
+build_dir_relative: "../build"
+repos_dir_relative: "../sources"
+"#;
+
+        let (yb_conf, migration) = load_yb_conf_with_migrations(conf.as_bytes()).unwrap();
+        assert_eq!(migration, YbConfMigration::Migrated { from_version: 1 });
+        assert_eq!(yb_conf.format_version(), YB_CONF_FORMAT_VERSION);
+        let migrated = serde_yaml::to_string(&yb_conf).unwrap();
+        assert!(migrated.contains("format_version: 2\n"));
+        assert!(migrated.contains("sources_dir_relative: ../sources\n"));
+
+        let newer = conf.replace("format_version: 1", "format_version: 99");
+        assert!(load_yb_conf_with_migrations(newer.as_bytes()).is_err());
+    }
+
     #[test]
     fn format_version_up_to_date() {
         assert_eq!(YB_CONF_FORMAT_VERSION, 2, "need to update migration code!");
@@ -1,24 +1,31 @@
+use color_eyre::Help;
 use core::fmt::{self, Debug, Formatter};
 use eyre::Context;
 use std::collections::HashMap;
-use std::fs;
 use std::fs::{File, OpenOptions};
 use std::io::Read;
+use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::{env, fs, io};
 
 use crate::core::tool_context::YoctoEnvironment;
 use crate::errors::YbResult;
 use crate::spec::{ActiveSpec, Spec};
 use crate::stream::Stream;
 use crate::stream_db::{StreamDb, StreamKey};
+use crate::user_conf::UserConf;
 use crate::util::paths::find_dir_recurse_upwards;
-use crate::yb_conf::YbConf;
+use crate::yb_conf::{load_yb_conf_with_migrations, YbConf, YbConfMigration};
 
-const YB_ENV_DIRECTORY: &str = ".yb";
+pub const YB_ENV_DIRECTORY: &str = ".yb";
 const STREAMS_SUBDIR: &str = "streams";
 const YB_CONF_FILE: &str = "yb.yaml";
+const USER_CONF_FILE: &str = "config.toml";
 const ACTIVE_SPEC_FILE: &str = "active_spec.yaml";
+const LOCK_FILE: &str = "sync.lock";
+/// Environment variable pointing directly at a .yb directory, bypassing the upward search
+pub const YB_DIR_ENV_VAR: &str = "YB_DIR";
 
 #[derive(Debug, Clone)]
 pub enum ActiveSpecStatus {
@@ -30,6 +37,7 @@ pub struct YbEnv {
     /// Absolute path to the .yb directory
     dir: PathBuf,
     config: YbConf,
+    user_conf: UserConf,
     active_spec_status: Option<ActiveSpecStatus>,
     streams: StreamDb,
 }
@@ -39,6 +47,7 @@ impl Debug for YbEnv {
         f.debug_struct("YbEnv")
             .field("dir", &self.dir)
             .field("config", &self.config)
+            .field("user_conf", &self.user_conf)
             .field("active_spec_status", &self.active_spec_status)
             .field("streams", &self.streams)
             .finish_non_exhaustive()
@@ -49,12 +58,14 @@ impl YbEnv {
     fn new(
         dir: PathBuf,
         config: YbConf,
+        user_conf: UserConf,
         active_spec: Option<ActiveSpecStatus>,
         streams: StreamDb,
     ) -> Self {
         Self {
             dir,
             config,
+            user_conf,
             active_spec_status: active_spec,
             streams,
         }
@@ -69,7 +80,13 @@ impl YbEnv {
     }
 
     pub fn activate_spec(&mut self, spec: Spec) -> YbResult<()> {
-        let active_spec = self.streams.make_active_spec(spec)?;
+        self.activate_spec_from(spec, None)
+    }
+
+    /// Like `activate_spec`, also recording the file the spec originally came from
+    pub fn activate_spec_from(&mut self, spec: Spec, spec_file: Option<PathBuf>) -> YbResult<()> {
+        let mut active_spec = self.streams.make_active_spec(spec)?;
+        active_spec.spec_file = spec_file;
 
         let dest = self.dir.join(ACTIVE_SPEC_FILE);
         let f = OpenOptions::new()
@@ -92,7 +109,9 @@ impl YbEnv {
         key.and_then(move |k| self.streams.stream_mut(k))
     }
 
-    pub fn find_spec<S: AsRef<str>>(&self, name: S) -> YbResult<Option<&Spec>> {
+    /// Find the spec with the given name in any stream. Fails with `YbError::SpecNotFound` if
+    /// there is no such spec.
+    pub fn find_spec<S: AsRef<str>>(&self, name: S) -> YbResult<&Spec> {
         self.streams.find_spec_by_name(name)
     }
 
@@ -116,6 +135,25 @@ impl YbEnv {
         self.config.poky_dir_relative().map(|p| self.dir.join(p))
     }
 
+    pub fn conf(&self) -> &YbConf {
+        &self.config
+    }
+
+    /// The user's settings from config.toml
+    pub fn user_conf(&self) -> &UserConf {
+        &self.user_conf
+    }
+
+    pub fn user_conf_mut(&mut self) -> &mut UserConf {
+        &mut self.user_conf
+    }
+
+    /// Write the (possibly modified) user settings back to config.toml. Hold the environment's
+    /// lock (see `lock`) while doing so.
+    pub fn save_user_conf(&self) -> YbResult<()> {
+        self.user_conf.save(&self.dir.join(USER_CONF_FILE))
+    }
+
     pub fn yb_dir(&self) -> &PathBuf {
         &self.dir
     }
@@ -124,6 +162,30 @@ impl YbEnv {
         self.yb_dir().join(STREAMS_SUBDIR)
     }
 
+    /// Take an exclusive lock on the environment so that concurrent yb processes don't clobber
+    /// each other. Fails immediately if another process holds the lock.
+    pub fn lock(&self) -> YbResult<YbEnvLock> {
+        let lock_path = self.dir.join(LOCK_FILE);
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open(&lock_path)
+            .with_context(|| format!("failed to open lock file {}", lock_path.display()))?;
+
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+                return Err(eyre::eyre!("another yb operation is in progress")
+                    .note(format!("lock file: {}", lock_path.display()))
+                    .suppress_backtrace(true));
+            }
+
+            return Err(err).with_context(|| format!("failed to lock {}", lock_path.display()));
+        }
+
+        Ok(YbEnvLock { _file: file })
+    }
+
     pub fn initialize<S: Into<PathBuf>>(
         location: S,
         yocto_env: &YoctoEnvironment,
@@ -145,28 +207,96 @@ impl YbEnv {
         let streams_dir = yb_dir.join(STREAMS_SUBDIR);
         fs::create_dir(streams_dir)?;
 
-        Ok(YbEnv::new(yb_dir, conf, None, StreamDb::new()))
+        Ok(YbEnv::new(
+            yb_dir,
+            conf,
+            UserConf::default(),
+            None,
+            StreamDb::new(),
+        ))
     }
 }
 
-/// Search upwards from `start_point` for a .yb directory and load the environment if found.
-pub fn try_discover_yb_env<S: AsRef<Path>>(
-    start_point: S,
-) -> YbResult<Option<YbEnv>> {
+/// Exclusive lock on a yb environment, released when dropped. See [`YbEnv::lock`].
+#[derive(Debug)]
+pub struct YbEnvLock {
+    _file: File,
+}
+
+/// Validate the .yb directory named by the YB_DIR environment variable
+fn yb_dir_from_env(yb_dir: PathBuf) -> YbResult<PathBuf> {
+    if !yb_dir.join(YB_CONF_FILE).is_file() {
+        return Err(eyre::eyre!(
+            "{}={} is not a yb environment: it has no {}",
+            YB_DIR_ENV_VAR,
+            yb_dir.display(),
+            YB_CONF_FILE
+        )
+        .suggestion(format!(
+            "point {YB_DIR_ENV_VAR} at the {YB_ENV_DIRECTORY} directory of a yb environment"
+        )));
+    }
+
+    yb_dir
+        .canonicalize()
+        .with_context(|| format!("failed to resolve {}", yb_dir.display()))
+}
+
+fn write_yb_conf(yb_dir: &Path, conf: &YbConf) -> YbResult<()> {
+    let conf_file = yb_dir.join(YB_CONF_FILE);
+    let f = File::create(&conf_file)
+        .with_context(|| format!("failed to open {} for writing", conf_file.display()))?;
+    serde_yaml::to_writer(f, conf)?;
+    Ok(())
+}
+
+/// Load the yb.yaml of the .yb directory `yb_dir`, upgrading it in place if it has an older
+/// format version
+pub fn migrate_yb_conf(yb_dir: &Path) -> YbResult<(YbConf, YbConfMigration)> {
+    let conf_file = yb_dir.join(YB_CONF_FILE);
+    let mut config_file_data = Vec::new();
+    File::open(&conf_file)
+        .with_context(|| format!("failed to open conf file {}", conf_file.display()))?
+        .read_to_end(&mut config_file_data)?;
+
+    let (conf, migration) = load_yb_conf_with_migrations(&config_file_data)
+        .with_context(|| format!("failed to load conf file {}", conf_file.display()))?;
+    if let YbConfMigration::Migrated { from_version } = migration {
+        tracing::info!(
+            "migrating {:?} from format version {} to {}",
+            conf_file,
+            from_version,
+            conf.format_version()
+        );
+        write_yb_conf(yb_dir, &conf)?;
+    }
+
+    Ok((conf, migration))
+}
+
+/// The .yb directory given by the YB_DIR environment variable, or else the first one found
+/// searching upwards from `start_point`
+pub fn find_yb_dir<S: AsRef<Path>>(start_point: S) -> YbResult<Option<PathBuf>> {
+    match env::var_os(YB_DIR_ENV_VAR) {
+        Some(yb_dir) => yb_dir_from_env(PathBuf::from(yb_dir)).map(Some),
+        None => find_dir_recurse_upwards(start_point, YB_ENV_DIRECTORY),
+    }
+}
+
+/// Load the environment whose .yb directory is given by the YB_DIR environment variable, or
+/// else search upwards from `start_point` for a .yb directory and load the environment if found.
+pub fn try_discover_yb_env<S: AsRef<Path>>(start_point: S) -> YbResult<Option<YbEnv>> {
     // Locate the hidden .yb directory
-    find_dir_recurse_upwards(start_point, YB_ENV_DIRECTORY)?
+    let yb_dir = find_yb_dir(start_point)?;
+
+    yb_dir
         .map(|yb_dir| -> YbResult<_> {
             tracing::info!("found .yb directory at {:?}", yb_dir);
-            let conf_file = yb_dir.join(YB_CONF_FILE);
             // TODO handle missing conf file?
-            assert!(conf_file.is_file());
-
-            let mut config_file_data = Vec::new();
-            File::open(&conf_file)
-                .with_context(|| format!("failed to open conf file {}", conf_file.display()))?
-                .read_to_end(&mut config_file_data)?;
+            assert!(yb_dir.join(YB_CONF_FILE).is_file());
 
-            let conf: YbConf = serde_yaml::from_slice(config_file_data.as_slice()).unwrap();
+            let (conf, _) = migrate_yb_conf(&yb_dir)?;
+            let user_conf = UserConf::load(&yb_dir.join(USER_CONF_FILE))?;
 
             let mut stream_db = StreamDb::new();
 
@@ -191,7 +321,7 @@ pub fn try_discover_yb_env<S: AsRef<Path>>(
                 }
             }
 
-            return Ok(YbEnv::new(yb_dir, conf, active_spec, stream_db));
+            return Ok(YbEnv::new(yb_dir, conf, user_conf, active_spec, stream_db));
         })
         .transpose()
 }
@@ -1,4 +1,7 @@
+use std::path::PathBuf;
+
 use crate::commands::Subcommands;
+use crate::util::output::OutputFormat;
 use crate::VERSION;
 
 #[derive(clap::Parser, Debug)]
@@ -12,9 +15,42 @@ pub struct YbOptions {
     #[clap(long, global = true)]
     pub color: Option<String>,
 
+    /// Emit machine-readable output (where supported), serialized as JSON
     #[clap(long, global = true)]
     pub porcelain: bool,
 
+    /// Emit machine-readable output (where supported) in the given format; --porcelain is
+    /// short for '--format json-pretty'
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        value_name = "FORMAT",
+        conflicts_with = "porcelain"
+    )]
+    pub format: Option<OutputFormat>,
+
+    /// Run as if yb was started in the given directory instead of the current one
+    #[clap(short = 'C', long, global = true)]
+    pub directory: Option<PathBuf>,
+
+    /// Keep temporary directories (e.g. intermediate clones) instead of deleting them, printing
+    /// their paths. Also enabled by setting YB_KEEP_TEMP=1.
+    #[clap(long, global = true)]
+    pub keep_temp: bool,
+
+    /// Log every git command yb runs (with its arguments and directory) and how it exited,
+    /// regardless of --level. Also enabled by setting YB_TRACE_GIT=1.
+    #[clap(long, global = true)]
+    pub trace_git: bool,
+
+    /// Answer yes to every confirmation prompt (e.g. removing layers from bblayers.conf, or
+    /// accepting related repos with 'yb sync --prompt-related'), so scripts can run without a
+    /// terminal. This only acknowledges prompts: actions that lose local work still require
+    /// --force.
+    #[clap(short = 'y', long, global = true)]
+    pub yes: bool,
+
     #[clap(subcommand)]
     pub command: Subcommands,
 }
@@ -0,0 +1,153 @@
+//! Upstream repos, streams and yb environments for the integration tests to work on
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::common::yb_cmd;
+
+/// Run git with the given arguments in `cwd`, asserting success. Returns trimmed stdout.
+pub fn git<P: AsRef<Path>>(cwd: P, args: &[&str]) -> String {
+    let output = std::process::Command::new("git")
+        .current_dir(cwd)
+        .args(["-c", "user.name=yb", "-c", "user.email=yb@localhost"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+/// Write `contents` to `file_name` inside `repo` and commit it
+pub fn commit_file<P: AsRef<Path>>(repo: P, file_name: &str, contents: &str) {
+    let repo = repo.as_ref();
+    let file_path = repo.join(file_name);
+    fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+    fs::write(&file_path, contents).unwrap();
+    git(repo, &["add", file_name]);
+    git(repo, &["commit", "-m", &format!("update {file_name}")]);
+}
+
+/// Make an empty commit in `repo` with the given author and committer date
+pub fn commit_at<P: AsRef<Path>>(repo: P, message: &str, date: &str) {
+    let output = std::process::Command::new("git")
+        .current_dir(repo)
+        .args(["-c", "user.name=yb", "-c", "user.email=yb@localhost"])
+        .args(["commit", "--allow-empty", "-m", message])
+        .env("GIT_AUTHOR_DATE", date)
+        .env("GIT_COMMITTER_DATE", date)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "git commit failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Create a git repository at `path` with a single commit on branch 'main'
+pub fn create_repo<P: AsRef<Path>>(path: P) {
+    let path = path.as_ref();
+    fs::create_dir_all(path).unwrap();
+    git(path, &["init", "-b", "main"]);
+    commit_file(path, "README", "initial");
+}
+
+/// Clone `url` to `dest`
+pub fn clone_repo<P: AsRef<Path>, D: AsRef<Path>>(url: P, dest: D) {
+    let dest = dest.as_ref();
+    git(
+        dest.parent().unwrap(),
+        &[
+            "clone",
+            url.as_ref().to_str().unwrap(),
+            dest.to_str().unwrap(),
+        ],
+    );
+}
+
+/// Generate the YAML for a spec named `name`. Each repo is given as (name, url, refspec).
+pub fn spec_yaml(name: &str, repos: &[(&str, &Path, &str)]) -> String {
+    let mut ret = format!("header:\n  version: 1\n  name: \"{name}\"\n\nrepos:\n");
+    for (repo_name, url, refspec) in repos {
+        ret += &format!(
+            "  {repo_name}:\n    url: \"{}\"\n    refspec: \"{refspec}\"\n",
+            url.display()
+        );
+    }
+    ret
+}
+
+/// Create a stream repository at `path` containing the given spec files (file name, YAML)
+pub fn create_stream_repo<P: AsRef<Path>>(path: P, specs: &[(&str, &str)]) {
+    let path = path.as_ref();
+    create_repo(path);
+    for (file_name, contents) in specs {
+        commit_file(path, file_name, contents);
+    }
+}
+
+/// Run `yb init` in `root`, add the stream at `stream`, and activate `spec`. Returns the path to
+/// the new yocto directory.
+pub fn setup_yb_env<P: AsRef<Path>, S: AsRef<Path>>(root: P, stream: S, spec: &str) -> PathBuf {
+    let root = root.as_ref();
+    let yocto_dir = root.join("yocto");
+    yb_cmd(root).arg("init").assert().success();
+    yb_cmd(&yocto_dir)
+        .arg("stream")
+        .arg("add")
+        .arg(stream.as_ref())
+        .assert()
+        .success();
+    yb_cmd(&yocto_dir)
+        .arg("activate")
+        .arg(spec)
+        .assert()
+        .success();
+    yocto_dir
+}
+
+/// A yb environment whose active spec, 'default', has a spec repo tracking 'main' of an upstream
+/// repo (see `create_repo`) for each of the given names
+pub struct SpecEnv {
+    root: PathBuf,
+    yocto_dir: PathBuf,
+}
+
+impl SpecEnv {
+    /// Set up the upstream repos, stream and environment under `root`. The repos are not synced.
+    pub fn new<P: AsRef<Path>>(root: P, repo_names: &[&str]) -> Self {
+        let root = root.as_ref().to_path_buf();
+        let upstreams = repo_names
+            .iter()
+            .map(|name| root.join(name))
+            .collect::<Vec<_>>();
+        for upstream in &upstreams {
+            create_repo(upstream);
+        }
+
+        let repos = repo_names
+            .iter()
+            .zip(&upstreams)
+            .map(|(name, upstream)| (*name, upstream.as_path(), "main"))
+            .collect::<Vec<_>>();
+        let stream = root.join("stream");
+        create_stream_repo(&stream, &[("default.yaml", &spec_yaml("default", &repos))]);
+        let yocto_dir = setup_yb_env(&root, &stream, "default");
+
+        Self { root, yocto_dir }
+    }
+
+    pub fn yocto_dir(&self) -> PathBuf {
+        self.yocto_dir.clone()
+    }
+
+    /// The upstream repo of the spec repo `name`
+    pub fn upstream(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+}
@@ -1,13 +1,18 @@
 use std::fs;
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
-use crate::common::yb_cmd;
-use crate::common::DebugTempDir;
+use crate::common::{yb_cmd, DebugTempDir};
+use crate::fixtures::{
+    clone_repo, commit_at, commit_file, create_repo, create_stream_repo, git, setup_yb_env,
+    spec_yaml, SpecEnv,
+};
 use assert_cmd::Command;
 use color_eyre::eyre::Result;
 use concurrent_git_pool_proc_macros::clone_repos;
 
 mod common;
+mod fixtures;
 
 #[test]
 fn yb_init_bare() -> Result<()> {
@@ -32,6 +37,32 @@ fn no_yb_init_over_existing() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn yb_init_dir() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+    yb_cmd(path)
+        .arg("init")
+        .arg("--dir")
+        .arg("mytree")
+        .assert()
+        .success();
+    let tree = path.join("mytree");
+    assert!(tree.join(".yb").is_dir());
+    assert!(tree.join("sources").is_dir());
+    assert!(tree.join("build").is_dir());
+    assert!(!path.join("yocto").exists());
+
+    // Initializing the same directory again should fail
+    yb_cmd(path)
+        .arg("init")
+        .arg("--dir")
+        .arg("mytree")
+        .assert()
+        .code(1);
+    Ok(())
+}
+
 #[test]
 fn yb_init() -> Result<()> {
     let conf_repo = create_yb_conf_repo()?;
@@ -58,140 +89,3869 @@ fn yb_init() -> Result<()> {
     Ok(())
 }
 
-async fn setup_yocto_env() -> Result<YoctoEnv> {
+#[test]
+fn stream_add_activate_activates_spec() -> Result<()> {
+    let conf_repo = create_yb_conf_repo()?;
+
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let yb_env_dir = path.join("yocto");
+
+    yb_cmd(path).arg("init").assert().success();
+    yb_cmd(&yb_env_dir)
+        .arg("stream")
+        .arg("add")
+        .arg(conf_repo.path.path())
+        .arg("--activate")
+        .arg("zeus")
+        .assert()
+        .success();
+
+    let output = yb_cmd(&yb_env_dir)
+        .arg("status")
+        .arg("--no-fetch")
+        .output()?;
+    assert!(output.status.success());
+    assert!(
+        std::str::from_utf8(&output.stdout)?.contains("active spec 'zeus' from stream 'default'")
+    );
+
+    // The spec has to come from the stream that was just added
+    let output = yb_cmd(&yb_env_dir)
+        .arg("stream")
+        .arg("add")
+        .arg(conf_repo.path.path())
+        .arg("--name")
+        .arg("other")
+        .arg("--activate")
+        .arg("nonexistent")
+        .output()?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)?.contains("spec with name 'nonexistent' not found"));
+
+    Ok(())
+}
+
+#[test]
+fn yb_directory_option() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+    yb_cmd(path).arg("init").assert().success();
+
+    // Run from an unrelated directory, pointing yb at the env with -C
+    let elsewhere = DebugTempDir::new()?;
+    yb_cmd(elsewhere.path())
+        .arg("-C")
+        .arg(path.join("yocto"))
+        .arg("status")
+        .assert()
+        .success();
+
+    yb_cmd(elsewhere.path())
+        .arg("-C")
+        .arg(path.join("nonexistent"))
+        .arg("status")
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn yb_dir_env_var_locates_env() -> Result<()> {
     let t = DebugTempDir::new()?;
     let path = t.path();
+    let spec_env = SpecEnv::new(path, &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+
+    // Run from a directory that isn't under the env
+    let elsewhere = DebugTempDir::new()?;
+    let output = yb_cmd(elsewhere.path())
+        .env("YB_DIR", yocto_dir.join(".yb"))
+        .arg("status")
+        .arg("--no-fetch")
+        .output()?;
+    assert!(output.status.success());
+    assert!(std::str::from_utf8(&output.stdout)?.contains("active spec 'default'"));
+
+    let output = yb_cmd(elsewhere.path())
+        .env("YB_DIR", elsewhere.path())
+        .arg("status")
+        .output()?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)?.contains("is not a yb environment"));
+
+    Ok(())
+}
 
+#[test]
+fn stream_add_broken_stream_cleans_up() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+    yb_cmd(path).arg("init").assert().success();
     let yocto_dir = path.join("yocto");
-    fs::create_dir(&yocto_dir)?;
 
-    let sources_dir = yocto_dir.join("sources");
-    fs::create_dir(&sources_dir)?;
+    let stream = path.join("stream");
+    create_stream_repo(&stream, &[("broken.yaml", "header: [")]);
 
-    clone_repos! {
-        "https://github.com/yoctoproject/poky.git" in &sources_dir,
-        "https://github.com/openembedded/meta-openembedded.git" in &sources_dir,
+    // Point yb at a private temp dir so we can check nothing was left behind
+    let tmp = DebugTempDir::new()?;
+    yb_cmd(&yocto_dir)
+        .env("TMPDIR", tmp.path())
+        .arg("stream")
+        .arg("add")
+        .arg(&stream)
+        .assert()
+        .failure();
+
+    assert_eq!(fs::read_dir(tmp.path())?.count(), 0);
+    assert!(!yocto_dir
+        .join(".yb")
+        .join("streams")
+        .join("default")
+        .exists());
+
+    Ok(())
+}
+
+#[test]
+fn sync_refuses_to_run_while_env_locked() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+
+    // The first sync holds the lock until the test creates 'release' (or a minute has passed)
+    fs::write(
+        yocto_dir.join(".yb").join("config.toml"),
+        "[hooks]\npre_sync = 'touch started; i=0; \
+         while [ ! -e release ] && [ $i -lt 600 ]; do sleep 0.1; i=$((i+1)); done'\n",
+    )?;
+    let mut first = std::process::Command::new(assert_cmd::cargo::cargo_bin("yb"))
+        .current_dir(&yocto_dir)
+        .env_clear()
+        .env("NO_COLOR", "1")
+        .args(["sync", "-a"])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+    let started = Instant::now();
+    while !yocto_dir.join("started").exists() {
+        assert!(first.try_wait()?.is_none(), "the first sync exited early");
+        assert!(started.elapsed() < Duration::from_secs(60));
+        std::thread::sleep(Duration::from_millis(50));
     }
 
-    let build_dir = yocto_dir.join("build");
-    let conf_dir = build_dir.join("conf");
-    let bblayers = conf_dir.join("bblayers.conf");
-    fs::create_dir_all(conf_dir).unwrap();
-    let mut contents =
-        r##"# POKY_BBLAYERS_CONF_VERSION is increased each time build/conf/bblayers.conf
-# changes incompatibly
-POKY_BBLAYERS_CONF_VERSION = "2"
+    let output = yb_cmd(&yocto_dir).arg("sync").arg("-a").output()?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(
+        stderr.contains("another yb operation is in progress"),
+        "{stderr}"
+    );
 
-BBPATH = "${TOPDIR}"
-BBFILES ??= ""
-BBLAYERS ?= " "##
-            .to_string();
+    // Once the first sync is done, the lock is released
+    fs::write(yocto_dir.join("release"), "")?;
+    assert!(first.wait()?.success());
+    fs::write(yocto_dir.join(".yb").join("config.toml"), "")?;
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
 
-    contents += sources_dir.join("poky").to_str().unwrap();
-    contents.push(' ');
-    contents += sources_dir.join("meta-openembedded").to_str().unwrap();
-    contents.push('"');
+    Ok(())
+}
 
-    fs::write(bblayers, contents).unwrap();
+/// Set up a yb env whose spec wants `meta-foo` from an upstream repo, but where sources/meta-foo
+/// was cloned from a fork of it (so it shares history but has the wrong remote).
+fn setup_env_with_related_repo(path: &std::path::Path) -> (PathBuf, PathBuf) {
+    let upstream = path.join("upstream").join("meta-foo");
+    create_repo(&upstream);
+    let fork = path.join("fork").join("meta-foo");
+    clone_repo(&upstream, &fork);
+    commit_file(&fork, "fork-only", "fork");
 
-    Command::new("sh")
-        .current_dir(&yocto_dir)
-        .arg("-c")
-        .arg(". sources/poky/oe-init-build-env")
-        .unwrap();
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml("default", &[("meta-foo", &upstream, "main")]),
+        )],
+    );
 
-    let path_var = std::env::var("PATH").unwrap();
-    let path_var = format!(
-        "{}:{}:{}",
-        sources_dir.join("poky").join("scripts").to_str().unwrap(),
-        sources_dir
-            .join("poky")
-            .join("bitbake")
-            .join("bin")
-            .to_str()
-            .unwrap(),
-        path_var
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+    let repo_dir = yocto_dir.join("sources").join("meta-foo");
+    clone_repo(&fork, &repo_dir);
+
+    (yocto_dir, upstream)
+}
+
+#[test]
+fn sync_skips_related_repo_by_default() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let (yocto_dir, _) = setup_env_with_related_repo(t.path());
+
+    let output = yb_cmd(&yocto_dir).arg("sync").arg("-a").output()?;
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains("--allow-unrelated"));
+
+    // The repo was left alone
+    let repo_dir = yocto_dir.join("sources").join("meta-foo");
+    assert_eq!(git(&repo_dir, &["remote"]), "origin");
+
+    Ok(())
+}
+
+#[test]
+fn sync_allow_unrelated_reconciles_related_repo() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let (yocto_dir, upstream) = setup_env_with_related_repo(t.path());
+
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--allow-unrelated")
+        .assert()
+        .success();
+
+    // The spec remote was added and a branch tracking it is checked out
+    let repo_dir = yocto_dir.join("sources").join("meta-foo");
+    assert_eq!(
+        git(&repo_dir, &["remote", "get-url", "meta-foo"]),
+        upstream.to_str().unwrap()
+    );
+    assert_eq!(
+        git(&repo_dir, &["rev-parse", "--abbrev-ref", "@{upstream}"]),
+        "meta-foo/main"
     );
 
-    Ok(YoctoEnv {
-        root: t,
-        sources_dir,
-        yocto_dir,
-        build_dir,
-        path_var,
-    })
+    Ok(())
 }
 
-struct YoctoEnv {
-    root: DebugTempDir,
-    sources_dir: PathBuf,
-    yocto_dir: PathBuf,
-    build_dir: PathBuf,
-    path_var: String,
+#[test]
+fn sync_persisted_url_alias_matches_related_repo() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let (yocto_dir, upstream) = setup_env_with_related_repo(t.path());
+    let fork = t.path().join("fork").join("meta-foo");
+
+    // As saved by an earlier 'yb sync --prompt-related'
+    fs::write(
+        yocto_dir.join(".yb").join("config.toml"),
+        format!(
+            "[url_aliases]\n'{}' = ['{}']\n",
+            upstream.display(),
+            fork.display()
+        ),
+    )?;
+
+    // Not a terminal, so there is no prompting; the alias is enough
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--prompt-related")
+        .output()?;
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(!stderr.contains("--allow-unrelated"), "{stderr}");
+    assert!(!stderr.contains("without a terminal"), "{stderr}");
+
+    // The repo is treated as matching the spec through its existing remote
+    let repo_dir = yocto_dir.join("sources").join("meta-foo");
+    assert_eq!(git(&repo_dir, &["remote"]), "origin");
+    assert_eq!(
+        git(&repo_dir, &["rev-parse", "--abbrev-ref", "@{upstream}"]),
+        "origin/main"
+    );
+
+    Ok(())
 }
 
-#[tokio::test]
-async fn yb_upgrade() -> Result<()> {
-    // Test that `yb upgrade` can upgrade an existing Yocto env
-    let env = setup_yocto_env().await?;
+#[test]
+fn sync_adds_remote_for_changed_spec_url() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
 
-    yb_cmd(env.yocto_dir)
-        .arg("upgrade")
-        .env("PATH", env.path_var)
-        .env("BBPATH", env.build_dir.to_str().unwrap())
+    let old_upstream = path.join("old").join("meta-foo");
+    create_repo(&old_upstream);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml("default", &[("meta-foo", &old_upstream, "main")]),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+
+    // The repo moves, and the spec follows it
+    let new_upstream = path.join("new").join("meta-foo");
+    clone_repo(&old_upstream, &new_upstream);
+    commit_file(&new_upstream, "moved.txt", "moved");
+    commit_file(
+        &stream,
+        "default.yaml",
+        &spec_yaml("default", &[("meta-foo", &new_upstream, "main")]),
+    );
+
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--create-missing-remotes-from-spec")
         .assert()
         .success();
 
+    let repo_dir = yocto_dir.join("sources").join("meta-foo");
+    assert_eq!(
+        git(&repo_dir, &["remote", "get-url", "meta-foo"]),
+        new_upstream.to_str().unwrap()
+    );
+    assert_eq!(
+        git(&repo_dir, &["rev-parse", "--abbrev-ref", "@{upstream}"]),
+        "meta-foo/main"
+    );
+    assert!(repo_dir.join("moved.txt").is_file());
+
     Ok(())
 }
 
-#[tokio::test]
-async fn yb_init_fails_on_yocto_env() -> Result<()> {
-    // Test that `yb init` fails inside a Yocto env
-    let env = setup_yocto_env().await?;
+#[test]
+fn stream_pin_survives_stream_update() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
 
-    let output = yb_cmd(env.yocto_dir)
-        .arg("init")
-        .env("PATH", env.path_var)
-        .env("BBPATH", env.build_dir.to_str().unwrap())
-        .output()
-        .unwrap();
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml("default", &[("meta-foo", &upstream, "main")]),
+        )],
+    );
+    let pinned_commit = git(&stream, &["rev-parse", "HEAD"]);
+    commit_file(&stream, "unrelated.txt", "1");
+
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+    let stream_contents = yocto_dir
+        .join(".yb")
+        .join("streams")
+        .join("default")
+        .join("contents");
+
+    yb_cmd(&yocto_dir)
+        .arg("stream")
+        .arg("pin")
+        .arg("default")
+        .arg(&pinned_commit)
+        .assert()
+        .success();
+    assert_eq!(git(&stream_contents, &["rev-parse", "HEAD"]), pinned_commit);
+
+    // New upstream commits are not picked up while pinned
+    commit_file(&stream, "unrelated.txt", "2");
+    yb_cmd(&yocto_dir)
+        .arg("stream")
+        .arg("update")
+        .assert()
+        .success();
+    assert_eq!(git(&stream_contents, &["rev-parse", "HEAD"]), pinned_commit);
+
+    // Unpinning goes back to following the branch
+    yb_cmd(&yocto_dir)
+        .arg("stream")
+        .arg("unpin")
+        .arg("default")
+        .assert()
+        .success();
+    assert_eq!(
+        git(&stream_contents, &["rev-parse", "HEAD"]),
+        git(&stream, &["rev-parse", "HEAD"])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn stream_update_without_upstream_is_a_clear_error() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml("default", &[("meta-foo", &upstream, "main")]),
+        )],
+    );
 
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+    let stream_contents = yocto_dir
+        .join(".yb")
+        .join("streams")
+        .join("default")
+        .join("contents");
+    git(&stream_contents, &["branch", "--unset-upstream"]);
+
+    let output = yb_cmd(&yocto_dir).arg("stream").arg("update").output()?;
+    assert!(!output.status.success());
     let stderr = std::str::from_utf8(&output.stderr)?;
-    assert!(stderr.contains("cannot init yb env within an activated Yocto environment"));
+    assert!(stderr.contains(
+        "stream 'default' is on branch 'main', which has no upstream branch to update from"
+    ));
 
     Ok(())
 }
 
-fn create_yb_conf_repo() -> Result<GitRepo> {
-    let dir = DebugTempDir::new().unwrap();
-    let dir_path = dir.path().to_path_buf();
+#[test]
+fn no_update_stream_skips_stream_fetch() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
 
-    Command::new("git")
-        .arg("init")
-        .current_dir(&dir_path)
-        .output()?;
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml("default", &[("meta-foo", &upstream, "main")]),
+        )],
+    );
 
-    let basic_yaml = include_bytes!("resources/confs/basic.yaml");
-    fs::write(dir_path.join("basic.yaml"), basic_yaml).unwrap();
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+    clone_repo(&upstream, yocto_dir.join("sources").join("meta-foo"));
+    let stream_contents = yocto_dir
+        .join(".yb")
+        .join("streams")
+        .join("default")
+        .join("contents");
+    // Any attempt to fetch the stream now fails
+    let remote = git(&stream_contents, &["remote"]);
+    let unreachable = path.join("unreachable");
+    git(
+        &stream_contents,
+        &["remote", "set-url", &remote, unreachable.to_str().unwrap()],
+    );
 
-    Command::new("git")
-        .current_dir(&dir_path)
-        .arg("add")
-        .arg("basic.yaml")
-        .output()?;
+    yb_cmd(&yocto_dir).arg("status").assert().failure();
+    yb_cmd(&yocto_dir)
+        .arg("status")
+        .arg("--no-update-stream")
+        .assert()
+        .success();
+    yb_cmd(&yocto_dir)
+        .arg("status")
+        .arg("--no-fetch")
+        .assert()
+        .success();
 
-    Command::new("git")
-        .current_dir(&dir_path)
-        .arg("commit")
-        .arg("-m")
-        .arg("'initial'")
-        .output()?;
+    yb_cmd(&yocto_dir).arg("sync").assert().failure();
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("--no-update-stream")
+        .assert()
+        .success();
 
-    Ok(GitRepo { path: dir })
+    Ok(())
 }
 
-#[derive(Debug)]
+#[test]
+fn sync_repos_from_file() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstreams = path.join("upstreams");
+    for name in ["meta-a", "meta-b", "meta-c"].iter() {
+        create_repo(upstreams.join(name));
+    }
+    let (a, b, c) = (
+        upstreams.join("meta-a"),
+        upstreams.join("meta-b"),
+        upstreams.join("meta-c"),
+    );
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml(
+                "default",
+                &[
+                    ("meta-a", &a, "main"),
+                    ("meta-b", &b, "main"),
+                    ("meta-c", &c, "main"),
+                ],
+            ),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    let repos_file = path.join("repos.txt");
+    fs::write(
+        &repos_file,
+        "# staged rollout
+meta-a
+
+meta-c
+",
+    )?;
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--repos-from")
+        .arg(&repos_file)
+        .assert()
+        .success();
+
+    let sources_dir = yocto_dir.join("sources");
+    assert!(sources_dir.join("meta-a").is_dir());
+    assert!(!sources_dir.join("meta-b").exists());
+    assert!(sources_dir.join("meta-c").is_dir());
+
+    // Unknown repo names are rejected
+    fs::write(
+        &repos_file,
+        "meta-d
+",
+    )?;
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("--repos-from")
+        .arg(&repos_file)
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains("valid options are: meta-a, meta-b, meta-c"));
+
+    Ok(())
+}
+
+#[test]
+fn sync_summary_lists_each_repo_once() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let a = path.join("upstreams").join("meta-a");
+    let b = path.join("upstreams").join("meta-b");
+    create_repo(&a);
+    create_repo(&b);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml("default", &[("meta-a", &a, "main"), ("meta-b", &b, "main")]),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    // meta-a is missing; meta-b is present but one commit behind
+    clone_repo(&b, yocto_dir.join("sources").join("meta-b"));
+    commit_file(&b, "new.txt", "new");
+
+    let output = yb_cmd(&yocto_dir).arg("sync").arg("--summary").output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    assert!(!stdout.contains("actions:"));
+    assert_eq!(stdout.matches("meta-a:").count(), 1);
+    assert_eq!(stdout.matches("meta-b:").count(), 1);
+    assert!(stdout.contains("meta-a: clone"));
+    assert!(stdout.contains("meta-b: pull 1"));
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--summary")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    assert!(stdout.contains("meta-a: clone ✓"));
+    assert!(stdout.contains("meta-b: pull 1 ✓"));
+
+    Ok(())
+}
+
+#[test]
+fn sync_runs_post_sync_hook() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+
+    fs::write(
+        yocto_dir.join(".yb").join("config.toml"),
+        "[hooks]\npost_sync = 'touch \"$YB_BUILD_DIR/sentinel\"'\n",
+    )?;
+
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+    assert!(yocto_dir.join("build").join("sentinel").is_file());
+
+    Ok(())
+}
+
+#[test]
+fn sync_aborts_on_failing_pre_sync_hook() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+
+    fs::write(
+        yocto_dir.join(".yb").join("config.toml"),
+        "[hooks]\npre_sync = 'exit 1'\n",
+    )?;
+
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().failure();
+    assert!(!yocto_dir.join("sources").join("meta-foo").exists());
+
+    Ok(())
+}
+
+#[test]
+fn sync_no_reset_skips_dirty_repos() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    let upstream = spec_env.upstream("meta-foo");
+
+    // The repo is behind upstream and has local changes
+    let repo_dir = yocto_dir.join("sources").join("meta-foo");
+    clone_repo(&upstream, &repo_dir);
+    commit_file(&upstream, "new.txt", "new");
+    fs::write(repo_dir.join("README"), "local changes")?;
+    let head_before = git(&repo_dir, &["rev-parse", "HEAD"]);
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--no-reset")
+        .output()?;
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains("has local changes - skipping"));
+
+    assert_eq!(
+        fs::read_to_string(repo_dir.join("README"))?,
+        "local changes"
+    );
+    assert_eq!(git(&repo_dir, &["rev-parse", "HEAD"]), head_before);
+
+    Ok(())
+}
+
+#[test]
+fn sync_runs_post_clone_commands() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    let mut spec = spec_yaml("default", &[("meta-foo", &upstream, "main")]);
+    spec += "    post_clone:\n      - \"echo done > post-clone-sentinel\"\n";
+    let stream = path.join("stream");
+    create_stream_repo(&stream, &[("default.yaml", &spec)]);
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+
+    let sentinel = yocto_dir
+        .join("sources")
+        .join("meta-foo")
+        .join("post-clone-sentinel");
+    assert_eq!(fs::read_to_string(sentinel)?.trim(), "done");
+
+    Ok(())
+}
+
+#[test]
+fn sync_initializes_submodules() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let submodule = path.join("meta-sub");
+    create_repo(&submodule);
+    commit_file(&submodule, "sub.txt", "from submodule");
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    git(
+        &upstream,
+        &[
+            "-c",
+            "protocol.file.allow=always",
+            "submodule",
+            "add",
+            submodule.to_str().unwrap(),
+            "meta-sub",
+        ],
+    );
+    git(&upstream, &["commit", "-m", "add submodule"]);
+
+    let mut spec = spec_yaml("default", &[("meta-foo", &upstream, "main")]);
+    spec += "    submodules: true\n";
+    let stream = path.join("stream");
+    create_stream_repo(&stream, &[("default.yaml", &spec)]);
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    // Local file:// submodules are disallowed by default in recent versions of git
+    yb_cmd(&yocto_dir)
+        .env("GIT_CONFIG_COUNT", "1")
+        .env("GIT_CONFIG_KEY_0", "protocol.file.allow")
+        .env("GIT_CONFIG_VALUE_0", "always")
+        .arg("sync")
+        .arg("-a")
+        .assert()
+        .success();
+
+    let sub_file = yocto_dir
+        .join("sources")
+        .join("meta-foo")
+        .join("meta-sub")
+        .join("sub.txt");
+    assert_eq!(fs::read_to_string(sub_file)?, "from submodule");
+
+    Ok(())
+}
+
+#[test]
+fn sync_dump_and_apply_plan() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+    let spec_env = SpecEnv::new(path, &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    let repo_dir = yocto_dir.join("sources").join("meta-foo");
+
+    // Dumping a plan doesn't apply it
+    let plan = path.join("plan.yaml");
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("--dump-plan")
+        .arg(&plan)
+        .assert()
+        .success();
+    assert!(plan.is_file());
+    assert!(!repo_dir.exists());
+
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("--apply-plan")
+        .arg(&plan)
+        .assert()
+        .success();
+    assert!(repo_dir.join("README").is_file());
+
+    // The plan no longer makes sense now that the repo has been cloned
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("--apply-plan")
+        .arg(&plan)
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains("already exists"));
+
+    Ok(())
+}
+
+#[test]
+fn log_emitted_right_before_exit_is_not_lost() -> Result<()> {
+    let t = DebugTempDir::new()?;
+
+    // Not a yb environment, so an error is logged and yb exits immediately afterwards
+    let output = yb_cmd(t.path()).arg("status").output()?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(stderr
+        .lines()
+        .any(|line| line.contains("ERROR") && line.contains("expected a yb or Yocto environment")));
+
+    Ok(())
+}
+
+#[test]
+fn stream_update_all() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    let stream_a = path.join("stream-a");
+    create_stream_repo(
+        &stream_a,
+        &[(
+            "a.yaml",
+            &spec_yaml("a", &[("meta-foo", &upstream, "main")]),
+        )],
+    );
+    let stream_b = path.join("stream-b");
+    create_stream_repo(
+        &stream_b,
+        &[(
+            "b.yaml",
+            &spec_yaml("b", &[("meta-foo", &upstream, "main")]),
+        )],
+    );
+
+    let yocto_dir = setup_yb_env(path, &stream_a, "a");
+    yb_cmd(&yocto_dir)
+        .arg("stream")
+        .arg("add")
+        .arg(&stream_b)
+        .arg("--name")
+        .arg("b")
+        .assert()
+        .success();
+
+    commit_file(&stream_a, "unrelated.txt", "1");
+    commit_file(&stream_b, "unrelated.txt", "1");
+
+    yb_cmd(&yocto_dir)
+        .arg("stream")
+        .arg("update")
+        .arg("--all")
+        .assert()
+        .success();
+
+    for (stream_name, upstream) in [("default", &stream_a), ("b", &stream_b)] {
+        let stream_contents = yocto_dir
+            .join(".yb")
+            .join("streams")
+            .join(stream_name)
+            .join("contents");
+        assert_eq!(
+            git(&stream_contents, &["rev-parse", "HEAD"]),
+            git(upstream, &["rev-parse", "HEAD"])
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn find_spec_reports_spec_not_found() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+
+    let yb_env = yb::yb_env::try_discover_yb_env(&yocto_dir)?.unwrap();
+    assert_eq!(yb_env.find_spec("default")?.name(), "default");
+
+    let err = yb_env.find_spec("nope").unwrap_err();
+    match err.downcast_ref::<yb::errors::YbError>() {
+        Some(yb::errors::YbError::SpecNotFound { name }) => assert_eq!(name, "nope"),
+        other => panic!("expected SpecNotFound, got {other:?}"),
+    }
+    assert_eq!(err.to_string(), "spec with name 'nope' not found");
+
+    Ok(())
+}
+
+#[test]
+fn stream_list_json() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    let stream_a = path.join("stream-a");
+    create_stream_repo(
+        &stream_a,
+        &[
+            (
+                "a.yaml",
+                &spec_yaml("a", &[("meta-foo", &upstream, "main")]),
+            ),
+            (
+                "a2.yaml",
+                &spec_yaml("a2", &[("meta-foo", &upstream, "main")]),
+            ),
+        ],
+    );
+    let stream_b = path.join("stream-b");
+    create_stream_repo(
+        &stream_b,
+        &[(
+            "b.yaml",
+            &spec_yaml("b", &[("meta-foo", &upstream, "main")]),
+        )],
+    );
+
+    let yocto_dir = setup_yb_env(path, &stream_a, "a");
+    yb_cmd(&yocto_dir)
+        .arg("stream")
+        .arg("add")
+        .arg(&stream_b)
+        .arg("--name")
+        .arg("b")
+        .assert()
+        .success();
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("stream")
+        .arg("list")
+        .arg("--json")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+
+    // Streams are sorted by name
+    let b = stdout.find(r#""name": "b""#).unwrap();
+    let default = stdout.find(r#""name": "default""#).unwrap();
+    assert!(b < default, "{stdout}");
+    assert!(stdout.contains(&format!(
+        r#""current_commit": "{}""#,
+        git(&stream_b, &["rev-parse", "HEAD"])
+    )));
+    assert!(stdout[b..default].contains("\"specs\": [\n      \"b\"\n    ]"));
+    assert!(stdout[default..].contains("\"specs\": [\n      \"a\",\n      \"a2\"\n    ]"));
+    assert!(stdout.contains(r#""broken": null"#));
+
+    Ok(())
+}
+
+#[test]
+fn sync_exact_requires_confirmation_to_remove_layers() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+    let spec_env = SpecEnv::new(path, &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+
+    // A layer the user enabled by hand
+    let local_layer = path.join("meta-local");
+    fs::create_dir_all(local_layer.join("conf"))?;
+    fs::write(local_layer.join("conf").join("layer.conf"), "")?;
+    let conf_dir = yocto_dir.join("build").join("conf");
+    fs::create_dir_all(&conf_dir)?;
+    let bblayers = conf_dir.join("bblayers.conf");
+    let bblayers_contents = format!("BBLAYERS ?= \"{}\"\n", local_layer.display());
+    fs::write(&bblayers, &bblayers_contents)?;
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("--exact")
+        .arg("-a")
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains(local_layer.to_str().unwrap()));
+    assert!(stderr.contains("--yes"));
+
+    // Nothing was applied
+    assert_eq!(fs::read_to_string(&bblayers)?, bblayers_contents);
+    assert!(!yocto_dir.join("sources").join("meta-foo").exists());
+
+    Ok(())
+}
+
+#[test]
+fn status_timings() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    let upstream = spec_env.upstream("meta-foo");
+    clone_repo(&upstream, yocto_dir.join("sources").join("meta-foo"));
+
+    let output = yb_cmd(&yocto_dir).arg("status").arg("--timings").output()?;
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    let seconds = |label: &str| -> f64 {
+        let line = stderr
+            .lines()
+            .find(|line| line.trim_start().starts_with(&format!("{label} ")))
+            .unwrap_or_else(|| panic!("no '{label}' timing in {stderr}"));
+        line.split_whitespace()
+            .last()
+            .unwrap()
+            .trim_end_matches('s')
+            .parse()
+            .unwrap()
+    };
+    // The repo's fetch is listed, and is part of the total
+    assert_eq!(seconds("meta-foo"), seconds("fetch"));
+    assert!(seconds("total") >= seconds("fetch"), "{stderr}");
+
+    Ok(())
+}
+
+#[test]
+fn status_warns_about_unversioned_layer() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    let upstream = spec_env.upstream("meta-foo");
+    let sources_dir = yocto_dir.join("sources");
+    clone_repo(&upstream, sources_dir.join("meta-foo"));
+
+    // A layer copied in by hand rather than cloned
+    let copied_layer = sources_dir.join("meta-copied");
+    fs::create_dir_all(copied_layer.join("conf"))?;
+    fs::write(copied_layer.join("conf").join("layer.conf"), "")?;
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("status")
+        .arg("--no-fetch")
+        .output()?;
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains(&format!(
+        "'{}' looks like a layer but is not a git repository; yb won't sync it",
+        copied_layer.display()
+    )));
+    assert!(!stderr.contains("meta-foo' looks like a layer"));
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("--porcelain")
+        .arg("status")
+        .arg("--no-fetch")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    assert!(stdout.contains("\"OnDiskNonRepo\""));
+    assert!(stdout.contains("\"looks_like_layer\": true"));
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("--format")
+        .arg("yaml")
+        .arg("status")
+        .arg("--no-fetch")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    assert!(stdout.contains("!OnDiskNonRepo"));
+    assert!(stdout.contains("looks_like_layer: true"));
+
+    Ok(())
+}
+
+#[test]
+fn sync_since_spec_reports_transitions() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let meta_foo = path.join("meta-foo");
+    create_repo(&meta_foo);
+    git(&meta_foo, &["branch", "next"]);
+    let meta_bar = path.join("meta-bar");
+    create_repo(&meta_bar);
+    let meta_baz = path.join("meta-baz");
+    create_repo(&meta_baz);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[
+            (
+                "old.yaml",
+                &spec_yaml(
+                    "old",
+                    &[
+                        ("meta-foo", &meta_foo, "main"),
+                        ("meta-bar", &meta_bar, "main"),
+                    ],
+                ),
+            ),
+            (
+                "new.yaml",
+                // Disabling meta-bar removes it just like leaving it out would
+                &(spec_yaml(
+                    "new",
+                    &[
+                        ("meta-foo", &meta_foo, "next"),
+                        ("meta-baz", &meta_baz, "main"),
+                    ],
+                ) + &format!(
+                    "  meta-bar:\n    url: \"{}\"\n    refspec: \"main\"\n    enabled: false\n",
+                    meta_bar.display()
+                )),
+            ),
+        ],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "old");
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("new")
+        .arg("--since-spec")
+        .arg("old")
+        .output()?;
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains("changes since spec 'old':"));
+    assert!(stderr.contains("\tmeta-bar: removed\n\tmeta-baz: added\n\tmeta-foo: main → next\n"));
+
+    // The transitions stay out of the heads report
+    let output = yb_cmd(&yocto_dir)
+        .args([
+            "sync",
+            "new",
+            "--since-spec",
+            "old",
+            "--print-heads",
+            "--json",
+        ])
+        .output()?;
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert!(report.is_array());
+
+    Ok(())
+}
+
+#[test]
+fn sync_shallow_since_limits_cloned_history() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    fs::create_dir_all(&upstream)?;
+    git(&upstream, &["init", "-b", "main"]);
+    commit_at(&upstream, "ancient", "2015-01-01T00:00:00");
+    commit_at(&upstream, "recent", "2021-06-01T00:00:00");
+    // The spec's branch isn't the default one, so the shallow clone has to fetch it separately
+    git(&upstream, &["checkout", "-b", "kirkstone"]);
+    commit_at(&upstream, "latest", "2022-06-01T00:00:00");
+    git(&upstream, &["checkout", "main"]);
+
+    // git ignores --shallow-since for plain local paths
+    let url = format!("file://{}", upstream.display());
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml(
+                "default",
+                &[("meta-foo", std::path::Path::new(&url), "kirkstone")],
+            ),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("--shallow-since")
+        .arg("last tuesday")
+        .output()?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)?.contains("invalid --shallow-since date"));
+
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--shallow-since")
+        .arg("2020-01-01")
+        .assert()
+        .success();
+
+    let clone = yocto_dir.join("sources").join("meta-foo");
+    assert_eq!(git(&clone, &["branch", "--show-current"]), "kirkstone");
+    let log = git(&clone, &["log", "--format=%s", "kirkstone"]);
+    assert_eq!(log, "latest\nrecent");
+
+    Ok(())
+}
+
+#[test]
+fn sync_spec_depth_clones_shallowly() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let mut urls = vec![];
+    for name in ["meta-full", "meta-shallow"] {
+        let upstream = path.join(name);
+        fs::create_dir_all(&upstream)?;
+        git(&upstream, &["init", "-b", "main"]);
+        commit_at(&upstream, "first", "2021-01-01T00:00:00");
+        commit_at(&upstream, "second", "2021-06-01T00:00:00");
+        git(&upstream, &["checkout", "-b", "kirkstone"]);
+        commit_at(&upstream, "third", "2022-06-01T00:00:00");
+        git(&upstream, &["checkout", "main"]);
+        // git ignores --depth for plain local paths
+        urls.push(format!("file://{}", upstream.display()));
+    }
+
+    let mut yaml = spec_yaml(
+        "default",
+        &[
+            ("meta-full", std::path::Path::new(&urls[0]), "kirkstone"),
+            ("meta-shallow", std::path::Path::new(&urls[1]), "kirkstone"),
+        ],
+    );
+    yaml += "    depth: 1\n";
+    let stream = path.join("stream");
+    create_stream_repo(&stream, &[("default.yaml", &yaml)]);
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+
+    let sources = yocto_dir.join("sources");
+    let full = sources.join("meta-full");
+    assert_eq!(git(&full, &["branch", "--show-current"]), "kirkstone");
+    assert_eq!(
+        git(&full, &["rev-parse", "--is-shallow-repository"]),
+        "false"
+    );
+
+    let shallow = sources.join("meta-shallow");
+    assert_eq!(git(&shallow, &["branch", "--show-current"]), "kirkstone");
+    assert_eq!(
+        git(&shallow, &["rev-parse", "--is-shallow-repository"]),
+        "true"
+    );
+    assert_eq!(git(&shallow, &["log", "--format=%s", "kirkstone"]), "third");
+
+    Ok(())
+}
+
+#[test]
+fn spec_default_refspec_applies_to_repos_without_one() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    for name in ["meta-foo", "meta-bar"] {
+        let upstream = path.join(name);
+        create_repo(&upstream);
+        git(&upstream, &["branch", "kirkstone"]);
+    }
+
+    let yaml = format!(
+        "header:\n  version: 1\n  name: \"default\"\n  default_refspec: \"kirkstone\"\n\n\
+         repos:\n  \
+           meta-foo:\n    url: \"{}\"\n  \
+           meta-bar:\n    url: \"{}\"\n    refspec: \"main\"\n",
+        path.join("meta-foo").display(),
+        path.join("meta-bar").display()
+    );
+    let stream = path.join("stream");
+    create_stream_repo(&stream, &[("default.yaml", &yaml)]);
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+
+    let sources = yocto_dir.join("sources");
+    assert_eq!(
+        git(sources.join("meta-foo"), &["branch", "--show-current"]),
+        "kirkstone"
+    );
+    assert_eq!(
+        git(sources.join("meta-bar"), &["branch", "--show-current"]),
+        "main"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn sync_write_local_conf_writes_fragment() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    let mut yaml = spec_yaml("default", &[("meta-foo", &upstream, "main")]);
+    yaml += "local_conf:\n  MACHINE: \"qemux86-64\"\n";
+    let stream = path.join("stream");
+    create_stream_repo(&stream, &[("default.yaml", &yaml)]);
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    let conf_dir = yocto_dir.join("build").join("conf");
+    fs::create_dir_all(&conf_dir)?;
+    fs::write(conf_dir.join("local.conf"), "DISTRO = \"poky\"\n")?;
+
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--write-local-conf")
+        .assert()
+        .success();
+
+    let fragment = fs::read_to_string(conf_dir.join("yb-local.conf"))?;
+    assert!(fragment.contains("MACHINE = \"qemux86-64\""), "{fragment}");
+    let local_conf = fs::read_to_string(conf_dir.join("local.conf"))?;
+    assert!(local_conf.starts_with("DISTRO = \"poky\"\n"));
+    assert_eq!(local_conf.matches("include conf/yb-local.conf").count(), 1);
+
+    // Nothing changes the second time around
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--write-local-conf")
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(conf_dir.join("local.conf"))?, local_conf);
+
+    Ok(())
+}
+
+#[test]
+fn status_only_problems_counts_problems() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    let upstream = spec_env.upstream("meta-foo");
+    let clone = yocto_dir.join("sources").join("meta-foo");
+    clone_repo(&upstream, &clone);
+
+    let only_problems = |porcelain: bool| -> Result<String> {
+        let mut cmd = yb_cmd(&yocto_dir);
+        if porcelain {
+            cmd.arg("--porcelain");
+        }
+        let output = cmd
+            .arg("status")
+            .arg("--no-fetch")
+            .arg("--only-problems")
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    // Clean environment
+    assert!(only_problems(false)?.contains("0 problems"));
+    let json = only_problems(true)?;
+    assert!(json.contains("\"problem_count\": 0"));
+    assert!(json.contains("\"source_dirs\": []"));
+
+    // A dirty working directory
+    fs::write(clone.join("README"), "local changes")?;
+    assert!(only_problems(false)?.contains("1 problem"));
+    let json = only_problems(true)?;
+    assert!(json.contains("\"problem_count\": 1"));
+    assert!(json.contains("\"is_workdir_dirty\": true"));
+
+    Ok(())
+}
+
+#[test]
+fn status_short_prints_one_line_per_repo() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let clean = path.join("meta-clean");
+    create_repo(&clean);
+    let dirty = path.join("meta-dirty");
+    create_repo(&dirty);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml(
+                "default",
+                &[
+                    ("meta-clean", &clean, "main"),
+                    ("meta-dirty", &dirty, "main"),
+                ],
+            ),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+    let sources = yocto_dir.join("sources");
+    clone_repo(&clean, sources.join("meta-clean"));
+    clone_repo(&dirty, sources.join("meta-dirty"));
+    fs::write(sources.join("meta-dirty").join("README"), "local changes")?;
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("status")
+        .arg("--no-fetch")
+        .arg("--short")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    assert_eq!(stdout, "   meta-clean main\n M meta-dirty main\n");
+
+    Ok(())
+}
+
+#[test]
+fn status_skip_duplicate_workdirs_leaves_out_nested_dirs() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+
+    // A stray repo wrapping the sources dir; plain dirs in it resolve to its workdir
+    let sources = yocto_dir.join("sources");
+    git(&sources, &["init", "-b", "main"]);
+    fs::create_dir(sources.join("stray-a"))?;
+    fs::create_dir(sources.join("stray-b"))?;
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("status")
+        .arg("--no-fetch")
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(
+        stderr.contains("multiple source directories are rooted at the same git workdir"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("--skip-duplicate-workdirs"), "{stderr}");
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("status")
+        .arg("--no-fetch")
+        .arg("--skip-duplicate-workdirs")
+        .output()?;
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains("skipping"), "{stderr}");
+    assert!(stderr.contains("stray-a"), "{stderr}");
+    assert!(stderr.contains("stray-b"), "{stderr}");
+
+    Ok(())
+}
+
+#[test]
+fn ignore_untracked_keeps_repo_clean() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+    let clone = yocto_dir.join("sources").join("meta-foo");
+    fs::write(clone.join("build-artifact.o"), "")?;
+
+    let short_status = |ignore_untracked: bool| -> Result<String> {
+        let mut cmd = yb_cmd(&yocto_dir);
+        cmd.arg("status").arg("--no-fetch").arg("--short");
+        if ignore_untracked {
+            cmd.arg("--ignore-untracked");
+        }
+        let output = cmd.output()?;
+        assert!(output.status.success());
+        Ok(std::str::from_utf8(&output.stdout)?.to_string())
+    };
+    assert_eq!(short_status(false)?, " M meta-foo main\n");
+    assert_eq!(short_status(true)?, "   meta-foo main\n");
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("--ignore-untracked")
+        .output()?;
+    assert!(output.status.success());
+    assert!(!std::str::from_utf8(&output.stdout)?.contains("ResetGitWorkdir"));
+
+    Ok(())
+}
+
+#[test]
+fn status_ahead_only_filters_repos() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let names = ["meta-ahead", "meta-behind", "meta-dirty", "meta-clean"];
+    let upstreams: Vec<_> = names.iter().map(|name| path.join(name)).collect();
+    for upstream in &upstreams {
+        create_repo(upstream);
+    }
+    let stream = path.join("stream");
+    let repos: Vec<_> = names
+        .iter()
+        .zip(&upstreams)
+        .map(|(name, upstream)| (*name, upstream.as_path(), "main"))
+        .collect();
+    create_stream_repo(&stream, &[("default.yaml", &spec_yaml("default", &repos))]);
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    let sources = yocto_dir.join("sources");
+    for (name, upstream) in names.iter().zip(&upstreams) {
+        clone_repo(upstream, sources.join(name));
+    }
+    commit_file(sources.join("meta-ahead"), "local.txt", "unpushed");
+    commit_file(&upstreams[1], "upstream.txt", "not pulled yet");
+    fs::write(sources.join("meta-dirty").join("README"), "local changes")?;
+
+    let filtered = |filters: &[&str]| -> Result<String> {
+        let output = yb_cmd(&yocto_dir)
+            .arg("--porcelain")
+            .arg("status")
+            .args(filters)
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    let json = filtered(&["--ahead-only"])?;
+    assert!(json.contains("meta-ahead"));
+    assert!(!json.contains("meta-behind"));
+    assert!(!json.contains("meta-dirty"));
+    assert!(!json.contains("meta-clean"));
+
+    // Filters combine
+    let json = filtered(&["--ahead-only", "--dirty-only"])?;
+    assert!(json.contains("meta-ahead"));
+    assert!(!json.contains("meta-behind"));
+    assert!(json.contains("meta-dirty"));
+    assert!(!json.contains("meta-clean"));
+
+    Ok(())
+}
+
+#[test]
+fn sync_expands_env_vars_in_spec_urls() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let mirror = path.join("mirror");
+    create_repo(mirror.join("meta-foo"));
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml(
+                "default",
+                &[(
+                    "meta-foo",
+                    std::path::Path::new("${MIRROR}/meta-foo"),
+                    "main",
+                )],
+            ),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    let output = yb_cmd(&yocto_dir).arg("sync").arg("-a").output()?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)?
+        .contains("environment variable 'MIRROR' used in '${MIRROR}/meta-foo' is not set"));
+
+    yb_cmd(&yocto_dir)
+        .env("MIRROR", &mirror)
+        .arg("sync")
+        .arg("-a")
+        .arg("--verify")
+        .assert()
+        .success();
+
+    let clone = yocto_dir.join("sources").join("meta-foo");
+    assert_eq!(
+        git(&clone, &["remote", "get-url", "origin"]),
+        mirror.join("meta-foo").to_str().unwrap()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn sync_mirror_clones_from_mirror_but_keeps_spec_origin() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    // The spec points at a host that doesn't exist; only the mirror can satisfy the clone
+    let spec_url = "https://git.invalid/foo/meta-foo.git";
+    let mirror = path.join("mirror");
+    create_repo(mirror.join("foo").join("meta-foo.git"));
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml(
+                "default",
+                &[("meta-foo", std::path::Path::new(spec_url), "main")],
+            ),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--mirror")
+        .arg(&mirror)
+        .assert()
+        .success();
+
+    let clone = yocto_dir.join("sources").join("meta-foo");
+    assert_eq!(git(&clone, &["branch", "--show-current"]), "main");
+    assert_eq!(git(&clone, &["remote", "get-url", "origin"]), spec_url);
+
+    Ok(())
+}
+
+#[test]
+fn max_behind_turns_stale_repo_into_error() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    let upstream = spec_env.upstream("meta-foo");
+    clone_repo(&upstream, yocto_dir.join("sources").join("meta-foo"));
+    for i in 0..5 {
+        commit_file(&upstream, "README", &format!("update {i}"));
+    }
+
+    let expected_error = "commits behind 'origin/main', more than the maximum of 2";
+    for command in ["sync", "status"] {
+        let output = yb_cmd(&yocto_dir)
+            .arg(command)
+            .arg("--max-behind")
+            .arg("2")
+            .output()?;
+        assert!(!output.status.success());
+        let stderr = std::str::from_utf8(&output.stderr)?;
+        assert!(
+            stderr.contains(&format!("is 5 {expected_error}")),
+            "{stderr}"
+        );
+    }
+
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("--max-behind")
+        .arg("5")
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn sync_reclone_replaces_wedged_repo() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    let upstream = spec_env.upstream("meta-foo");
+    let repo = yocto_dir.join("sources").join("meta-foo");
+    clone_repo(&upstream, &repo);
+
+    // Wedge the repo: wrong remote, wrong branch and local changes
+    git(
+        &repo,
+        &["remote", "set-url", "origin", "/nonexistent/meta-foo"],
+    );
+    git(&repo, &["checkout", "-b", "wip"]);
+    fs::write(repo.join("README"), "local changes")?;
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--reclone")
+        .arg("meta-foo")
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(
+        stderr.contains("its local changes will be lost"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("refusing to reclone repos with local changes without --force"));
+    assert_eq!(git(&repo, &["branch", "--show-current"]), "wip");
+
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--force")
+        .arg("--reclone")
+        .arg("meta-foo")
+        .assert()
+        .success();
+
+    assert_eq!(git(&repo, &["branch", "--show-current"]), "main");
+    assert_eq!(
+        git(&repo, &["remote", "get-url", "origin"]),
+        upstream.to_str().unwrap()
+    );
+    assert_eq!(git(&repo, &["status", "--porcelain"]), "");
+    assert_eq!(
+        git(&repo, &["rev-parse", "HEAD"]),
+        git(&upstream, &["rev-parse", "HEAD"])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn sync_print_heads_matches_cloned_repos() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let meta_foo = path.join("meta-foo");
+    create_repo(&meta_foo);
+    let meta_bar = path.join("meta-bar");
+    create_repo(&meta_bar);
+    commit_file(&meta_bar, "README", "second");
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml(
+                "default",
+                &[
+                    ("meta-foo", &meta_foo, "main"),
+                    ("meta-bar", &meta_bar, "main"),
+                ],
+            ),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--print-heads")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    for name in ["meta-foo", "meta-bar"] {
+        let head = git(yocto_dir.join("sources").join(name), &["rev-parse", "HEAD"]);
+        assert!(
+            stdout
+                .lines()
+                .any(|line| line == format!("{name} {head} main")),
+            "{stdout}"
+        );
+    }
+
+    // Nothing left to do, so the planned heads are the current ones
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("--print-heads")
+        .arg("--json")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    let head = git(
+        yocto_dir.join("sources").join("meta-bar"),
+        &["rev-parse", "HEAD"],
+    );
+    assert!(stdout.contains(&format!(r#""head": "{head}""#)), "{stdout}");
+
+    Ok(())
+}
+
+#[test]
+fn failed_clone_reports_git_stderr() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let missing = path.join("meta-missing");
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml("default", &[("meta-missing", &missing, "main")]),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    let output = yb_cmd(&yocto_dir).arg("sync").arg("-a").output()?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(
+        stderr.contains("The git clone operation failed"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("does not exist"), "{stderr}");
+
+    Ok(())
+}
+
+#[test]
+fn sync_json_events_reports_plan_and_actions() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--json-events")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+
+    // Every line of stdout is an event
+    let lines: Vec<_> = stdout.lines().collect();
+    assert!(
+        lines.iter().all(|line| line.starts_with("{\"type\":")),
+        "{stdout}"
+    );
+
+    let plan = lines
+        .iter()
+        .position(|line| line.starts_with("{\"type\":\"plan\""))
+        .expect("no plan event");
+    assert!(lines[plan].contains("\"action\":\"clone-repo\""));
+    let finished: Vec<_> = lines[plan..]
+        .iter()
+        .filter(|line| line.starts_with("{\"type\":\"action_finished\""))
+        .collect();
+    assert!(!finished.is_empty());
+    assert!(finished.iter().all(|line| line.contains("\"ok\":true")));
+
+    Ok(())
+}
+
+#[test]
+fn sync_dry_run_exit_code_reports_drift() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+
+    // meta-foo hasn't been cloned yet
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("--dry-run-exit-code")
+        .output()?;
+    assert_eq!(output.status.code(), Some(2));
+    assert!(std::str::from_utf8(&output.stderr)?
+        .contains("the environment is not in sync with the active spec"));
+    assert!(!yocto_dir.join("sources").join("meta-foo").exists());
+
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("--dry-run-exit-code")
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn sync_check_compat_warns_about_incompatible_layer() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let poky = path.join("poky");
+    create_repo(&poky);
+    commit_file(
+        &poky,
+        "meta/conf/layer.conf",
+        "LAYERSERIES_CORENAMES = \"kirkstone\"\nLAYERSERIES_COMPAT_core = \"kirkstone\"\n",
+    );
+    let meta_foo = path.join("meta-foo");
+    create_repo(&meta_foo);
+    commit_file(
+        &meta_foo,
+        "conf/layer.conf",
+        "BBFILE_COLLECTIONS += \"foo\"\nLAYERSERIES_COMPAT_foo = \"dunfell\"\n",
+    );
+
+    let stream = path.join("stream");
+    let spec = format!(
+        "header:\n  version: 1\n  name: \"default\"\n\nrepos:\n  \
+         poky:\n    url: \"{}\"\n    refspec: \"main\"\n    layers:\n      meta:\n  \
+         meta-foo:\n    url: \"{}\"\n    refspec: \"main\"\n    layers:\n      .:\n",
+        poky.display(),
+        meta_foo.display()
+    );
+    create_stream_repo(&stream, &[("default.yaml", &spec)]);
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--check-compat")
+        .output()?;
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(
+        stderr.contains("(foo) is compatible with 'dunfell', not 'kirkstone'"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("(core)"), "{stderr}");
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("--check-compat")
+        .arg("--strict")
+        .output()?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)?
+        .contains("1 layer(s) are not compatible with the 'kirkstone' release"));
+
+    Ok(())
+}
+
+/// Create a stream whose 'default' spec has `upstream` as meta-foo, enabling its root as a
+/// layer, and an environment with it synced
+fn setup_synced_layer_env(path: &std::path::Path, upstream: &std::path::Path) -> PathBuf {
+    let stream = path.join("stream");
+    let spec = format!(
+        "header:\n  version: 1\n  name: \"default\"\n\nrepos:\n  \
+         meta-foo:\n    url: \"{}\"\n    refspec: \"main\"\n    layers:\n      .:\n",
+        upstream.display()
+    );
+    create_stream_repo(&stream, &[("default.yaml", &spec)]);
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+    yocto_dir
+}
+
+#[test]
+fn sync_layers_only_fixes_bblayers_without_pulling() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    let yocto_dir = setup_synced_layer_env(path, &upstream);
+    let clone = yocto_dir.join("sources").join("meta-foo");
+    let bblayers = yocto_dir.join("build").join("conf").join("bblayers.conf");
+    assert!(fs::read_to_string(&bblayers)?.contains("sources/meta-foo"));
+
+    // Lose the layer and fall behind upstream
+    fs::remove_file(&bblayers)?;
+    let old_head = git(&clone, &["rev-parse", "HEAD"]);
+    commit_file(&upstream, "new-file", "new");
+
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("--layers-only")
+        .arg("-a")
+        .assert()
+        .success();
+
+    assert!(fs::read_to_string(&bblayers)?.contains("sources/meta-foo"));
+    assert_eq!(git(&clone, &["rev-parse", "HEAD"]), old_head);
+    assert_eq!(git(&clone, &["rev-parse", "--abbrev-ref", "HEAD"]), "main");
+
+    Ok(())
+}
+
+#[test]
+fn sync_git_only_pulls_without_touching_bblayers() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    let yocto_dir = setup_synced_layer_env(path, &upstream);
+    let clone = yocto_dir.join("sources").join("meta-foo");
+    let bblayers = yocto_dir.join("build").join("conf").join("bblayers.conf");
+
+    // Lose the layer and fall behind upstream
+    fs::remove_file(&bblayers)?;
+    commit_file(&upstream, "new-file", "new");
+
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("--git-only")
+        .arg("-a")
+        .assert()
+        .success();
+
+    assert_eq!(
+        git(&clone, &["rev-parse", "HEAD"]),
+        git(&upstream, &["rev-parse", "HEAD"])
+    );
+    assert!(!bblayers.exists());
+
+    Ok(())
+}
+
+#[test]
+fn sync_without_poky_refuses_partial_sync() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let poky = path.join("poky");
+    create_repo(&poky);
+    let meta_foo = path.join("meta-foo");
+    create_repo(&meta_foo);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml(
+                "default",
+                &[("poky", &poky, "main"), ("meta-foo", &meta_foo, "main")],
+            ),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--only")
+        .arg("meta-foo")
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(
+        stderr.contains("the active spec needs poky (spec repo 'poky')"),
+        "{stderr}"
+    );
+    assert!(!yocto_dir.join("sources").join("meta-foo").exists());
+
+    // Syncing everything clones poky along with the rest
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+    assert!(yocto_dir.join("sources").join("poky").is_dir());
+
+    Ok(())
+}
+
+#[test]
+fn log_interleaves_commits_across_repos_by_time() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+    yb_cmd(path).arg("init").assert().success();
+    let yocto_dir = path.join("yocto");
+
+    let meta_a = yocto_dir.join("sources").join("meta-a");
+    let meta_b = yocto_dir.join("sources").join("meta-b");
+    for repo in [&meta_a, &meta_b] {
+        fs::create_dir_all(repo)?;
+        git(repo, &["init", "-b", "main"]);
+    }
+    commit_at(&meta_a, "a-old", "2020-01-01T00:00:00");
+    commit_at(&meta_b, "b-mid", "2020-02-01T00:00:00");
+    commit_at(&meta_a, "a-new", "2020-03-01T00:00:00");
+
+    let output = yb_cmd(&yocto_dir).arg("log").output()?;
+    assert!(output.status.success());
+    let summaries: Vec<_> = std::str::from_utf8(&output.stdout)?
+        .lines()
+        .map(|line| {
+            let mut fields = line.split(' ');
+            let repo = fields.next().unwrap();
+            let summary = fields.nth(1).unwrap();
+            format!("{repo} {summary}")
+        })
+        .collect();
+    assert_eq!(
+        summaries,
+        vec!["meta-a a-new", "meta-b b-mid", "meta-a a-old"]
+    );
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("log")
+        .arg("-n")
+        .arg("1")
+        .arg("--json")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    assert!(stdout.contains("\"summary\": \"a-new\""), "{stdout}");
+    assert!(!stdout.contains("b-mid"), "{stdout}");
+
+    Ok(())
+}
+
+#[test]
+fn status_shallow_repo_is_not_reported_as_behind() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    let upstream_url = format!("file://{}", upstream.display());
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml(
+                "default",
+                &[("meta-foo", std::path::Path::new(&upstream_url), "main")],
+            ),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+    let sources = yocto_dir.join("sources");
+    git(
+        &sources,
+        &["clone", "--depth", "1", &upstream_url, "meta-foo"],
+    );
+
+    // Fetching shallowly leaves a gap in history, so a revwalk would count 1 commit behind
+    for i in 0..5 {
+        commit_file(&upstream, "README", &format!("update {i}"));
+    }
+    let clone = sources.join("meta-foo");
+    git(&clone, &["fetch", "--depth", "1", "origin"]);
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("status")
+        .arg("--no-fetch")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    assert!(!stdout.contains("commits behind"), "{stdout}");
+    assert!(
+        stdout.contains("differs from 'origin/main' (unknown: shallow clone)"),
+        "{stdout}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn completions_include_subcommands_and_spec_names() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[
+            (
+                "default.yaml",
+                &spec_yaml("default", &[("meta-foo", &upstream, "main")]),
+            ),
+            (
+                "other.yaml",
+                &spec_yaml("other", &[("meta-foo", &upstream, "main")]),
+            ),
+        ],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    let output = yb_cmd(&yocto_dir).arg("completions").arg("bash").output()?;
+    assert!(output.status.success());
+    let script = std::str::from_utf8(&output.stdout)?;
+    assert!(script.contains("activate"));
+    assert!(script.contains("yb completions --list-specs"));
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("completions")
+        .arg("--list-specs")
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(std::str::from_utf8(&output.stdout)?, "default\nother\n");
+
+    Ok(())
+}
+
+#[test]
+fn sync_reflog_note_backs_up_head_before_reset() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    let upstream = spec_env.upstream("meta-foo");
+    let clone = yocto_dir.join("sources").join("meta-foo");
+    clone_repo(&upstream, &clone);
+    fs::write(clone.join("README"), "local changes")?;
+    let pre_reset = git(&clone, &["rev-parse", "HEAD"]);
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--force")
+        .arg("--reflog-note")
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(clone.join("README"))?, "initial");
+
+    let backup_ref = git(
+        &clone,
+        &["for-each-ref", "--format=%(refname)", "refs/yb/backup/"],
+    );
+    assert!(backup_ref.ends_with("-reset"), "{backup_ref}");
+    assert!(std::str::from_utf8(&output.stderr)?.contains(&backup_ref));
+    assert_eq!(git(&clone, &["rev-parse", &backup_ref]), pre_reset);
+    assert!(git(&clone, &["reflog", "show", &backup_ref])
+        .contains(&format!("yb sync: pre-reset @ {pre_reset}")));
+
+    Ok(())
+}
+
+#[test]
+fn sync_assume_clean_does_not_reset_dirty_repo() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    let upstream = spec_env.upstream("meta-foo");
+    let clone = yocto_dir.join("sources").join("meta-foo");
+    clone_repo(&upstream, &clone);
+    fs::write(clone.join("README"), "local changes")?;
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--force")
+        .arg("--assume-clean")
+        .output()?;
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains("local changes in repos will not be detected"));
+    assert_eq!(fs::read_to_string(clone.join("README"))?, "local changes");
+
+    // Without it, the repo is reset
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--force")
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(clone.join("README"))?, "initial");
+
+    Ok(())
+}
+
+#[test]
+fn init_bare_metadata_derives_spec() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let meta_foo = path.join("upstreams").join("meta-foo");
+    create_repo(&meta_foo);
+    commit_file(&meta_foo, "conf/layer.conf", "");
+    let meta_bar = path.join("upstreams").join("meta-bar");
+    create_repo(&meta_bar);
+    commit_file(&meta_bar, "meta-baz/conf/layer.conf", "");
+
+    // An existing Yocto tree that yb knows nothing about
+    let yocto_dir = path.join("existing");
+    let sources_dir = yocto_dir.join("sources");
+    fs::create_dir_all(&sources_dir)?;
+    clone_repo(&meta_foo, sources_dir.join("meta-foo"));
+    clone_repo(&meta_bar, sources_dir.join("meta-bar"));
+    let conf_dir = yocto_dir.join("build").join("conf");
+    fs::create_dir_all(&conf_dir)?;
+    fs::write(
+        conf_dir.join("bblayers.conf"),
+        format!(
+            "BBLAYERS ?= \"{} {}\"\n",
+            sources_dir.join("meta-foo").display(),
+            sources_dir.join("meta-bar").join("meta-baz").display()
+        ),
+    )?;
+
+    yb_cmd(path)
+        .arg("init")
+        .arg("--dir")
+        .arg(&yocto_dir)
+        .arg("--bare-metadata")
+        .assert()
+        .success();
+
+    let spec = fs::read_to_string(
+        yocto_dir
+            .join(".yb")
+            .join("streams")
+            .join("local")
+            .join("contents")
+            .join("local.yaml"),
+    )?;
+    assert!(spec.contains(meta_foo.to_str().unwrap()));
+    assert!(spec.contains(meta_bar.to_str().unwrap()));
+    assert!(spec.contains("meta-baz"));
+
+    // The derived spec describes the tree exactly
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--verify")
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn status_header_shows_active_spec_and_stream() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "myspec.yaml",
+            &spec_yaml("myspec", &[("meta-foo", &upstream, "main")]),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "myspec");
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("status")
+        .arg("--no-fetch")
+        .arg("--group-by-stream")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    let stream_commit = git(&stream, &["rev-parse", "--short", "HEAD"]);
+    assert!(stdout.contains(&format!(
+        "active spec 'myspec' from stream 'default' at {stream_commit}"
+    )));
+    assert!(stdout.contains("stream 'default' (spec 'myspec'):"));
+    assert!(stdout.contains("meta-foo: missing"));
+
+    Ok(())
+}
+
+#[test]
+fn spec_repos_lists_sorted_repos() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let poky = path.join("poky");
+    create_repo(&poky);
+    let meta_foo = path.join("meta-foo");
+    create_repo(&meta_foo);
+    let stream = path.join("stream");
+    // Disabled repos aren't listed
+    let spec = spec_yaml(
+        "myspec",
+        &[
+            ("poky", &poky, "kirkstone"),
+            ("meta-foo", &meta_foo, "main"),
+        ],
+    ) + &format!(
+        "  meta-old:\n    url: \"{}\"\n    refspec: \"main\"\n    enabled: false\n",
+        meta_foo.display()
+    );
+    create_stream_repo(&stream, &[("myspec.yaml", &spec)]);
+    let yocto_dir = setup_yb_env(path, &stream, "myspec");
+
+    let expected = format!(
+        "meta-foo\t{}\tmain\npoky\t{}\tkirkstone\n",
+        meta_foo.display(),
+        poky.display()
+    );
+    for args in [&["spec", "repos"][..], &["spec", "repos", "myspec"][..]] {
+        let output = yb_cmd(&yocto_dir).args(args).output()?;
+        assert!(output.status.success());
+        assert_eq!(std::str::from_utf8(&output.stdout)?, expected);
+    }
+
+    let output = yb_cmd(&yocto_dir)
+        .args(["spec", "repos", "--json"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    assert!(stdout.find("\"meta-foo\"").unwrap() < stdout.find("\"poky\"").unwrap());
+    assert!(stdout.contains("\"refspec\": \"kirkstone\""));
+
+    yb_cmd(&yocto_dir)
+        .args(["spec", "repos", "nonexistent"])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn keep_temp_retains_failed_stream_clone() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    yb_cmd(path).arg("init").assert().success();
+    let yocto_dir = path.join("yocto");
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("--keep-temp")
+        .arg("stream")
+        .arg("add")
+        .arg(path.join("nonexistent"))
+        .output()?;
+    assert!(!output.status.success());
+
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    let retained = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("retaining temporary directory at: "))
+        .expect("temp dir path was not printed");
+    let retained = PathBuf::from(retained);
+    assert!(retained.is_dir());
+    fs::remove_dir_all(&retained)?;
+
+    Ok(())
+}
+
+#[test]
+fn info_reports_version_and_context() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let output = yb_cmd(path).arg("info").output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    assert!(stdout.contains(&format!("version: {}", yb::VERSION)));
+    assert!(stdout.contains("context: none"));
+
+    yb_cmd(path).arg("init").assert().success();
+    let output = yb_cmd(path.join("yocto"))
+        .arg("info")
+        .arg("--json")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    assert!(stdout.contains(&format!("\"version\": \"{}\"", yb::VERSION)));
+    assert!(stdout.contains("\"context\": \"yb\""));
+
+    Ok(())
+}
+
+async fn setup_yocto_env() -> Result<YoctoEnv> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let yocto_dir = path.join("yocto");
+    fs::create_dir(&yocto_dir)?;
+
+    let sources_dir = yocto_dir.join("sources");
+    fs::create_dir(&sources_dir)?;
+
+    clone_repos! {
+        "https://github.com/yoctoproject/poky.git" in &sources_dir,
+        "https://github.com/openembedded/meta-openembedded.git" in &sources_dir,
+    }
+
+    let build_dir = yocto_dir.join("build");
+    let conf_dir = build_dir.join("conf");
+    let bblayers = conf_dir.join("bblayers.conf");
+    fs::create_dir_all(conf_dir).unwrap();
+    let mut contents =
+        r##"# POKY_BBLAYERS_CONF_VERSION is increased each time build/conf/bblayers.conf
+# changes incompatibly
+POKY_BBLAYERS_CONF_VERSION = "2"
+
+BBPATH = "${TOPDIR}"
+BBFILES ??= ""
+BBLAYERS ?= " "##
+            .to_string();
+
+    contents += sources_dir.join("poky").to_str().unwrap();
+    contents.push(' ');
+    contents += sources_dir.join("meta-openembedded").to_str().unwrap();
+    contents.push('"');
+
+    fs::write(bblayers, contents).unwrap();
+
+    Command::new("sh")
+        .current_dir(&yocto_dir)
+        .arg("-c")
+        .arg(". sources/poky/oe-init-build-env")
+        .unwrap();
+
+    let path_var = std::env::var("PATH").unwrap();
+    let path_var = format!(
+        "{}:{}:{}",
+        sources_dir.join("poky").join("scripts").to_str().unwrap(),
+        sources_dir
+            .join("poky")
+            .join("bitbake")
+            .join("bin")
+            .to_str()
+            .unwrap(),
+        path_var
+    );
+
+    Ok(YoctoEnv {
+        root: t,
+        sources_dir,
+        yocto_dir,
+        build_dir,
+        path_var,
+    })
+}
+
+struct YoctoEnv {
+    root: DebugTempDir,
+    sources_dir: PathBuf,
+    yocto_dir: PathBuf,
+    build_dir: PathBuf,
+    path_var: String,
+}
+
+#[tokio::test]
+async fn yb_upgrade() -> Result<()> {
+    // Test that `yb upgrade` can upgrade an existing Yocto env
+    let env = setup_yocto_env().await?;
+
+    yb_cmd(env.yocto_dir)
+        .arg("upgrade")
+        .env("PATH", env.path_var)
+        .env("BBPATH", env.build_dir.to_str().unwrap())
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn yb_init_fails_on_yocto_env() -> Result<()> {
+    // Test that `yb init` fails inside a Yocto env
+    let env = setup_yocto_env().await?;
+
+    let output = yb_cmd(env.yocto_dir)
+        .arg("init")
+        .env("PATH", env.path_var)
+        .env("BBPATH", env.build_dir.to_str().unwrap())
+        .output()
+        .unwrap();
+
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains("cannot init yb env within an activated Yocto environment"));
+
+    Ok(())
+}
+
+fn create_yb_conf_repo() -> Result<GitRepo> {
+    let dir = DebugTempDir::new().unwrap();
+    let dir_path = dir.path().to_path_buf();
+
+    Command::new("git")
+        .arg("init")
+        .current_dir(&dir_path)
+        .output()?;
+
+    let basic_yaml = include_bytes!("resources/confs/basic.yaml");
+    fs::write(dir_path.join("basic.yaml"), basic_yaml).unwrap();
+
+    Command::new("git")
+        .current_dir(&dir_path)
+        .arg("add")
+        .arg("basic.yaml")
+        .output()?;
+
+    Command::new("git")
+        .current_dir(&dir_path)
+        .arg("commit")
+        .arg("-m")
+        .arg("'initial'")
+        .output()?;
+
+    Ok(GitRepo { path: dir })
+}
+
+#[derive(Debug)]
 struct GitRepo {
     path: DebugTempDir,
 }
+
+#[test]
+fn non_utf8_remote_name_does_not_break_status() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+
+    // Add a remote whose name is Latin-1 rather than UTF-8
+    let git_config = yocto_dir
+        .join("sources")
+        .join("meta-foo")
+        .join(".git")
+        .join("config");
+    let mut config = fs::read(&git_config)?;
+    config.extend_from_slice(b"[remote \"caf\xe9\"]\n\turl = /nowhere\n");
+    fs::write(&git_config, config)?;
+
+    yb_cmd(&yocto_dir)
+        .arg("status")
+        .arg("--no-fetch")
+        .assert()
+        .success();
+    yb_cmd(&yocto_dir).arg("sync").assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn status_expected_machine_warns_about_mismatch() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    let mut yaml = spec_yaml("default", &[("meta-foo", &upstream, "main")]);
+    yaml += "local_conf:\n  MACHINE: \"qemux86-64\"\n";
+    let stream = path.join("stream");
+    create_stream_repo(&stream, &[("default.yaml", &yaml)]);
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+
+    let conf_dir = yocto_dir.join("build").join("conf");
+    fs::create_dir_all(&conf_dir)?;
+    fs::write(conf_dir.join("local.conf"), "MACHINE ?= \"raspberrypi4\"\n")?;
+
+    let output = yb_cmd(&yocto_dir)
+        .arg("status")
+        .arg("--no-fetch")
+        .arg("--expected-machine")
+        .output()?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("sets MACHINE to 'raspberrypi4', but the active spec expects 'qemux86-64'"),
+        "{stderr}"
+    );
+
+    yb_cmd(&yocto_dir)
+        .arg("status")
+        .arg("--no-fetch")
+        .arg("--expected-machine")
+        .arg("--strict")
+        .assert()
+        .failure();
+
+    // Once the spec's settings are written, they take precedence over the soft assignment
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--write-local-conf")
+        .assert()
+        .success();
+    let output = yb_cmd(&yocto_dir)
+        .arg("status")
+        .arg("--no-fetch")
+        .arg("--expected-machine")
+        .arg("--strict")
+        .output()?;
+    assert!(output.status.success());
+    assert!(!String::from_utf8(output.stderr)?.contains("MACHINE"));
+
+    Ok(())
+}
+
+#[test]
+fn sync_checkout_strategy_sets_head_state() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    let upstream = spec_env.upstream("meta-foo");
+    let clone = yocto_dir.join("sources").join("meta-foo");
+    let upstream_head = git(&upstream, &["rev-parse", "main"]);
+
+    // 'detach' clones with a detached HEAD
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--checkout-strategy")
+        .arg("detach")
+        .assert()
+        .success();
+    assert_eq!(git(&clone, &["rev-parse", "--abbrev-ref", "HEAD"]), "HEAD");
+    assert_eq!(git(&clone, &["rev-parse", "HEAD"]), upstream_head);
+
+    // 'track' switches to a local branch tracking the spec's branch
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+    assert_eq!(git(&clone, &["rev-parse", "--abbrev-ref", "HEAD"]), "main");
+    assert_eq!(
+        git(&clone, &["rev-parse", "--abbrev-ref", "main@{upstream}"]),
+        "origin/main"
+    );
+
+    // 'reset' drops local commits on the branch, which takes --force
+    commit_file(&clone, "local.txt", "local work");
+    git(&clone, &["checkout", "-b", "feature"]);
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--checkout-strategy")
+        .arg("reset")
+        .output()?;
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(
+        stderr.contains("refusing to reset-branch main in") && stderr.contains("without --force"),
+        "{stderr}"
+    );
+    assert_eq!(
+        git(&clone, &["rev-parse", "--abbrev-ref", "HEAD"]),
+        "feature"
+    );
+    yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("-a")
+        .arg("--force")
+        .arg("--checkout-strategy")
+        .arg("reset")
+        .assert()
+        .success();
+    assert_eq!(git(&clone, &["rev-parse", "--abbrev-ref", "HEAD"]), "main");
+    assert_eq!(git(&clone, &["rev-parse", "HEAD"]), upstream_head);
+    assert_eq!(
+        git(&clone, &["rev-parse", "--abbrev-ref", "main@{upstream}"]),
+        "origin/main"
+    );
+
+    // Once there, nothing more is planned
+    let output = yb_cmd(&yocto_dir)
+        .arg("sync")
+        .arg("--checkout-strategy")
+        .arg("reset")
+        .output()?;
+    assert!(output.status.success());
+    assert!(!String::from_utf8(output.stdout)?.contains("ResetBranch"));
+
+    Ok(())
+}
+
+#[test]
+fn spec_exclude_drops_repo_inherited_from_base() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let meta_foo = path.join("meta-foo");
+    create_repo(&meta_foo);
+    let meta_bar = path.join("meta-bar");
+    create_repo(&meta_bar);
+    let mut base_yaml = spec_yaml(
+        "base",
+        &[
+            ("meta-foo", &meta_foo, "main"),
+            ("meta-bar", &meta_bar, "main"),
+        ],
+    );
+    base_yaml += "local_conf:\n  MACHINE: \"qemux86-64\"\n";
+    let derived_yaml = "header:\n  version: 1\n  name: \"derived\"\n\n\
+                        base: base\nexclude:\n  - meta-bar\nrepos: {}\n";
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[("base.yaml", &base_yaml), ("derived.yaml", derived_yaml)],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "derived");
+
+    let output = yb_cmd(&yocto_dir)
+        .args(["spec", "repos", "derived"])
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        std::str::from_utf8(&output.stdout)?,
+        format!("meta-foo\t{}\tmain\n", meta_foo.display())
+    );
+
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+    assert!(yocto_dir.join("sources").join("meta-foo").is_dir());
+    assert!(!yocto_dir.join("sources").join("meta-bar").exists());
+
+    Ok(())
+}
+
+#[test]
+fn env_export_writes_sourced_build_env() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+    yb_cmd(path).arg("init").assert().success();
+    let yocto_dir = path.join("yocto");
+
+    // Stand-in for poky's init script: just the parts yb cares about
+    let poky_dir = yocto_dir.join("sources").join("poky");
+    create_repo(&poky_dir);
+    fs::write(
+        poky_dir.join("oe-init-build-env"),
+        "BBPATH=\"$(cd \"$1\" && pwd -P)\"\nexport BBPATH\nexport BUILDDIR=\"$BBPATH\"\n\
+         cd \"$BBPATH\"\necho \"### Shell environment set up for builds. ###\"\n",
+    )?;
+
+    yb_cmd(&yocto_dir)
+        .args(["env", "--export", "build.env"])
+        .assert()
+        .success();
+
+    let build_dir = yocto_dir.join("build").canonicalize()?;
+    let exported = fs::read_to_string(yocto_dir.join("build.env"))?;
+    assert!(exported.contains(&format!("BBPATH=\"{}\"\n", build_dir.display())));
+    assert!(!exported.contains("### Shell environment"));
+
+    let output = yb_cmd(&yocto_dir)
+        .args(["run", "--env-file", "build.env", "printenv", "BBPATH"])
+        .output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains(&build_dir.display().to_string()));
+
+    Ok(())
+}
+
+#[test]
+fn status_max_commits_controls_log_depth() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    for i in 0..12 {
+        commit_file(&upstream, "file", &i.to_string());
+    }
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml("default", &[("meta-foo", &upstream, "main")]),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+
+    let history = git(&upstream, &["rev-list", "HEAD"]);
+    let history: Vec<_> = history.lines().collect();
+
+    let log_output = |max_commits: Option<&str>| -> Result<String> {
+        let mut cmd = yb_cmd(&yocto_dir);
+        cmd.args(["--porcelain", "status", "--no-fetch", "--log"]);
+        if let Some(max_commits) = max_commits {
+            cmd.args(["--max-commits", max_commits]);
+        }
+        let output = cmd.output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    let stdout = log_output(None)?;
+    assert!(history[..5].iter().all(|id| stdout.contains(id)));
+    assert!(!stdout.contains(history[5]));
+
+    let stdout = log_output(Some("10"))?;
+    assert!(history[..10].iter().all(|id| stdout.contains(id)));
+    assert!(!stdout.contains(history[10]));
+
+    // --max-commits only means something with --log
+    yb_cmd(&yocto_dir)
+        .args(["status", "--no-fetch", "--max-commits", "10"])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn activate_qualified_spec_name_disambiguates_streams() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let meta_foo = path.join("meta-foo");
+    create_repo(&meta_foo);
+    let meta_bar = path.join("meta-bar");
+    create_repo(&meta_bar);
+    let stream_a = path.join("stream-a");
+    create_stream_repo(
+        &stream_a,
+        &[(
+            "default.yaml",
+            &spec_yaml("default", &[("meta-foo", &meta_foo, "main")]),
+        )],
+    );
+    let stream_b = path.join("stream-b");
+    create_stream_repo(
+        &stream_b,
+        &[(
+            "default.yaml",
+            &spec_yaml("default", &[("meta-bar", &meta_bar, "main")]),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream_a, "default");
+    yb_cmd(&yocto_dir)
+        .args(["stream", "add"])
+        .arg(&stream_b)
+        .args(["--name", "other"])
+        .assert()
+        .success();
+
+    let output = yb_cmd(&yocto_dir).args(["activate", "default"]).output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("found in multiple streams"), "{stderr}");
+    assert!(
+        stderr.contains("'default/default' or 'other/default'"),
+        "{stderr}"
+    );
+
+    yb_cmd(&yocto_dir)
+        .args(["activate", "other/default"])
+        .assert()
+        .success();
+    let output = yb_cmd(&yocto_dir).args(["status", "--no-fetch"]).output()?;
+    assert!(output.status.success());
+    assert!(
+        std::str::from_utf8(&output.stdout)?.contains("active spec 'default' from stream 'other'")
+    );
+
+    yb_cmd(&yocto_dir)
+        .args(["activate", "nonexistent/default"])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn sync_clean_ignored_removes_ignored_files() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    commit_file(&upstream, ".gitignore", "*.o\n");
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml("default", &[("meta-foo", &upstream, "main")]),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+
+    let stale = yocto_dir.join("sources").join("meta-foo").join("stale.o");
+    fs::write(&stale, "")?;
+
+    let output = yb_cmd(&yocto_dir)
+        .args(["sync", "-a", "--clean-ignored"])
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("without --force"));
+    assert!(stale.exists());
+
+    yb_cmd(&yocto_dir)
+        .args(["sync", "-a", "--clean-ignored", "--force"])
+        .assert()
+        .success();
+    assert!(!stale.exists());
+    assert!(yocto_dir
+        .join("sources")
+        .join("meta-foo")
+        .join(".gitignore")
+        .exists());
+
+    Ok(())
+}
+
+#[test]
+fn status_remote_compares_repo_with_remote_ref() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    let local = path.join("local");
+    clone_repo(&upstream, &local);
+    commit_file(&upstream, "a", "a");
+    commit_file(&upstream, "b", "b");
+
+    // No yb environment is needed
+    let remote = format!("{}@main", upstream.display());
+    let output = yb_cmd(path)
+        .args(["status", "--remote", &remote, "--path", "local"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("is 2 commits behind"), "{stdout}");
+
+    commit_file(&local, "c", "c");
+    let output = yb_cmd(&local)
+        .args(["--porcelain", "status", "--remote", &remote])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("\"ahead\": 1"), "{stdout}");
+    assert!(stdout.contains("\"behind\": 2"), "{stdout}");
+
+    yb_cmd(path)
+        .args(["status", "--remote", "no-ref-given"])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn status_notes_specs_changed_by_stream_update() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let meta_foo = path.join("meta-foo");
+    create_repo(&meta_foo);
+    let meta_bar = path.join("meta-bar");
+    create_repo(&meta_bar);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[
+            (
+                "default.yaml",
+                &spec_yaml("default", &[("meta-foo", &meta_foo, "main")]),
+            ),
+            (
+                "other.yaml",
+                &spec_yaml("other", &[("meta-foo", &meta_foo, "main")]),
+            ),
+        ],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    let status_stderr = |extra_args: &[&str]| -> Result<String> {
+        let output = yb_cmd(&yocto_dir).arg("status").args(extra_args).output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stderr)?)
+    };
+
+    // The first run only records what the stream looks like
+    assert!(!status_stderr(&[])?.contains("changed since the last run"));
+
+    commit_file(
+        &stream,
+        "other.yaml",
+        &spec_yaml(
+            "other",
+            &[
+                ("meta-foo", &meta_foo, "main"),
+                ("meta-bar", &meta_bar, "main"),
+            ],
+        ),
+    );
+    let stderr = status_stderr(&[])?;
+    assert!(
+        stderr.contains("stream 'default' changed since the last run"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("spec 'other' modified"), "{stderr}");
+    assert!(stderr.contains("meta-bar: added"), "{stderr}");
+    assert!(!stderr.contains("spec 'default'"), "{stderr}");
+
+    // Nothing to report once seen
+    assert!(!status_stderr(&[])?.contains("changed since the last run"));
+
+    commit_file(
+        &stream,
+        "third.yaml",
+        &spec_yaml("third", &[("meta-bar", &meta_bar, "main")]),
+    );
+    assert!(!status_stderr(&["--no-stream-diff"])?.contains("changed since the last run"));
+
+    Ok(())
+}
+
+#[test]
+fn sync_force_refspec_resets_ahead_branch() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    let upstream = spec_env.upstream("meta-foo");
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+
+    let clone = yocto_dir.join("sources").join("meta-foo");
+    commit_file(&clone, "local", "local work");
+    let local_head = git(&clone, &["rev-parse", "HEAD"]);
+    let upstream_head = git(&upstream, &["rev-parse", "HEAD"]);
+
+    // Dropping the local commit needs --force; without it sync fails cleanly rather than panicking
+    let output = yb_cmd(&yocto_dir)
+        .args(["sync", "-a", "--force-refspec"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(
+        stderr.contains("refusing to reset-branch main in") && stderr.contains("without --force"),
+        "{stderr}"
+    );
+    assert_eq!(git(&clone, &["rev-parse", "HEAD"]), local_head);
+
+    yb_cmd(&yocto_dir)
+        .args(["sync", "-a", "--force-refspec", "--force"])
+        .assert()
+        .success();
+    assert_eq!(git(&clone, &["rev-parse", "HEAD"]), upstream_head);
+    assert_eq!(git(&clone, &["rev-parse", "--abbrev-ref", "HEAD"]), "main");
+
+    // The dropped commit is kept in a backup ref
+    let backup_ref = git(
+        &clone,
+        &["for-each-ref", "--format=%(refname)", "refs/yb/backup/"],
+    );
+    assert_eq!(git(&clone, &["rev-parse", &backup_ref]), local_head);
+
+    Ok(())
+}
+
+#[test]
+fn activate_spec_file_without_stream() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+    yb_cmd(path).arg("init").assert().success();
+    let yocto_dir = path.join("yocto");
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    fs::write(
+        path.join("experiment.yaml"),
+        spec_yaml("experiment", &[("meta-foo", &upstream, "main")]),
+    )?;
+
+    yb_cmd(&yocto_dir)
+        .args(["activate", "--spec-file", "../experiment.yaml"])
+        .assert()
+        .success();
+    let active_spec = fs::read_to_string(yocto_dir.join(".yb").join("active_spec.yaml"))?;
+    assert!(active_spec.contains("experiment.yaml"), "{active_spec}");
+
+    let output = yb_cmd(&yocto_dir).args(["status", "--no-fetch"]).output()?;
+    assert!(output.status.success());
+    assert!(std::str::from_utf8(&output.stdout)?
+        .contains("active spec 'experiment' from stream 'spec-files'"));
+
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+    assert_eq!(
+        git(
+            yocto_dir.join("sources").join("meta-foo"),
+            &["rev-parse", "HEAD"]
+        ),
+        git(&upstream, &["rev-parse", "HEAD"])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn sync_max_clone_size_refuses_big_repo() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    // Pseudo-random contents, so that git can't compress them away
+    let mut state: u32 = 1;
+    let blob: String = (0..256 * 1024)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            char::from(b'a' + ((state >> 16) % 26) as u8)
+        })
+        .collect();
+    let upstream = path.join("meta-big");
+    create_repo(&upstream);
+    commit_file(&upstream, "blob", &blob);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml("default", &[("meta-big", &upstream, "main")]),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    let output = yb_cmd(&yocto_dir)
+        .args(["sync", "-a", "--max-clone-size", "64K"])
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("bigger than --max-clone-size"), "{stderr}");
+    assert!(!yocto_dir.join("sources").join("meta-big").exists());
+
+    yb_cmd(&yocto_dir)
+        .args(["sync", "-a", "--max-clone-size", "10M"])
+        .assert()
+        .success();
+    assert!(yocto_dir.join("sources").join("meta-big").is_dir());
+
+    Ok(())
+}
+
+#[test]
+fn status_include_submodule_status_flags_wrong_commit() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let submodule = path.join("meta-sub");
+    create_repo(&submodule);
+    commit_file(&submodule, "sub.txt", "from submodule");
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    git(
+        &upstream,
+        &[
+            "-c",
+            "protocol.file.allow=always",
+            "submodule",
+            "add",
+            submodule.to_str().unwrap(),
+            "meta-sub",
+        ],
+    );
+    git(&upstream, &["commit", "-m", "add submodule"]);
+
+    let mut spec = spec_yaml("default", &[("meta-foo", &upstream, "main")]);
+    spec += "    submodules: true\n";
+    let stream = path.join("stream");
+    create_stream_repo(&stream, &[("default.yaml", &spec)]);
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    yb_cmd(&yocto_dir)
+        .env("GIT_CONFIG_COUNT", "1")
+        .env("GIT_CONFIG_KEY_0", "protocol.file.allow")
+        .env("GIT_CONFIG_VALUE_0", "always")
+        .arg("sync")
+        .arg("-a")
+        .assert()
+        .success();
+
+    let status = || -> Result<String> {
+        let output = yb_cmd(&yocto_dir)
+            .args([
+                "--porcelain",
+                "status",
+                "--no-fetch",
+                "--include-submodule-status",
+            ])
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    let stdout = status()?;
+    assert!(stdout.contains("OnRecordedCommit"), "{stdout}");
+    assert!(!stdout.contains("WrongCommit"), "{stdout}");
+
+    // Move the submodule's checkout away from the commit the superproject records
+    let sub_checkout = yocto_dir.join("sources").join("meta-foo").join("meta-sub");
+    commit_file(&sub_checkout, "local.txt", "local change");
+
+    let stdout = status()?;
+    assert!(stdout.contains("WrongCommit"), "{stdout}");
+    assert!(stdout.contains("meta-sub"), "{stdout}");
+
+    Ok(())
+}
+
+#[test]
+fn global_yes_confirms_prompts_but_not_force() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+    let spec_env = SpecEnv::new(path, &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+
+    // A layer the user enabled by hand
+    let local_layer = path.join("meta-local");
+    fs::create_dir_all(local_layer.join("conf"))?;
+    fs::write(local_layer.join("conf").join("layer.conf"), "")?;
+    let conf_dir = yocto_dir.join("build").join("conf");
+    fs::create_dir_all(&conf_dir)?;
+    let bblayers = conf_dir.join("bblayers.conf");
+    fs::write(
+        &bblayers,
+        format!("BBLAYERS ?= \"{}\"\n", local_layer.display()),
+    )?;
+
+    // No terminal is attached, so without --yes this would fail asking for confirmation
+    yb_cmd(&yocto_dir)
+        .args(["--yes", "sync", "--exact", "-a"])
+        .assert()
+        .success();
+    assert!(!fs::read_to_string(&bblayers)?.contains(local_layer.to_str().unwrap()));
+    assert!(yocto_dir.join("sources").join("meta-foo").is_dir());
+
+    // --yes acknowledges prompts, but destructive actions still need --force
+    let output = yb_cmd(&yocto_dir)
+        .args(["sync", "-a", "-y", "--clean-ignored"])
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("without --force"), "{stderr}");
+
+    Ok(())
+}
+
+#[test]
+fn stream_info_shows_remote_and_specs() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[
+            (
+                "default.yaml",
+                &spec_yaml("default", &[("meta-foo", &upstream, "main")]),
+            ),
+            (
+                "other.yaml",
+                &spec_yaml("other", &[("meta-foo", &upstream, "main")]),
+            ),
+        ],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    let output = yb_cmd(&yocto_dir)
+        .args(["stream", "info", "default"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains(stream.to_str().unwrap()), "{stdout}");
+    assert!(stdout.contains("    default\n    other\n"), "{stdout}");
+
+    let output = yb_cmd(&yocto_dir)
+        .args(["stream", "info", "default", "--json"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(
+        stdout.contains(&format!(r#""remote_url": "{}""#, stream.display())),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains(&format!(
+            r#""current_commit": "{}""#,
+            git(&stream, &["rev-parse", "HEAD"])
+        )),
+        "{stdout}"
+    );
+    assert!(stdout.contains("\"specs\": [\n    \"default\",\n    \"other\"\n  ]"));
+
+    yb_cmd(&yocto_dir)
+        .args(["stream", "info", "nope"])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn disabled_spec_repo_is_neither_missing_nor_enabled() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let foo = path.join("meta-foo");
+    create_repo(&foo);
+    let bar = path.join("meta-bar");
+    create_repo(&bar);
+    let stream = path.join("stream");
+    let spec = format!(
+        "header:\n  version: 1\n  name: \"default\"\n\nrepos:\n  \
+         meta-foo:\n    url: \"{}\"\n    refspec: \"main\"\n    layers:\n      .:\n  \
+         meta-bar:\n    url: \"{}\"\n    refspec: \"main\"\n    layers:\n      .:\n    \
+         enabled: false\n",
+        foo.display(),
+        bar.display()
+    );
+    create_stream_repo(&stream, &[("default.yaml", &spec)]);
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+    assert!(yocto_dir.join("sources").join("meta-foo").is_dir());
+    assert!(!yocto_dir.join("sources").join("meta-bar").exists());
+
+    let output = yb_cmd(&yocto_dir)
+        .args(["--porcelain", "status", "--no-fetch", "--only-problems"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("\"problem_count\": 0"), "{stdout}");
+    assert!(!stdout.contains("meta-bar"), "{stdout}");
+
+    // Even when it is on disk, the disabled repo's layers are left alone
+    clone_repo(&bar, yocto_dir.join("sources").join("meta-bar"));
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+    let bblayers = fs::read_to_string(yocto_dir.join("build").join("conf").join("bblayers.conf"))?;
+    assert!(bblayers.contains("sources/meta-foo"), "{bblayers}");
+    assert!(!bblayers.contains("sources/meta-bar"), "{bblayers}");
+
+    // Nor can it be picked out by name
+    for args in [&["--only", "meta-bar"], &["--reclone", "meta-bar"]] {
+        let output = yb_cmd(&yocto_dir).arg("sync").args(args).output()?;
+        assert!(!output.status.success());
+        let stderr = std::str::from_utf8(&output.stderr)?;
+        assert!(stderr.contains("meta-bar"), "{stderr}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn sync_from_report_reproduces_recorded_heads() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let upstream = path.join("meta-foo");
+    create_repo(&upstream);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml("default", &[("meta-foo", &upstream, "main")]),
+        )],
+    );
+
+    let first_root = path.join("first");
+    fs::create_dir_all(&first_root)?;
+    let first_env = setup_yb_env(&first_root, &stream, "default");
+    yb_cmd(&first_env).arg("sync").arg("-a").assert().success();
+    let recorded_head = git(&upstream, &["rev-parse", "HEAD"]);
+
+    let output = yb_cmd(&first_env)
+        .args(["sync", "--print-heads", "--json"])
+        .output()?;
+    assert!(output.status.success());
+    let report = path.join("heads.json");
+    fs::write(&report, output.stdout)?;
+
+    // Upstream moves on after the report was written
+    commit_file(&upstream, "new-file", "new");
+
+    // A fresh environment clones the repo, then goes back to the recorded commit
+    let second_root = path.join("second");
+    fs::create_dir_all(&second_root)?;
+    let second_env = setup_yb_env(&second_root, &stream, "default");
+    yb_cmd(&second_env)
+        .args(["sync", "-a", "--from-report"])
+        .arg(&report)
+        .assert()
+        .success();
+    let clone = second_env.join("sources").join("meta-foo");
+    assert_eq!(git(&clone, &["rev-parse", "HEAD"]), recorded_head);
+    assert_eq!(git(&clone, &["rev-parse", "--abbrev-ref", "HEAD"]), "HEAD");
+
+    // An existing repo is synced and then detached at the recorded commit too
+    yb_cmd(&first_env)
+        .args(["sync", "-a", "--from-report"])
+        .arg(&report)
+        .assert()
+        .success();
+    let clone = first_env.join("sources").join("meta-foo");
+    assert_eq!(git(&clone, &["rev-parse", "HEAD"]), recorded_head);
+    assert_eq!(git(&clone, &["rev-parse", "--abbrev-ref", "HEAD"]), "HEAD");
+
+    Ok(())
+}
+
+#[test]
+fn version_1_yb_conf_is_migrated_in_place() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+
+    // Turn yb.yaml back into the version 1 format
+    let conf_file = yocto_dir.join(".yb").join("yb.yaml");
+    let current = fs::read_to_string(&conf_file)?;
+    let version_1 = current
+        .replace("format_version: 2", "format_version: 1")
+        .replace("sources_dir_relative", "repos_dir_relative");
+    assert_ne!(current, version_1);
+
+    // Loading the environment upgrades it
+    fs::write(&conf_file, &version_1)?;
+    yb_cmd(&yocto_dir)
+        .args(["status", "--no-fetch"])
+        .assert()
+        .success();
+    let migrated = fs::read_to_string(&conf_file)?;
+    assert!(migrated.contains("format_version: 2"), "{migrated}");
+    assert!(migrated.contains("sources_dir_relative"), "{migrated}");
+
+    fs::write(&conf_file, &version_1)?;
+    let output = yb_cmd(&yocto_dir).args(["config", "migrate"]).output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("from format version 1 to 2"), "{stdout}");
+    assert!(fs::read_to_string(&conf_file)?.contains("format_version: 2"));
+
+    let output = yb_cmd(&yocto_dir).args(["config", "migrate"]).output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("already at format version 2"), "{stdout}");
+
+    Ok(())
+}
+
+#[test]
+fn trace_git_logs_git_invocations() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+
+    let output = yb_cmd(&yocto_dir)
+        .args(["--trace-git", "sync", "-a"])
+        .output()?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("running 'git clone"), "{stderr}");
+    assert!(
+        stderr.contains("running 'git checkout main' in"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("'git checkout main' finished"), "{stderr}");
+
+    // Nothing is logged without it
+    let output = yb_cmd(&yocto_dir)
+        .args(["sync", "-a", "--reclone", "meta-foo", "--force"])
+        .output()?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(!stderr.contains("running 'git"), "{stderr}");
+
+    // The environment variable works too
+    let output = yb_cmd(&yocto_dir)
+        .env("YB_TRACE_GIT", "1")
+        .args(["sync", "-a", "--reclone", "meta-foo", "--force"])
+        .output()?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("running 'git checkout main' in"),
+        "{stderr}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn status_json_stream_prints_one_line_per_repo_and_a_summary() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let meta_foo = path.join("meta-foo");
+    create_repo(&meta_foo);
+    let meta_bar = path.join("meta-bar");
+    create_repo(&meta_bar);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml(
+                "default",
+                &[
+                    ("meta-foo", &meta_foo, "main"),
+                    ("meta-bar", &meta_bar, "main"),
+                ],
+            ),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+
+    let output = yb_cmd(&yocto_dir)
+        .args(["status", "--no-fetch", "--json-stream"])
+        .output()?;
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "{stdout}");
+    assert!(lines[..2]
+        .iter()
+        .all(|line| line.starts_with(r#"{"type":"source_dir""#)));
+    assert!(lines[2].starts_with(r#"{"type":"summary","source_dirs":2,"#));
+
+    Ok(())
+}
+
+#[test]
+fn sync_report_only_changed_lists_just_the_changed_repos() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let meta_foo = path.join("meta-foo");
+    create_repo(&meta_foo);
+    let meta_bar = path.join("meta-bar");
+    create_repo(&meta_bar);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml(
+                "default",
+                &[
+                    ("meta-foo", &meta_foo, "main"),
+                    ("meta-bar", &meta_bar, "main"),
+                ],
+            ),
+        )],
+    );
+    let yocto_dir = setup_yb_env(path, &stream, "default");
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+
+    // Only meta-foo has new commits to pull
+    commit_file(&meta_foo, "README", "update");
+
+    let output = yb_cmd(&yocto_dir)
+        .args([
+            "sync",
+            "-a",
+            "--print-heads",
+            "--json",
+            "--report-only-changed",
+        ])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    let head = git(&meta_foo, &["rev-parse", "HEAD"]);
+    assert!(stdout.contains(r#""name": "meta-foo""#), "{stdout}");
+    assert!(stdout.contains(&format!(r#""head": "{head}""#)), "{stdout}");
+    assert!(stdout.contains(r#""changed": true"#), "{stdout}");
+    assert!(!stdout.contains("meta-bar"), "{stdout}");
+
+    // Without the filter, the untouched repo is listed too
+    let output = yb_cmd(&yocto_dir)
+        .args(["sync", "--print-heads", "--json"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    assert!(stdout.contains(r#""name": "meta-bar""#), "{stdout}");
+    assert!(!stdout.contains(r#""changed": true"#), "{stdout}");
+
+    Ok(())
+}
+
+#[test]
+fn activate_dry_run_prints_plan_without_activating() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+
+    let meta_foo = path.join("meta-foo");
+    create_repo(&meta_foo);
+    let stream = path.join("stream");
+    create_stream_repo(
+        &stream,
+        &[(
+            "default.yaml",
+            &spec_yaml("default", &[("meta-foo", &meta_foo, "main")]),
+        )],
+    );
+    yb_cmd(path).arg("init").assert().success();
+    let yocto_dir = path.join("yocto");
+    yb_cmd(&yocto_dir)
+        .arg("stream")
+        .arg("add")
+        .arg(&stream)
+        .assert()
+        .success();
+
+    let output = yb_cmd(&yocto_dir)
+        .args(["activate", "default", "--dry-run"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    assert!(stdout.contains("meta-foo: clone"), "{stdout}");
+    assert!(!yocto_dir.join(".yb").join("active_spec.yaml").exists());
+    assert!(!yocto_dir.join("sources").join("meta-foo").exists());
+
+    Ok(())
+}
+
+#[test]
+fn sync_print_heads_json_ignores_unknown_repos() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+    create_repo(yocto_dir.join("sources").join("meta-unknown"));
+
+    let output = yb_cmd(&yocto_dir)
+        .args(["sync", "--print-heads", "--json"])
+        .output()?;
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains("skipped"), "{stderr}");
+    assert!(stderr.contains("meta-unknown"), "{stderr}");
+
+    // stdout is nothing but the report
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let heads = report.as_array().unwrap();
+    assert_eq!(heads.len(), 1);
+    assert_eq!(heads[0]["name"], "meta-foo");
+
+    Ok(())
+}
+
+#[test]
+fn sync_uses_preferred_remote_from_user_conf() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    let upstream = spec_env.upstream("meta-foo");
+
+    // 'origin' and 'mirror' both point at the spec URL
+    let repo_dir = yocto_dir.join("sources").join("meta-foo");
+    clone_repo(&upstream, &repo_dir);
+    git(
+        &repo_dir,
+        &["remote", "add", "mirror", upstream.to_str().unwrap()],
+    );
+    git(&repo_dir, &["fetch", "mirror"]);
+
+    fs::write(
+        yocto_dir.join(".yb").join("config.toml"),
+        "preferred_remotes = ['mirror']\n",
+    )?;
+
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+    assert_eq!(
+        git(&repo_dir, &["rev-parse", "--abbrev-ref", "@{upstream}"]),
+        "mirror/main"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn sync_prompt_related_saves_aliases_only_when_applying() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let (yocto_dir, _) = setup_env_with_related_repo(t.path());
+    let fork = t.path().join("fork").join("meta-foo");
+    let user_conf = yocto_dir.join(".yb").join("config.toml");
+
+    let output = yb_cmd(&yocto_dir)
+        .args(["--yes", "sync", "--prompt-related"])
+        .output()?;
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains("only saved with -a"), "{stderr}");
+    assert!(!user_conf.exists());
+
+    yb_cmd(&yocto_dir)
+        .args(["--yes", "sync", "-a", "--prompt-related"])
+        .assert()
+        .success();
+    let saved = fs::read_to_string(&user_conf)?;
+    assert!(saved.contains(fork.to_str().unwrap()), "{saved}");
+
+    // Later syncs match the repo through the saved alias
+    let output = yb_cmd(&yocto_dir).arg("sync").arg("-a").output()?;
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(!stderr.contains("--allow-unrelated"), "{stderr}");
+
+    Ok(())
+}
+
+#[test]
+fn sync_reads_parallel_fetch_limit_from_user_conf() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+
+    fs::write(
+        yocto_dir.join(".yb").join("config.toml"),
+        "parallel_fetch_limit = 0\n",
+    )?;
+    let output = yb_cmd(&yocto_dir).arg("sync").output()?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains("must be at least 1"), "{stderr}");
+
+    // The command line takes precedence
+    yb_cmd(&yocto_dir)
+        .args(["sync", "-a", "--parallel-fetch-limit", "1"])
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn import_manifest_into_stream_dir_validates_first() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let path = t.path();
+    let stream_dir = path.join("stream");
+    fs::create_dir(&stream_dir)?;
+
+    let manifest = path.join("good.xml");
+    fs::write(
+        &manifest,
+        r#"<manifest>
+  <remote name="origin" fetch="https://example.com/"/>
+  <default remote="origin" revision="main"/>
+  <project name="meta-foo" path="sources/meta-foo"/>
+</manifest>
+"#,
+    )?;
+    yb_cmd(path)
+        .args(["import", "manifest"])
+        .arg(&manifest)
+        .arg("--stream-dir")
+        .arg(&stream_dir)
+        .assert()
+        .success();
+    let spec = fs::read_to_string(stream_dir.join("good.yaml"))?;
+    assert!(spec.contains("https://example.com/meta-foo"), "{spec}");
+
+    // Both projects have the same URL, which specs don't allow
+    let manifest = path.join("bad.xml");
+    fs::write(
+        &manifest,
+        r#"<manifest>
+  <remote name="origin" fetch="https://example.com/"/>
+  <default remote="origin" revision="main"/>
+  <project name="meta-foo" path="a/meta-foo"/>
+  <project name="meta-foo" path="b/meta-foo-copy"/>
+</manifest>
+"#,
+    )?;
+    let output = yb_cmd(path)
+        .args(["import", "manifest"])
+        .arg(&manifest)
+        .arg("--stream-dir")
+        .arg(&stream_dir)
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains("more than one spec repo"), "{stderr}");
+
+    // Nothing was left behind in the stream
+    let mut entries = fs::read_dir(&stream_dir)?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    assert_eq!(entries, ["good.yaml"]);
+
+    Ok(())
+}
+
+#[test]
+fn sync_resumes_interrupted_clone() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo"]);
+    let yocto_dir = spec_env.yocto_dir();
+    let upstream = spec_env.upstream("meta-foo");
+
+    // What a clone killed before it fetched anything leaves behind: 'origin' is set up, but HEAD
+    // is unborn
+    let meta_foo = yocto_dir.join("sources").join("meta-foo");
+    fs::create_dir_all(&meta_foo)?;
+    git(&meta_foo, &["init", "-b", "main"]);
+    git(
+        &meta_foo,
+        &["remote", "add", "origin", upstream.to_str().unwrap()],
+    );
+
+    let output = yb_cmd(&yocto_dir).arg("sync").arg("-a").output()?;
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(output.status.success(), "{stderr}");
+    assert!(stderr.contains("resuming interrupted clone"), "{stderr}");
+    assert_eq!(
+        git(&meta_foo, &["rev-parse", "HEAD"]),
+        git(&upstream, &["rev-parse", "HEAD"])
+    );
+    assert_eq!(
+        git(&meta_foo, &["rev-parse", "--abbrev-ref", "main@{upstream}"]),
+        "origin/main"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn sync_verify_fails_when_repo_left_on_wrong_branch() -> Result<()> {
+    let t = DebugTempDir::new()?;
+    let spec_env = SpecEnv::new(t.path(), &["meta-foo", "meta-bar"]);
+    let yocto_dir = spec_env.yocto_dir();
+    yb_cmd(&yocto_dir).arg("sync").arg("-a").assert().success();
+
+    // Syncing only meta-bar leaves meta-foo on a branch that doesn't track the spec's
+    let meta_foo = yocto_dir.join("sources").join("meta-foo");
+    git(&meta_foo, &["checkout", "-b", "scratch"]);
+    let output = yb_cmd(&yocto_dir)
+        .args(["sync", "-a", "--only", "meta-bar", "--verify"])
+        .output()?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stdout)?.contains("is not on a branch tracking"));
+    assert!(std::str::from_utf8(&output.stderr)?.contains("verification failed"));
+
+    // Without -a nothing is applied, so there is nothing to verify
+    let output = yb_cmd(&yocto_dir).args(["sync", "--verify"]).output()?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)?.contains("it needs -a"));
+
+    Ok(())
+}